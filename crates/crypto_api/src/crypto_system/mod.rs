@@ -3,7 +3,7 @@ use crate::{Buffer, CryptoResult};
 /// A trait describing a cryptographic system implementation compatible
 /// with Lib3h and Holochain.
 #[allow(clippy::borrowed_box)]
-pub trait CryptoSystem: Sync {
+pub trait CryptoSystem: Send + Sync {
     /// Crypto System is designed to be used as a trait-object
     /// Since we can't get a sized clone, provide clone in a Box.
     fn box_clone(&self) -> Box<dyn CryptoSystem>;
@@ -0,0 +1,201 @@
+//! Anti-entropy between pairs of peers of a fullsync DHT.
+//!
+//! A peer periodically sends an `AspectDigest` of the aspect addresses it holds.
+//! The receiver answers with the `BucketContent` of every bucket whose hash differs,
+//! and each side then pushes the aspects the other one lacks.
+//! Peers that missed some gossip converge without a full rebroadcast.
+
+use lib3h_protocol::Address;
+use std::collections::{HashMap, HashSet};
+
+/// Number of buckets the entry address space is split into
+pub const DIGEST_BUCKET_COUNT: usize = 64;
+
+/// Storage layout of held aspects: entry_address -> aspect addresses
+pub type EntryAspectMap = HashMap<Address, HashSet<Address>>;
+
+/// Compact summary of a set of held aspects: one hash per bucket
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AspectDigest {
+    pub bucket_hash_list: Vec<u64>,
+}
+
+/// Aspect addresses held in some buckets
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BucketContent {
+    pub bucket_index_list: Vec<usize>,
+    /// Aspect addresses per entry, for every entry in the listed buckets
+    pub entry_list: Vec<(Address, Vec<Address>)>,
+    /// Receiver should answer with its own content of the same buckets
+    pub reply_expected: bool,
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a, because digests are compared across nodes:
+/// std's DefaultHasher is not guaranteed to be stable between builds.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Bucket an entry, and all of its aspects, belong to
+pub fn bucket_of(entry_address: &Address) -> usize {
+    let hash = fnv1a(FNV_OFFSET_BASIS, entry_address.to_string().as_bytes());
+    (hash % DIGEST_BUCKET_COUNT as u64) as usize
+}
+
+fn aspect_hash(entry_address: &Address, aspect_address: &Address) -> u64 {
+    let hash = fnv1a(FNV_OFFSET_BASIS, entry_address.to_string().as_bytes());
+    let hash = fnv1a(hash, &[0]);
+    fnv1a(hash, aspect_address.to_string().as_bytes())
+}
+
+impl AspectDigest {
+    /// Bucket hashes are the XOR of their aspect hashes, so insertion order does not matter
+    pub fn new(entry_map: &EntryAspectMap) -> Self {
+        let mut bucket_hash_list = vec![0; DIGEST_BUCKET_COUNT];
+        for (entry_address, aspect_set) in entry_map {
+            let bucket = bucket_of(entry_address);
+            for aspect_address in aspect_set {
+                bucket_hash_list[bucket] ^= aspect_hash(entry_address, aspect_address);
+            }
+        }
+        AspectDigest { bucket_hash_list }
+    }
+
+    /// Indexes of the buckets that differ between the two digests.
+    /// A digest with another bucket count differs everywhere.
+    pub fn diff(&self, other: &AspectDigest) -> Vec<usize> {
+        if self.bucket_hash_list.len() != other.bucket_hash_list.len() {
+            return (0..self.bucket_hash_list.len()).collect();
+        }
+        self.bucket_hash_list
+            .iter()
+            .zip(other.bucket_hash_list.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+impl BucketContent {
+    /// Collect the aspect addresses held in the listed buckets
+    pub fn new(
+        entry_map: &EntryAspectMap,
+        bucket_index_list: Vec<usize>,
+        reply_expected: bool,
+    ) -> Self {
+        let bucket_set: HashSet<usize> = bucket_index_list.iter().cloned().collect();
+        let entry_list = entry_map
+            .iter()
+            .filter(|(entry_address, _)| bucket_set.contains(&bucket_of(entry_address)))
+            .map(|(entry_address, aspect_set)| {
+                (entry_address.clone(), aspect_set.iter().cloned().collect())
+            })
+            .collect();
+        BucketContent {
+            bucket_index_list,
+            entry_list,
+            reply_expected,
+        }
+    }
+
+    /// Aspects held in `entry_map` that are not in this content, per entry.
+    /// Only entries of the listed buckets are considered.
+    pub fn missing_from(&self, entry_map: &EntryAspectMap) -> Vec<(Address, Vec<Address>)> {
+        let bucket_set: HashSet<usize> = self.bucket_index_list.iter().cloned().collect();
+        let content_map: HashMap<&Address, HashSet<&Address>> = self
+            .entry_list
+            .iter()
+            .map(|(entry_address, aspect_list)| (entry_address, aspect_list.iter().collect()))
+            .collect();
+        let mut missing_list = Vec::new();
+        for (entry_address, aspect_set) in entry_map {
+            if !bucket_set.contains(&bucket_of(entry_address)) {
+                continue;
+            }
+            let maybe_known = content_map.get(entry_address);
+            let missing: Vec<Address> = aspect_set
+                .iter()
+                .filter(|aspect_address| match maybe_known {
+                    None => true,
+                    Some(known) => !known.contains(aspect_address),
+                })
+                .cloned()
+                .collect();
+            if !missing.is_empty() {
+                missing_list.push((entry_address.clone(), missing));
+            }
+        }
+        missing_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_map(list: &[(&str, &[&str])]) -> EntryAspectMap {
+        list.iter()
+            .map(|(entry, aspect_list)| {
+                (
+                    Address::from(*entry),
+                    aspect_list.iter().map(|a| Address::from(*a)).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_digest_diff() {
+        let map_a = entry_map(&[
+            ("entry_1", &["aspect_1", "aspect_2"]),
+            ("entry_2", &["aspect_3"]),
+        ]);
+        let map_b = entry_map(&[
+            ("entry_2", &["aspect_3"]),
+            ("entry_1", &["aspect_2", "aspect_1"]),
+        ]);
+        let digest_a = AspectDigest::new(&map_a);
+        assert_eq!(digest_a.bucket_hash_list.len(), DIGEST_BUCKET_COUNT);
+        assert!(digest_a.diff(&AspectDigest::new(&map_b)).is_empty());
+        // Missing an aspect only changes the bucket of its entry
+        let map_c = entry_map(&[("entry_1", &["aspect_1"]), ("entry_2", &["aspect_3"])]);
+        let diff = digest_a.diff(&AspectDigest::new(&map_c));
+        assert_eq!(diff, vec![bucket_of(&"entry_1".into())]);
+        // Malformed digest differs everywhere
+        let short = AspectDigest {
+            bucket_hash_list: vec![],
+        };
+        assert_eq!(digest_a.diff(&short).len(), DIGEST_BUCKET_COUNT);
+    }
+
+    #[test]
+    fn test_bucket_content_missing_from() {
+        let map_a = entry_map(&[
+            ("entry_1", &["aspect_1", "aspect_2"]),
+            ("entry_2", &["aspect_3"]),
+        ]);
+        let map_b = entry_map(&[("entry_1", &["aspect_1"])]);
+        let bucket_list = AspectDigest::new(&map_a).diff(&AspectDigest::new(&map_b));
+        let content_b = BucketContent::new(&map_b, bucket_list.clone(), true);
+        let mut missing = content_b.missing_from(&map_a);
+        missing.sort_by_cached_key(|(entry_address, _)| entry_address.to_string());
+        assert_eq!(
+            missing,
+            vec![
+                ("entry_1".into(), vec!["aspect_2".into()]),
+                ("entry_2".into(), vec!["aspect_3".into()]),
+            ]
+        );
+        // Nothing is missing the other way around
+        let content_a = BucketContent::new(&map_a, bucket_list, false);
+        assert!(content_a.missing_from(&map_b).is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+//! Conformance suite for `Dht` implementations.
+//!
+//! Enabled with the `dht-conformance` feature. Every check builds fresh instances
+//! through a `DhtFactory` and panics on the first violation, so they can be called
+//! straight from a test:
+//!
+//! ```ignore
+//! #[test]
+//! fn my_dht_conformance() {
+//!     lib3h::dht::conformance::run_all(MyDht::new_with_config);
+//! }
+//! ```
+
+use crate::{
+    dht::{
+        dht_protocol::*,
+        dht_trait::{Dht, DhtConfig, DhtFactory},
+        PeerAddress, PeerAddressRef,
+    },
+    time,
+};
+use lib3h_protocol::{
+    data_types::{EntryAspectData, EntryData},
+    Address,
+};
+use std::{thread, time::Duration};
+use url::Url;
+
+/// Time given to instances to converge before a check fails
+const CONVERGENCE_TIMEOUT_MS: u64 = 5000;
+
+/// Pause between two rounds while waiting for convergence
+const ROUND_PAUSE_MS: u64 = 10;
+
+/// Run every check
+pub fn run_all<D: Dht>(factory: DhtFactory<D>) {
+    check_hold_peer(factory);
+    check_peer_timeout(factory);
+    check_hold_entry(factory);
+    check_broadcast_entry(factory);
+    check_drop_entry(factory);
+    check_fetch_entry(factory);
+    check_gossip_convergence(factory, 5);
+}
+
+/// Config of an instance for `peer_address`, with intervals short enough for tests
+pub fn test_config(peer_address: &PeerAddressRef) -> DhtConfig {
+    let mut config = DhtConfig::new(peer_address, &test_uri(peer_address));
+    config.gossip_interval = 100;
+    config
+}
+
+fn test_uri(peer_address: &PeerAddressRef) -> Url {
+    Url::parse(format!("test://{}", peer_address).as_str()).unwrap()
+}
+
+fn test_peer(peer_address: &PeerAddressRef, timestamp: u64) -> PeerData {
+    PeerData {
+        peer_address: peer_address.to_owned(),
+        peer_uri: test_uri(peer_address),
+        timestamp,
+    }
+}
+
+fn test_entry(entry_address: &str, aspect_count: usize) -> EntryData {
+    EntryData {
+        entry_address: entry_address.into(),
+        aspect_list: (0..aspect_count)
+            .map(|index| EntryAspectData {
+                aspect_address: format!("{}_aspect_{}", entry_address, index).into(),
+                type_hint: "conformance".to_owned(),
+                aspect: format!("content {}", index).into_bytes(),
+                publish_ts: time::since_epoch_ms(),
+            })
+            .collect(),
+    }
+}
+
+fn new_dht<D: Dht>(factory: DhtFactory<D>, config: &DhtConfig) -> D {
+    factory(config).expect("DhtFactory failed")
+}
+
+/// Aspect addresses of an entry, sorted
+fn aspect_address_list(entry: &EntryData) -> Vec<Address> {
+    let mut list: Vec<Address> = entry
+        .aspect_list
+        .iter()
+        .map(|aspect| aspect.aspect_address.clone())
+        .collect();
+    list.sort();
+    list
+}
+
+fn held_aspect_list(dht: &dyn Dht, entry_address: &Address) -> Option<Vec<Address>> {
+    dht.get_aspects_of(entry_address).map(|mut list| {
+        list.sort();
+        list
+    })
+}
+
+fn process_events(dht: &mut dyn Dht) -> Vec<DhtEvent> {
+    let (_, event_list) = dht.process().expect("process() failed");
+    event_list
+}
+
+/// Held peers can be looked up, and only newer peer data replaces them
+pub fn check_hold_peer<D: Dht>(factory: DhtFactory<D>) {
+    let mut dht = new_dht(factory, &test_config("peer_0"));
+    let peer = test_peer("peer_1", time::since_epoch_ms());
+    dht.post(DhtCommand::HoldPeer(peer.clone())).unwrap();
+    process_events(&mut dht);
+    assert_eq!(dht.get_peer("peer_1"), Some(peer.clone()), "held peer");
+    assert!(
+        dht.get_peer_list().contains(&peer),
+        "held peer is in peer list"
+    );
+    let mut stale_peer = peer.clone();
+    stale_peer.timestamp -= 10;
+    dht.post(DhtCommand::HoldPeer(stale_peer)).unwrap();
+    process_events(&mut dht);
+    assert_eq!(
+        dht.get_peer("peer_1").unwrap().timestamp,
+        peer.timestamp,
+        "older peer data is ignored"
+    );
+    let mut newer_peer = peer.clone();
+    newer_peer.timestamp += 10;
+    dht.post(DhtCommand::HoldPeer(newer_peer.clone())).unwrap();
+    process_events(&mut dht);
+    assert_eq!(
+        dht.get_peer("peer_1").unwrap().timestamp,
+        newer_peer.timestamp,
+        "newer peer data replaces held one"
+    );
+}
+
+/// A peer not heard of for `timeout_threshold` is reported once with PeerTimedOut
+pub fn check_peer_timeout<D: Dht>(factory: DhtFactory<D>) {
+    let mut config = test_config("peer_0");
+    config.timeout_threshold = 100;
+    let mut dht = new_dht(factory, &config);
+    dht.post(DhtCommand::HoldPeer(test_peer(
+        "peer_1",
+        time::since_epoch_ms(),
+    )))
+    .unwrap();
+    let timed_out = DhtEvent::PeerTimedOut("peer_1".to_owned());
+    let mut timed_out_count = 0;
+    let deadline = time::since_epoch_ms() + config.timeout_threshold * 10;
+    while time::since_epoch_ms() < deadline {
+        timed_out_count += process_events(&mut dht)
+            .iter()
+            .filter(|event| **event == timed_out)
+            .count();
+        thread::sleep(Duration::from_millis(ROUND_PAUSE_MS));
+    }
+    assert_eq!(timed_out_count, 1, "PeerTimedOut reported once");
+}
+
+/// Held entries can be looked up
+pub fn check_hold_entry<D: Dht>(factory: DhtFactory<D>) {
+    let mut dht = new_dht(factory, &test_config("peer_0"));
+    let entry = test_entry("entry_1", 2);
+    dht.post(DhtCommand::HoldEntryAspectAddress(entry.clone()))
+        .unwrap();
+    process_events(&mut dht);
+    assert!(
+        dht.get_entry_address_list().contains(&&entry.entry_address),
+        "held entry is in entry list"
+    );
+    assert_eq!(
+        held_aspect_list(&dht, &entry.entry_address),
+        Some(aspect_address_list(&entry)),
+        "held entry aspects"
+    );
+}
+
+/// Broadcast entries are held locally too
+pub fn check_broadcast_entry<D: Dht>(factory: DhtFactory<D>) {
+    let mut dht = new_dht(factory, &test_config("peer_0"));
+    let entry = test_entry("entry_1", 1);
+    dht.post(DhtCommand::BroadcastEntry(entry.clone())).unwrap();
+    process_events(&mut dht);
+    assert_eq!(
+        held_aspect_list(&dht, &entry.entry_address),
+        Some(aspect_address_list(&entry)),
+        "broadcast entry is held"
+    );
+}
+
+/// Dropped entries are not held anymore
+pub fn check_drop_entry<D: Dht>(factory: DhtFactory<D>) {
+    let mut dht = new_dht(factory, &test_config("peer_0"));
+    let entry = test_entry("entry_1", 1);
+    dht.post(DhtCommand::HoldEntryAspectAddress(entry.clone()))
+        .unwrap();
+    process_events(&mut dht);
+    dht.post(DhtCommand::DropEntryAddress(entry.entry_address.clone()))
+        .unwrap();
+    process_events(&mut dht);
+    assert!(
+        !dht.get_entry_address_list().contains(&&entry.entry_address),
+        "dropped entry is not in entry list"
+    );
+    assert_eq!(
+        dht.get_aspects_of(&entry.entry_address),
+        None,
+        "dropped entry has no aspects"
+    );
+}
+
+/// Fetching a held entry asks owner for its data with EntryDataRequested,
+/// and the owner's answer comes back as the FetchEntryResponse
+pub fn check_fetch_entry<D: Dht>(factory: DhtFactory<D>) {
+    let mut dht = new_dht(factory, &test_config("peer_0"));
+    let entry = test_entry("entry_1", 2);
+    dht.post(DhtCommand::HoldEntryAspectAddress(entry.clone()))
+        .unwrap();
+    process_events(&mut dht);
+    let fetch = FetchDhtEntryData {
+        msg_id: "conformance_fetch".to_owned(),
+        entry_address: entry.entry_address.clone(),
+    };
+    dht.post(DhtCommand::FetchEntry(fetch.clone())).unwrap();
+    let request = process_events(&mut dht)
+        .into_iter()
+        .filter_map(|event| match event {
+            DhtEvent::EntryDataRequested(request) => Some(request),
+            _ => None,
+        })
+        .next()
+        .expect("EntryDataRequested for a held entry");
+    assert_eq!(request.entry_address, entry.entry_address);
+    dht.post(DhtCommand::EntryDataResponse(FetchDhtEntryResponseData {
+        msg_id: request.msg_id,
+        entry: entry.clone(),
+    }))
+    .unwrap();
+    let response_list: Vec<DhtEvent> = process_events(&mut dht)
+        .into_iter()
+        .filter(|event| match event {
+            DhtEvent::FetchEntryResponse(_) => true,
+            _ => false,
+        })
+        .collect();
+    assert_eq!(
+        response_list,
+        vec![DhtEvent::FetchEntryResponse(FetchDhtEntryResponseData {
+            msg_id: fetch.msg_id,
+            entry,
+        })],
+        "fetch response"
+    );
+}
+
+/// `instance_count` instances that only know the first one get to know each other,
+/// hold an entry one of them broadcast, and can all fetch it
+pub fn check_gossip_convergence<D: Dht>(factory: DhtFactory<D>, instance_count: usize) {
+    assert!(instance_count > 1);
+    let mut network = DhtNetwork::new(factory, instance_count);
+    let peer_address_list = network.peer_address_list();
+    for index in 1..instance_count {
+        let now = time::since_epoch_ms();
+        network.dht_list[0]
+            .post(DhtCommand::HoldPeer(test_peer(
+                &peer_address_list[index],
+                now,
+            )))
+            .unwrap();
+        network.dht_list[index]
+            .post(DhtCommand::HoldPeer(test_peer(&peer_address_list[0], now)))
+            .unwrap();
+    }
+    network.run_until("peers know each other", |network| {
+        network.dht_list.iter().enumerate().all(|(index, dht)| {
+            peer_address_list
+                .iter()
+                .enumerate()
+                .all(|(other, peer_address)| other == index || dht.get_peer(peer_address).is_some())
+        })
+    });
+    // Last instance authors an entry
+    let entry = test_entry("entry_1", 2);
+    let last = instance_count - 1;
+    network.held_list[last].push(entry.clone());
+    network.dht_list[last]
+        .post(DhtCommand::BroadcastEntry(entry.clone()))
+        .unwrap();
+    let expected_aspect_list = Some(aspect_address_list(&entry));
+    network.run_until("entry is held everywhere", |network| {
+        network
+            .dht_list
+            .iter()
+            .all(|dht| held_aspect_list(dht, &entry.entry_address) == expected_aspect_list)
+    });
+    // Every instance can fetch it
+    for (index, dht) in network.dht_list.iter_mut().enumerate() {
+        dht.post(DhtCommand::FetchEntry(FetchDhtEntryData {
+            msg_id: format!("conformance_fetch_{}", index),
+            entry_address: entry.entry_address.clone(),
+        }))
+        .unwrap();
+    }
+    network.run_until("fetches are answered", |network| {
+        network
+            .fetch_response_list
+            .iter()
+            .all(|response_list| !response_list.is_empty())
+    });
+    for (index, response_list) in network.fetch_response_list.iter().enumerate() {
+        assert_eq!(response_list.len(), 1);
+        assert_eq!(
+            response_list[0].msg_id,
+            format!("conformance_fetch_{}", index)
+        );
+        assert_eq!(
+            aspect_address_list(&response_list[0].entry),
+            aspect_address_list(&entry),
+            "fetched entry"
+        );
+    }
+}
+
+/// Instances of a Dht wired together the way their owners would:
+/// gossip is delivered to its recipients, hold requests are accepted,
+/// and fetches are answered with what each owner holds.
+pub struct DhtNetwork<D: Dht> {
+    pub dht_list: Vec<D>,
+    /// Entries each owner holds, to answer EntryDataRequested
+    pub held_list: Vec<Vec<EntryData>>,
+    /// FetchEntryResponse received by each owner
+    pub fetch_response_list: Vec<Vec<FetchDhtEntryResponseData>>,
+}
+
+impl<D: Dht> DhtNetwork<D> {
+    /// Instances are named `peer_0`, `peer_1`, ...
+    pub fn new(factory: DhtFactory<D>, instance_count: usize) -> Self {
+        DhtNetwork {
+            dht_list: (0..instance_count)
+                .map(|index| new_dht(factory, &test_config(&format!("peer_{}", index))))
+                .collect(),
+            held_list: vec![Vec::new(); instance_count],
+            fetch_response_list: vec![Vec::new(); instance_count],
+        }
+    }
+
+    pub fn peer_address_list(&self) -> Vec<PeerAddress> {
+        self.dht_list
+            .iter()
+            .map(|dht| dht.this_peer().peer_address.clone())
+            .collect()
+    }
+
+    /// Process every instance once and route its events
+    pub fn process_all(&mut self) {
+        for index in 0..self.dht_list.len() {
+            let from_peer_address = self.dht_list[index].this_peer().peer_address.clone();
+            for event in process_events(&mut self.dht_list[index]) {
+                self.route(index, &from_peer_address, event);
+            }
+        }
+    }
+
+    /// Process rounds until `is_done` holds. Panic if it takes too long.
+    pub fn run_until<F: Fn(&Self) -> bool>(&mut self, description: &str, is_done: F) {
+        let deadline = time::since_epoch_ms() + CONVERGENCE_TIMEOUT_MS;
+        loop {
+            self.process_all();
+            if is_done(self) {
+                return;
+            }
+            if time::since_epoch_ms() > deadline {
+                panic!("DHT instances did not converge: {}", description);
+            }
+            thread::sleep(Duration::from_millis(ROUND_PAUSE_MS));
+        }
+    }
+
+    fn route(&mut self, index: usize, from_peer_address: &PeerAddressRef, event: DhtEvent) {
+        match event {
+            DhtEvent::GossipTo(gossip_to) | DhtEvent::GossipUnreliablyTo(gossip_to) => {
+                for to_peer_address in gossip_to.peer_address_list.iter() {
+                    let maybe_to = self
+                        .dht_list
+                        .iter_mut()
+                        .find(|dht| &dht.this_peer().peer_address == to_peer_address);
+                    if let Some(to) = maybe_to {
+                        to.post(DhtCommand::HandleGossip(RemoteGossipBundleData {
+                            from_peer_address: from_peer_address.to_owned(),
+                            bundle: gossip_to.bundle.clone(),
+                        }))
+                        .unwrap();
+                    }
+                }
+            }
+            DhtEvent::HoldPeerRequested(peer_data) => {
+                self.dht_list[index]
+                    .post(DhtCommand::HoldPeer(peer_data))
+                    .unwrap();
+            }
+            DhtEvent::HoldEntryRequested(_, entry) => {
+                let held_list = &mut self.held_list[index];
+                match held_list
+                    .iter_mut()
+                    .find(|held| held.entry_address == entry.entry_address)
+                {
+                    None => held_list.push(entry.clone()),
+                    Some(held) => {
+                        for aspect in entry.aspect_list.iter() {
+                            if !held.aspect_list.contains(aspect) {
+                                held.aspect_list.push(aspect.clone());
+                            }
+                        }
+                    }
+                }
+                self.dht_list[index]
+                    .post(DhtCommand::HoldEntryAspectAddress(entry))
+                    .unwrap();
+            }
+            DhtEvent::EntryDataRequested(request) => {
+                let maybe_entry = self.held_list[index]
+                    .iter()
+                    .find(|held| held.entry_address == request.entry_address)
+                    .cloned();
+                if let Some(entry) = maybe_entry {
+                    self.dht_list[index]
+                        .post(DhtCommand::EntryDataResponse(FetchDhtEntryResponseData {
+                            msg_id: request.msg_id,
+                            entry,
+                        }))
+                        .unwrap();
+                }
+            }
+            DhtEvent::FetchEntryResponse(response) => {
+                self.fetch_response_list[index].push(response);
+            }
+            DhtEvent::PeerTimedOut(_) | DhtEvent::PeerEvicted(_) | DhtEvent::EntryPruned(_) => (),
+        }
+    }
+}
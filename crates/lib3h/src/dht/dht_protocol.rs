@@ -1,5 +1,5 @@
-use crate::dht::PeerAddress;
-use lib3h_protocol::{data_types::EntryData, Address};
+use crate::dht::{dht_trait::Dht, gossip::GspConstraints, PeerAddress};
+use lib3h_protocol::{data_types::EntryData, opaque::Opaque, Address};
 use url::Url;
 
 pub type FromPeerAddress = PeerAddress;
@@ -21,6 +21,9 @@ pub enum DhtCommand {
     DropEntryAddress(Address),
     /// Owner's response to ProvideEntry request
     EntryDataResponse(FetchDhtEntryResponseData),
+    /// Owner wants to sync with a peer the aspects that satisfy some constraints,
+    /// e.g. `GspConstraints::since(timestamp)` for an incremental sync.
+    OpenGossipRound(PeerAddress, GspConstraints),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -45,18 +48,21 @@ pub enum DhtEvent {
     /// Notify owner that we are no longer tracking this entry internally.
     /// Owner should purge this address from storage, but they can, of course, choose not to.
     EntryPruned(Address),
+    /// Notify owner that a timed-out peer has been forgotten.
+    /// Owner should drop its connection to it.
+    PeerEvicted(PeerData),
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RemoteGossipBundleData {
     pub from_peer_address: PeerAddress,
-    pub bundle: Vec<u8>,
+    pub bundle: Opaque,
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct GossipToData {
     pub peer_address_list: Vec<PeerAddress>,
-    pub bundle: Vec<u8>,
+    pub bundle: Opaque,
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
@@ -78,3 +84,56 @@ pub struct FetchDhtEntryResponseData {
     pub msg_id: String,
     pub entry: EntryData,
 }
+
+/// Diagnostic snapshot of a Dht's state
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct DhtSnapshot {
+    pub this_peer: PeerData,
+    pub peer_list: Vec<PeerData>,
+    pub entry_list: Vec<EntrySnapshot>,
+    /// Fetch requests sent to Core and not answered yet
+    pub pending_fetch_request_list: Vec<String>,
+    pub timed_out_peer_list: Vec<PeerAddress>,
+    /// Peers silent for a while, but not timed out yet
+    #[serde(default)]
+    pub suspect_peer_list: Vec<PeerAddress>,
+    /// Number of DhtCommands waiting to be processed
+    pub inbox_depth: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct EntrySnapshot {
+    pub entry_address: Address,
+    pub aspect_address_list: Vec<Address>,
+}
+
+impl DhtSnapshot {
+    /// Build a snapshot from what the Dht trait getters expose.
+    /// Implementation specific fields are left empty.
+    pub fn from_dht<D: Dht + ?Sized>(dht: &D) -> Self {
+        let mut peer_list = dht.get_peer_list();
+        peer_list.sort_by(|a, b| a.peer_address.cmp(&b.peer_address));
+        let mut entry_list: Vec<EntrySnapshot> = dht
+            .get_entry_address_list()
+            .into_iter()
+            .map(|entry_address| {
+                let mut aspect_address_list = dht.get_aspects_of(entry_address).unwrap_or_default();
+                aspect_address_list.sort_by_cached_key(|a| a.to_string());
+                EntrySnapshot {
+                    entry_address: entry_address.clone(),
+                    aspect_address_list,
+                }
+            })
+            .collect();
+        entry_list.sort_by_cached_key(|e| e.entry_address.to_string());
+        DhtSnapshot {
+            this_peer: dht.this_peer().clone(),
+            peer_list,
+            entry_list,
+            pending_fetch_request_list: Vec::new(),
+            timed_out_peer_list: Vec::new(),
+            suspect_peer_list: Vec::new(),
+            inbox_depth: 0,
+        }
+    }
+}
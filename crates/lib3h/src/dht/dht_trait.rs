@@ -1,15 +1,19 @@
 use crate::{
     dht::{
-        dht_protocol::{DhtCommand, DhtEvent, PeerData},
+        dht_protocol::{DhtCommand, DhtEvent, DhtSnapshot, PeerData},
         PeerAddress, PeerAddressRef,
     },
     error::Lib3hResult,
 };
-use lib3h_protocol::{Address, DidWork};
+use lib3h_protocol::{data_types::StorageBudgetData, Address, DidWork};
+use std::path::PathBuf;
 use url::Url;
 
 pub const DEFAULT_GOSSIP_INTERVAL_MS: u64 = 1000;
 pub const DEFAULT_TIMEOUT_THRESHOLD_MS: u64 = 60000;
+pub const DEFAULT_ANTI_ENTROPY_INTERVAL_MS: u64 = 10000;
+pub const DEFAULT_REPLICATION_FACTOR: u64 = 3;
+pub const DEFAULT_EVICTION_GRACE_PERIOD_MS: u64 = 60000;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DhtConfig {
@@ -19,6 +23,35 @@ pub struct DhtConfig {
     pub custom: Vec<u8>,
     pub gossip_interval: u64,
     pub timeout_threshold: u64,
+    /// Interval between anti-entropy rounds with a peer. 0 disables anti-entropy.
+    #[serde(default = "default_anti_entropy_interval")]
+    pub anti_entropy_interval: u64,
+    /// Target number of peers holding each entry.
+    /// Entries are re-published when holders drop below it. 0 disables re-publishing.
+    #[serde(default = "default_replication_factor")]
+    pub replication_factor: u64,
+    /// File to save peer and entry-address tables to, restored at construction.
+    /// None keeps them in memory only.
+    #[serde(default)]
+    pub storage_path: Option<PathBuf>,
+    /// Time a timed-out peer is kept before being evicted
+    #[serde(default = "default_eviction_grace_period")]
+    pub eviction_grace_period: u64,
+    /// Limit on held entries, beyond which some are pruned. None means no limit.
+    #[serde(default)]
+    pub storage_budget: Option<StorageBudgetData>,
+}
+
+pub fn default_anti_entropy_interval() -> u64 {
+    DEFAULT_ANTI_ENTROPY_INTERVAL_MS
+}
+
+pub fn default_replication_factor() -> u64 {
+    DEFAULT_REPLICATION_FACTOR
+}
+
+pub fn default_eviction_grace_period() -> u64 {
+    DEFAULT_EVICTION_GRACE_PERIOD_MS
 }
 
 impl DhtConfig {
@@ -29,6 +62,11 @@ impl DhtConfig {
             custom: vec![],
             gossip_interval: DEFAULT_GOSSIP_INTERVAL_MS,
             timeout_threshold: DEFAULT_TIMEOUT_THRESHOLD_MS,
+            anti_entropy_interval: DEFAULT_ANTI_ENTROPY_INTERVAL_MS,
+            replication_factor: DEFAULT_REPLICATION_FACTOR,
+            storage_path: None,
+            eviction_grace_period: DEFAULT_EVICTION_GRACE_PERIOD_MS,
+            storage_budget: None,
         }
     }
 }
@@ -37,7 +75,8 @@ pub type DhtFactory<D> = fn(config: &DhtConfig) -> Lib3hResult<D>;
 /// Allow storage and retrieval of peer & entry data.
 /// Trait API is for querying local dht data
 /// DhtCommand is for mutating storage or network/async queries
-pub trait Dht {
+/// Must be `Send` so space gateways can be processed by worker threads.
+pub trait Dht: Send {
     /// Peer info
     fn get_peer_list(&self) -> Vec<PeerData>;
     fn get_peer(&self, peer_address: &PeerAddressRef) -> Option<PeerData>;
@@ -48,4 +87,14 @@ pub trait Dht {
     /// Processing
     fn post(&mut self, cmd: DhtCommand) -> Lib3hResult<()>;
     fn process(&mut self) -> Lib3hResult<(DidWork, Vec<DhtEvent>)>;
+    /// Earliest time (ms since epoch) at which `process()` has work to do,
+    /// e.g. next gossip or timeout check. None if only a `post()` can create work.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        None
+    }
+    /// Debug
+    /// Snapshot of this DHT's state for diagnostics
+    fn dht_snapshot(&self) -> DhtSnapshot {
+        DhtSnapshot::from_dht(self)
+    }
 }
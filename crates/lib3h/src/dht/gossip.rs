@@ -0,0 +1,475 @@
+//! Constraint-based gossip, following the `MsgGsp*` messages of `p2p.capnp`.
+//!
+//! A gossip round between two peers goes:
+//!  1. The initiator sends an `ArcRequest` with some constraints
+//!     and the aspect hashes it holds that satisfy them.
+//!  2. The receiver answers with an `ArcResponse` holding its own aspect hashes
+//!     under the same constraints, and requests the aspects it lacks.
+//!  3. The initiator requests the aspects it lacks.
+//!  4. An `AspectDataRequest` is answered with an `AspectDataResponse`
+//!     once the DHT got the aspect data from its owner.
+//!
+//! Constraining by publish time allows incremental "everything since T" syncs.
+//! `GossipEngine` holds no aspect data, only what is needed to run rounds,
+//! so it can be shared by DHT implementations.
+
+use crate::dht::{
+    location::{entry_location, Location, LocationArc},
+    PeerAddress, PeerAddressRef,
+};
+use lib3h_protocol::{data_types::EntryData, Address};
+use std::collections::HashMap;
+
+/// Aspects must have an entry location within the arc.
+/// `arc_start` is inclusive, `arc_end` exclusive. The arc wraps around the
+/// address space when `arc_start > arc_end`, and is empty when they are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AspectConstraintArc {
+    pub arc_start: Location,
+    pub arc_end: Location,
+}
+
+impl AspectConstraintArc {
+    pub fn contains(self, location: Location) -> bool {
+        LocationArc::new(self.arc_start, self.arc_end).contains(location)
+    }
+}
+
+/// Aspects must have a publish time >= `gte_epoch_ms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AspectConstraintTime {
+    pub gte_epoch_ms: u64,
+}
+
+/// Aspects must have a local store count >= `gte_local_count`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AspectConstraintCount {
+    pub gte_local_count: u64,
+}
+
+/// Constraints of a gossip round. A missing constraint does not filter anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GspConstraints {
+    pub aspect_constraint_arc: Option<AspectConstraintArc>,
+    pub aspect_constraint_time: Option<AspectConstraintTime>,
+    pub aspect_constraint_count: Option<AspectConstraintCount>,
+}
+
+impl GspConstraints {
+    /// Constraints of an incremental round: every aspect published since `gte_epoch_ms`
+    pub fn since(gte_epoch_ms: u64) -> Self {
+        GspConstraints {
+            aspect_constraint_time: Some(AspectConstraintTime { gte_epoch_ms }),
+            ..Default::default()
+        }
+    }
+
+    fn accept(&self, location: Location, aspect: &IndexedAspect) -> bool {
+        if let Some(arc) = &self.aspect_constraint_arc {
+            if !arc.contains(location) {
+                return false;
+            }
+        }
+        if let Some(time) = &self.aspect_constraint_time {
+            if aspect.publish_ts < time.gte_epoch_ms {
+                return false;
+            }
+        }
+        if let Some(count) = &self.aspect_constraint_count {
+            if aspect.local_count < count.gte_local_count {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Aspect hashes associated with an entry address
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AspectHashList {
+    pub entry_address: Address,
+    pub aspect_hash_list: Vec<Address>,
+}
+
+impl AspectHashList {
+    /// Keep only the aspects of `entry` listed here
+    pub fn select(&self, entry: &EntryData) -> EntryData {
+        EntryData {
+            entry_address: entry.entry_address.clone(),
+            aspect_list: entry
+                .aspect_list
+                .iter()
+                .filter(|aspect| self.aspect_hash_list.contains(&aspect.aspect_address))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Data of `ArcRequest` and `ArcResponse`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MsgGspArc {
+    pub constraints: GspConstraints,
+    /// Aspect hashes that fall within all the constraints
+    pub aspect_hash_list: Vec<AspectHashList>,
+}
+
+/// Gossip messages exchanged between peers during a round
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum GspMessage {
+    /// Open a gossip round with a remote peer
+    ArcRequest(MsgGspArc),
+    /// Second stage of a gossip round
+    ArcResponse(MsgGspArc),
+    /// Request the data of aspects we lack
+    AspectDataRequest(Vec<AspectHashList>),
+    /// Give requested aspect data to a remote peer
+    AspectDataResponse(Vec<EntryData>),
+}
+
+/// What the DHT must do after the engine handled a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum GossipOutput {
+    /// Send a message to a peer
+    Send(PeerAddress, GspMessage),
+    /// A peer requested these aspects: get their data from the owner,
+    /// then answer with an `AspectDataResponse`
+    ProvideAspectData(PeerAddress, Vec<AspectHashList>),
+    /// Received aspects we lack: ask the owner to hold them
+    HoldAspectData(PeerAddress, EntryData),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct IndexedAspect {
+    publish_ts: u64,
+    /// Number of local stores of the aspect. Re-holding it is not a new store.
+    local_count: u64,
+}
+
+/// Runs constraint-based gossip rounds over an index of held aspects
+#[derive(Debug, Default)]
+pub struct GossipEngine {
+    /// entry_address -> aspect_address -> aspect metadata
+    aspect_index: HashMap<Address, HashMap<Address, IndexedAspect>>,
+    /// Rounds we opened and that await an ArcResponse: peer -> opening time
+    open_round_map: HashMap<PeerAddress, u64>,
+    /// Opening time of the last completed round with each peer
+    last_round_map: HashMap<PeerAddress, u64>,
+}
+
+impl GossipEngine {
+    pub fn new() -> Self {
+        GossipEngine::default()
+    }
+
+    /// Index the aspects of an entry stored locally.
+    /// Aspects already indexed are left as they are, so re-holding an entry is a no-op.
+    pub fn index_entry(&mut self, entry: &EntryData) {
+        let aspect_map = self
+            .aspect_index
+            .entry(entry.entry_address.clone())
+            .or_insert_with(HashMap::new);
+        for aspect in &entry.aspect_list {
+            aspect_map
+                .entry(aspect.aspect_address.clone())
+                .or_insert(IndexedAspect {
+                    publish_ts: aspect.publish_ts,
+                    local_count: 1,
+                });
+        }
+    }
+
+    /// Remove an entry and all of its aspects from the index
+    pub fn drop_entry(&mut self, entry_address: &Address) {
+        self.aspect_index.remove(entry_address);
+    }
+
+    /// Forget the round opened with a peer, e.g. because it timed out
+    pub fn close_round(&mut self, peer_address: &PeerAddressRef) {
+        self.open_round_map.remove(peer_address);
+    }
+
+    /// Opening time of the last completed round with a peer.
+    /// Use it with `GspConstraints::since()` for incremental rounds.
+    pub fn last_round_ms(&self, peer_address: &PeerAddressRef) -> Option<u64> {
+        self.last_round_map.get(peer_address).cloned()
+    }
+
+    /// Aspect hashes we hold that fall within the constraints
+    pub fn aspect_hash_list(&self, constraints: &GspConstraints) -> Vec<AspectHashList> {
+        let mut hash_list: Vec<AspectHashList> = self
+            .aspect_index
+            .iter()
+            .filter_map(|(entry_address, aspect_map)| {
+                let location = entry_location(entry_address);
+                let mut aspect_hash_list: Vec<Address> = aspect_map
+                    .iter()
+                    .filter(|(_, aspect)| constraints.accept(location, aspect))
+                    .map(|(aspect_address, _)| aspect_address.clone())
+                    .collect();
+                if aspect_hash_list.is_empty() {
+                    return None;
+                }
+                aspect_hash_list.sort_by_cached_key(|a| a.to_string());
+                Some(AspectHashList {
+                    entry_address: entry_address.clone(),
+                    aspect_hash_list,
+                })
+            })
+            .collect();
+        hash_list.sort_by_cached_key(|h| h.entry_address.to_string());
+        hash_list
+    }
+
+    /// Open a round with a peer. Returns the `ArcRequest` to send to it.
+    pub fn open_round(
+        &mut self,
+        peer_address: &PeerAddressRef,
+        constraints: GspConstraints,
+        now: u64,
+    ) -> GspMessage {
+        self.open_round_map.insert(peer_address.to_owned(), now);
+        GspMessage::ArcRequest(MsgGspArc {
+            aspect_hash_list: self.aspect_hash_list(&constraints),
+            constraints,
+        })
+    }
+
+    /// Handle a message received from a peer
+    pub fn handle_message(
+        &mut self,
+        from_peer_address: &PeerAddressRef,
+        msg: GspMessage,
+    ) -> Vec<GossipOutput> {
+        let from = from_peer_address.to_owned();
+        let mut output_list = Vec::new();
+        match msg {
+            GspMessage::ArcRequest(arc) => {
+                let response = MsgGspArc {
+                    aspect_hash_list: self.aspect_hash_list(&arc.constraints),
+                    constraints: arc.constraints,
+                };
+                output_list.push(GossipOutput::Send(
+                    from.clone(),
+                    GspMessage::ArcResponse(response),
+                ));
+                let missing = self.missing(&arc.aspect_hash_list);
+                if !missing.is_empty() {
+                    output_list.push(GossipOutput::Send(
+                        from,
+                        GspMessage::AspectDataRequest(missing),
+                    ));
+                }
+            }
+            GspMessage::ArcResponse(arc) => {
+                let opened_at = match self.open_round_map.remove(&from) {
+                    None => {
+                        warn!("Received ArcResponse from {} for no open round", from);
+                        return output_list;
+                    }
+                    Some(opened_at) => opened_at,
+                };
+                self.last_round_map.insert(from.clone(), opened_at);
+                let missing = self.missing(&arc.aspect_hash_list);
+                if !missing.is_empty() {
+                    output_list.push(GossipOutput::Send(
+                        from,
+                        GspMessage::AspectDataRequest(missing),
+                    ));
+                }
+            }
+            GspMessage::AspectDataRequest(hash_list) => {
+                let held = self.held(&hash_list);
+                if !held.is_empty() {
+                    output_list.push(GossipOutput::ProvideAspectData(from, held));
+                }
+            }
+            GspMessage::AspectDataResponse(entry_list) => {
+                for mut entry in entry_list {
+                    let maybe_aspect_map = self.aspect_index.get(&entry.entry_address);
+                    entry.aspect_list.retain(|aspect| match maybe_aspect_map {
+                        None => true,
+                        Some(aspect_map) => !aspect_map.contains_key(&aspect.aspect_address),
+                    });
+                    if !entry.aspect_list.is_empty() {
+                        output_list.push(GossipOutput::HoldAspectData(from.clone(), entry));
+                    }
+                }
+            }
+        }
+        output_list
+    }
+
+    /// Filter a hash list down to the aspects we do not hold
+    fn missing(&self, hash_list: &[AspectHashList]) -> Vec<AspectHashList> {
+        self.filter(hash_list, false)
+    }
+
+    /// Filter a hash list down to the aspects we hold
+    fn held(&self, hash_list: &[AspectHashList]) -> Vec<AspectHashList> {
+        self.filter(hash_list, true)
+    }
+
+    fn filter(&self, hash_list: &[AspectHashList], is_held: bool) -> Vec<AspectHashList> {
+        hash_list
+            .iter()
+            .filter_map(|hashes| {
+                let maybe_aspect_map = self.aspect_index.get(&hashes.entry_address);
+                let aspect_hash_list: Vec<Address> = hashes
+                    .aspect_hash_list
+                    .iter()
+                    .filter(|aspect_address| {
+                        let held = maybe_aspect_map
+                            .map(|aspect_map| aspect_map.contains_key(*aspect_address))
+                            .unwrap_or(false);
+                        held == is_held
+                    })
+                    .cloned()
+                    .collect();
+                if aspect_hash_list.is_empty() {
+                    return None;
+                }
+                Some(AspectHashList {
+                    entry_address: hashes.entry_address.clone(),
+                    aspect_hash_list,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib3h_protocol::data_types::EntryAspectData;
+
+    fn entry(entry_address: &str, aspect_list: &[(&str, u64)]) -> EntryData {
+        EntryData {
+            entry_address: entry_address.into(),
+            aspect_list: aspect_list
+                .iter()
+                .map(|(aspect_address, publish_ts)| EntryAspectData {
+                    aspect_address: (*aspect_address).into(),
+                    type_hint: "gossip_test".to_string(),
+                    aspect: vec![],
+                    publish_ts: *publish_ts,
+                })
+                .collect(),
+        }
+    }
+
+    fn sent(output: &GossipOutput) -> GspMessage {
+        match output {
+            GossipOutput::Send(_, msg) => msg.clone(),
+            _ => panic!("expected a Send, got {:?}", output),
+        }
+    }
+
+    #[test]
+    fn test_arc_contains() {
+        let arc = AspectConstraintArc {
+            arc_start: 10,
+            arc_end: 20,
+        };
+        assert!(arc.contains(10));
+        assert!(!arc.contains(20));
+        let wrapping = AspectConstraintArc {
+            arc_start: 20,
+            arc_end: 10,
+        };
+        assert!(wrapping.contains(Location::max_value()));
+        assert!(wrapping.contains(0));
+        assert!(!wrapping.contains(15));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let mut engine = GossipEngine::new();
+        engine.index_entry(&entry("entry_1", &[("aspect_1", 100), ("aspect_2", 200)]));
+        engine.index_entry(&entry("entry_1", &[("aspect_2", 200)]));
+        assert_eq!(engine.aspect_hash_list(&Default::default()).len(), 1);
+        // Time
+        let hash_list = engine.aspect_hash_list(&GspConstraints::since(150));
+        assert_eq!(
+            hash_list[0].aspect_hash_list,
+            vec![Address::from("aspect_2")]
+        );
+        assert!(engine
+            .aspect_hash_list(&GspConstraints::since(300))
+            .is_empty());
+        // Count: holding aspect_2 again did not count as a new store
+        let constraints = GspConstraints {
+            aspect_constraint_count: Some(AspectConstraintCount { gte_local_count: 2 }),
+            ..Default::default()
+        };
+        assert!(engine.aspect_hash_list(&constraints).is_empty());
+        let constraints = GspConstraints {
+            aspect_constraint_count: Some(AspectConstraintCount { gte_local_count: 1 }),
+            ..Default::default()
+        };
+        let hash_list = engine.aspect_hash_list(&constraints);
+        assert_eq!(hash_list[0].aspect_hash_list.len(), 2);
+        // Arc
+        let location = entry_location(&"entry_1".into());
+        let constraints = GspConstraints {
+            aspect_constraint_arc: Some(AspectConstraintArc {
+                arc_start: location.wrapping_add(1),
+                arc_end: location,
+            }),
+            ..Default::default()
+        };
+        assert!(engine.aspect_hash_list(&constraints).is_empty());
+    }
+
+    #[test]
+    fn test_round() {
+        let mut engine_a = GossipEngine::new();
+        let mut engine_b = GossipEngine::new();
+        engine_a.index_entry(&entry("entry_1", &[("aspect_1", 100), ("aspect_2", 200)]));
+        engine_b.index_entry(&entry("entry_1", &[("aspect_1", 100)]));
+        engine_b.index_entry(&entry("entry_2", &[("aspect_3", 300)]));
+        // A opens an incremental round
+        let request = engine_a.open_round("b", GspConstraints::since(150), 42);
+        let output_list = engine_b.handle_message("a", request);
+        assert_eq!(output_list.len(), 2);
+        // B only lacks aspect_2
+        assert_eq!(
+            output_list[1],
+            GossipOutput::Send(
+                "a".to_string(),
+                GspMessage::AspectDataRequest(vec![AspectHashList {
+                    entry_address: "entry_1".into(),
+                    aspect_hash_list: vec!["aspect_2".into()],
+                }]),
+            )
+        );
+        // A lacks aspect_3
+        let output_list = engine_a.handle_message("b", sent(&output_list[0]));
+        assert_eq!(engine_a.last_round_ms("b"), Some(42));
+        let output_list = engine_b.handle_message("a", sent(&output_list[0]));
+        let hash_list = match &output_list[0] {
+            GossipOutput::ProvideAspectData(_, hash_list) => hash_list.clone(),
+            output => panic!("expected a ProvideAspectData, got {:?}", output),
+        };
+        assert_eq!(hash_list[0].entry_address, "entry_2".into());
+        // B answers with the data, A only holds what it lacks
+        let data = hash_list[0].select(&entry("entry_2", &[("aspect_3", 300), ("aspect_4", 1)]));
+        let output_list = engine_a.handle_message("b", GspMessage::AspectDataResponse(vec![data]));
+        assert_eq!(
+            output_list,
+            vec![GossipOutput::HoldAspectData(
+                "b".to_string(),
+                entry("entry_2", &[("aspect_3", 300)])
+            )]
+        );
+        // Unsolicited response is ignored
+        let output_list = engine_b.handle_message(
+            "a",
+            GspMessage::ArcResponse(MsgGspArc {
+                constraints: Default::default(),
+                aspect_hash_list: vec![],
+            }),
+        );
+        assert!(output_list.is_empty());
+    }
+}
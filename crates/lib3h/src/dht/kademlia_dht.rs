@@ -0,0 +1,974 @@
+use crate::{
+    dht::{
+        dht_protocol::*,
+        dht_trait::{Dht, DhtConfig},
+        gossip::{AspectHashList, GossipEngine, GossipOutput, GspMessage},
+        location::{entry_location, peer_location, Location},
+        storage::{DhtStorage, StoredDhtState, StoredEntry},
+        PeerAddress, PeerAddressRef,
+    },
+    error::{ErrorKind, Lib3hError, Lib3hResult},
+    time,
+};
+use lib3h_protocol::{data_types::EntryData, Address, DidWork};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rmp_serde::{Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_K: usize = 20;
+pub const DEFAULT_ALPHA: usize = 3;
+pub const DEFAULT_LOOKUP_TIMEOUT_MS: u64 = 5000;
+
+/// One k-bucket per bit of the location space
+const BUCKET_COUNT: usize = 32;
+
+/// Prefix of msg_ids of fetch requests made to answer a FindValue
+const FIND_FETCH_PREFIX: &str = "__find::";
+
+/// Prefix of msg_ids of fetch requests made to provide aspects during a gossip round
+const GSP_FETCH_PREFIX: &str = "__gsp::";
+
+/// KademliaDht parameters, read from `DhtConfig::custom` as JSON.
+/// Empty `custom` means default parameters.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KademliaConfig {
+    /// Bucket size, and number of closest peers an entry is stored at
+    #[serde(default = "default_k")]
+    pub k: usize,
+    /// Number of lookup queries in flight at once
+    #[serde(default = "default_alpha")]
+    pub alpha: usize,
+    /// Time after which a lookup gives up
+    #[serde(default = "default_lookup_timeout")]
+    pub lookup_timeout_ms: u64,
+}
+
+fn default_k() -> usize {
+    DEFAULT_K
+}
+
+fn default_alpha() -> usize {
+    DEFAULT_ALPHA
+}
+
+fn default_lookup_timeout() -> u64 {
+    DEFAULT_LOOKUP_TIMEOUT_MS
+}
+
+impl Default for KademliaConfig {
+    fn default() -> Self {
+        KademliaConfig {
+            k: DEFAULT_K,
+            alpha: DEFAULT_ALPHA,
+            lookup_timeout_ms: DEFAULT_LOOKUP_TIMEOUT_MS,
+        }
+    }
+}
+
+impl KademliaConfig {
+    pub fn from_custom(custom: &[u8]) -> Lib3hResult<Self> {
+        if custom.is_empty() {
+            return Ok(KademliaConfig::default());
+        }
+        let config: KademliaConfig = serde_json::from_slice(custom)?;
+        if config.k == 0 || config.alpha == 0 {
+            return Err(Lib3hError::new(ErrorKind::Other(format!(
+                "Invalid KademliaDht config, k and alpha must be > 0: {:?}",
+                config
+            ))));
+        }
+        Ok(config)
+    }
+}
+
+/// Enum holding all types of gossip messages used by KademliaDht
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+enum KadGossip {
+    Peer(PeerData),
+    /// Peers closest to the receiver, sent when we first hear about it
+    PeerList(Vec<PeerData>),
+    /// Receiver is among the k closest peers to this entry and should hold it
+    Store(EntryData),
+    /// Lookup query: answer with `Value` if held, otherwise with closer `Nodes`
+    FindValue {
+        request_id: String,
+        entry_address: Address,
+        from_peer: PeerData,
+    },
+    Value {
+        request_id: String,
+        entry: EntryData,
+    },
+    Nodes {
+        request_id: String,
+        peer_list: Vec<PeerData>,
+    },
+    /// Message of a gossip round, see `GossipEngine`
+    Gsp(GspMessage),
+}
+
+/// Index of the k-bucket for a XOR distance: position of its highest set bit
+fn bucket_index(distance: Location) -> usize {
+    if distance == 0 {
+        return 0;
+    }
+    BUCKET_COUNT - 1 - distance.leading_zeros() as usize
+}
+
+/// State of an iterative lookup for an entry
+struct Lookup {
+    entry_address: Address,
+    /// k closest peers heard of so far, closest first
+    candidate_list: Vec<PeerAddress>,
+    queried_set: HashSet<PeerAddress>,
+    in_flight_set: HashSet<PeerAddress>,
+    deadline: u64,
+}
+
+/// Kademlia-style DHT implementation
+///  - Peers are routed through k-buckets by XOR distance of their locations.
+///    Peers that do not fit in a full bucket are kept as replacements.
+///  - Broadcast entries are stored at the k closest peers.
+///  - FetchEntry on an entry we do not hold runs an iterative lookup.
+///  - Gossip rounds opened with `DhtCommand::OpenGossipRound` sync held aspects with a peer.
+///  - Peer and entry-address tables are persisted if `DhtConfig::storage_path` is set
+pub struct KademliaDht {
+    /// FIFO of DhtCommands send to us
+    inbox: VecDeque<DhtCommand>,
+    /// Storage of EntryData with empty aspect content
+    entry_list: HashMap<Address, HashSet<Address>>,
+    /// Every known peer, including the ones that do not fit in a bucket
+    peer_map: HashMap<PeerAddress, PeerData>,
+    /// Track if peer timed out
+    timed_out_map: HashMap<PeerAddress, bool>,
+    /// k-buckets of live peers, least recently seen first
+    bucket_list: Vec<Vec<PeerAddress>>,
+    /// PeerData of this peer
+    this_peer: PeerData,
+    this_location: Location,
+    /// Keep track of fetch requests sent to Core
+    pending_fetch_request_list: HashSet<String>,
+    /// Fetch requests made to answer a FindValue: msg_id -> (requester, request_id)
+    find_fetch_map: HashMap<String, (PeerAddress, String)>,
+    /// FindValue queries from peers not held yet: peer -> (request_id, entry_address)
+    parked_find_map: HashMap<PeerAddress, Vec<(String, Address)>>,
+    /// Lookups in progress, by msg_id of the FetchEntry that started them
+    lookup_map: HashMap<String, Lookup>,
+    /// Index of held aspects for gossip rounds
+    gossip_engine: GossipEngine,
+    /// Fetch requests made to answer an AspectDataRequest: msg_id -> (requester, aspects)
+    gsp_fetch_map: HashMap<String, (PeerAddress, AspectHashList)>,
+    /// Keep track of last time this peer gossiped self to others
+    last_gossip_of_self: u64,
+    /// Where tables are persisted, if configured
+    storage: Option<DhtStorage>,
+    /// Tables changed since last save
+    is_storage_dirty: bool,
+    last_save: u64,
+    /// Store Dht config used by this peer
+    config: DhtConfig,
+    kad_config: KademliaConfig,
+}
+
+/// Constructors
+impl KademliaDht {
+    pub fn new_with_config(config: &DhtConfig) -> Lib3hResult<Self> {
+        let kad_config = KademliaConfig::from_custom(&config.custom)?;
+        let timestamp = time::since_epoch_ms();
+        let mut this = KademliaDht {
+            inbox: VecDeque::new(),
+            entry_list: HashMap::new(),
+            peer_map: HashMap::new(),
+            timed_out_map: HashMap::new(),
+            bucket_list: vec![Vec::new(); BUCKET_COUNT],
+            this_peer: PeerData {
+                peer_address: config.this_peer_address.to_owned(),
+                peer_uri: config.this_peer_uri.clone(),
+                timestamp,
+            },
+            this_location: peer_location(&config.this_peer_address),
+            pending_fetch_request_list: HashSet::new(),
+            find_fetch_map: HashMap::new(),
+            parked_find_map: HashMap::new(),
+            lookup_map: HashMap::new(),
+            gossip_engine: GossipEngine::new(),
+            gsp_fetch_map: HashMap::new(),
+            last_gossip_of_self: timestamp,
+            storage: None,
+            is_storage_dirty: false,
+            last_save: timestamp,
+            config: config.clone(),
+            kad_config,
+        };
+        if let Some(storage_path) = &config.storage_path {
+            let storage = DhtStorage::new(storage_path);
+            if let Some(state) = storage.load()? {
+                this.restore(state);
+            }
+            this.storage = Some(storage);
+        }
+        Ok(this)
+    }
+}
+
+/// Impl Dht interface
+impl Dht for KademliaDht {
+    // -- Peer info -- //
+
+    fn get_peer_list(&self) -> Vec<PeerData> {
+        self.peer_map.values().cloned().collect()
+    }
+
+    fn get_peer(&self, peer_address: &PeerAddressRef) -> Option<PeerData> {
+        self.peer_map.get(peer_address).cloned()
+    }
+
+    fn this_peer(&self) -> &PeerData {
+        &self.this_peer
+    }
+
+    // -- Entry -- //
+
+    fn get_entry_address_list(&self) -> Vec<&Address> {
+        self.entry_list.keys().collect()
+    }
+
+    fn get_aspects_of(&self, entry_address: &Address) -> Option<Vec<Address>> {
+        self.entry_list
+            .get(entry_address)
+            .map(|aspect_set| aspect_set.iter().cloned().collect())
+    }
+
+    // -- Processing -- //
+
+    fn post(&mut self, cmd: DhtCommand) -> Lib3hResult<()> {
+        self.inbox.push_back(cmd);
+        Ok(())
+    }
+
+    fn process(&mut self) -> Lib3hResult<(DidWork, Vec<DhtEvent>)> {
+        let now = time::since_epoch_ms();
+        let mut outbox = Vec::new();
+        let mut did_work = false;
+        // Process inbox
+        while let Some(cmd) = self.inbox.pop_front() {
+            let res = self.serve_DhtCommand(&cmd);
+            if let Ok(mut output) = res {
+                did_work = true;
+                outbox.append(&mut output);
+            } else {
+                error!("serve_DhtCommand() failed: {:?}", res);
+            }
+        }
+        // Check if others timed-out, and replace them in their bucket
+        let timed_out_list: Vec<PeerAddress> = self
+            .peer_map
+            .iter()
+            .filter(|(peer_address, peer)| {
+                *peer_address != &self.this_peer.peer_address
+                    && self.timed_out_map.get(*peer_address) != Some(&true)
+                    && now.saturating_sub(peer.timestamp) > self.config.timeout_threshold
+            })
+            .map(|(peer_address, _)| peer_address.clone())
+            .collect();
+        for peer_address in timed_out_list {
+            debug!("@KademliaDht@ peer {} timed-out", peer_address);
+            self.timed_out_map.insert(peer_address.clone(), true);
+            self.remove_from_bucket(&peer_address);
+            self.gossip_engine.close_round(&peer_address);
+            outbox.push(DhtEvent::PeerTimedOut(peer_address));
+            did_work = true;
+        }
+        // Check if must gossip self
+        if now - self.last_gossip_of_self > self.config.gossip_interval {
+            self.last_gossip_of_self = now;
+            self.this_peer.timestamp = now;
+            let peer_address_list = self.routing_peer_list();
+            if !peer_address_list.is_empty() {
+                let gossip = KadGossip::Peer(self.this_peer.clone());
+                outbox.push(DhtEvent::GossipTo(
+                    self.gossip_to(peer_address_list, &gossip),
+                ));
+                did_work = true;
+            }
+        }
+        // Move lookups forward
+        let mut lookup_event_list = self.advance_lookups(now);
+        if !lookup_event_list.is_empty() {
+            did_work = true;
+            outbox.append(&mut lookup_event_list);
+        }
+        // Persist changes, at most once per gossip interval
+        if did_work {
+            self.is_storage_dirty = true;
+        }
+        if self.is_storage_dirty && now - self.last_save > self.config.gossip_interval {
+            self.last_save = now;
+            self.save();
+        }
+        Ok((did_work, outbox))
+    }
+
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if !self.inbox.is_empty() {
+            return Some(time::since_epoch_ms());
+        }
+        // Checks in process() use a strict comparison, hence the +1
+        let mut deadline = self.last_gossip_of_self + self.config.gossip_interval + 1;
+        for (peer_address, peer) in self.peer_map.iter() {
+            if peer_address == &self.this_peer.peer_address
+                || self.timed_out_map.get(peer_address) == Some(&true)
+            {
+                continue;
+            }
+            deadline = deadline.min(peer.timestamp + self.config.timeout_threshold + 1);
+        }
+        for lookup in self.lookup_map.values() {
+            deadline = deadline.min(lookup.deadline + 1);
+        }
+        Some(deadline)
+    }
+
+    fn dht_snapshot(&self) -> DhtSnapshot {
+        let mut snapshot = DhtSnapshot::from_dht(self);
+        snapshot.pending_fetch_request_list =
+            self.pending_fetch_request_list.iter().cloned().collect();
+        snapshot.pending_fetch_request_list.sort();
+        snapshot.timed_out_peer_list = self
+            .timed_out_map
+            .iter()
+            .filter(|(_, has_timed_out)| **has_timed_out)
+            .map(|(peer_address, _)| peer_address.clone())
+            .collect();
+        snapshot.timed_out_peer_list.sort();
+        snapshot.inbox_depth = self.inbox.len();
+        snapshot
+    }
+}
+
+/// Internals
+impl KademliaDht {
+    /// Load peer and entry-address tables saved by a previous run
+    fn restore(&mut self, state: StoredDhtState) {
+        if state.this_peer_address != self.this_peer.peer_address {
+            warn!(
+                "@KademliaDht@ Not restoring state saved by {}",
+                state.this_peer_address
+            );
+            return;
+        }
+        for peer in state.peer_list {
+            self.peer_map
+                .insert(peer.peer_address.clone(), peer.clone());
+            if state.timed_out_peer_list.contains(&peer.peer_address) {
+                self.timed_out_map.insert(peer.peer_address, true);
+            } else {
+                self.timed_out_map.insert(peer.peer_address.clone(), false);
+                self.touch_bucket(&peer.peer_address);
+            }
+        }
+        // Restored aspects count as published now, so gossip rounds still offer them
+        let now = time::since_epoch_ms();
+        for stored_entry in state.entry_list {
+            self.add_entry_aspects(&stored_entry.to_entry_data(now));
+        }
+        debug!(
+            "@KademliaDht@ Restored {} peers and {} entries",
+            self.peer_map.len(),
+            self.entry_list.len()
+        );
+    }
+
+    /// Save peer and entry-address tables, if storage is configured
+    fn save(&mut self) {
+        let storage = match &self.storage {
+            None => return,
+            Some(storage) => storage,
+        };
+        let mut state = StoredDhtState::new(&self.this_peer.peer_address);
+        state.peer_list = self
+            .peer_map
+            .values()
+            .filter(|peer| peer.peer_address != self.this_peer.peer_address)
+            .cloned()
+            .collect();
+        state.timed_out_peer_list = self
+            .timed_out_map
+            .iter()
+            .filter(|(_, has_timed_out)| **has_timed_out)
+            .map(|(peer_address, _)| peer_address.clone())
+            .collect();
+        state.entry_list = self
+            .entry_list
+            .iter()
+            .map(|(entry_address, aspect_set)| StoredEntry {
+                entry_address: entry_address.clone(),
+                aspect_address_list: aspect_set.iter().cloned().collect(),
+            })
+            .collect();
+        match storage.save(&state) {
+            Ok(()) => self.is_storage_dirty = false,
+            Err(e) => error!(
+                "@KademliaDht@ Failed saving state to {}: {:?}",
+                storage.path().display(),
+                e
+            ),
+        }
+    }
+
+    fn distance_to(&self, peer_address: &PeerAddressRef) -> Location {
+        self.this_location ^ peer_location(peer_address)
+    }
+
+    /// Peers in the k-buckets
+    fn routing_peer_list(&self) -> Vec<PeerAddress> {
+        self.bucket_list.iter().flatten().cloned().collect()
+    }
+
+    /// Up to `count` peers of the k-buckets closest to a location, closest first
+    fn closest_peer_list(&self, location: Location, count: usize) -> Vec<PeerAddress> {
+        let mut peer_list = self.routing_peer_list();
+        peer_list.sort_by_key(|peer_address| location ^ peer_location(peer_address));
+        peer_list.truncate(count);
+        peer_list
+    }
+
+    /// Put a peer at the tail of its bucket, if there is room for it.
+    /// Otherwise it stays known as a replacement.
+    fn touch_bucket(&mut self, peer_address: &PeerAddressRef) {
+        if peer_address == self.this_peer.peer_address {
+            return;
+        }
+        let k = self.kad_config.k;
+        let index = bucket_index(self.distance_to(peer_address));
+        let bucket = &mut self.bucket_list[index];
+        if let Some(index) = bucket.iter().position(|p| p == peer_address) {
+            let peer_address = bucket.remove(index);
+            bucket.push(peer_address);
+        } else if bucket.len() < k {
+            bucket.push(peer_address.to_owned());
+        }
+    }
+
+    /// Remove a peer from its bucket, and promote the most recently seen replacement
+    fn remove_from_bucket(&mut self, peer_address: &PeerAddressRef) {
+        let index = bucket_index(self.distance_to(peer_address));
+        self.bucket_list[index].retain(|p| p != peer_address);
+        let maybe_replacement = self
+            .peer_map
+            .values()
+            .filter(|peer| {
+                peer.peer_address != self.this_peer.peer_address
+                    && self.timed_out_map.get(&peer.peer_address) != Some(&true)
+                    && bucket_index(self.distance_to(&peer.peer_address)) == index
+                    && !self.bucket_list[index].contains(&peer.peer_address)
+            })
+            .max_by_key(|peer| peer.timestamp)
+            .map(|peer| peer.peer_address.clone());
+        if let Some(replacement) = maybe_replacement {
+            self.bucket_list[index].push(replacement);
+        }
+    }
+
+    /// Return true if new peer or updated peer
+    fn add_peer(&mut self, peer_info: &PeerData) -> bool {
+        let now = time::since_epoch_ms();
+        match self.peer_map.get_mut(&peer_info.peer_address) {
+            None => {
+                self.peer_map
+                    .insert(peer_info.peer_address.clone(), peer_info.clone());
+                self.timed_out_map
+                    .insert(peer_info.peer_address.clone(), false);
+            }
+            Some(peer) => {
+                if peer_info.timestamp <= peer.timestamp {
+                    return false;
+                }
+                peer.timestamp = peer_info.timestamp;
+                if now.saturating_sub(peer.timestamp) >= self.config.timeout_threshold {
+                    return true;
+                }
+                self.timed_out_map
+                    .insert(peer_info.peer_address.clone(), false);
+            }
+        }
+        self.touch_bucket(&peer_info.peer_address);
+        true
+    }
+
+    /// Add aspect addresses for an entry in our local storage.
+    /// Return the aspects of the entry that were not held.
+    fn add_entry_aspects(&mut self, entry: &EntryData) -> EntryData {
+        let aspect_set = self
+            .entry_list
+            .entry(entry.entry_address.clone())
+            .or_insert_with(HashSet::new);
+        let new_entry = EntryData {
+            entry_address: entry.entry_address.clone(),
+            aspect_list: entry
+                .aspect_list
+                .iter()
+                .filter(|aspect| aspect_set.insert(aspect.aspect_address.clone()))
+                .cloned()
+                .collect(),
+        };
+        self.gossip_engine.index_entry(&new_entry);
+        new_entry
+    }
+
+    /// Aspects of the entry we do not hold
+    fn new_aspects(&self, entry: &EntryData) -> EntryData {
+        let maybe_aspect_set = self.entry_list.get(&entry.entry_address);
+        EntryData {
+            entry_address: entry.entry_address.clone(),
+            aspect_list: entry
+                .aspect_list
+                .iter()
+                .filter(|aspect| match maybe_aspect_set {
+                    None => true,
+                    Some(aspect_set) => !aspect_set.contains(&aspect.aspect_address),
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Convert what the gossip engine wants done into events for owner
+    fn handle_gossip_output(&mut self, output_list: Vec<GossipOutput>) -> Vec<DhtEvent> {
+        let mut event_list = Vec::new();
+        for output in output_list {
+            match output {
+                GossipOutput::Send(peer_address, msg) => {
+                    let gossip_data = self.gossip_to(vec![peer_address], &KadGossip::Gsp(msg));
+                    event_list.push(DhtEvent::GossipTo(gossip_data));
+                }
+                GossipOutput::ProvideAspectData(peer_address, hash_list) => {
+                    for hashes in hash_list {
+                        let msg_id = format!(
+                            "{}{}::{}",
+                            GSP_FETCH_PREFIX, peer_address, hashes.entry_address
+                        );
+                        let entry_address = hashes.entry_address.clone();
+                        self.pending_fetch_request_list.insert(msg_id.clone());
+                        self.gsp_fetch_map
+                            .insert(msg_id.clone(), (peer_address.clone(), hashes));
+                        event_list.push(DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                            msg_id,
+                            entry_address,
+                        }));
+                    }
+                }
+                GossipOutput::HoldAspectData(_, entry) => {
+                    let new_entry = self.new_aspects(&entry);
+                    if !new_entry.aspect_list.is_empty() {
+                        event_list.push(DhtEvent::HoldEntryRequested(
+                            self.this_peer.peer_address.clone(),
+                            new_entry,
+                        ));
+                    }
+                }
+            }
+        }
+        event_list
+    }
+
+    fn gossip_to(&self, peer_address_list: Vec<PeerAddress>, gossip: &KadGossip) -> GossipToData {
+        let mut buf = Vec::new();
+        gossip.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        GossipToData {
+            peer_address_list,
+            bundle: buf.into(),
+        }
+    }
+
+    /// Send lookup queries, and end the lookups that found nothing
+    fn advance_lookups(&mut self, now: u64) -> Vec<DhtEvent> {
+        let alpha = self.kad_config.alpha;
+        let mut query_list = Vec::new();
+        let mut failed_list = Vec::new();
+        let peer_map = &self.peer_map;
+        for (msg_id, lookup) in self.lookup_map.iter_mut() {
+            if now > lookup.deadline {
+                failed_list.push(msg_id.clone());
+                continue;
+            }
+            // Only query peers we know: gossip can only go to held peers
+            let slot_count = alpha.saturating_sub(lookup.in_flight_set.len());
+            let next_list: Vec<PeerAddress> = lookup
+                .candidate_list
+                .iter()
+                .filter(|peer_address| {
+                    !lookup.queried_set.contains(*peer_address)
+                        && peer_map.contains_key(*peer_address)
+                })
+                .take(slot_count)
+                .cloned()
+                .collect();
+            for peer_address in next_list {
+                lookup.queried_set.insert(peer_address.clone());
+                lookup.in_flight_set.insert(peer_address.clone());
+                query_list.push((peer_address, msg_id.clone(), lookup.entry_address.clone()));
+            }
+            // Every candidate answered: the entry could not be found
+            let is_exhausted = lookup
+                .candidate_list
+                .iter()
+                .all(|peer_address| lookup.queried_set.contains(peer_address));
+            if lookup.in_flight_set.is_empty() && is_exhausted {
+                failed_list.push(msg_id.clone());
+            }
+        }
+        let mut event_list = Vec::new();
+        for (peer_address, request_id, entry_address) in query_list {
+            let gossip = KadGossip::FindValue {
+                request_id,
+                entry_address,
+                from_peer: self.this_peer.clone(),
+            };
+            event_list.push(DhtEvent::GossipTo(
+                self.gossip_to(vec![peer_address], &gossip),
+            ));
+        }
+        // Respond with an entry without aspects
+        for msg_id in failed_list {
+            let lookup = self.lookup_map.remove(&msg_id).expect("lookup exists");
+            debug!(
+                "@KademliaDht@ lookup {} for {} found nothing",
+                msg_id, lookup.entry_address
+            );
+            event_list.push(DhtEvent::FetchEntryResponse(FetchDhtEntryResponseData {
+                msg_id,
+                entry: EntryData {
+                    entry_address: lookup.entry_address,
+                    aspect_list: vec![],
+                },
+            }));
+        }
+        event_list
+    }
+
+    /// Answer a FindValue query with the entry if held, otherwise with closer peers
+    fn answer_find_value(
+        &mut self,
+        from_peer_address: &PeerAddressRef,
+        request_id: String,
+        entry_address: Address,
+    ) -> DhtEvent {
+        // Held: get the entry from owner before answering
+        if self.entry_list.contains_key(&entry_address) {
+            let msg_id = format!("{}{}::{}", FIND_FETCH_PREFIX, from_peer_address, request_id);
+            self.pending_fetch_request_list.insert(msg_id.clone());
+            self.find_fetch_map
+                .insert(msg_id.clone(), (from_peer_address.to_owned(), request_id));
+            return DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                msg_id,
+                entry_address,
+            });
+        }
+        let k = self.kad_config.k;
+        let peer_list: Vec<PeerData> = self
+            .closest_peer_list(entry_location(&entry_address), k + 1)
+            .into_iter()
+            .filter(|peer_address| peer_address != from_peer_address)
+            .take(k)
+            .filter_map(|peer_address| self.peer_map.get(&peer_address).cloned())
+            .collect();
+        let gossip = KadGossip::Nodes {
+            request_id,
+            peer_list,
+        };
+        DhtEvent::GossipTo(self.gossip_to(vec![from_peer_address.to_owned()], &gossip))
+    }
+
+    #[allow(non_snake_case)]
+    fn serve_DhtCommand(&mut self, cmd: &DhtCommand) -> Lib3hResult<Vec<DhtEvent>> {
+        debug!("@KademliaDht@ serving cmd: {:?}", cmd);
+        // Note: use same order as the enum
+        match cmd {
+            // Received gossip from remote node. Bundle must be a serialized KadGossip
+            DhtCommand::HandleGossip(msg) => {
+                let mut de = Deserializer::new(&msg.bundle[..]);
+                let gossip: KadGossip = Deserialize::deserialize(&mut de)
+                    .map_err(|e| Lib3hError::new(ErrorKind::RmpSerdeDecodeError(e)))?;
+                self.handle_gossip(&msg.from_peer_address, gossip)
+            }
+            // Ask owner if we hold it, otherwise look it up
+            DhtCommand::FetchEntry(fetch_entry) => {
+                if self.entry_list.contains_key(&fetch_entry.entry_address) {
+                    self.pending_fetch_request_list
+                        .insert(fetch_entry.msg_id.clone());
+                    return Ok(vec![DhtEvent::EntryDataRequested(fetch_entry.clone())]);
+                }
+                let k = self.kad_config.k;
+                let candidate_list =
+                    self.closest_peer_list(entry_location(&fetch_entry.entry_address), k);
+                self.lookup_map.insert(
+                    fetch_entry.msg_id.clone(),
+                    Lookup {
+                        entry_address: fetch_entry.entry_address.clone(),
+                        candidate_list,
+                        queried_set: HashSet::new(),
+                        in_flight_set: HashSet::new(),
+                        deadline: time::since_epoch_ms() + self.kad_config.lookup_timeout_ms,
+                    },
+                );
+                // Queries are sent at the end of process()
+                Ok(vec![])
+            }
+            // Owner is asking us to hold a peer info
+            DhtCommand::HoldPeer(new_peer_data) => {
+                if !self.add_peer(new_peer_data) {
+                    return Ok(vec![]);
+                }
+                if new_peer_data.peer_address == self.this_peer.peer_address {
+                    return Ok(vec![]);
+                }
+                let to = vec![new_peer_data.peer_address.clone()];
+                let mut event_list = Vec::new();
+                // Answer the queries it sent before we could reach it
+                let parked_list = self
+                    .parked_find_map
+                    .remove(&new_peer_data.peer_address)
+                    .unwrap_or_default();
+                for (request_id, entry_address) in parked_list {
+                    event_list.push(self.answer_find_value(
+                        &new_peer_data.peer_address,
+                        request_id,
+                        entry_address,
+                    ));
+                }
+                // Gossip back our own PeerData
+                let gossip = KadGossip::Peer(self.this_peer.clone());
+                event_list.push(DhtEvent::GossipTo(self.gossip_to(to.clone(), &gossip)));
+                // Tell it about the peers closest to it
+                let k = self.kad_config.k;
+                let peer_list: Vec<PeerData> = self
+                    .closest_peer_list(peer_location(&new_peer_data.peer_address), k + 1)
+                    .into_iter()
+                    .filter(|peer_address| peer_address != &new_peer_data.peer_address)
+                    .take(k)
+                    .filter_map(|peer_address| self.peer_map.get(&peer_address).cloned())
+                    .collect();
+                if !peer_list.is_empty() {
+                    let gossip = KadGossip::PeerList(peer_list);
+                    event_list.push(DhtEvent::GossipTo(self.gossip_to(to, &gossip)));
+                }
+                Ok(event_list)
+            }
+            // Owner is holding some entry. Store its address for bookkeeping.
+            DhtCommand::HoldEntryAspectAddress(entry) => {
+                self.add_entry_aspects(entry);
+                Ok(vec![])
+            }
+            // Owner has some entry and wants it stored on the network:
+            // Bookkeep address and store it at the k closest peers.
+            DhtCommand::BroadcastEntry(entry) => {
+                if self.add_entry_aspects(entry).aspect_list.is_empty() {
+                    return Ok(vec![]);
+                }
+                let peer_address_list =
+                    self.closest_peer_list(entry_location(&entry.entry_address), self.kad_config.k);
+                if peer_address_list.is_empty() {
+                    return Ok(vec![]);
+                }
+                let gossip = KadGossip::Store(entry.clone());
+                Ok(vec![DhtEvent::GossipTo(
+                    self.gossip_to(peer_address_list, &gossip),
+                )])
+            }
+            DhtCommand::DropEntryAddress(entry_address) => {
+                self.entry_list.remove(entry_address);
+                self.gossip_engine.drop_entry(entry_address);
+                Ok(vec![])
+            }
+            // EntryDataResponse:
+            //   - From a FindValue: send the entry to the requester
+            //   - From a gossip round: send the requested aspects to the peer
+            //   - From a FetchEntry: forward response back to self
+            DhtCommand::EntryDataResponse(response) => {
+                if !self.pending_fetch_request_list.remove(&response.msg_id) {
+                    return Err(Lib3hError::new(ErrorKind::Other(String::from(
+                        "Received response for an unknown request",
+                    ))));
+                }
+                if let Some((peer_address, request_id)) =
+                    self.find_fetch_map.remove(&response.msg_id)
+                {
+                    let gossip = KadGossip::Value {
+                        request_id,
+                        entry: response.entry.clone(),
+                    };
+                    return Ok(vec![DhtEvent::GossipTo(
+                        self.gossip_to(vec![peer_address], &gossip),
+                    )]);
+                }
+                if let Some((peer_address, hashes)) = self.gsp_fetch_map.remove(&response.msg_id) {
+                    let entry = hashes.select(&response.entry);
+                    if entry.aspect_list.is_empty() {
+                        return Ok(vec![]);
+                    }
+                    let gossip = KadGossip::Gsp(GspMessage::AspectDataResponse(vec![entry]));
+                    return Ok(vec![DhtEvent::GossipTo(
+                        self.gossip_to(vec![peer_address], &gossip),
+                    )]);
+                }
+                Ok(vec![DhtEvent::FetchEntryResponse(response.clone())])
+            }
+            // Owner wants to sync with a peer: open a gossip round
+            DhtCommand::OpenGossipRound(peer_address, constraints) => {
+                if !self.peer_map.contains_key(peer_address) {
+                    return Err(Lib3hError::new(ErrorKind::Other(format!(
+                        "Cannot open gossip round with unknown peer {}",
+                        peer_address
+                    ))));
+                }
+                let gsp_msg = self.gossip_engine.open_round(
+                    peer_address,
+                    constraints.clone(),
+                    time::since_epoch_ms(),
+                );
+                let gossip = KadGossip::Gsp(gsp_msg);
+                Ok(vec![DhtEvent::GossipTo(
+                    self.gossip_to(vec![peer_address.clone()], &gossip),
+                )])
+            }
+        }
+    }
+
+    fn handle_gossip(
+        &mut self,
+        from_peer_address: &PeerAddressRef,
+        gossip: KadGossip,
+    ) -> Lib3hResult<Vec<DhtEvent>> {
+        match gossip {
+            KadGossip::Peer(gossiped_peer) => {
+                if !self.peer_map.contains_key(&gossiped_peer.peer_address) {
+                    return Ok(vec![DhtEvent::HoldPeerRequested(gossiped_peer)]);
+                }
+                self.add_peer(&gossiped_peer);
+                Ok(vec![])
+            }
+            KadGossip::PeerList(peer_list) => Ok(peer_list
+                .into_iter()
+                .filter(|peer| {
+                    peer.peer_address != self.this_peer.peer_address
+                        && !self.peer_map.contains_key(&peer.peer_address)
+                })
+                .map(DhtEvent::HoldPeerRequested)
+                .collect()),
+            KadGossip::Store(entry) => {
+                let new_entry = self.new_aspects(&entry);
+                if new_entry.aspect_list.is_empty() {
+                    return Ok(vec![]);
+                }
+                Ok(vec![DhtEvent::HoldEntryRequested(
+                    self.this_peer.peer_address.clone(),
+                    new_entry,
+                )])
+            }
+            KadGossip::FindValue {
+                request_id,
+                entry_address,
+                from_peer,
+            } => {
+                // Gossip can only go to held peers: wait for owner to hold it
+                if !self.peer_map.contains_key(from_peer_address) {
+                    self.parked_find_map
+                        .entry(from_peer_address.to_owned())
+                        .or_insert_with(Vec::new)
+                        .push((request_id, entry_address));
+                    return Ok(vec![DhtEvent::HoldPeerRequested(from_peer)]);
+                }
+                Ok(vec![self.answer_find_value(
+                    from_peer_address,
+                    request_id,
+                    entry_address,
+                )])
+            }
+            KadGossip::Value { request_id, entry } => {
+                if self.lookup_map.remove(&request_id).is_none() {
+                    return Ok(vec![]);
+                }
+                Ok(vec![DhtEvent::FetchEntryResponse(
+                    FetchDhtEntryResponseData {
+                        msg_id: request_id,
+                        entry,
+                    },
+                )])
+            }
+            KadGossip::Nodes {
+                request_id,
+                peer_list,
+            } => {
+                let k = self.kad_config.k;
+                let this_peer_address = self.this_peer.peer_address.clone();
+                let mut event_list = Vec::new();
+                let lookup = match self.lookup_map.get_mut(&request_id) {
+                    None => return Ok(vec![]),
+                    Some(lookup) => lookup,
+                };
+                lookup.in_flight_set.remove(from_peer_address);
+                for peer in peer_list {
+                    if peer.peer_address == this_peer_address
+                        || lookup.candidate_list.contains(&peer.peer_address)
+                    {
+                        continue;
+                    }
+                    lookup.candidate_list.push(peer.peer_address.clone());
+                    // Must hold a peer before we can query it
+                    if !self.peer_map.contains_key(&peer.peer_address) {
+                        event_list.push(DhtEvent::HoldPeerRequested(peer));
+                    }
+                }
+                let location = entry_location(&lookup.entry_address);
+                lookup
+                    .candidate_list
+                    .sort_by_key(|peer_address| location ^ peer_location(peer_address));
+                lookup.candidate_list.truncate(k);
+                Ok(event_list)
+            }
+            KadGossip::Gsp(gsp_msg) => {
+                let output_list = self
+                    .gossip_engine
+                    .handle_message(from_peer_address, gsp_msg);
+                Ok(self.handle_gossip_output(output_list))
+            }
+        }
+    }
+}
+
+impl Drop for KademliaDht {
+    fn drop(&mut self) {
+        if self.is_storage_dirty {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_index() {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(1), 0);
+        assert_eq!(bucket_index(2), 1);
+        assert_eq!(bucket_index(3), 1);
+        assert_eq!(bucket_index(Location::max_value()), BUCKET_COUNT - 1);
+    }
+
+    #[test]
+    fn test_config_from_custom() {
+        assert_eq!(
+            KademliaConfig::from_custom(&[]).unwrap(),
+            KademliaConfig::default()
+        );
+        let config = KademliaConfig::from_custom(br#"{"k": 4}"#).unwrap();
+        assert_eq!(config.k, 4);
+        assert_eq!(config.alpha, DEFAULT_ALPHA);
+        assert!(KademliaConfig::from_custom(br#"{"k": 0}"#).is_err());
+        assert!(KademliaConfig::from_custom(b"not json").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Peer liveness lifecycle.
+//!
+//! A peer is Alive while we hear of it, Suspect once silent for half the timeout
+//! threshold, and Dead past the threshold. A Dead peer is evicted from the DHT
+//! once the eviction grace period is over. Fresh PeerData brings a peer back to Alive.
+
+use crate::dht::dht_trait::DhtConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PeerState {
+    /// Heard of recently
+    Alive,
+    /// Silent for a while: still gossiped to, but not relied upon
+    Suspect,
+    /// Timed out: not gossiped to anymore, and its connection is closed
+    Dead,
+}
+
+/// Liveness thresholds of a DHT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Liveness {
+    pub timeout_threshold: u64,
+    pub eviction_grace_period: u64,
+}
+
+impl Liveness {
+    pub fn new(config: &DhtConfig) -> Self {
+        Liveness {
+            timeout_threshold: config.timeout_threshold,
+            eviction_grace_period: config.eviction_grace_period,
+        }
+    }
+
+    /// State of a peer last heard of at `timestamp`. None if it should be evicted.
+    pub fn state_of(&self, timestamp: u64, now: u64) -> Option<PeerState> {
+        let silence = now.saturating_sub(timestamp);
+        if silence > self.timeout_threshold + self.eviction_grace_period {
+            None
+        } else if silence > self.timeout_threshold {
+            Some(PeerState::Dead)
+        } else if silence > self.timeout_threshold / 2 {
+            Some(PeerState::Suspect)
+        } else {
+            Some(PeerState::Alive)
+        }
+    }
+
+    /// Time at which a peer last heard of at `timestamp` leaves `state`
+    pub fn next_transition_ms(&self, timestamp: u64, state: PeerState) -> u64 {
+        // state_of() uses strict comparisons, hence the +1
+        match state {
+            PeerState::Alive => timestamp + self.timeout_threshold / 2 + 1,
+            PeerState::Suspect => timestamp + self.timeout_threshold + 1,
+            PeerState::Dead => timestamp + self.timeout_threshold + self.eviction_grace_period + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_of() {
+        let liveness = Liveness {
+            timeout_threshold: 100,
+            eviction_grace_period: 1000,
+        };
+        assert_eq!(liveness.state_of(1000, 1000), Some(PeerState::Alive));
+        assert_eq!(liveness.state_of(1000, 1050), Some(PeerState::Alive));
+        assert_eq!(liveness.state_of(1000, 1051), Some(PeerState::Suspect));
+        assert_eq!(liveness.state_of(1000, 1101), Some(PeerState::Dead));
+        assert_eq!(liveness.state_of(1000, 2100), Some(PeerState::Dead));
+        assert_eq!(liveness.state_of(1000, 2101), None);
+        // Timestamps ahead of us count as fresh
+        assert_eq!(liveness.state_of(2000, 1000), Some(PeerState::Alive));
+        for state in &[PeerState::Alive, PeerState::Suspect, PeerState::Dead] {
+            let transition = liveness.next_transition_ms(1000, *state);
+            assert_eq!(liveness.state_of(1000, transition - 1), Some(*state));
+            assert_ne!(liveness.state_of(1000, transition), Some(*state));
+        }
+    }
+}
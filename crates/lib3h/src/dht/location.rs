@@ -0,0 +1,209 @@
+//! Locations in the u32 address space, and arcs of it.
+//!
+//! Entries and peers are placed on a ring of u32 locations, as in the wire schema's
+//! `storeArcRadius`, `queryArcRadius` and `AspectConstraintArc { arcStart, arcEnd }`.
+//! A location is the SHA-256 of an address folded to 32 bits. It is the hash of
+//! `CryptoSystem::hash_sha256`, computed here directly since DHTs are built from
+//! a `DhtConfig` alone, so every node agrees on it whatever its DHT.
+//! A peer is responsible for the entries whose location falls within its store arc,
+//! or, lacking arcs, for the entries it is among the closest peers to.
+
+use crate::dht::PeerAddress;
+use lib3h_protocol::Address;
+use ring::digest::{digest, SHA256};
+
+/// Location in the u32 address space
+pub type Location = u32;
+
+/// Location of some data: SHA-256 hash folded to 32 bits by XOR-ing its 4-byte words
+pub fn compute_location(data: &[u8]) -> Location {
+    digest(&SHA256, data)
+        .as_ref()
+        .chunks(4)
+        .fold(0, |location, word| {
+            let mut bytes = [0; 4];
+            bytes[..word.len()].copy_from_slice(word);
+            location ^ Location::from_be_bytes(bytes)
+        })
+}
+
+/// Location of an entry
+pub fn entry_location(entry_address: &Address) -> Location {
+    compute_location(entry_address.to_string().as_bytes())
+}
+
+/// Location of a peer
+pub fn peer_location(peer_address: &str) -> Location {
+    compute_location(peer_address.as_bytes())
+}
+
+/// Shortest distance between two locations, going either way around the ring
+pub fn ring_distance(a: Location, b: Location) -> Location {
+    std::cmp::min(a.wrapping_sub(b), b.wrapping_sub(a))
+}
+
+/// Range of locations going clockwise from `start` (inclusive) to `end` (exclusive).
+/// Wraps around the ring when `start > end`. Empty when they are equal,
+/// unless it is `LocationArc::full()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LocationArc {
+    pub start: Location,
+    pub end: Location,
+    /// Set for the arc covering the whole ring, as `start == end` means empty
+    #[serde(default)]
+    pub is_full: bool,
+}
+
+impl LocationArc {
+    pub fn new(start: Location, end: Location) -> Self {
+        LocationArc {
+            start,
+            end,
+            is_full: false,
+        }
+    }
+
+    pub fn empty() -> Self {
+        LocationArc::new(0, 0)
+    }
+
+    pub fn full() -> Self {
+        LocationArc {
+            start: 0,
+            end: 0,
+            is_full: true,
+        }
+    }
+
+    /// Arc of the locations within `radius` of `center`, both ends included
+    pub fn from_radius(center: Location, radius: Location) -> Self {
+        if radius > Location::max_value() / 2 {
+            return LocationArc::full();
+        }
+        LocationArc::new(
+            center.wrapping_sub(radius),
+            center.wrapping_add(radius).wrapping_add(1),
+        )
+    }
+
+    /// Number of locations in the arc
+    pub fn len(&self) -> u64 {
+        if self.is_full {
+            return u64::from(Location::max_value()) + 1;
+        }
+        u64::from(self.end.wrapping_sub(self.start))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if the arc goes through the end of the ring back to 0
+    pub fn is_wrapping(&self) -> bool {
+        !self.is_full && self.start > self.end && self.end != 0
+    }
+
+    pub fn contains(&self, location: Location) -> bool {
+        self.is_full || location.wrapping_sub(self.start) < self.end.wrapping_sub(self.start)
+    }
+
+    /// True if some location is in both arcs
+    pub fn overlaps(&self, other: &LocationArc) -> bool {
+        if self.is_empty() || other.is_empty() {
+            return false;
+        }
+        self.contains(other.start) || other.contains(self.start)
+    }
+}
+
+/// The `count` peers closest to a location, closest first.
+/// Ties are broken by peer address, so every node picks the same peers.
+pub fn closest_peer_list(
+    location: Location,
+    peer_location_list: &[(PeerAddress, Location)],
+    count: usize,
+) -> Vec<PeerAddress> {
+    let mut candidate_list: Vec<&(PeerAddress, Location)> = peer_location_list.iter().collect();
+    candidate_list.sort_by(|(a_address, a_location), (b_address, b_location)| {
+        ring_distance(location, *a_location)
+            .cmp(&ring_distance(location, *b_location))
+            .then_with(|| a_address.cmp(b_address))
+    });
+    candidate_list
+        .into_iter()
+        .take(count)
+        .map(|(peer_address, _)| peer_address.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib3h_crypto_api::{Buffer, CryptoSystem};
+    use lib3h_sodium::SodiumCryptoSystem;
+
+    #[test]
+    fn test_compute_location() {
+        let location_1 = entry_location(&"entry_1".into());
+        assert_eq!(location_1, entry_location(&"entry_1".into()));
+        assert_ne!(location_1, entry_location(&"entry_2".into()));
+        assert_eq!(peer_location("entry_1"), location_1);
+        // Same as folding the CryptoSystem's hash
+        let crypto = SodiumCryptoSystem::new();
+        let data: Box<dyn Buffer> = Box::new(b"entry_1".to_vec());
+        let mut hash: Box<dyn Buffer> = Box::new(vec![0; crypto.hash_sha256_bytes()]);
+        crypto.hash_sha256(&mut hash, &data).unwrap();
+        assert_eq!(&hash.read_lock()[..], digest(&SHA256, b"entry_1").as_ref());
+    }
+
+    #[test]
+    fn test_ring_distance() {
+        assert_eq!(ring_distance(10, 20), 10);
+        assert_eq!(ring_distance(20, 10), 10);
+        assert_eq!(ring_distance(Location::max_value(), 1), 2);
+    }
+
+    #[test]
+    fn test_arc() {
+        let arc = LocationArc::new(10, 20);
+        assert!(arc.contains(10) && arc.contains(19));
+        assert!(!arc.contains(20) && !arc.contains(9));
+        assert_eq!(arc.len(), 10);
+        assert!(!arc.is_wrapping());
+        // Wrap-around
+        let wrapping = LocationArc::new(Location::max_value() - 4, 6);
+        assert!(wrapping.is_wrapping());
+        assert!(wrapping.contains(Location::max_value()) && wrapping.contains(0));
+        assert!(wrapping.contains(5) && !wrapping.contains(6));
+        assert_eq!(wrapping.len(), 11);
+        assert_eq!(LocationArc::from_radius(0, 5), wrapping);
+        // Empty and full
+        assert!(LocationArc::empty().is_empty() && !LocationArc::empty().contains(0));
+        assert!(LocationArc::full().contains(12345));
+        assert_eq!(LocationArc::full().len(), 1 << 32);
+        assert_eq!(
+            LocationArc::from_radius(42, Location::max_value()),
+            LocationArc::full()
+        );
+        // Overlap
+        assert!(arc.overlaps(&LocationArc::new(19, 30)));
+        assert!(!arc.overlaps(&LocationArc::new(20, 30)));
+        assert!(wrapping.overlaps(&LocationArc::new(3, 4)));
+        assert!(LocationArc::new(3, 4).overlaps(&wrapping));
+        assert!(!wrapping.overlaps(&arc));
+        assert!(!LocationArc::empty().overlaps(&LocationArc::full()));
+    }
+
+    #[test]
+    fn test_responsible_peers() {
+        let peer_location_list = vec![
+            ("a".to_string(), 0),
+            ("b".to_string(), 100),
+            ("c".to_string(), Location::max_value() - 10),
+        ];
+        assert_eq!(
+            closest_peer_list(5, &peer_location_list, 2),
+            vec!["a".to_string(), "c".to_string()]
+        );
+    }
+}
@@ -1,32 +1,149 @@
 use crate::{
     dht::{
+        anti_entropy::{AspectDigest, BucketContent},
         dht_protocol::*,
-        dht_trait::{Dht, DhtConfig},
+        dht_trait::{Dht, DhtConfig, DEFAULT_ANTI_ENTROPY_INTERVAL_MS},
+        gossip::{AspectHashList, GossipEngine, GossipOutput, GspMessage},
+        liveness::{Liveness, PeerState},
+        quota::StorageQuota,
+        replication::HolderMap,
+        storage::{DhtStorage, StoredDhtState, StoredEntry},
         PeerAddress, PeerAddressRef,
     },
     error::{ErrorKind, Lib3hError, Lib3hResult},
     time,
 };
-use lib3h_protocol::{data_types::EntryData, Address, DidWork};
+use lib3h_protocol::{
+    data_types::{EntryAspectData, EntryData},
+    Address, DidWork,
+};
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-type HasTimedOut = bool;
+/// Version of the `DhtConfig::custom` schema understood by MirrorDht
+pub const MIRROR_CONFIG_VERSION: u32 = 1;
+
+/// MirrorDht tuning, read from `DhtConfig::custom` as JSON.
+/// Every field is optional: missing ones keep the values of `DhtConfig`, or no limit.
+/// Unknown fields are ignored, so the same `custom` can also configure other DHTs.
+/// Empty `custom` means no tuning.
+///
+/// Example: `{"version": 1, "gossip_fanout": 8, "max_bundle_size": 65536}`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct MirrorConfig {
+    /// Schema version, must be `MIRROR_CONFIG_VERSION`
+    #[serde(default = "default_mirror_config_version")]
+    pub version: u32,
+    /// Maximum number of peers an entry or our PeerData is gossiped to at once.
+    /// Others get it later through anti-entropy or next gossip of self.
+    pub gossip_fanout: Option<usize>,
+    /// Maximum size in bytes of a gossip bundle carrying entry data.
+    /// Entries are split by aspects to fit; entries with an aspect bigger than this are refused.
+    pub max_bundle_size: Option<usize>,
+    /// Overrides `DhtConfig::gossip_interval`: interval between gossips of our PeerData
+    pub peer_gossip_interval_ms: Option<u64>,
+    /// Interval between gossips of the entries held or broadcast since the last one.
+    /// Entries are gossiped as soon as they are held, if not set.
+    pub entry_gossip_interval_ms: Option<u64>,
+    /// Overrides `DhtConfig::timeout_threshold`
+    pub timeout_threshold_ms: Option<u64>,
+    /// Overrides `DhtConfig::eviction_grace_period`
+    pub eviction_grace_period_ms: Option<u64>,
+    /// How long anti-entropy fetches and repaired aspects are tracked, waiting for Core.
+    /// Defaults to `SYNC_EXPIRY_ROUND_COUNT` anti-entropy intervals.
+    pub sync_expiry_ms: Option<u64>,
+}
+
+fn default_mirror_config_version() -> u32 {
+    MIRROR_CONFIG_VERSION
+}
+
+impl MirrorConfig {
+    pub fn from_custom(custom: &[u8]) -> Lib3hResult<Self> {
+        if custom.is_empty() {
+            return Ok(MirrorConfig::default_version());
+        }
+        let config: MirrorConfig = serde_json::from_slice(custom)?;
+        if config.version != MIRROR_CONFIG_VERSION {
+            return Err(Lib3hError::new(ErrorKind::Other(format!(
+                "Unsupported MirrorDht config version {}, expected {}",
+                config.version, MIRROR_CONFIG_VERSION
+            ))));
+        }
+        if config.gossip_fanout == Some(0)
+            || config.max_bundle_size == Some(0)
+            || config.timeout_threshold_ms == Some(0)
+        {
+            return Err(Lib3hError::new(ErrorKind::Other(format!(
+                "Invalid MirrorDht config, gossip_fanout, max_bundle_size and timeout_threshold_ms must be > 0: {:?}",
+                config
+            ))));
+        }
+        Ok(config)
+    }
+
+    fn default_version() -> Self {
+        MirrorConfig {
+            version: MIRROR_CONFIG_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// DhtConfig with the overrides of this config applied
+    pub fn apply(&self, config: &DhtConfig) -> DhtConfig {
+        let mut config = config.clone();
+        if let Some(interval) = self.peer_gossip_interval_ms {
+            config.gossip_interval = interval;
+        }
+        if let Some(threshold) = self.timeout_threshold_ms {
+            config.timeout_threshold = threshold;
+        }
+        if let Some(period) = self.eviction_grace_period_ms {
+            config.eviction_grace_period = period;
+        }
+        config
+    }
+}
+
+/// Anti-entropy state is forgotten after this many anti-entropy intervals, by default
+pub const SYNC_EXPIRY_ROUND_COUNT: u64 = 3;
+
+/// Prefix of msg_ids of fetch requests made to repair a peer through anti-entropy
+const SYNC_FETCH_PREFIX: &str = "__sync::";
+/// Prefix of msg_ids of fetch requests made to answer an AspectDataRequest
+const GSP_FETCH_PREFIX: &str = "__gsp::";
+/// Prefix of msg_ids of fetch requests made to re-publish an entry to new holders
+const REPUBLISH_FETCH_PREFIX: &str = "__republish::";
 
 /// Enum holding all types of gossip messages used by MirrorDht
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 enum MirrorGossip {
     Entry(EntryData),
     Peer(PeerData),
+    /// Anti-entropy: summary of the aspects we hold
+    Digest(AspectDigest),
+    /// Anti-entropy: aspects we hold in the buckets that differ
+    BucketContent(BucketContent),
+    /// Anti-entropy: aspects the receiver is missing
+    SyncEntry(EntryData),
+    /// Constraint-based gossip round
+    Gsp(GspMessage),
 }
 
 /// Mirror DHT implementation: Holds and reflect everything back to other nodes (fullsync)
 ///  - On *HoldRequest, store and gossip data back to every known peer.
 ///  - Gossip can only be a *HoldRequest
 ///  - Monotonic data
+///  - Periodic anti-entropy with one peer at a time repairs missed gossip
+///  - Owner can open constraint-based gossip rounds, e.g. for incremental syncs
+///  - Entries are re-published to new holders when holders time out
+///  - Peer and entry-address tables are persisted if `DhtConfig::storage_path` is set
+///  - Silent peers become Suspect, then Dead, and are evicted after a grace period
+///  - Tunable through `DhtConfig::custom`, see `MirrorConfig`
+///  - Entries are pruned once `DhtConfig::storage_budget` is exceeded
 pub struct MirrorDht {
     /// FIFO of DhtCommands send to us
     inbox: VecDeque<DhtCommand>,
@@ -34,16 +151,56 @@ pub struct MirrorDht {
     entry_list: HashMap<Address, HashSet<Address>>,
     /// Monotonic Storage of PeerData
     peer_map: HashMap<PeerAddress, PeerData>,
-    /// Track if peer timed out
-    timed_out_map: HashMap<PeerAddress, HasTimedOut>,
+    /// Liveness state of each peer
+    peer_state_map: HashMap<PeerAddress, PeerState>,
+    /// Thresholds of the peer lifecycle
+    liveness: Liveness,
     /// PeerData of this peer
     this_peer: PeerData,
     /// Keep track of fetch requests sent to Core
     pending_fetch_request_list: HashSet<String>,
     /// Keep track of last time this peer gossiped self to others
     last_gossip_of_self: u64,
-    /// Store Dht config used by this peer
+    /// Keep track of last time this peer started an anti-entropy round
+    last_anti_entropy: u64,
+    /// Entries waiting for the next entry gossip, if there is an `entry_gossip_interval_ms`
+    pending_entry_gossip: HashMap<Address, EntryData>,
+    /// Keep track of last time this peer gossiped the entries it got
+    last_entry_gossip: u64,
+    /// Round-robin cursor over peers for anti-entropy
+    anti_entropy_cursor: usize,
+    /// Fetch requests made to repair a peer: msg_id -> peer to send the entry to,
+    /// and when it was requested
+    sync_fetch_map: HashMap<String, (PeerAddress, u64)>,
+    /// Aspects received through anti-entropy, not to be rebroadcast once held:
+    /// aspect_address -> when it was received
+    synced_aspect_map: HashMap<Address, u64>,
+    /// Runs constraint-based gossip rounds
+    gossip_engine: GossipEngine,
+    /// Fetch requests made to answer an AspectDataRequest:
+    /// msg_id -> peer to send the aspects to, and requested aspects
+    gsp_fetch_map: HashMap<String, (PeerAddress, AspectHashList)>,
+    /// Known holders of entries, to maintain the replication factor
+    holder_map: HolderMap,
+    /// Fetch requests made to re-publish an entry: msg_id -> new holders
+    republish_fetch_map: HashMap<String, Vec<PeerAddress>>,
+    /// Where peer and entry-address tables are persisted, if anywhere
+    storage: Option<DhtStorage>,
+    /// State changed since last save
+    is_storage_dirty: bool,
+    /// Keep track of last time state was saved
+    last_save: u64,
+    /// Store Dht config used by this peer, with `mirror_config` overrides applied
     config: DhtConfig,
+    /// Tuning read from `DhtConfig::custom`
+    mirror_config: MirrorConfig,
+    /// Rotates the peers picked when gossip is limited by `gossip_fanout`
+    fanout_cursor: usize,
+    /// Usage of held entries, if there is a storage budget
+    quota: Option<StorageQuota>,
+    /// Entries let go to stay within the storage budget.
+    /// Not held again from gossip, only if owner holds or broadcasts them itself.
+    pruned_set: HashSet<Address>,
 }
 
 /// Constructors
@@ -54,11 +211,14 @@ impl MirrorDht {
     }
 
     pub fn new_with_config(config: &DhtConfig) -> Lib3hResult<Self> {
+        let mirror_config = MirrorConfig::from_custom(&config.custom)?;
+        let config = &mirror_config.apply(config);
         let timestamp = time::since_epoch_ms();
-        let this = MirrorDht {
+        let mut this = MirrorDht {
             inbox: VecDeque::new(),
             peer_map: HashMap::new(),
-            timed_out_map: HashMap::new(),
+            peer_state_map: HashMap::new(),
+            liveness: Liveness::new(config),
             entry_list: HashMap::new(),
             this_peer: PeerData {
                 peer_address: config.this_peer_address.to_owned(),
@@ -67,8 +227,35 @@ impl MirrorDht {
             },
             pending_fetch_request_list: HashSet::new(),
             last_gossip_of_self: timestamp,
+            last_anti_entropy: timestamp,
+            pending_entry_gossip: HashMap::new(),
+            last_entry_gossip: timestamp,
+            anti_entropy_cursor: 0,
+            sync_fetch_map: HashMap::new(),
+            synced_aspect_map: HashMap::new(),
+            gossip_engine: GossipEngine::new(),
+            gsp_fetch_map: HashMap::new(),
+            holder_map: HolderMap::new(),
+            republish_fetch_map: HashMap::new(),
+            storage: None,
+            is_storage_dirty: false,
+            last_save: timestamp,
             config: config.clone(),
+            mirror_config,
+            fanout_cursor: 0,
+            quota: config
+                .storage_budget
+                .as_ref()
+                .map(|budget| StorageQuota::new(budget, &config.this_peer_address)),
+            pruned_set: HashSet::new(),
         };
+        if let Some(storage_path) = &config.storage_path {
+            let storage = DhtStorage::new(storage_path);
+            if let Some(state) = storage.load()? {
+                this.restore(state);
+            }
+            this.storage = Some(storage);
+        }
         Ok(this)
     }
 }
@@ -112,7 +299,14 @@ impl Dht for MirrorDht {
     // -- Processing -- //
 
     /// Add to inbox
+    /// Refuses entries with an aspect too big to be gossiped, see `max_bundle_size`
     fn post(&mut self, cmd: DhtCommand) -> Lib3hResult<()> {
+        match &cmd {
+            DhtCommand::BroadcastEntry(entry) | DhtCommand::HoldEntryAspectAddress(entry) => {
+                self.check_aspect_size(entry)?;
+            }
+            _ => (),
+        }
         self.inbox.push_back(cmd);
         Ok(())
     }
@@ -136,34 +330,83 @@ impl Dht for MirrorDht {
                 error!("serve_DhtCommand() failed: {:?}", res);
             }
         }
-        // Check if others timed-out
+        self.expire_sync_state(now);
+        // Let go of entries beyond the storage budget
+        let prune_list = self
+            .quota
+            .as_ref()
+            .map(|quota| quota.select_prune())
+            .unwrap_or_default();
+        for entry_address in prune_list {
+            debug!("@MirrorDht@ pruning entry {}", entry_address);
+            self.drop_entry(&entry_address);
+            self.pruned_set.insert(entry_address.clone());
+            outbox.push(DhtEvent::EntryPruned(entry_address));
+            did_work = true;
+        }
+        // Move peers through their lifecycle
         // TODO: Might need to optimize performance as walking a map is expensive
         // see comment: https://github.com/holochain/lib3h/pull/210/#discussion_r304518608
         let mut timed_out_list = Vec::new();
+        let mut evicted_list = Vec::new();
+        let mut suspect_list = Vec::new();
         for (peer_address, peer) in self.peer_map.iter() {
             // Skip self
             if peer_address == &self.this_peer.peer_address {
                 continue;
             }
-            // Skip already timed out
-            let has_timed_out = self
-                .timed_out_map
+            let state = *self
+                .peer_state_map
                 .get(peer_address)
-                .expect("Should always have time_out value for a peer");
-            if *has_timed_out {
+                .expect("Should always have a state for a peer");
+            let maybe_new_state = self.liveness.state_of(peer.timestamp, now);
+            if maybe_new_state == Some(state) {
                 continue;
             }
-            // Check if timed-out
-            if now - peer.timestamp > self.config.timeout_threshold {
-                debug!("@MirrorDht@ peer {} timed-out", peer_address);
-                outbox.push(DhtEvent::PeerTimedOut(peer_address.clone()));
-                timed_out_list.push(peer_address.clone());
-                did_work = true;
+            match maybe_new_state {
+                Some(PeerState::Alive) => (),
+                Some(PeerState::Suspect) => {
+                    debug!("@MirrorDht@ peer {} is suspect", peer_address);
+                    suspect_list.push(peer_address.clone());
+                }
+                Some(PeerState::Dead) => {
+                    debug!("@MirrorDht@ peer {} timed-out", peer_address);
+                    timed_out_list.push(peer_address.clone());
+                }
+                None => {
+                    debug!("@MirrorDht@ peer {} evicted", peer_address);
+                    if state != PeerState::Dead {
+                        timed_out_list.push(peer_address.clone());
+                    }
+                    evicted_list.push(peer_address.clone());
+                }
             }
         }
+        for peer_address in suspect_list {
+            self.peer_state_map.insert(peer_address, PeerState::Suspect);
+        }
         // Mark peers that timed out
+        for peer_address in timed_out_list.iter() {
+            outbox.push(DhtEvent::PeerTimedOut(peer_address.clone()));
+            self.gossip_engine.close_round(peer_address);
+            self.peer_state_map
+                .insert(peer_address.clone(), PeerState::Dead);
+            did_work = true;
+        }
+        // Re-publish entries that lost holders, once all dropped peers are known
         for peer_address in timed_out_list {
-            self.timed_out_map.insert(peer_address, true);
+            let mut republish_event_list = self.republish_lost_holder(&peer_address);
+            outbox.append(&mut republish_event_list);
+        }
+        // Forget peers dead for too long
+        for peer_address in evicted_list {
+            self.peer_state_map.remove(&peer_address);
+            let peer = self
+                .peer_map
+                .remove(&peer_address)
+                .expect("Should have evicted peer");
+            outbox.push(DhtEvent::PeerEvicted(peer));
+            did_work = true;
         }
         // Check if must gossip self
         trace!(
@@ -174,15 +417,89 @@ impl Dht for MirrorDht {
         );
         if now - self.last_gossip_of_self > self.config.gossip_interval {
             self.last_gossip_of_self = now;
-            let gossip_data = self.gossip_self(self.get_other_peer_list());
+            let peer_list = self.select_fanout(self.get_reachable_peer_list());
+            let gossip_data = self.gossip_self(peer_list);
             if gossip_data.peer_address_list.len() > 0 {
                 outbox.push(DhtEvent::GossipTo(gossip_data));
                 did_work = true;
             }
         }
+        // Check if must start an anti-entropy round
+        if self.config.anti_entropy_interval > 0
+            && now - self.last_anti_entropy > self.config.anti_entropy_interval
+        {
+            self.last_anti_entropy = now;
+            if let Some(gossip_data) = self.gossip_digest() {
+                outbox.push(DhtEvent::GossipTo(gossip_data));
+                did_work = true;
+            }
+        }
+        // Check if must gossip the entries got since last time
+        let mut entry_gossip_list = self.gossip_pending_entries(now);
+        did_work |= !entry_gossip_list.is_empty();
+        outbox.append(&mut entry_gossip_list);
+        // Persist changes, at most once per gossip interval
+        if did_work {
+            self.is_storage_dirty = true;
+        }
+        if self.is_storage_dirty && now - self.last_save > self.config.gossip_interval {
+            self.last_save = now;
+            self.save();
+        }
         // Done
         Ok((did_work, outbox))
     }
+
+    /// Earliest of: pending commands, next gossip of self, next peer state change
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if !self.inbox.is_empty() {
+            return Some(time::since_epoch_ms());
+        }
+        // Checks in process() use a strict comparison, hence the +1
+        let mut deadline = self.last_gossip_of_self + self.config.gossip_interval + 1;
+        if self.config.anti_entropy_interval > 0 {
+            let anti_entropy_deadline =
+                self.last_anti_entropy + self.config.anti_entropy_interval + 1;
+            if anti_entropy_deadline < deadline {
+                deadline = anti_entropy_deadline;
+            }
+        }
+        if let Some(interval) = self.mirror_config.entry_gossip_interval_ms {
+            if !self.pending_entry_gossip.is_empty() {
+                let entry_gossip_deadline = self.last_entry_gossip + interval + 1;
+                if entry_gossip_deadline < deadline {
+                    deadline = entry_gossip_deadline;
+                }
+            }
+        }
+        for (peer_address, peer) in self.peer_map.iter() {
+            if peer_address == &self.this_peer.peer_address {
+                continue;
+            }
+            let state = match self.peer_state_map.get(peer_address) {
+                None => continue,
+                Some(state) => *state,
+            };
+            let peer_deadline = self.liveness.next_transition_ms(peer.timestamp, state);
+            if peer_deadline < deadline {
+                deadline = peer_deadline;
+            }
+        }
+        Some(deadline)
+    }
+
+    fn dht_snapshot(&self) -> DhtSnapshot {
+        let mut snapshot = DhtSnapshot::from_dht(self);
+        snapshot.pending_fetch_request_list =
+            self.pending_fetch_request_list.iter().cloned().collect();
+        snapshot.pending_fetch_request_list.sort();
+        snapshot.timed_out_peer_list = self.get_peer_list_in_state(PeerState::Dead);
+        snapshot.timed_out_peer_list.sort();
+        snapshot.suspect_peer_list = self.get_peer_list_in_state(PeerState::Suspect);
+        snapshot.suspect_peer_list.sort();
+        snapshot.inbox_depth = self.inbox.len();
+        snapshot
+    }
 }
 
 /// Internals
@@ -196,8 +513,99 @@ impl MirrorDht {
             .collect()
     }
 
-    // Create gossipTo event of your own PeerData (but not to yourself)
+    fn get_peer_list_in_state(&self, wanted_state: PeerState) -> Vec<PeerAddress> {
+        self.peer_state_map
+            .iter()
+            .filter(|(_, state)| **state == wanted_state)
+            .map(|(peer_address, _)| peer_address.clone())
+            .collect()
+    }
+
+    /// Other peers we still gossip to: Alive or Suspect
+    fn get_reachable_peer_list(&self) -> Vec<PeerAddress> {
+        self.get_other_peer_list()
+            .into_iter()
+            .filter(|peer_address| self.peer_state_map.get(peer_address) != Some(&PeerState::Dead))
+            .collect()
+    }
+
+    /// Other peers we rely on, e.g. to hold entries: Alive only
+    fn get_live_peer_list(&self) -> Vec<PeerAddress> {
+        self.get_other_peer_list()
+            .into_iter()
+            .filter(|peer_address| self.peer_state_map.get(peer_address) == Some(&PeerState::Alive))
+            .collect()
+    }
+
+    /// Load peer and entry-address tables saved by a previous run
+    fn restore(&mut self, state: StoredDhtState) {
+        if state.this_peer_address != self.this_peer.peer_address {
+            warn!(
+                "@MirrorDht@ Not restoring state saved by {}",
+                state.this_peer_address
+            );
+            return;
+        }
+        for peer in state.peer_list {
+            self.peer_state_map
+                .insert(peer.peer_address.clone(), PeerState::Alive);
+            self.peer_map.insert(peer.peer_address.clone(), peer);
+        }
+        for peer_address in state.timed_out_peer_list {
+            if self.peer_map.contains_key(&peer_address) {
+                self.peer_state_map.insert(peer_address, PeerState::Dead);
+            }
+        }
+        // Hold entries again like new ones, so they are indexed for gossip and
+        // count against the budget. Restored aspects count as published now,
+        // so incremental gossip rounds still offer them.
+        let now = time::since_epoch_ms();
+        for stored_entry in state.entry_list {
+            self.add_entry_aspects(&stored_entry.to_entry_data(now));
+        }
+        debug!(
+            "@MirrorDht@ Restored {} peers and {} entries",
+            self.peer_map.len(),
+            self.entry_list.len()
+        );
+    }
+
+    /// Save peer and entry-address tables, if storage is configured
+    fn save(&mut self) {
+        let storage = match &self.storage {
+            None => return,
+            Some(storage) => storage,
+        };
+        let mut state = StoredDhtState::new(&self.this_peer.peer_address);
+        state.peer_list = self
+            .peer_map
+            .values()
+            .filter(|peer| peer.peer_address != self.this_peer.peer_address)
+            .cloned()
+            .collect();
+        state.timed_out_peer_list = self.get_peer_list_in_state(PeerState::Dead);
+        state.entry_list = self
+            .entry_list
+            .iter()
+            .map(|(entry_address, aspect_set)| StoredEntry {
+                entry_address: entry_address.clone(),
+                aspect_address_list: aspect_set.iter().cloned().collect(),
+            })
+            .collect();
+        match storage.save(&state) {
+            Ok(()) => self.is_storage_dirty = false,
+            Err(e) => error!(
+                "@MirrorDht@ Failed saving state to {}: {:?}",
+                storage.path().display(),
+                e
+            ),
+        }
+    }
+
+    // Create gossipTo event of your own PeerData (but not to yourself),
+    // timestamped now so receivers keep us Alive, or reinstate us
     fn gossip_self(&mut self, peer_address_list: Vec<PeerAddress>) -> GossipToData {
+        self.this_peer.timestamp = time::since_epoch_ms();
         let this_peer = self.this_peer();
         let gossip_this_peer = MirrorGossip::Peer(this_peer.clone());
         let mut buf = Vec::new();
@@ -211,7 +619,182 @@ impl MirrorDht {
         );
         GossipToData {
             peer_address_list,
-            bundle: buf,
+            bundle: buf.into(),
+        }
+    }
+
+    /// Create GossipTo event of our aspect digest for the next live peer, round-robin
+    fn gossip_digest(&mut self) -> Option<GossipToData> {
+        let mut peer_list = self.get_live_peer_list();
+        if peer_list.is_empty() {
+            return None;
+        }
+        peer_list.sort();
+        let peer_address = peer_list[self.anti_entropy_cursor % peer_list.len()].clone();
+        self.anti_entropy_cursor = self.anti_entropy_cursor.wrapping_add(1);
+        trace!("@MirrorDht@ anti-entropy with: {}", peer_address);
+        let digest = AspectDigest::new(&self.entry_list);
+        Some(self.gossip_to(vec![peer_address], &MirrorGossip::Digest(digest)))
+    }
+
+    /// A holder timed out: fetch the entries that fell below the replication factor,
+    /// so they can be pushed to new holders.
+    fn republish_lost_holder(&mut self, lost_peer_address: &PeerAddressRef) -> Vec<DhtEvent> {
+        if self.config.replication_factor == 0 {
+            return vec![];
+        }
+        let live_peer_list = self.get_live_peer_list();
+        let republish_list = self.holder_map.drop_holder(
+            lost_peer_address,
+            &self.this_peer.peer_address,
+            &live_peer_list,
+            self.config.replication_factor as usize,
+        );
+        let mut event_list = Vec::new();
+        for republish in republish_list {
+            debug!(
+                "@MirrorDht@ re-publishing {} to {:?}",
+                republish.entry_address, republish.peer_address_list
+            );
+            let msg_id = format!("{}{}", REPUBLISH_FETCH_PREFIX, republish.entry_address);
+            let is_fetching = self.republish_fetch_map.contains_key(&msg_id);
+            self.republish_fetch_map
+                .entry(msg_id.clone())
+                .or_insert_with(Vec::new)
+                .extend(republish.peer_address_list);
+            if is_fetching {
+                continue;
+            }
+            self.pending_fetch_request_list.insert(msg_id.clone());
+            event_list.push(DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                msg_id,
+                entry_address: republish.entry_address,
+            }));
+        }
+        event_list
+    }
+
+    /// Bookkeep the entries a gossip shows its sender holds
+    fn add_gossip_holders(&mut self, from_peer_address: &PeerAddressRef, gossip: &MirrorGossip) {
+        let entry_address_list: Vec<&Address> = match gossip {
+            MirrorGossip::Entry(entry) | MirrorGossip::SyncEntry(entry) => {
+                vec![&entry.entry_address]
+            }
+            MirrorGossip::BucketContent(content) => content
+                .entry_list
+                .iter()
+                .map(|(entry_address, _)| entry_address)
+                .collect(),
+            MirrorGossip::Gsp(GspMessage::ArcRequest(arc))
+            | MirrorGossip::Gsp(GspMessage::ArcResponse(arc)) => arc
+                .aspect_hash_list
+                .iter()
+                .map(|hashes| &hashes.entry_address)
+                .collect(),
+            MirrorGossip::Gsp(GspMessage::AspectDataResponse(entry_list)) => entry_list
+                .iter()
+                .map(|entry| &entry.entry_address)
+                .collect(),
+            _ => return,
+        };
+        for entry_address in entry_address_list {
+            self.holder_map.add_holder(entry_address, from_peer_address);
+        }
+    }
+
+    /// Forget anti-entropy fetches Core did not answer, and synced aspects it did not hold,
+    /// in time. A late answer is then refused as unknown.
+    fn expire_sync_state(&mut self, now: u64) {
+        let expiry = self.mirror_config.sync_expiry_ms.unwrap_or_else(|| {
+            let interval = match self.config.anti_entropy_interval {
+                0 => DEFAULT_ANTI_ENTROPY_INTERVAL_MS,
+                interval => interval,
+            };
+            interval * SYNC_EXPIRY_ROUND_COUNT
+        });
+        let expired_list: Vec<String> = self
+            .sync_fetch_map
+            .iter()
+            .filter(|(_, (_, requested_at))| now.saturating_sub(*requested_at) > expiry)
+            .map(|(msg_id, _)| msg_id.clone())
+            .collect();
+        for msg_id in expired_list {
+            debug!("@MirrorDht@ sync fetch {} expired", msg_id);
+            self.sync_fetch_map.remove(&msg_id);
+            self.pending_fetch_request_list.remove(&msg_id);
+        }
+        self.synced_aspect_map
+            .retain(|_, received_at| now.saturating_sub(*received_at) <= expiry);
+    }
+
+    /// Ask owner to hold aspects we were missing, received from a sync.
+    /// They will not be rebroadcast once held.
+    fn hold_synced_entry(&mut self, mut entry: EntryData) -> Option<DhtEvent> {
+        if self.pruned_set.contains(&entry.entry_address) {
+            return None;
+        }
+        let diff = self.diff_aspects(&entry);
+        if diff.is_empty() {
+            return None;
+        }
+        entry
+            .aspect_list
+            .retain(|aspect| diff.contains(&aspect.aspect_address));
+        let now = time::since_epoch_ms();
+        self.synced_aspect_map
+            .extend(diff.into_iter().map(|aspect_address| (aspect_address, now)));
+        Some(DhtEvent::HoldEntryRequested(
+            self.this_peer.peer_address.clone(),
+            entry,
+        ))
+    }
+
+    /// Convert what the gossip engine wants done into events for owner
+    fn handle_gossip_output(&mut self, output_list: Vec<GossipOutput>) -> Vec<DhtEvent> {
+        let mut event_list = Vec::new();
+        for output in output_list {
+            match output {
+                GossipOutput::Send(peer_address, msg) => {
+                    let gossip_data = self.gossip_to(vec![peer_address], &MirrorGossip::Gsp(msg));
+                    event_list.push(DhtEvent::GossipTo(gossip_data));
+                }
+                GossipOutput::ProvideAspectData(peer_address, hash_list) => {
+                    for hashes in hash_list {
+                        let msg_id = format!(
+                            "{}{}::{}",
+                            GSP_FETCH_PREFIX, peer_address, hashes.entry_address
+                        );
+                        let entry_address = hashes.entry_address.clone();
+                        self.pending_fetch_request_list.insert(msg_id.clone());
+                        self.gsp_fetch_map
+                            .insert(msg_id.clone(), (peer_address.clone(), hashes));
+                        event_list.push(DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                            msg_id,
+                            entry_address,
+                        }));
+                    }
+                }
+                GossipOutput::HoldAspectData(_, entry) => {
+                    if let Some(event) = self.hold_synced_entry(entry) {
+                        event_list.push(event);
+                    }
+                }
+            }
+        }
+        event_list
+    }
+
+    /// Create GossipTo data of a MirrorGossip for some peers
+    fn gossip_to(
+        &self,
+        peer_address_list: Vec<PeerAddress>,
+        gossip: &MirrorGossip,
+    ) -> GossipToData {
+        let mut buf = Vec::new();
+        gossip.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        GossipToData {
+            peer_address_list,
+            bundle: buf.into(),
         }
     }
 
@@ -224,8 +807,8 @@ impl MirrorDht {
                 trace!("@MirrorDht@ Adding peer - OK NEW");
                 self.peer_map
                     .insert(peer_info.peer_address.clone(), peer_info.clone());
-                self.timed_out_map
-                    .insert(peer_info.peer_address.clone(), false);
+                self.peer_state_map
+                    .insert(peer_info.peer_address.clone(), PeerState::Alive);
                 true
             }
             Some(mut peer) => {
@@ -239,9 +822,18 @@ impl MirrorDht {
                     peer.timestamp,
                 );
                 peer.timestamp = peer_info.timestamp;
-                if crate::time::since_epoch_ms() - peer.timestamp < self.config.timeout_threshold {
-                    self.timed_out_map
-                        .insert(peer_info.peer_address.clone(), false);
+                // Fresh enough PeerData reinstates a dead peer
+                let now = crate::time::since_epoch_ms();
+                match self.liveness.state_of(peer.timestamp, now) {
+                    Some(PeerState::Dead) | None => (),
+                    Some(state) => {
+                        let previous_state = self
+                            .peer_state_map
+                            .insert(peer_info.peer_address.clone(), state);
+                        if previous_state == Some(PeerState::Dead) {
+                            debug!("@MirrorDht@ peer {} reinstated", peer_info.peer_address);
+                        }
+                    }
                 }
                 true
             }
@@ -270,13 +862,35 @@ impl MirrorDht {
         diff
     }
 
+    /// Stop tracking an entry
+    fn drop_entry(&mut self, entry_address: &Address) {
+        self.entry_list.remove(entry_address);
+        self.pending_entry_gossip.remove(entry_address);
+        self.gossip_engine.drop_entry(entry_address);
+        self.holder_map.drop_entry(entry_address);
+        if let Some(quota) = self.quota.as_mut() {
+            quota.remove(entry_address);
+        }
+    }
+
     /// Add aspect addresses for an entry in our local storage.
     /// Return true if at least one new aspect address was added.
     fn add_entry_aspects(&mut self, entry: &EntryData) -> bool {
+        self.gossip_engine.index_entry(entry);
+        self.holder_map
+            .add_holder(&entry.entry_address, &self.this_peer.peer_address);
         let diff: HashSet<_> = self.diff_aspects(&entry);
         if diff.len() == 0 {
             return false;
         }
+        if let Some(quota) = self.quota.as_mut() {
+            let new_aspect_list: Vec<&EntryAspectData> = entry
+                .aspect_list
+                .iter()
+                .filter(|aspect| diff.contains(&aspect.aspect_address))
+                .collect();
+            quota.add(&entry.entry_address, &new_aspect_list);
+        }
         let maybe_known_aspects = self.entry_list.get(&entry.entry_address);
         let new_aspects: HashSet<_> = match maybe_known_aspects {
             None => diff,
@@ -290,18 +904,155 @@ impl MirrorDht {
         true
     }
 
-    /// Create GossipTo event for entry to all known peers
-    fn gossip_entry(&self, entry: &EntryData) -> DhtEvent {
-        let entry_gossip = MirrorGossip::Entry(entry.clone());
-        let mut buf = Vec::new();
-        entry_gossip
-            .serialize(&mut Serializer::new(&mut buf))
-            .unwrap();
-        let gossip_evt = GossipToData {
-            peer_address_list: self.get_other_peer_list(),
-            bundle: buf,
+    /// Create GossipTo events for entry to known peers, up to the gossip fanout,
+    /// or keep it for the next entry gossip if there is an `entry_gossip_interval_ms`
+    fn gossip_entry(&mut self, entry: &EntryData) -> Vec<DhtEvent> {
+        if self.mirror_config.entry_gossip_interval_ms.is_none() {
+            return self.gossip_entry_now(entry);
+        }
+        let pending = self
+            .pending_entry_gossip
+            .entry(entry.entry_address.clone())
+            .or_insert_with(|| EntryData {
+                entry_address: entry.entry_address.clone(),
+                aspect_list: Vec::new(),
+            });
+        for aspect in entry.aspect_list.iter() {
+            if !pending
+                .aspect_list
+                .iter()
+                .any(|known| known.aspect_address == aspect.aspect_address)
+            {
+                pending.aspect_list.push(aspect.clone());
+            }
+        }
+        vec![]
+    }
+
+    /// Gossip the entries kept since the last entry gossip, if it is time to
+    fn gossip_pending_entries(&mut self, now: u64) -> Vec<DhtEvent> {
+        match self.mirror_config.entry_gossip_interval_ms {
+            Some(interval) if now - self.last_entry_gossip > interval => (),
+            _ => return vec![],
+        }
+        self.last_entry_gossip = now;
+        let entry_list: Vec<EntryData> = self
+            .pending_entry_gossip
+            .drain()
+            .map(|(_, entry)| entry)
+            .collect();
+        let mut gossip_list = Vec::new();
+        for entry in entry_list {
+            gossip_list.append(&mut self.gossip_entry_now(&entry));
+        }
+        gossip_list
+    }
+
+    fn gossip_entry_now(&mut self, entry: &EntryData) -> Vec<DhtEvent> {
+        let peer_address_list = self.select_fanout(self.get_reachable_peer_list());
+        self.gossip_entry_data(peer_address_list, entry, MirrorGossip::Entry)
+    }
+
+    /// Fail if an aspect alone does not fit in a gossip bundle of `max_bundle_size`
+    fn check_aspect_size(&self, entry: &EntryData) -> Lib3hResult<()> {
+        let max_bundle_size = match self.mirror_config.max_bundle_size {
+            None => return Ok(()),
+            Some(max_bundle_size) => max_bundle_size,
+        };
+        for aspect in entry.aspect_list.iter() {
+            let single = EntryData {
+                entry_address: entry.entry_address.clone(),
+                aspect_list: vec![aspect.clone()],
+            };
+            let bundle_size = self
+                .gossip_to(vec![], &MirrorGossip::Entry(single))
+                .bundle
+                .len();
+            if bundle_size > max_bundle_size {
+                return Err(Lib3hError::new(ErrorKind::Other(format!(
+                    "Aspect {} of {} takes {} bytes once gossiped, over max bundle size {}",
+                    aspect.aspect_address, entry.entry_address, bundle_size, max_bundle_size,
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create GossipTo events of an entry, split by aspects to fit in `max_bundle_size`
+    fn gossip_entry_data(
+        &self,
+        peer_address_list: Vec<PeerAddress>,
+        entry: &EntryData,
+        to_gossip: fn(EntryData) -> MirrorGossip,
+    ) -> Vec<DhtEvent> {
+        let max_bundle_size = match self.mirror_config.max_bundle_size {
+            None => {
+                let gossip_data = self.gossip_to(peer_address_list, &to_gossip(entry.clone()));
+                return vec![DhtEvent::GossipTo(gossip_data)];
+            }
+            Some(max_bundle_size) => max_bundle_size,
+        };
+        // Bundle size of a chunk is known from the size of each of its aspects
+        // alone, so the entry is serialized only once more, chunk by chunk
+        let chunk_of = |aspect_list: Vec<EntryAspectData>| {
+            to_gossip(EntryData {
+                entry_address: entry.entry_address.clone(),
+                aspect_list,
+            })
         };
-        DhtEvent::GossipTo(gossip_evt)
+        let empty_size = self.gossip_to(vec![], &chunk_of(vec![])).bundle.len();
+        let bundle_size = |aspect_count: usize, aspect_total_size: usize| {
+            // The count of the aspect list is 1 byte up to 15, then 3 bytes, then 5
+            let count_size = match aspect_count {
+                0..=15 => 1,
+                16..=0xffff => 3,
+                _ => 5,
+            };
+            empty_size - 1 + count_size + aspect_total_size
+        };
+        let mut gossip_list = Vec::new();
+        let mut chunk = Vec::new();
+        let mut chunk_size = 0;
+        for aspect in entry.aspect_list.iter() {
+            let aspect_size = self
+                .gossip_to(vec![], &chunk_of(vec![aspect.clone()]))
+                .bundle
+                .len()
+                - empty_size;
+            if bundle_size(1, aspect_size) > max_bundle_size {
+                warn!(
+                    "@MirrorDht@ aspect {} of {} exceeds max bundle size {}, not gossiped",
+                    aspect.aspect_address, entry.entry_address, max_bundle_size,
+                );
+                continue;
+            }
+            // Does not fit: flush previous aspects
+            if bundle_size(chunk.len() + 1, chunk_size + aspect_size) > max_bundle_size {
+                let aspect_list = std::mem::replace(&mut chunk, Vec::new());
+                gossip_list.push(self.gossip_to(peer_address_list.clone(), &chunk_of(aspect_list)));
+                chunk_size = 0;
+            }
+            chunk.push(aspect.clone());
+            chunk_size += aspect_size;
+        }
+        if !chunk.is_empty() {
+            gossip_list.push(self.gossip_to(peer_address_list, &chunk_of(chunk)));
+        }
+        gossip_list.into_iter().map(DhtEvent::GossipTo).collect()
+    }
+
+    /// Pick at most `gossip_fanout` peers, rotating through them across calls
+    fn select_fanout(&mut self, mut peer_address_list: Vec<PeerAddress>) -> Vec<PeerAddress> {
+        let fanout = match self.mirror_config.gossip_fanout {
+            Some(fanout) if fanout < peer_address_list.len() => fanout,
+            _ => return peer_address_list,
+        };
+        peer_address_list.sort();
+        let start = self.fanout_cursor % peer_address_list.len();
+        peer_address_list.rotate_left(start);
+        peer_address_list.truncate(fanout);
+        self.fanout_cursor = self.fanout_cursor.wrapping_add(fanout);
+        peer_address_list
     }
 
     /// Process a DhtEvent Command, sent by our owner.
@@ -321,11 +1072,13 @@ impl MirrorDht {
                     error!("Failed to deserialize gossip.");
                     return Err(Lib3hError::new(ErrorKind::RmpSerdeDecodeError(e)));
                 }
+                let gossip = maybe_gossip.unwrap();
+                self.add_gossip_holders(&msg.from_peer_address, &gossip);
                 // Handle gossiped data
-                match maybe_gossip.unwrap() {
+                match gossip {
                     MirrorGossip::Entry(entry) => {
                         let diff = self.diff_aspects(&entry);
-                        if diff.len() > 0 {
+                        if diff.len() > 0 && !self.pruned_set.contains(&entry.entry_address) {
                             return Ok(vec![DhtEvent::HoldEntryRequested(
                                 self.this_peer.peer_address.clone(),
                                 entry,
@@ -345,10 +1098,73 @@ impl MirrorDht {
                         }
                         return Ok(vec![]);
                     }
+                    // Anti-entropy: answer with our content of the buckets that differ
+                    MirrorGossip::Digest(digest) => {
+                        let bucket_index_list = AspectDigest::new(&self.entry_list).diff(&digest);
+                        if bucket_index_list.is_empty() {
+                            return Ok(vec![]);
+                        }
+                        let content = BucketContent::new(&self.entry_list, bucket_index_list, true);
+                        let gossip_data = self.gossip_to(
+                            vec![msg.from_peer_address.clone()],
+                            &MirrorGossip::BucketContent(content),
+                        );
+                        return Ok(vec![DhtEvent::GossipTo(gossip_data)]);
+                    }
+                    // Anti-entropy: fetch what the sender is missing so we can push it,
+                    // and send our own content so it can do the same for us.
+                    MirrorGossip::BucketContent(content) => {
+                        let mut event_list = Vec::new();
+                        if content.reply_expected {
+                            let reply = BucketContent::new(
+                                &self.entry_list,
+                                content.bucket_index_list.clone(),
+                                false,
+                            );
+                            let gossip_data = self.gossip_to(
+                                vec![msg.from_peer_address.clone()],
+                                &MirrorGossip::BucketContent(reply),
+                            );
+                            event_list.push(DhtEvent::GossipTo(gossip_data));
+                        }
+                        for (entry_address, _) in content.missing_from(&self.entry_list) {
+                            let msg_id = format!(
+                                "{}{}::{}",
+                                SYNC_FETCH_PREFIX, msg.from_peer_address, entry_address
+                            );
+                            // Already fetching it for that peer
+                            if self.sync_fetch_map.contains_key(&msg_id) {
+                                continue;
+                            }
+                            self.pending_fetch_request_list.insert(msg_id.clone());
+                            self.sync_fetch_map.insert(
+                                msg_id.clone(),
+                                (msg.from_peer_address.clone(), time::since_epoch_ms()),
+                            );
+                            event_list.push(DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                                msg_id,
+                                entry_address,
+                            }));
+                        }
+                        return Ok(event_list);
+                    }
+                    // Anti-entropy: hold the aspects we were missing
+                    MirrorGossip::SyncEntry(entry) => {
+                        return Ok(self.hold_synced_entry(entry).into_iter().collect());
+                    }
+                    MirrorGossip::Gsp(gsp_msg) => {
+                        let output_list = self
+                            .gossip_engine
+                            .handle_message(&msg.from_peer_address, gsp_msg);
+                        return Ok(self.handle_gossip_output(output_list));
+                    }
                 }
             }
             // Ask owner to respond to self
             DhtCommand::FetchEntry(fetch_entry) => {
+                if let Some(quota) = self.quota.as_mut() {
+                    quota.touch(&fetch_entry.entry_address);
+                }
                 self.pending_fetch_request_list
                     .insert(fetch_entry.msg_id.clone());
                 return Ok(vec![DhtEvent::EntryDataRequested(fetch_entry.clone())]);
@@ -356,7 +1172,7 @@ impl MirrorDht {
             // Owner is asking us to hold a peer info
             DhtCommand::HoldPeer(new_peer_data) => {
                 // Get peer_list before adding new peer (to use when doing gossipTo)
-                let others_list = self.get_other_peer_list();
+                let others_list = self.get_reachable_peer_list();
                 // Store it
                 let received_new_content = self.add_peer(new_peer_data);
                 // Bail if peer is known and up to date.
@@ -381,7 +1197,7 @@ impl MirrorDht {
                 );
                 let gossip_evt = GossipToData {
                     peer_address_list: others_list,
-                    bundle: buf,
+                    bundle: buf.into(),
                 };
                 event_list.push(DhtEvent::GossipTo(gossip_evt));
 
@@ -398,10 +1214,23 @@ impl MirrorDht {
             // Owner is holding some entry. Store its address for bookkeeping.
             // Ask for its data and broadcast it because we want fullsync.
             DhtCommand::HoldEntryAspectAddress(entry) => {
+                self.pruned_set.remove(&entry.entry_address);
+                let new_aspects = self.diff_aspects(&entry);
                 let received_new_content = self.add_entry_aspects(&entry);
                 if !received_new_content {
                     return Ok(vec![]);
                 }
+                // Aspects repaired through anti-entropy are not rebroadcast,
+                // other peers reconcile with us on their own.
+                let synced_count = new_aspects
+                    .iter()
+                    .filter(|aspect_address| {
+                        self.synced_aspect_map.remove(*aspect_address).is_some()
+                    })
+                    .count();
+                if synced_count == new_aspects.len() {
+                    return Ok(vec![]);
+                }
                 // Use entry_address as request_id
                 let address_str = entry.entry_address.clone();
                 self.pending_fetch_request_list
@@ -415,18 +1244,21 @@ impl MirrorDht {
             // Owner has some entry and wants it stored on the network
             // Bookkeep address and gossip entry to every known peer.
             DhtCommand::BroadcastEntry(entry) => {
+                self.pruned_set.remove(&entry.entry_address);
                 // Store address
                 let received_new_content = self.add_entry_aspects(&entry);
                 //// Bail if did not receive new content
                 if !received_new_content {
                     return Ok(vec![]);
                 }
-                let gossip_evt = self.gossip_entry(entry);
                 // Done
-                Ok(vec![gossip_evt])
+                Ok(self.gossip_entry(entry))
+            }
+            // Owner is not holding this entry anymore: stop tracking it
+            DhtCommand::DropEntryAddress(entry_address) => {
+                self.drop_entry(entry_address);
+                Ok(vec![])
             }
-            // N/A. Do nothing since this is a monotonic fullsync dht
-            DhtCommand::DropEntryAddress(_) => Ok(vec![]),
             // EntryDataResponse:
             //   - From a Publish: Forward response back to self
             //   - From a Hold   : Broadcast entry
@@ -436,14 +1268,70 @@ impl MirrorDht {
                         "Received response for an unknown request",
                     ))));
                 }
+                // Entry is in use: make it the last to be pruned
+                if let Some(quota) = self.quota.as_mut() {
+                    quota.touch(&response.entry.entry_address);
+                }
+                // From an anti-entropy round: push entry to the peer missing it
+                if let Some((peer_address, _)) = self.sync_fetch_map.remove(&response.msg_id) {
+                    return Ok(self.gossip_entry_data(
+                        vec![peer_address],
+                        &response.entry,
+                        MirrorGossip::SyncEntry,
+                    ));
+                }
+                // From a re-publish: push entry to its new holders
+                if let Some(peer_address_list) = self.republish_fetch_map.remove(&response.msg_id) {
+                    return Ok(self.gossip_entry_data(
+                        peer_address_list,
+                        &response.entry,
+                        MirrorGossip::SyncEntry,
+                    ));
+                }
+                // From a gossip round: send requested aspects to the peer
+                if let Some((peer_address, hashes)) = self.gsp_fetch_map.remove(&response.msg_id) {
+                    let entry = hashes.select(&response.entry);
+                    if entry.aspect_list.is_empty() {
+                        return Ok(vec![]);
+                    }
+                    let gossip_data = self.gossip_to(
+                        vec![peer_address],
+                        &MirrorGossip::Gsp(GspMessage::AspectDataResponse(vec![entry])),
+                    );
+                    return Ok(vec![DhtEvent::GossipTo(gossip_data)]);
+                }
                 // From a Hold if msg_id matches one set in HoldEntryAspectAddress
                 let address_str: String = (&response.entry.entry_address).clone().into();
                 if address_str == response.msg_id {
-                    let gossip_evt = self.gossip_entry(&response.entry);
-                    return Ok(vec![gossip_evt]);
+                    return Ok(self.gossip_entry(&response.entry));
                 }
                 Ok(vec![DhtEvent::FetchEntryResponse(response.clone())])
             }
+            // Owner wants to sync with a peer: open a gossip round
+            DhtCommand::OpenGossipRound(peer_address, constraints) => {
+                if self.get_peer(peer_address).is_none() {
+                    return Err(Lib3hError::new(ErrorKind::Other(format!(
+                        "Cannot open gossip round with unknown peer {}",
+                        peer_address
+                    ))));
+                }
+                let gsp_msg = self.gossip_engine.open_round(
+                    peer_address,
+                    constraints.clone(),
+                    time::since_epoch_ms(),
+                );
+                let gossip_data =
+                    self.gossip_to(vec![peer_address.clone()], &MirrorGossip::Gsp(gsp_msg));
+                Ok(vec![DhtEvent::GossipTo(gossip_data)])
+            }
+        }
+    }
+}
+
+impl Drop for MirrorDht {
+    fn drop(&mut self) {
+        if self.is_storage_dirty {
+            self.save();
         }
     }
 }
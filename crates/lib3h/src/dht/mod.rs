@@ -1,7 +1,17 @@
+pub mod anti_entropy;
+#[cfg(any(test, feature = "dht-conformance"))]
+pub mod conformance;
 pub mod dht_protocol;
 pub mod dht_trait;
+pub mod gossip;
+pub mod kademlia_dht;
+pub mod liveness;
+pub mod location;
 pub mod mirror_dht;
+pub mod quota;
+pub mod replication;
 pub mod rrdht;
+pub mod storage;
 
 /// a Peer identifier
 pub type PeerAddress = String;
@@ -11,12 +21,19 @@ pub type PeerAddressRef = str;
 pub mod tests {
     use crate::{
         dht::{
-            dht_protocol::*, dht_trait::Dht, mirror_dht::MirrorDht, rrdht::RrDht, PeerAddressRef,
+            conformance,
+            dht_protocol::*,
+            dht_trait::{Dht, DhtConfig, DEFAULT_GOSSIP_INTERVAL_MS},
+            gossip::GspConstraints,
+            kademlia_dht::KademliaDht,
+            mirror_dht::{MirrorConfig, MirrorDht},
+            rrdht::RrDht,
+            PeerAddressRef,
         },
         tests::enable_logging_for_test,
     };
     use lib3h_protocol::{
-        data_types::{EntryAspectData, EntryData},
+        data_types::{EntryAspectData, EntryData, PrunePolicy, StorageBudgetData},
         Address,
     };
     use url::Url;
@@ -217,6 +234,27 @@ pub mod tests {
         assert!(peer.timestamp > ref_time);
     }
 
+    #[test]
+    fn test_mirror_next_deadline() {
+        enable_logging_for_test(true);
+        let mut dht = new_dht(true, PEER_A);
+        // Next deadline should be the next gossip of self
+        let now = crate::time::since_epoch_ms();
+        let deadline = dht.next_deadline_ms().unwrap();
+        assert!(deadline > now);
+        assert!(deadline <= now + DEFAULT_GOSSIP_INTERVAL_MS + 1);
+        // Pending command should be due right away
+        dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        let deadline = dht.next_deadline_ms().unwrap();
+        assert!(deadline <= crate::time::since_epoch_ms());
+        // Peer timeout is further away than next gossip
+        let (did_work, _) = dht.process().unwrap();
+        assert!(did_work);
+        let deadline = dht.next_deadline_ms().unwrap();
+        assert!(deadline <= crate::time::since_epoch_ms() + DEFAULT_GOSSIP_INTERVAL_MS + 1);
+    }
+
     #[test]
     fn test_mirror_broadcast_entry() {
         enable_logging_for_test(true);
@@ -318,4 +356,805 @@ pub mod tests {
         let peer_info = dht_b.get_peer(PEER_C).unwrap();
         assert_eq!(peer_info, peer_c_data);
     }
+
+    fn new_mirror_dht(peer_address: &PeerAddressRef, anti_entropy_interval: u64) -> Box<dyn Dht> {
+        let mut config = DhtConfig::new(peer_address, &create_test_uri(peer_address));
+        config.anti_entropy_interval = anti_entropy_interval;
+        Box::new(MirrorDht::new_with_config(&config).unwrap())
+    }
+
+    /// Process `from` and route its events like its owner would:
+    /// gossip goes to `to`, fetches are answered from `held_list`.
+    /// Return true if `from` asked to rebroadcast an entry.
+    fn process_and_route(from: &mut dyn Dht, to: &mut dyn Dht, held_list: &[EntryData]) -> bool {
+        let from_peer_address = from.this_peer().peer_address.clone();
+        let mut did_rebroadcast = false;
+        let (_, event_list) = from.process().unwrap();
+        for event in event_list {
+            match event {
+                DhtEvent::GossipTo(gossip_to) => {
+                    to.post(DhtCommand::HandleGossip(RemoteGossipBundleData {
+                        from_peer_address: from_peer_address.clone(),
+                        bundle: gossip_to.bundle,
+                    }))
+                    .unwrap();
+                }
+                DhtEvent::HoldEntryRequested(_, entry) => {
+                    from.post(DhtCommand::HoldEntryAspectAddress(entry))
+                        .unwrap();
+                }
+                DhtEvent::EntryDataRequested(fetch) => {
+                    // Fetches made to rebroadcast an entry use its address as msg_id
+                    if fetch.msg_id == fetch.entry_address.to_string() {
+                        did_rebroadcast = true;
+                    }
+                    let maybe_entry = held_list
+                        .iter()
+                        .find(|entry| entry.entry_address == fetch.entry_address);
+                    if let Some(entry) = maybe_entry {
+                        from.post(DhtCommand::EntryDataResponse(FetchDhtEntryResponseData {
+                            msg_id: fetch.msg_id,
+                            entry: entry.clone(),
+                        }))
+                        .unwrap();
+                    }
+                }
+                _ => (),
+            }
+        }
+        did_rebroadcast
+    }
+
+    #[test]
+    fn test_mirror_anti_entropy() {
+        enable_logging_for_test(true);
+        let mut dht_a = new_mirror_dht(PEER_A, 1);
+        let mut dht_b = new_mirror_dht(PEER_B, 1);
+        // DHT A holds an entry before knowing about B, so B misses the gossip
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        dht_a
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .unwrap();
+        dht_a.process().unwrap();
+        dht_a
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        // Let anti-entropy rounds run, routing events between both DHTs
+        let mut did_rebroadcast = false;
+        for _ in 0..10 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            did_rebroadcast |= process_and_route(&mut *dht_a, &mut *dht_b, &[entry_data.clone()]);
+            did_rebroadcast |= process_and_route(&mut *dht_b, &mut *dht_a, &[]);
+        }
+        // DHT B should have repaired the entry without rebroadcasting it
+        let entry_list = dht_b.get_entry_address_list();
+        assert_eq!(entry_list, vec![&*ENTRY_ADDRESS_1]);
+        let aspect_list = dht_b.get_aspects_of(&ENTRY_ADDRESS_1).unwrap();
+        assert_eq!(aspect_list, vec![ASPECT_ADDRESS_1.clone()]);
+        assert!(!did_rebroadcast);
+    }
+
+    #[test]
+    fn test_mirror_sync_fetch_expiry() {
+        enable_logging_for_test(true);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.anti_entropy_interval = 1;
+        config.custom = br#"{"version": 1, "sync_expiry_ms": 5}"#.to_vec();
+        let mut dht_a = MirrorDht::new_with_config(&config).unwrap();
+        let mut dht_b = new_mirror_dht(PEER_B, 0);
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        dht_a
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .unwrap();
+        dht_a
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        // A sends its digest, B its content, A fetches the entry B is missing
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        process_and_route(&mut dht_a, &mut *dht_b, &[]);
+        process_and_route(&mut *dht_b, &mut dht_a, &[]);
+        let (_, event_list) = dht_a.process().unwrap();
+        let sync_fetch = event_list
+            .into_iter()
+            .find_map(|event| match event {
+                DhtEvent::EntryDataRequested(fetch) => Some(fetch),
+                _ => None,
+            })
+            .unwrap();
+        assert!(dht_a
+            .dht_snapshot()
+            .pending_fetch_request_list
+            .contains(&sync_fetch.msg_id));
+        // Core never answers: the fetch is forgotten
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        dht_a.process().unwrap();
+        assert!(!dht_a
+            .dht_snapshot()
+            .pending_fetch_request_list
+            .contains(&sync_fetch.msg_id));
+    }
+
+    #[test]
+    fn test_mirror_gossip_round_since() {
+        enable_logging_for_test(true);
+        let mut dht_a = new_mirror_dht(PEER_A, 0);
+        let mut dht_b = new_mirror_dht(PEER_B, 0);
+        // DHT A holds an old and a new entry before knowing about B
+        let since = crate::time::since_epoch_ms();
+        let mut old_entry =
+            create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        old_entry.aspect_list[0].publish_ts = since - 1;
+        let new_entry = create_EntryData(&ENTRY_ADDRESS_2, &ASPECT_ADDRESS_2, &ASPECT_CONTENT_2);
+        let held_list = vec![old_entry.clone(), new_entry.clone()];
+        for entry in held_list.iter() {
+            dht_a
+                .post(DhtCommand::BroadcastEntry(entry.clone()))
+                .unwrap();
+        }
+        dht_a.process().unwrap();
+        dht_a
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        // B syncs everything published since `since`
+        dht_b.process().unwrap();
+        dht_b
+            .post(DhtCommand::OpenGossipRound(
+                PEER_A.to_owned(),
+                GspConstraints::since(since),
+            ))
+            .unwrap();
+        let mut did_rebroadcast = false;
+        for _ in 0..10 {
+            did_rebroadcast |= process_and_route(&mut *dht_b, &mut *dht_a, &[]);
+            did_rebroadcast |= process_and_route(&mut *dht_a, &mut *dht_b, &held_list);
+        }
+        // DHT B should only have the new entry
+        let entry_list = dht_b.get_entry_address_list();
+        assert_eq!(entry_list, vec![&*ENTRY_ADDRESS_2]);
+        let aspect_list = dht_b.get_aspects_of(&ENTRY_ADDRESS_2).unwrap();
+        assert_eq!(aspect_list, vec![ASPECT_ADDRESS_2.clone()]);
+        assert!(!did_rebroadcast);
+    }
+
+    #[test]
+    fn test_mirror_republish_on_holder_loss() {
+        enable_logging_for_test(true);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.anti_entropy_interval = 0;
+        config.timeout_threshold = 1000;
+        config.replication_factor = 2;
+        let mut dht_a = MirrorDht::new_with_config(&config).unwrap();
+        let mut dht_b = new_mirror_dht(PEER_B, 0);
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        // B holds the entry and gossips it to A
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        dht_b
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .unwrap();
+        let (_, event_list) = dht_b.process().unwrap();
+        let bundle = event_list
+            .into_iter()
+            .filter_map(|event| match event {
+                DhtEvent::GossipTo(gossip_to) => Some(gossip_to.bundle),
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        // A holds the entry too, knows C, and B is about to time out
+        dht_a
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .unwrap();
+        let mut peer_b_data = create_PeerData(PEER_B);
+        peer_b_data.timestamp -= 2000;
+        dht_a.post(DhtCommand::HoldPeer(peer_b_data)).unwrap();
+        dht_a
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_C)))
+            .unwrap();
+        dht_a
+            .post(DhtCommand::HandleGossip(RemoteGossipBundleData {
+                from_peer_address: PEER_B.to_owned(),
+                bundle,
+            }))
+            .unwrap();
+        let (_, event_list) = dht_a.process().unwrap();
+        assert!(event_list.contains(&DhtEvent::PeerTimedOut(PEER_B.to_owned())));
+        // A is the only holder left, it should fetch the entry to re-publish it
+        let fetch = event_list
+            .into_iter()
+            .filter_map(|event| match event {
+                DhtEvent::EntryDataRequested(fetch) => Some(fetch),
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        assert_eq!(fetch.entry_address, *ENTRY_ADDRESS_1);
+        dht_a
+            .post(DhtCommand::EntryDataResponse(FetchDhtEntryResponseData {
+                msg_id: fetch.msg_id,
+                entry: entry_data.clone(),
+            }))
+            .unwrap();
+        let (_, event_list) = dht_a.process().unwrap();
+        // C is the new holder
+        assert_eq!(event_list.len(), 1);
+        let gossip_to = unwrap_to!(event_list[0] => DhtEvent::GossipTo);
+        assert_eq!(gossip_to.peer_address_list, vec![PEER_C.to_owned()]);
+    }
+
+    #[test]
+    fn test_mirror_peer_lifecycle() {
+        enable_logging_for_test(true);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.timeout_threshold = 1000;
+        config.eviction_grace_period = 1000;
+        let mut dht = MirrorDht::new_with_config(&config).unwrap();
+        // Silent for a bit more than half the threshold: Suspect, still gossiped to
+        let mut peer_b_data = create_PeerData(PEER_B);
+        peer_b_data.timestamp -= 600;
+        dht.post(DhtCommand::HoldPeer(peer_b_data)).unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert!(!event_list.contains(&DhtEvent::PeerTimedOut(PEER_B.to_owned())));
+        let snapshot = dht.dht_snapshot();
+        assert_eq!(snapshot.suspect_peer_list, vec![PEER_B.to_owned()]);
+        assert!(snapshot.timed_out_peer_list.is_empty());
+        // Silent past the threshold: Dead
+        let mut peer_c_data = create_PeerData(PEER_C);
+        peer_c_data.timestamp -= 1500;
+        dht.post(DhtCommand::HoldPeer(peer_c_data.clone())).unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert!(event_list.contains(&DhtEvent::PeerTimedOut(PEER_C.to_owned())));
+        assert!(dht.get_peer(PEER_C).is_some());
+        // Fresh PeerData reinstates it
+        dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_C)))
+            .unwrap();
+        dht.process().unwrap();
+        assert!(dht.dht_snapshot().timed_out_peer_list.is_empty());
+        // Silent past the grace period: evicted
+        let mut peer_d_data = create_PeerData("dan");
+        peer_d_data.timestamp -= 2500;
+        dht.post(DhtCommand::HoldPeer(peer_d_data.clone())).unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert!(event_list.contains(&DhtEvent::PeerTimedOut("dan".to_owned())));
+        assert!(event_list.contains(&DhtEvent::PeerEvicted(peer_d_data)));
+        assert!(dht.get_peer("dan").is_none());
+        assert_eq!(dht.get_peer_list().len(), 2);
+    }
+
+    #[test]
+    fn test_mirror_custom_config() {
+        enable_logging_for_test(true);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        // Invalid configs are refused at construction
+        for custom in &[
+            &br#"{"version": 2}"#[..],
+            &br#"{"gossip_fanout": 0}"#[..],
+            &br#"{"max_bundle_size": 0}"#[..],
+            &br#"{"timeout_threshold_ms": 0}"#[..],
+            &b"not json"[..],
+        ] {
+            config.custom = custom.to_vec();
+            assert!(MirrorDht::new_with_config(&config).is_err());
+        }
+        // Overrides apply to DhtConfig values
+        let mirror_config =
+            MirrorConfig::from_custom(br#"{"version": 1, "timeout_threshold_ms": 42}"#).unwrap();
+        assert_eq!(mirror_config.apply(&config).timeout_threshold, 42);
+        assert_eq!(
+            mirror_config.apply(&config).gossip_interval,
+            config.gossip_interval
+        );
+        // Entries are gossiped to one peer at a time, in bundles of one aspect
+        config.custom = br#"{"version": 1, "gossip_fanout": 1, "max_bundle_size": 200}"#.to_vec();
+        let mut dht = MirrorDht::new_with_config(&config).unwrap();
+        dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_C)))
+            .unwrap();
+        dht.process().unwrap();
+        let mut entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &[1; 100]);
+        entry_data
+            .aspect_list
+            .extend(create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_2, &[2; 100]).aspect_list);
+        let mut gossiped_to = Vec::new();
+        for entry_data in &[
+            entry_data,
+            create_EntryData(&ENTRY_ADDRESS_2, &ASPECT_ADDRESS_3, &ASPECT_CONTENT_3),
+        ] {
+            dht.post(DhtCommand::BroadcastEntry(entry_data.clone()))
+                .unwrap();
+            let (_, event_list) = dht.process().unwrap();
+            let gossip_list: Vec<GossipToData> = event_list
+                .into_iter()
+                .filter_map(|event| match event {
+                    DhtEvent::GossipTo(gossip_to) => Some(gossip_to),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(gossip_list.len(), entry_data.aspect_list.len());
+            for gossip_to in gossip_list {
+                assert!(gossip_to.bundle.len() <= 200);
+                assert_eq!(gossip_to.peer_address_list.len(), 1);
+                gossiped_to.push(gossip_to.peer_address_list[0].clone());
+            }
+        }
+        gossiped_to.sort();
+        gossiped_to.dedup();
+        assert_eq!(gossiped_to, vec![PEER_B.to_owned(), PEER_C.to_owned()]);
+        // Bundles are filled up to the max, however many aspects they hold
+        config.custom = br#"{"version": 1, "max_bundle_size": 2000}"#.to_vec();
+        let mut many_dht = MirrorDht::new_with_config(&config).unwrap();
+        many_dht
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        many_dht.process().unwrap();
+        let mut entry_data = create_EntryData(&ENTRY_ADDRESS_3, &ASPECT_ADDRESS_1, &[0; 10]);
+        for i in 1..100 {
+            let aspect_address: Address = format!("aspect_{}", i).into();
+            entry_data
+                .aspect_list
+                .extend(create_EntryData(&ENTRY_ADDRESS_3, &aspect_address, &[i; 10]).aspect_list);
+        }
+        many_dht
+            .post(DhtCommand::BroadcastEntry(entry_data))
+            .unwrap();
+        let (_, event_list) = many_dht.process().unwrap();
+        let size_list: Vec<usize> = event_list
+            .iter()
+            .map(|event| unwrap_to!(event => DhtEvent::GossipTo).bundle.len())
+            .collect();
+        assert!(size_list.len() > 1);
+        assert!(size_list.iter().all(|size| *size <= 2000));
+        // Only the last bundle has room left for another aspect
+        assert!(size_list[..size_list.len() - 1]
+            .iter()
+            .all(|size| *size > 1900));
+        // Aspects too big to ever be gossiped are refused
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_3, &ASPECT_ADDRESS_3, &[3; 300]);
+        assert!(dht
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .is_err());
+        assert!(dht
+            .post(DhtCommand::HoldEntryAspectAddress(entry_data))
+            .is_err());
+        dht.process().unwrap();
+        assert_eq!(dht.get_aspects_of(&ENTRY_ADDRESS_3), None);
+        // Entries wait for the entry gossip interval, anti-entropy keeps its own
+        config.custom = br#"{"version": 1, "entry_gossip_interval_ms": 20}"#.to_vec();
+        assert_eq!(
+            MirrorConfig::from_custom(&config.custom)
+                .unwrap()
+                .apply(&config)
+                .anti_entropy_interval,
+            config.anti_entropy_interval
+        );
+        // Gossip of self is a second away, any GossipTo is of the entry
+        let is_entry_gossip = |event: &DhtEvent| match event {
+            DhtEvent::GossipTo(_) => true,
+            _ => false,
+        };
+        let mut dht = MirrorDht::new_with_config(&config).unwrap();
+        dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht.process().unwrap();
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        dht.post(DhtCommand::BroadcastEntry(entry_data)).unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert!(event_list.iter().all(|event| !is_entry_gossip(event)));
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        let (_, event_list) = dht.process().unwrap();
+        assert_eq!(
+            event_list
+                .iter()
+                .filter(|event| is_entry_gossip(event))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_mirror_storage_budget() {
+        enable_logging_for_test(true);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.storage_budget = Some(StorageBudgetData {
+            max_aspect_count: Some(2),
+            max_byte_count: None,
+            prune_policy: PrunePolicy::LeastRecentlyUsed,
+        });
+        let mut dht = MirrorDht::new_with_config(&config).unwrap();
+        let hold = |dht: &mut MirrorDht, entry_address: &Address| {
+            let entry_data = create_EntryData(entry_address, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+            dht.post(DhtCommand::HoldEntryAspectAddress(entry_data))
+                .unwrap();
+            let (_, event_list) = dht.process().unwrap();
+            event_list
+        };
+        hold(&mut dht, &ENTRY_ADDRESS_1);
+        hold(&mut dht, &ENTRY_ADDRESS_2);
+        // Requesting entry 1 makes entry 2 the least recently used
+        dht.post(DhtCommand::FetchEntry(create_FetchEntry(&ENTRY_ADDRESS_1)))
+            .unwrap();
+        dht.process().unwrap();
+        let event_list = hold(&mut dht, &ENTRY_ADDRESS_3);
+        assert!(event_list.contains(&DhtEvent::EntryPruned(ENTRY_ADDRESS_2.clone())));
+        let mut entry_list = dht.get_entry_address_list();
+        entry_list.sort_by_key(|entry_address| entry_address.to_string());
+        assert_eq!(entry_list, vec![&*ENTRY_ADDRESS_1, &*ENTRY_ADDRESS_3]);
+        // Entry 2 gossiped back by a peer is not held again
+        let mut dht_b = new_mirror_dht(PEER_B, 0);
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        dht_b.process().unwrap();
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_2, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        dht_b.post(DhtCommand::BroadcastEntry(entry_data)).unwrap();
+        process_and_route(&mut *dht_b, &mut dht, &[]);
+        let (_, event_list) = dht.process().unwrap();
+        assert!(!event_list.iter().any(|event| match event {
+            DhtEvent::HoldEntryRequested(_, _) => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_mirror_storage_restore() {
+        enable_logging_for_test(true);
+        let storage_path = std::env::temp_dir().join(format!(
+            "lib3h_test_mirror_storage_restore_{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&storage_path);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.storage_path = Some(storage_path.clone());
+        // Fill a DHT and drop it
+        {
+            let mut dht = MirrorDht::new_with_config(&config).unwrap();
+            dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+                .unwrap();
+            let entry_data =
+                create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+            dht.post(DhtCommand::HoldEntryAspectAddress(entry_data))
+                .unwrap();
+            let (did_work, _) = dht.process().unwrap();
+            assert!(did_work);
+        }
+        // A new DHT with the same config gets it all back
+        let mut dht = MirrorDht::new_with_config(&config).unwrap();
+        assert_eq!(dht.get_peer(PEER_B).unwrap().peer_address, PEER_B);
+        assert_eq!(
+            dht.get_aspects_of(&ENTRY_ADDRESS_1),
+            Some(vec![ASPECT_ADDRESS_1.clone()])
+        );
+        // Restored entries are held like new ones: gossip rounds offer them
+        let held_list = vec![create_EntryData(
+            &ENTRY_ADDRESS_1,
+            &ASPECT_ADDRESS_1,
+            &ASPECT_CONTENT_1,
+        )];
+        let mut dht_b = new_mirror_dht(PEER_B, 0);
+        dht_b
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        dht_b.process().unwrap();
+        dht_b
+            .post(DhtCommand::OpenGossipRound(
+                PEER_A.to_owned(),
+                GspConstraints::default(),
+            ))
+            .unwrap();
+        for _ in 0..10 {
+            process_and_route(&mut *dht_b, &mut dht, &[]);
+            process_and_route(&mut dht, &mut *dht_b, &held_list);
+        }
+        assert_eq!(
+            dht_b.get_aspects_of(&ENTRY_ADDRESS_1),
+            Some(vec![ASPECT_ADDRESS_1.clone()])
+        );
+        drop(dht);
+        // Another peer does not restore it
+        config.this_peer_address = PEER_C.to_owned();
+        let dht = MirrorDht::new_with_config(&config).unwrap();
+        assert!(dht.get_peer(PEER_B).is_none());
+        drop(dht);
+        std::fs::remove_file(&storage_path).unwrap();
+    }
+
+    #[test]
+    fn test_mirror_drop_entry_address() {
+        enable_logging_for_test(true);
+        let mut dht = new_mirror_dht(PEER_A, 0);
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        dht.post(DhtCommand::HoldEntryAspectAddress(entry_data.clone()))
+            .unwrap();
+        dht.process().unwrap();
+        assert_eq!(dht.get_entry_address_list(), vec![&*ENTRY_ADDRESS_1]);
+        dht.post(DhtCommand::DropEntryAddress(ENTRY_ADDRESS_1.clone()))
+            .unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert!(event_list.is_empty());
+        assert!(dht.get_entry_address_list().is_empty());
+        assert_eq!(dht.get_aspects_of(&ENTRY_ADDRESS_1), None);
+        // Entry is fully forgotten: holding it again is like holding a new one
+        dht.post(DhtCommand::HoldEntryAspectAddress(entry_data))
+            .unwrap();
+        let (_, event_list) = dht.process().unwrap();
+        assert_eq!(
+            event_list,
+            vec![DhtEvent::EntryDataRequested(FetchDhtEntryData {
+                msg_id: ENTRY_ADDRESS_1.to_string(),
+                entry_address: ENTRY_ADDRESS_1.clone(),
+            })]
+        );
+    }
+
+    // RrDht does not run the conformance suite: it is a stub that holds nothing
+    // and has no `DhtFactory`, see TODO #167.
+    #[test]
+    fn test_mirror_conformance() {
+        enable_logging_for_test(true);
+        conformance::run_all(MirrorDht::new_with_config);
+    }
+
+    fn new_kademlia_dht(peer_address: &PeerAddressRef) -> Box<dyn Dht> {
+        let config = DhtConfig::new(peer_address, &create_test_uri(peer_address));
+        Box::new(KademliaDht::new_with_config(&config).unwrap())
+    }
+
+    /// Process every DHT and route its events like its owner would:
+    /// gossip goes to its recipients in the list, hold requests are accepted,
+    /// fetches are answered from the DHT's held list.
+    /// Return the fetch responses.
+    fn process_and_route_all(
+        dht_list: &mut [Box<dyn Dht>],
+        held_list: &[Vec<EntryData>],
+    ) -> Vec<FetchDhtEntryResponseData> {
+        let mut response_list = Vec::new();
+        for index in 0..dht_list.len() {
+            let from_peer_address = dht_list[index].this_peer().peer_address.clone();
+            let (_, event_list) = dht_list[index].process().unwrap();
+            for event in event_list {
+                match event {
+                    DhtEvent::GossipTo(gossip_to) => {
+                        for to_peer_address in gossip_to.peer_address_list.iter() {
+                            let maybe_to = dht_list
+                                .iter_mut()
+                                .find(|dht| &dht.this_peer().peer_address == to_peer_address);
+                            if let Some(to) = maybe_to {
+                                to.post(DhtCommand::HandleGossip(RemoteGossipBundleData {
+                                    from_peer_address: from_peer_address.clone(),
+                                    bundle: gossip_to.bundle.clone(),
+                                }))
+                                .unwrap();
+                            }
+                        }
+                    }
+                    DhtEvent::HoldPeerRequested(peer_data) => {
+                        dht_list[index]
+                            .post(DhtCommand::HoldPeer(peer_data))
+                            .unwrap();
+                    }
+                    DhtEvent::HoldEntryRequested(_, entry) => {
+                        dht_list[index]
+                            .post(DhtCommand::HoldEntryAspectAddress(entry))
+                            .unwrap();
+                    }
+                    DhtEvent::EntryDataRequested(fetch) => {
+                        let maybe_entry = held_list[index]
+                            .iter()
+                            .find(|entry| entry.entry_address == fetch.entry_address);
+                        if let Some(entry) = maybe_entry {
+                            dht_list[index]
+                                .post(DhtCommand::EntryDataResponse(FetchDhtEntryResponseData {
+                                    msg_id: fetch.msg_id,
+                                    entry: entry.clone(),
+                                }))
+                                .unwrap();
+                        }
+                    }
+                    DhtEvent::FetchEntryResponse(response) => response_list.push(response),
+                    _ => (),
+                }
+            }
+        }
+        response_list
+    }
+
+    #[test]
+    fn test_kademlia_lookup() {
+        enable_logging_for_test(true);
+        let mut dht_list = vec![
+            new_kademlia_dht(PEER_A),
+            new_kademlia_dht(PEER_B),
+            new_kademlia_dht(PEER_C),
+        ];
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        let held_list = vec![vec![], vec![], vec![entry_data.clone()]];
+        // C holds the entry. A only knows B, and B knows both.
+        dht_list[2]
+            .post(DhtCommand::BroadcastEntry(entry_data.clone()))
+            .unwrap();
+        dht_list[0]
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht_list[1]
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        dht_list[1]
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_C)))
+            .unwrap();
+        // Keep C offline meanwhile, so it does not introduce itself to A
+        for _ in 0..3 {
+            process_and_route_all(&mut dht_list[..2], &held_list[..2]);
+        }
+        assert!(dht_list[0].get_peer(PEER_C).is_none());
+        // A finds the entry through B
+        let fetch = create_FetchEntry(&ENTRY_ADDRESS_1);
+        dht_list[0]
+            .post(DhtCommand::FetchEntry(fetch.clone()))
+            .unwrap();
+        let mut response_list = Vec::new();
+        for _ in 0..10 {
+            response_list.append(&mut process_and_route_all(&mut dht_list, &held_list));
+        }
+        assert_eq!(
+            response_list,
+            vec![FetchDhtEntryResponseData {
+                msg_id: fetch.msg_id,
+                entry: entry_data,
+            }]
+        );
+        assert!(dht_list[0].get_peer(PEER_C).is_some());
+        // Looking up an entry nobody holds ends with an empty response
+        let fetch = create_FetchEntry(&ENTRY_ADDRESS_2);
+        dht_list[0]
+            .post(DhtCommand::FetchEntry(fetch.clone()))
+            .unwrap();
+        let mut response_list = Vec::new();
+        for _ in 0..10 {
+            response_list.append(&mut process_and_route_all(&mut dht_list, &held_list));
+        }
+        assert_eq!(response_list.len(), 1);
+        assert_eq!(response_list[0].msg_id, fetch.msg_id);
+        assert!(response_list[0].entry.aspect_list.is_empty());
+    }
+
+    #[test]
+    fn test_kademlia_store_at_closest() {
+        enable_logging_for_test(true);
+        let mut dht_list = vec![
+            new_kademlia_dht(PEER_A),
+            new_kademlia_dht(PEER_B),
+            new_kademlia_dht(PEER_C),
+        ];
+        let held_list: Vec<Vec<EntryData>> = vec![vec![]; 3];
+        for (index, peer_address) in [PEER_A, PEER_B, PEER_C].iter().enumerate() {
+            for other in [PEER_A, PEER_B, PEER_C].iter() {
+                if other != peer_address {
+                    dht_list[index]
+                        .post(DhtCommand::HoldPeer(create_PeerData(other)))
+                        .unwrap();
+                }
+            }
+        }
+        process_and_route_all(&mut dht_list, &held_list);
+        // With fewer peers than k, every peer is among the closest
+        let entry_data = create_EntryData(&ENTRY_ADDRESS_3, &ASPECT_ADDRESS_3, &ASPECT_CONTENT_3);
+        dht_list[0]
+            .post(DhtCommand::BroadcastEntry(entry_data))
+            .unwrap();
+        for _ in 0..3 {
+            process_and_route_all(&mut dht_list, &held_list);
+        }
+        for dht in dht_list.iter() {
+            assert_eq!(
+                dht.get_aspects_of(&ENTRY_ADDRESS_3),
+                Some(vec![ASPECT_ADDRESS_3.clone()])
+            );
+        }
+    }
+
+    #[test]
+    fn test_kademlia_gossip_round_since() {
+        enable_logging_for_test(true);
+        let mut dht_list = vec![new_kademlia_dht(PEER_A), new_kademlia_dht(PEER_B)];
+        // DHT A holds an old and a new entry before knowing about B
+        let since = crate::time::since_epoch_ms();
+        let mut old_entry =
+            create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+        old_entry.aspect_list[0].publish_ts = since - 1;
+        let new_entry = create_EntryData(&ENTRY_ADDRESS_2, &ASPECT_ADDRESS_2, &ASPECT_CONTENT_2);
+        let held_list = vec![vec![old_entry.clone(), new_entry.clone()], vec![]];
+        for entry in held_list[0].iter() {
+            dht_list[0]
+                .post(DhtCommand::BroadcastEntry(entry.clone()))
+                .unwrap();
+        }
+        dht_list[0]
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+            .unwrap();
+        dht_list[1]
+            .post(DhtCommand::HoldPeer(create_PeerData(PEER_A)))
+            .unwrap();
+        process_and_route_all(&mut dht_list, &held_list);
+        // B syncs everything published since `since`
+        dht_list[1]
+            .post(DhtCommand::OpenGossipRound(
+                PEER_A.to_owned(),
+                GspConstraints::since(since),
+            ))
+            .unwrap();
+        for _ in 0..10 {
+            process_and_route_all(&mut dht_list, &held_list);
+        }
+        // DHT B should only have the new entry
+        assert_eq!(
+            dht_list[1].get_entry_address_list(),
+            vec![&*ENTRY_ADDRESS_2]
+        );
+        assert_eq!(
+            dht_list[1].get_aspects_of(&ENTRY_ADDRESS_2),
+            Some(vec![ASPECT_ADDRESS_2.clone()])
+        );
+        // Rounds can only be opened with known peers
+        dht_list[1]
+            .post(DhtCommand::OpenGossipRound(
+                PEER_C.to_owned(),
+                GspConstraints::default(),
+            ))
+            .unwrap();
+        let (_, event_list) = dht_list[1].process().unwrap();
+        assert!(!event_list.iter().any(|event| match event {
+            DhtEvent::GossipTo(gossip_to) => gossip_to.peer_address_list == vec![PEER_C],
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_kademlia_storage_restore() {
+        enable_logging_for_test(true);
+        let storage_path = std::env::temp_dir().join(format!(
+            "lib3h_test_kademlia_storage_restore_{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&storage_path);
+        let mut config = DhtConfig::new(PEER_A, &create_test_uri(PEER_A));
+        config.storage_path = Some(storage_path.clone());
+        // Fill a DHT and drop it
+        {
+            let mut dht = KademliaDht::new_with_config(&config).unwrap();
+            dht.post(DhtCommand::HoldPeer(create_PeerData(PEER_B)))
+                .unwrap();
+            let entry_data =
+                create_EntryData(&ENTRY_ADDRESS_1, &ASPECT_ADDRESS_1, &ASPECT_CONTENT_1);
+            dht.post(DhtCommand::HoldEntryAspectAddress(entry_data))
+                .unwrap();
+            let (did_work, _) = dht.process().unwrap();
+            assert!(did_work);
+        }
+        // A new DHT with the same config gets it all back
+        let dht = KademliaDht::new_with_config(&config).unwrap();
+        assert_eq!(dht.get_peer(PEER_B).unwrap().peer_address, PEER_B);
+        assert_eq!(
+            dht.get_aspects_of(&ENTRY_ADDRESS_1),
+            Some(vec![ASPECT_ADDRESS_1.clone()])
+        );
+        drop(dht);
+        std::fs::remove_file(&storage_path).unwrap();
+    }
+
+    #[test]
+    fn test_kademlia_conformance() {
+        enable_logging_for_test(true);
+        conformance::run_all(KademliaDht::new_with_config);
+    }
 }
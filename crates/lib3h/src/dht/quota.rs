@@ -0,0 +1,199 @@
+//! Storage budget of held entries.
+//!
+//! Tracks how many aspects and content bytes each held entry accounts for,
+//! when it started being held and when it was last requested.
+//! "When" is a tick counted by the quota itself rather than wall-clock time,
+//! so entries held or requested in the same millisecond are still ordered.
+//! Once the budget is exceeded, entries are picked for pruning according to the
+//! `PrunePolicy`, until what is left fits in the budget.
+
+use crate::dht::{
+    location::{entry_location, peer_location, ring_distance, Location},
+    PeerAddressRef,
+};
+use lib3h_protocol::{
+    data_types::{EntryAspectData, PrunePolicy, StorageBudgetData},
+    Address,
+};
+use std::collections::HashMap;
+
+/// What a held entry accounts for
+#[derive(Debug, Clone, PartialEq)]
+struct EntryUsage {
+    aspect_count: u64,
+    byte_count: u64,
+    /// Ticks of the first hold and of the last request
+    held_since: u64,
+    last_access: u64,
+}
+
+/// Usage of held entries against a budget
+#[derive(Debug)]
+pub struct StorageQuota {
+    budget: StorageBudgetData,
+    this_location: Location,
+    usage_map: HashMap<Address, EntryUsage>,
+    aspect_count: u64,
+    byte_count: u64,
+    /// Incremented on every hold or request
+    tick: u64,
+}
+
+impl StorageQuota {
+    pub fn new(budget: &StorageBudgetData, this_peer_address: &PeerAddressRef) -> Self {
+        StorageQuota {
+            budget: budget.clone(),
+            this_location: peer_location(this_peer_address),
+            usage_map: HashMap::new(),
+            aspect_count: 0,
+            byte_count: 0,
+            tick: 0,
+        }
+    }
+
+    /// Bookkeep newly held aspects of an entry
+    pub fn add(&mut self, entry_address: &Address, new_aspect_list: &[&EntryAspectData]) {
+        let aspect_count = new_aspect_list.len() as u64;
+        let byte_count: u64 = new_aspect_list
+            .iter()
+            .map(|aspect| aspect.aspect.len() as u64)
+            .sum();
+        self.tick += 1;
+        let now = self.tick;
+        let usage = self
+            .usage_map
+            .entry(entry_address.clone())
+            .or_insert(EntryUsage {
+                aspect_count: 0,
+                byte_count: 0,
+                held_since: now,
+                last_access: now,
+            });
+        usage.aspect_count += aspect_count;
+        usage.byte_count += byte_count;
+        self.aspect_count += aspect_count;
+        self.byte_count += byte_count;
+    }
+
+    /// Bookkeep that an entry was requested
+    pub fn touch(&mut self, entry_address: &Address) {
+        self.tick += 1;
+        if let Some(usage) = self.usage_map.get_mut(entry_address) {
+            usage.last_access = self.tick;
+        }
+    }
+
+    pub fn remove(&mut self, entry_address: &Address) {
+        if let Some(usage) = self.usage_map.remove(entry_address) {
+            self.aspect_count -= usage.aspect_count;
+            self.byte_count -= usage.byte_count;
+        }
+    }
+
+    fn is_exceeded(&self, aspect_count: u64, byte_count: u64) -> bool {
+        self.budget
+            .max_aspect_count
+            .map_or(false, |max| aspect_count > max)
+            || self
+                .budget
+                .max_byte_count
+                .map_or(false, |max| byte_count > max)
+    }
+
+    /// Entries to let go to get back within budget, first to go first.
+    /// They are not removed: caller is expected to `remove()` them.
+    pub fn select_prune(&self) -> Vec<Address> {
+        if !self.is_exceeded(self.aspect_count, self.byte_count) {
+            return vec![];
+        }
+        let mut candidate_list: Vec<(&Address, &EntryUsage)> = self.usage_map.iter().collect();
+        // Sort by entry address first, so ties are broken the same way every time
+        candidate_list.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+        match self.budget.prune_policy {
+            PrunePolicy::Oldest => candidate_list.sort_by_key(|(_, usage)| usage.held_since),
+            PrunePolicy::LeastRecentlyUsed => {
+                candidate_list.sort_by_key(|(_, usage)| usage.last_access)
+            }
+            PrunePolicy::Farthest => candidate_list.sort_by_key(|(entry_address, _)| {
+                std::cmp::Reverse(ring_distance(
+                    entry_location(entry_address),
+                    self.this_location,
+                ))
+            }),
+        }
+        let mut aspect_count = self.aspect_count;
+        let mut byte_count = self.byte_count;
+        let mut prune_list = Vec::new();
+        for (entry_address, usage) in candidate_list {
+            if !self.is_exceeded(aspect_count, byte_count) {
+                break;
+            }
+            aspect_count -= usage.aspect_count;
+            byte_count -= usage.byte_count;
+            prune_list.push(entry_address.clone());
+        }
+        prune_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aspect(content_len: usize) -> EntryAspectData {
+        EntryAspectData {
+            aspect_address: "aspect".into(),
+            type_hint: "quota_test".to_string(),
+            aspect: vec![0; content_len],
+            publish_ts: 0,
+        }
+    }
+
+    fn budget(
+        max_aspect_count: Option<u64>,
+        max_byte_count: Option<u64>,
+        prune_policy: PrunePolicy,
+    ) -> StorageBudgetData {
+        StorageBudgetData {
+            max_aspect_count,
+            max_byte_count,
+            prune_policy,
+        }
+    }
+
+    #[test]
+    fn test_select_prune() {
+        let entry_1: Address = "entry_1".into();
+        let entry_2: Address = "entry_2".into();
+        let entry_3: Address = "entry_3".into();
+        let fill = |quota: &mut StorageQuota| {
+            quota.add(&entry_1, &[&aspect(10)]);
+            quota.add(&entry_2, &[&aspect(10), &aspect(10)]);
+            quota.add(&entry_3, &[&aspect(10)]);
+        };
+        // Within budget
+        let mut quota = StorageQuota::new(&budget(Some(4), None, PrunePolicy::Oldest), "entry_1");
+        fill(&mut quota);
+        assert!(quota.select_prune().is_empty());
+        // Oldest goes first
+        let mut quota = StorageQuota::new(&budget(Some(3), None, PrunePolicy::Oldest), "entry_1");
+        fill(&mut quota);
+        assert_eq!(quota.select_prune(), vec![entry_1.clone()]);
+        // Least recently used goes first
+        let mut quota = StorageQuota::new(
+            &budget(None, Some(20), PrunePolicy::LeastRecentlyUsed),
+            "entry_1",
+        );
+        fill(&mut quota);
+        quota.touch(&entry_1);
+        assert_eq!(quota.select_prune(), vec![entry_2.clone()]);
+        quota.remove(&entry_2);
+        assert!(quota.select_prune().is_empty());
+        // Farthest goes first, never the entry at our own location
+        let mut quota = StorageQuota::new(&budget(Some(1), None, PrunePolicy::Farthest), "entry_1");
+        fill(&mut quota);
+        let prune_list = quota.select_prune();
+        assert_eq!(prune_list.len(), 2);
+        assert!(!prune_list.contains(&entry_1));
+    }
+}
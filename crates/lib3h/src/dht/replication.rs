@@ -0,0 +1,170 @@
+//! Replication-factor maintenance.
+//!
+//! Tracks which peers hold each entry, as learned from gossip.
+//! When a holder drops out and an entry falls below the target replication factor,
+//! new holders are picked among live peers by closeness to the entry's location,
+//! so every remaining holder agrees on them without coordination.
+//! Only the live holder closest to the entry re-publishes, to avoid duplicate pushes.
+
+use crate::dht::{
+    location::{closest_peer_list, entry_location, peer_location, Location},
+    PeerAddress, PeerAddressRef,
+};
+use lib3h_protocol::Address;
+use std::collections::{HashMap, HashSet};
+
+/// Peers paired with their location, as `closest_peer_list()` takes them
+fn with_location<'a>(
+    peer_address_list: impl Iterator<Item = &'a PeerAddress>,
+) -> Vec<(PeerAddress, Location)> {
+    peer_address_list
+        .map(|peer_address| (peer_address.clone(), peer_location(peer_address)))
+        .collect()
+}
+
+/// Entry to re-publish to new holders
+#[derive(Debug, Clone, PartialEq)]
+pub struct Republish {
+    pub entry_address: Address,
+    pub peer_address_list: Vec<PeerAddress>,
+}
+
+/// Known holders of entries
+#[derive(Debug, Default)]
+pub struct HolderMap {
+    holder_map: HashMap<Address, HashSet<PeerAddress>>,
+}
+
+impl HolderMap {
+    pub fn new() -> Self {
+        HolderMap::default()
+    }
+
+    /// Bookkeep that a peer holds an entry
+    pub fn add_holder(&mut self, entry_address: &Address, peer_address: &PeerAddressRef) {
+        self.holder_map
+            .entry(entry_address.clone())
+            .or_insert_with(HashSet::new)
+            .insert(peer_address.to_owned());
+    }
+
+    pub fn holder_count(&self, entry_address: &Address) -> usize {
+        self.holder_map
+            .get(entry_address)
+            .map(|holder_set| holder_set.len())
+            .unwrap_or(0)
+    }
+
+    pub fn drop_entry(&mut self, entry_address: &Address) {
+        self.holder_map.remove(entry_address);
+    }
+
+    /// A peer dropped out: forget what it held, and return the entries this peer
+    /// must re-publish to get back to `replication_factor` holders.
+    /// `live_peer_list` holds the peers we can still reach, excluding this peer.
+    pub fn drop_holder(
+        &mut self,
+        lost_peer_address: &PeerAddressRef,
+        this_peer_address: &PeerAddressRef,
+        live_peer_list: &[PeerAddress],
+        replication_factor: usize,
+    ) -> Vec<Republish> {
+        let mut republish_list = Vec::new();
+        for (entry_address, holder_set) in self.holder_map.iter_mut() {
+            if !holder_set.remove(lost_peer_address) {
+                continue;
+            }
+            // Only entries we hold can be re-published by us
+            if !holder_set.contains(this_peer_address) {
+                continue;
+            }
+            let live_holder_list =
+                with_location(holder_set.iter().filter(|holder| {
+                    *holder == this_peer_address || live_peer_list.contains(*holder)
+                }));
+            if live_holder_list.len() >= replication_factor {
+                continue;
+            }
+            // Let the live holder closest to the entry do it
+            let location = entry_location(entry_address);
+            if closest_peer_list(location, &live_holder_list, 1)[0] != this_peer_address {
+                continue;
+            }
+            let candidate_list = with_location(
+                live_peer_list
+                    .iter()
+                    .filter(|peer_address| !holder_set.contains(*peer_address)),
+            );
+            let peer_address_list = closest_peer_list(
+                location,
+                &candidate_list,
+                replication_factor - live_holder_list.len(),
+            );
+            if peer_address_list.is_empty() {
+                warn!(
+                    "No live peer left to replicate {} to ({} holders)",
+                    entry_address,
+                    live_holder_list.len()
+                );
+                continue;
+            }
+            republish_list.push(Republish {
+                entry_address: entry_address.clone(),
+                peer_address_list,
+            });
+        }
+        // New holders are expected to hold it once pushed
+        for republish in republish_list.iter() {
+            for peer_address in republish.peer_address_list.iter() {
+                self.add_holder(&republish.entry_address, peer_address);
+            }
+        }
+        republish_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer_list(list: &[&str]) -> Vec<PeerAddress> {
+        list.iter().map(|peer| peer.to_string()).collect()
+    }
+
+    #[test]
+    fn test_drop_holder() {
+        let entry_address: Address = "entry_1".into();
+        let all_peer_list = peer_list(&["a", "b", "c", "d", "e"]);
+        // a, b and c hold the entry
+        let mut holder_map_list: Vec<HolderMap> = (0..3).map(|_| HolderMap::new()).collect();
+        for holder_map in holder_map_list.iter_mut() {
+            for peer in &["a", "b", "c"] {
+                holder_map.add_holder(&entry_address, peer);
+            }
+            assert_eq!(holder_map.holder_count(&entry_address), 3);
+        }
+        // c drops out: exactly one of a and b re-publishes, to one new holder
+        let mut republish_list = Vec::new();
+        for (index, this_peer) in ["a", "b"].iter().enumerate() {
+            let live_peer_list: Vec<PeerAddress> = all_peer_list
+                .iter()
+                .filter(|peer| peer.as_str() != *this_peer && peer.as_str() != "c")
+                .cloned()
+                .collect();
+            republish_list.extend(holder_map_list[index].drop_holder(
+                "c",
+                this_peer,
+                &live_peer_list,
+                3,
+            ));
+        }
+        assert_eq!(republish_list.len(), 1);
+        assert_eq!(republish_list[0].entry_address, entry_address);
+        assert_eq!(republish_list[0].peer_address_list.len(), 1);
+        let new_holder = &republish_list[0].peer_address_list[0];
+        assert!(new_holder == "d" || new_holder == "e");
+        // Nothing to do when enough holders are left
+        let republish_list = holder_map_list[2].drop_holder("c", "a", &peer_list(&["b"]), 2);
+        assert!(republish_list.is_empty());
+    }
+}
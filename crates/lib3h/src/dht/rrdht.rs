@@ -8,6 +8,7 @@ use url::Url;
 
 /// RedRibbon DHT implementation
 /// TODO #167
+/// Stub that holds nothing, so it does not pass `dht::conformance` yet.
 pub struct RrDht {
     /// FIFO of DhtCommands send to us
     inbox: VecDeque<DhtCommand>,
@@ -80,4 +81,17 @@ impl Dht for RrDht {
         }
         Ok((did_work, outbox))
     }
+
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if self.inbox.is_empty() {
+            return None;
+        }
+        Some(crate::time::since_epoch_ms())
+    }
+
+    fn dht_snapshot(&self) -> DhtSnapshot {
+        let mut snapshot = DhtSnapshot::from_dht(self);
+        snapshot.inbox_depth = self.inbox.len();
+        snapshot
+    }
 }
@@ -0,0 +1,173 @@
+//! On-disk persistence of DHT state.
+//!
+//! Peer and entry-address tables are saved as JSON tagged with a format version,
+//! so a restarted node gets them back at construction instead of starting empty.
+//! Files are written to a temporary path first and renamed over the previous one,
+//! so a crash while saving leaves the previous state intact.
+
+use crate::{
+    dht::{dht_protocol::PeerData, PeerAddress},
+    error::{ErrorKind, Lib3hError, Lib3hResult},
+};
+use lib3h_protocol::{
+    data_types::{EntryAspectData, EntryData},
+    Address,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Version of the on-disk format written by this code
+pub const DHT_STORAGE_VERSION: u32 = 1;
+
+/// Aspect addresses held for an entry
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StoredEntry {
+    pub entry_address: Address,
+    pub aspect_address_list: Vec<Address>,
+}
+
+impl StoredEntry {
+    /// Entry to hold again on restore. Content and publish time are not saved:
+    /// aspects are empty and considered published at `publish_ts`.
+    pub fn to_entry_data(&self, publish_ts: u64) -> EntryData {
+        EntryData {
+            entry_address: self.entry_address.clone(),
+            aspect_list: self
+                .aspect_address_list
+                .iter()
+                .map(|aspect_address| EntryAspectData {
+                    aspect_address: aspect_address.clone(),
+                    type_hint: String::new(),
+                    aspect: vec![],
+                    publish_ts,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// DHT state as saved on disk
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StoredDhtState {
+    pub version: u32,
+    /// Peer the state was saved by. A file saved by another peer is not restored.
+    pub this_peer_address: PeerAddress,
+    pub peer_list: Vec<PeerData>,
+    pub timed_out_peer_list: Vec<PeerAddress>,
+    pub entry_list: Vec<StoredEntry>,
+}
+
+impl StoredDhtState {
+    pub fn new(this_peer_address: &str) -> Self {
+        StoredDhtState {
+            version: DHT_STORAGE_VERSION,
+            this_peer_address: this_peer_address.to_owned(),
+            peer_list: Vec::new(),
+            timed_out_peer_list: Vec::new(),
+            entry_list: Vec::new(),
+        }
+    }
+}
+
+/// Only the version, to check it before parsing the rest
+#[derive(Deserialize)]
+struct StoredVersion {
+    version: u32,
+}
+
+/// File backend for DHT state
+#[derive(Debug, Clone)]
+pub struct DhtStorage {
+    path: PathBuf,
+}
+
+impl DhtStorage {
+    pub fn new(path: &Path) -> Self {
+        DhtStorage {
+            path: path.to_owned(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read saved state. Return None if nothing was saved yet.
+    pub fn load(&self) -> Lib3hResult<Option<StoredDhtState>> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let stored_version: StoredVersion = serde_json::from_slice(&content)?;
+        if stored_version.version != DHT_STORAGE_VERSION {
+            return Err(Lib3hError::new(ErrorKind::Other(format!(
+                "Unsupported DHT storage version {} in {}, expected {}",
+                stored_version.version,
+                self.path.display(),
+                DHT_STORAGE_VERSION,
+            ))));
+        }
+        let state: StoredDhtState = serde_json::from_slice(&content)?;
+        Ok(Some(state))
+    }
+
+    /// Write state, replacing the previously saved one
+    pub fn save(&self, state: &StoredDhtState) -> Lib3hResult<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_vec(state)?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn test_storage(name: &str) -> DhtStorage {
+        let path = std::env::temp_dir().join(format!(
+            "lib3h_dht_storage_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        DhtStorage::new(&path)
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let storage = test_storage("save_and_load");
+        assert_eq!(storage.load().unwrap(), None);
+        let mut state = StoredDhtState::new("alex");
+        state.peer_list.push(PeerData {
+            peer_address: "billy".to_owned(),
+            peer_uri: Url::parse("test://billy").unwrap(),
+            timestamp: 42,
+        });
+        state.timed_out_peer_list.push("billy".to_owned());
+        state.entry_list.push(StoredEntry {
+            entry_address: "entry_1".into(),
+            aspect_address_list: vec!["aspect_1".into()],
+        });
+        storage.save(&state).unwrap();
+        assert_eq!(storage.load().unwrap(), Some(state));
+        fs::remove_file(storage.path()).unwrap();
+    }
+
+    #[test]
+    fn test_load_unsupported_version() {
+        let storage = test_storage("unsupported_version");
+        fs::write(storage.path(), br#"{"version": 999}"#).unwrap();
+        assert!(storage.load().is_err());
+        fs::remove_file(storage.path()).unwrap();
+    }
+}
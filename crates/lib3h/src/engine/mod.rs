@@ -3,18 +3,20 @@ pub mod p2p_protocol;
 pub mod real_engine;
 mod space_layer;
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 use crate::{
-    dht::dht_trait::{Dht, DhtFactory},
-    gateway::P2pGateway,
-    transport::{transport_trait::Transport, ConnectionId},
-    transport_wss::TlsConfig,
+    dht::dht_trait::{
+        default_anti_entropy_interval, default_eviction_grace_period, default_replication_factor,
+        Dht, DhtFactory,
+    },
+    gateway::{GatewaySnapshot, P2pGateway, TransportHandle},
+    transport::{protocol::TransportSnapshot, transport_trait::Transport, ConnectionId},
+    transport_wss::{trust::TlsTrustPolicy, TlsConfig},
 };
 
 use lib3h_crypto_api::{Buffer, CryptoSystem};
 use lib3h_protocol::{protocol_client::Lib3hClientProtocol, Address};
-use std::{cell::RefCell, rc::Rc};
 use url::Url;
 
 /// Identifier of a source chain: SpaceAddress+AgentId
@@ -26,15 +28,60 @@ pub static NETWORK_GATEWAY_ID: &'static str = "__network__";
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RealEngineConfig {
     pub tls_config: TlsConfig,
+    /// Require remotes to present certificates satisfying this policy (mutual TLS)
+    #[serde(default)]
+    pub tls_trust_policy: Option<TlsTrustPolicy>,
     pub socket_type: String,
     pub bootstrap_nodes: Vec<String>,
+    /// Directory space DHTs persist their state in. Empty keeps it in memory only.
     pub work_dir: String,
     pub log_level: char,
     #[serde(with = "url_serde")]
     pub bind_url: Url,
     pub dht_gossip_interval: u64,
     pub dht_timeout_threshold: u64,
+    /// Interval between anti-entropy rounds of the DHTs, 0 disables them.
+    /// See `DhtConfig::anti_entropy_interval`
+    #[serde(default = "default_anti_entropy_interval")]
+    pub dht_anti_entropy_interval: u64,
+    /// Time a timed-out peer is kept before being evicted.
+    /// See `DhtConfig::eviction_grace_period`
+    #[serde(default = "default_eviction_grace_period")]
+    pub dht_eviction_grace_period: u64,
+    /// JSON tuning passed to every DHT, see `MirrorConfig` and `KademliaConfig`
     pub dht_custom_config: Vec<u8>,
+    /// Number of worker threads processing space gateways in parallel.
+    /// 0 processes them sequentially on the caller's thread.
+    #[serde(default)]
+    pub space_worker_count: usize,
+    /// Target number of holders of each entry in a space, see `DhtConfig::replication_factor`
+    #[serde(default = "default_replication_factor")]
+    pub dht_replication_factor: u64,
+    /// Per-space override of `dht_replication_factor`, keyed by space address
+    #[serde(default)]
+    pub space_replication_factor_map: HashMap<String, u64>,
+}
+
+/// Diagnostic snapshot of a RealEngine's internal state, see `RealEngine::snapshot()`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EngineSnapshot {
+    pub name: String,
+    /// ms since epoch at which the snapshot was taken
+    pub timestamp: u64,
+    pub process_count: u64,
+    /// Number of Lib3hClientProtocol messages waiting to be processed
+    pub inbox_depth: usize,
+    pub network_connections: Vec<ConnectionId>,
+    pub network_transport: TransportSnapshot,
+    pub network_gateway: GatewaySnapshot,
+    pub space_gateway_list: Vec<SpaceGatewaySnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpaceGatewaySnapshot {
+    pub space_address: Address,
+    pub agent_id: Address,
+    pub gateway: GatewaySnapshot,
 }
 
 pub struct TransportKeys {
@@ -57,15 +104,16 @@ pub struct RealEngine<T: Transport, D: Dht> {
     /// Factory for building DHT's of type D
     dht_factory: DhtFactory<D>,
     // TODO #176: Remove this if we resolve #176 without it.
-    #[allow(dead_code)]
     /// Transport used by the network gateway
-    network_transport: Rc<RefCell<T>>,
+    network_transport: TransportHandle<T>,
     /// P2p gateway for the network layer
-    network_gateway: Rc<RefCell<P2pGateway<T, D>>>,
+    network_gateway: TransportHandle<P2pGateway<T, D>>,
     /// Store active connections?
     network_connections: HashSet<ConnectionId>,
-    /// Map of P2p gateway per Space+Agent
-    space_gateway_map: HashMap<ChainId, P2pGateway<P2pGateway<T, D>, D>>,
+    /// Map of P2p gateway per Space+Agent, ordered so spaces are processed in ChainId order
+    space_gateway_map: BTreeMap<ChainId, P2pGateway<P2pGateway<T, D>, D>>,
+    /// Worker pool for processing space gateways, if enabled in config
+    space_worker_pool: Option<rayon::ThreadPool>,
     #[allow(dead_code)]
     /// crypto system to use
     crypto: Box<dyn CryptoSystem>,
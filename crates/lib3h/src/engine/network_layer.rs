@@ -4,6 +4,7 @@ use crate::{
     dht::{dht_protocol::*, dht_trait::Dht},
     engine::{p2p_protocol::P2pProtocol, RealEngine, NETWORK_GATEWAY_ID},
     error::{ErrorKind, Lib3hError, Lib3hResult},
+    gateway::lock_handle,
     transport::{protocol::*, transport_trait::Transport, ConnectionIdRef},
 };
 use lib3h_protocol::{data_types::*, protocol_server::Lib3hServerProtocol, DidWork};
@@ -20,7 +21,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
         let mut outbox = Vec::new();
         // Process the network gateway as a Transport
         let (tranport_did_work, event_list) =
-            Transport::process(&mut *self.network_gateway.borrow_mut())?;
+            Transport::process(&mut *lock_handle(&self.network_gateway)?)?;
         debug!(
             "{} - network_gateway Transport.process(): {} {}",
             self.name,
@@ -34,7 +35,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             }
         }
         // Process the network gateway as a DHT
-        let (dht_did_work, event_list) = Dht::process(&mut *self.network_gateway.borrow_mut())?;
+        let (dht_did_work, event_list) = Dht::process(&mut *lock_handle(&self.network_gateway)?)?;
         if dht_did_work {
             for evt in event_list {
                 let mut output = self.handle_netDhtEvent(evt)?;
@@ -63,11 +64,11 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
                     self.name, peer_data.peer_address, peer_data.peer_uri,
                 );
                 let cmd = TransportCommand::Connect(peer_data.peer_uri.clone());
-                Transport::post(&mut *self.network_gateway.borrow_mut(), cmd)?;
+                Transport::post(&mut *lock_handle(&self.network_gateway)?, cmd)?;
             }
             DhtEvent::PeerTimedOut(peer_address) => {
                 // Disconnect from that peer by calling a Close on it.
-                let mut network_gateway = self.network_gateway.borrow_mut();
+                let mut network_gateway = lock_handle(&self.network_gateway)?;
                 let maybe_connection_id = network_gateway.get_connection_id(&peer_address);
                 trace!(
                     "{} -- maybe_connection_id: {:?}",
@@ -81,6 +82,9 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
                     )?;
                 }
             }
+            DhtEvent::PeerEvicted(_peer_data) => {
+                // no-op - network gateway closed its connection, if still open
+            }
             // No entries in Network DHT
             DhtEvent::HoldEntryRequested(_, _) => {
                 unreachable!();
@@ -103,7 +107,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
         id: &ConnectionIdRef,
     ) -> Lib3hResult<Vec<Lib3hServerProtocol>> {
         let mut outbox = Vec::new();
-        let mut network_gateway = self.network_gateway.borrow_mut();
+        let mut network_gateway = lock_handle(&self.network_gateway)?;
         if let Some(uri) = network_gateway.get_uri(id) {
             info!("Network Connection opened: {} ({})", id, uri);
             // TODO #150 - Should do this in next process instead
@@ -125,7 +129,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             let maybe_peer_data = peer_list.iter().find(|pd| pd.peer_uri == uri);
             if let Some(peer_data) = maybe_peer_data {
                 trace!("AllJoinedSpaceList ; sending back to {:?}", peer_data);
-                network_gateway.send(&[&peer_data.peer_address], &buf)?;
+                network_gateway.send(&[&peer_data.peer_address], &buf.into())?;
             }
             // TODO END
 
@@ -215,7 +219,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
                 });
                 // Check if its for the network_gateway
                 if msg.space_address.to_string() == NETWORK_GATEWAY_ID {
-                    Dht::post(&mut *self.network_gateway.borrow_mut(), cmd)?;
+                    Dht::post(&mut *lock_handle(&self.network_gateway)?, cmd)?;
                 } else {
                     // otherwise should be for one of our space
                     let maybe_space_gateway = self
@@ -2,21 +2,33 @@
 
 //#[cfg(test)]
 use crate::transport::memory_mock::transport_memory::TransportMemory;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use url::Url;
 
+#[cfg(unix)]
+use crate::transport_tcp::unix::TransportUnix;
 use crate::{
     dht::{
         dht_protocol::{self, *},
         dht_trait::*,
     },
     engine::{
-        p2p_protocol::P2pProtocol, RealEngine, RealEngineConfig, TransportKeys, NETWORK_GATEWAY_ID,
+        p2p_protocol::P2pProtocol, space_layer, EngineSnapshot, RealEngine, RealEngineConfig,
+        SpaceGatewaySnapshot, TransportKeys, NETWORK_GATEWAY_ID,
+    },
+    error::{Lib3hError, Lib3hResult},
+    gateway::{lock_handle, P2pGateway},
+    time,
+    transport::{
+        error::TransportError, protocol::TransportCommand, transport_composite::TransportComposite,
+        transport_trait::Transport,
+    },
+    transport_tcp::TransportTcp,
+    transport_udp::TransportUdp,
+    transport_wss::{
+        certificate::{generate_self_signed, parse_certificate_binding},
+        TlsCertificate, TlsConfig, TransportWss,
     },
-    error::Lib3hResult,
-    gateway::P2pGateway,
-    transport::{protocol::TransportCommand, transport_trait::Transport},
-    transport_wss::TransportWss,
 };
 use lib3h_crypto_api::{Buffer, CryptoSystem};
 use lib3h_protocol::{
@@ -25,7 +37,190 @@ use lib3h_protocol::{
 };
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+/// File a space DHT persists its state in, if the engine has a work directory.
+/// Addresses come from the network, so they are hex encoded: no separator or
+/// path component can sneak into the name, and no two pairs share a file.
+fn space_dht_storage_path(work_dir: &str, join_msg: &JoinSpaceData) -> Option<PathBuf> {
+    if work_dir.is_empty() {
+        return None;
+    }
+    let to_hex = |address: &Address| -> String {
+        address
+            .to_string()
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    };
+    let file_name = format!(
+        "space_{}_{}.json",
+        to_hex(&join_msg.space_address),
+        to_hex(&join_msg.agent_id)
+    );
+    Some(Path::new(work_dir).join("dht").join(file_name))
+}
+
+/// Transport keys of a `SelfSigned` node and their certificate, saved in its work directory
+#[derive(Serialize, Deserialize)]
+struct StoredTransportIdentity {
+    transport_public_key: Vec<u8>,
+    transport_secret_key: Vec<u8>,
+    certificate: TlsCertificate,
+}
+
+/// Transport keys and certificate saved in `path`, if readable and still matching
+fn load_transport_identity(
+    crypto: &dyn CryptoSystem,
+    path: &Path,
+) -> Lib3hResult<(TransportKeys, TlsCertificate)> {
+    let stored: StoredTransportIdentity = serde_json::from_slice(&std::fs::read(path)?)?;
+    if stored.transport_public_key.len() != crypto.sign_public_key_bytes()
+        || stored.transport_secret_key.len() != crypto.sign_secret_key_bytes()
+    {
+        return Err(Lib3hError::new_other("transport keys have the wrong size"));
+    }
+    let transport_id =
+        hcid::HcidEncoding::with_kind("hcm0")?.encode(&stored.transport_public_key)?;
+    let certificate_der = stored
+        .certificate
+        .certificate_chain()
+        .first()
+        .ok_or_else(|| Lib3hError::new_other("certificate chain is empty"))?;
+    if parse_certificate_binding(certificate_der)?.transport_id != transport_id {
+        return Err(Lib3hError::new_other(
+            "certificate is not bound to the transport keys",
+        ));
+    }
+    let mut transport_secret_key = crypto.buf_new_secure(stored.transport_secret_key.len());
+    transport_secret_key.write(0, &stored.transport_secret_key)?;
+    let transport_keys = TransportKeys {
+        transport_id,
+        transport_public_key: Box::new(stored.transport_public_key),
+        transport_secret_key,
+    };
+    Ok((transport_keys, stored.certificate))
+}
+
+/// Save transport keys and their certificate to `path`, readable by the owner only
+fn save_transport_identity(
+    transport_keys: &TransportKeys,
+    certificate: &TlsCertificate,
+    path: &Path,
+) -> Lib3hResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let stored = StoredTransportIdentity {
+        transport_public_key: transport_keys.transport_public_key.read_lock().to_vec(),
+        transport_secret_key: transport_keys.transport_secret_key.read_lock().to_vec(),
+        certificate: certificate.clone(),
+    };
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // A file saved by an older version may be more permissive
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options
+        .open(path)?
+        .write_all(&serde_json::to_vec(&stored)?)?;
+    Ok(())
+}
+
+/// Transport keys, and a certificate bound to them: loaded from the work directory if
+/// saved by a previous run, generated (and saved) otherwise.
+/// A file that cannot be loaded is replaced.
+fn self_signed_transport_identity(
+    crypto: &dyn CryptoSystem,
+    work_dir: &str,
+) -> Lib3hResult<(TransportKeys, TlsConfig)> {
+    let path = if work_dir.is_empty() {
+        None
+    } else {
+        Some(Path::new(work_dir).join("tls").join("certificate.json"))
+    };
+    if let Some(path) = &path {
+        if path.exists() {
+            match load_transport_identity(crypto, path) {
+                Ok((transport_keys, certificate)) => {
+                    return Ok((transport_keys, TlsConfig::SuppliedCertificate(certificate)));
+                }
+                Err(e) => warn!(
+                    "Replacing unusable certificate file {}: {:?}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+    let transport_keys = TransportKeys::new(crypto)?;
+    let certificate = generate_self_signed(&transport_keys.transport_id, |public_key| {
+        let message: Box<dyn Buffer> = Box::new(public_key.to_vec());
+        let mut signature: Box<dyn Buffer> = Box::new(vec![0; crypto.sign_bytes()]);
+        crypto
+            .sign(
+                &mut signature,
+                &message,
+                &transport_keys.transport_secret_key,
+            )
+            .map_err(|e| TransportError::new(format!("{:?}", e)))?;
+        let signature = signature.read_lock().to_vec();
+        Ok(signature)
+    })?;
+    if let Some(path) = &path {
+        save_transport_identity(&transport_keys, &certificate, path)?;
+    }
+    Ok((transport_keys, TlsConfig::SuppliedCertificate(certificate)))
+}
+
+/// Transport keys of the engine and TLS config of its network transport,
+/// with `SelfSigned` resolved to a certificate
+fn transport_identity(
+    crypto: &dyn CryptoSystem,
+    config: &RealEngineConfig,
+) -> Lib3hResult<(TransportKeys, TlsConfig)> {
+    match &config.tls_config {
+        TlsConfig::SelfSigned => self_signed_transport_identity(crypto, &config.work_dir),
+        tls_config => Ok((TransportKeys::new(crypto)?, tls_config.clone())),
+    }
+}
+
+/// Build a RealEngine whose network transport is picked by `config.socket_type`:
+/// "ws" or "wss" for TransportWss, "tcp" for TransportTcp, "unix" for TransportTcp over
+/// Unix domain sockets, "udp" for TransportUdp, "mem" for TransportMemory.
+pub fn new_network_engine<D: Dht + 'static>(
+    crypto: Box<dyn CryptoSystem>,
+    config: RealEngineConfig,
+    name: &str,
+    dht_factory: DhtFactory<D>,
+) -> Lib3hResult<Box<dyn NetworkEngine>> {
+    let engine: Box<dyn NetworkEngine> = match config.socket_type.as_str() {
+        "ws" | "wss" => Box::new(RealEngine::new(crypto, config, name, dht_factory)?),
+        "tcp" => Box::new(RealEngine::new_tcp(crypto, config, name, dht_factory)?),
+        #[cfg(unix)]
+        "unix" => Box::new(RealEngine::new_unix(crypto, config, name, dht_factory)?),
+        "udp" => Box::new(RealEngine::new_udp(crypto, config, name, dht_factory)?),
+        "mem" => Box::new(RealEngine::new_mock(crypto, config, name, dht_factory)?),
+        socket_type => {
+            return Err(Lib3hError::new_other(&format!(
+                "unknown socket_type: {}",
+                socket_type
+            )));
+        }
+    };
+    Ok(engine)
+}
 
 impl TransportKeys {
     pub fn new(crypto: &dyn CryptoSystem) -> Lib3hResult<Self> {
@@ -49,14 +244,135 @@ impl<D: Dht> RealEngine<TransportWss<std::net::TcpStream>, D> {
         name: &str,
         dht_factory: DhtFactory<D>,
     ) -> Lib3hResult<Self> {
-        // Create Transport and bind
-        let network_transport = Rc::new(RefCell::new(TransportWss::with_std_tcp_stream(
-            config.tls_config.clone(),
-        )));
-        let binding = network_transport.borrow_mut().bind(&config.bind_url)?;
-        // Generate keys
+        // Generate keys, or load them along with a SelfSigned certificate
         // TODO #209 - Check persistence first before generating
+        let (transport_keys, tls_config) = transport_identity(crypto.as_crypto_system(), &config)?;
+        // Create Transport
+        let mut network_transport = TransportWss::with_std_tcp_stream(tls_config);
+        network_transport.set_trust_policy(config.tls_trust_policy.clone());
+        network_transport
+            .set_require_client_certificate(config.tls_config == TlsConfig::SelfSigned);
+        Self::with_network_transport(
+            crypto,
+            config,
+            name,
+            dht_factory,
+            transport_keys,
+            network_transport,
+        )
+    }
+}
+
+impl<D: Dht> RealEngine<TransportTcp<std::net::TcpStream>, D> {
+    /// Constructor with TransportTcp
+    pub fn new_tcp(
+        crypto: Box<dyn CryptoSystem>,
+        config: RealEngineConfig,
+        name: &str,
+        dht_factory: DhtFactory<D>,
+    ) -> Lib3hResult<Self> {
+        let (transport_keys, tls_config) = transport_identity(crypto.as_crypto_system(), &config)?;
+        let mut network_transport = TransportTcp::with_std_tcp_stream(tls_config);
+        network_transport.set_trust_policy(config.tls_trust_policy.clone());
+        network_transport
+            .set_require_client_certificate(config.tls_config == TlsConfig::SelfSigned);
+        Self::with_network_transport(
+            crypto,
+            config,
+            name,
+            dht_factory,
+            transport_keys,
+            network_transport,
+        )
+    }
+}
+
+#[cfg(unix)]
+impl<D: Dht> RealEngine<TransportUnix, D> {
+    /// Constructor with TransportTcp over Unix domain sockets, for nodes on the same host
+    pub fn new_unix(
+        crypto: Box<dyn CryptoSystem>,
+        config: RealEngineConfig,
+        name: &str,
+        dht_factory: DhtFactory<D>,
+    ) -> Lib3hResult<Self> {
+        let (transport_keys, tls_config) = transport_identity(crypto.as_crypto_system(), &config)?;
+        let mut network_transport = TransportUnix::with_std_unix_stream(tls_config);
+        network_transport.set_trust_policy(config.tls_trust_policy.clone());
+        network_transport
+            .set_require_client_certificate(config.tls_config == TlsConfig::SelfSigned);
+        Self::with_network_transport(
+            crypto,
+            config,
+            name,
+            dht_factory,
+            transport_keys,
+            network_transport,
+        )
+    }
+}
+
+impl<D: Dht> RealEngine<TransportUdp, D> {
+    /// Constructor with TransportUdp. Its payloads are not encrypted,
+    /// so `config.tls_config` must be `TlsConfig::Unencrypted`.
+    pub fn new_udp(
+        crypto: Box<dyn CryptoSystem>,
+        config: RealEngineConfig,
+        name: &str,
+        dht_factory: DhtFactory<D>,
+    ) -> Lib3hResult<Self> {
+        if config.tls_config != TlsConfig::Unencrypted {
+            return Err(Lib3hError::new_other(
+                "TransportUdp does not support TLS, use TlsConfig::Unencrypted",
+            ));
+        }
+        let transport_keys = TransportKeys::new(crypto.as_crypto_system())?;
+        Self::with_network_transport(
+            crypto,
+            config,
+            name,
+            dht_factory,
+            transport_keys,
+            TransportUdp::new(),
+        )
+    }
+}
+
+impl<D: Dht> RealEngine<TransportComposite, D> {
+    /// Constructor with a TransportComposite, for nodes reachable over several url schemes.
+    /// The inner transports come with their own TLS config: `config.tls_config` is not used.
+    pub fn new_composite(
+        crypto: Box<dyn CryptoSystem>,
+        config: RealEngineConfig,
+        name: &str,
+        dht_factory: DhtFactory<D>,
+        network_transport: TransportComposite,
+    ) -> Lib3hResult<Self> {
         let transport_keys = TransportKeys::new(crypto.as_crypto_system())?;
+        Self::with_network_transport(
+            crypto,
+            config,
+            name,
+            dht_factory,
+            transport_keys,
+            network_transport,
+        )
+    }
+}
+
+impl<T: Transport, D: Dht> RealEngine<T, D> {
+    /// Bind the network transport and build the network gateway on top of it
+    fn with_network_transport(
+        crypto: Box<dyn CryptoSystem>,
+        config: RealEngineConfig,
+        name: &str,
+        dht_factory: DhtFactory<D>,
+        transport_keys: TransportKeys,
+        network_transport: T,
+    ) -> Lib3hResult<Self> {
+        let network_transport = Arc::new(Mutex::new(network_transport));
+        let binding = lock_handle(&network_transport)?.bind(&config.bind_url)?;
+        let space_worker_pool = space_layer::new_space_worker_pool(config.space_worker_count)?;
         // Generate DHT config and create network_gateway
         let dht_config = DhtConfig {
             this_peer_address: transport_keys.transport_id.clone(),
@@ -64,13 +380,32 @@ impl<D: Dht> RealEngine<TransportWss<std::net::TcpStream>, D> {
             custom: config.dht_custom_config.clone(),
             gossip_interval: config.dht_gossip_interval,
             timeout_threshold: config.dht_timeout_threshold,
+            anti_entropy_interval: config.dht_anti_entropy_interval,
+            replication_factor: config.dht_replication_factor,
+            // Transport id changes on every run, so there is nothing to restore
+            storage_path: None,
+            eviction_grace_period: config.dht_eviction_grace_period,
+            storage_budget: None,
         };
-        let network_gateway = Rc::new(RefCell::new(P2pGateway::new(
+        let network_gateway = Arc::new(Mutex::new(P2pGateway::new(
             NETWORK_GATEWAY_ID,
-            Rc::clone(&network_transport),
+            Arc::clone(&network_transport),
             dht_factory,
             &dht_config,
         )));
+        // Make sure remotes own the certificate they present,
+        // and that they advertise the transport id it is bound to.
+        // Connections without a certificate are closed.
+        if config.tls_config == TlsConfig::SelfSigned {
+            lock_handle(&network_gateway)?.set_certificate_verifier(Box::new(|certificate_der| {
+                let binding = parse_certificate_binding(certificate_der).ok()?;
+                if binding.is_signed() {
+                    Some(binding.transport_id)
+                } else {
+                    None
+                }
+            }));
+        }
         // Done
         Ok(RealEngine {
             crypto,
@@ -81,7 +416,8 @@ impl<D: Dht> RealEngine<TransportWss<std::net::TcpStream>, D> {
             network_transport,
             network_gateway,
             network_connections: HashSet::new(),
-            space_gateway_map: HashMap::new(),
+            space_gateway_map: BTreeMap::new(),
+            space_worker_pool,
             transport_keys,
             process_count: 0,
         })
@@ -99,10 +435,9 @@ impl<D: Dht> RealEngine<TransportMemory, D> {
         dht_factory: DhtFactory<D>,
     ) -> Lib3hResult<Self> {
         // Create TransportMemory as the network transport
-        let network_transport = Rc::new(RefCell::new(TransportMemory::new()));
+        let network_transport = Arc::new(Mutex::new(TransportMemory::new()));
         // Bind & create DhtConfig
-        let binding = network_transport
-            .borrow_mut()
+        let binding = lock_handle(&network_transport)?
             .bind(&config.bind_url)
             .expect("TransportMemory.bind() failed. bind-url might not be unique?");
         let dht_config = DhtConfig {
@@ -111,20 +446,27 @@ impl<D: Dht> RealEngine<TransportMemory, D> {
             custom: config.dht_custom_config.clone(),
             gossip_interval: config.dht_gossip_interval,
             timeout_threshold: config.dht_timeout_threshold,
+            anti_entropy_interval: config.dht_anti_entropy_interval,
+            replication_factor: config.dht_replication_factor,
+            // Transport id changes on every run, so there is nothing to restore
+            storage_path: None,
+            eviction_grace_period: config.dht_eviction_grace_period,
+            storage_budget: None,
         };
         // Create network gateway
-        let network_gateway = Rc::new(RefCell::new(P2pGateway::new(
+        let network_gateway = Arc::new(Mutex::new(P2pGateway::new(
             NETWORK_GATEWAY_ID,
-            Rc::clone(&network_transport),
+            Arc::clone(&network_transport),
             dht_factory,
             &dht_config,
         )));
         debug!(
             "New MOCK RealEngine {} -> {:?}",
             name,
-            network_gateway.borrow().this_peer()
+            lock_handle(&network_gateway)?.this_peer()
         );
         let transport_keys = TransportKeys::new(crypto.as_crypto_system())?;
+        let space_worker_pool = space_layer::new_space_worker_pool(config.space_worker_count)?;
         Ok(RealEngine {
             crypto,
             config,
@@ -134,7 +476,8 @@ impl<D: Dht> RealEngine<TransportMemory, D> {
             network_transport,
             network_gateway,
             network_connections: HashSet::new(),
-            space_gateway_map: HashMap::new(),
+            space_gateway_map: BTreeMap::new(),
+            space_worker_pool,
             transport_keys,
             process_count: 0,
         })
@@ -143,8 +486,10 @@ impl<D: Dht> RealEngine<TransportMemory, D> {
 
 impl<T: Transport, D: Dht> NetworkEngine for RealEngine<T, D> {
     fn advertise(&self) -> Url {
+        // Only reads the gateway, so a poisoned lock can be read through
         self.network_gateway
-            .borrow()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
             .this_peer()
             .peer_uri
             .to_owned()
@@ -179,6 +524,93 @@ impl<T: Transport, D: Dht> NetworkEngine for RealEngine<T, D> {
         // Done
         Ok((inbox_did_work || net_did_work, outbox))
     }
+
+    /// Earliest deadline of the network gateway and all space gateways
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if !self.inbox.is_empty() {
+            return Some(time::since_epoch_ms());
+        }
+        let network_gateway = match lock_handle(&self.network_gateway) {
+            Ok(network_gateway) => network_gateway,
+            // process() right away, so it reports the poisoned lock
+            Err(_) => return Some(time::since_epoch_ms()),
+        };
+        let mut deadline = time::earliest_deadline(
+            Transport::next_deadline_ms(&*network_gateway),
+            Dht::next_deadline_ms(&*network_gateway),
+        );
+        for space_gateway in self.space_gateway_map.values() {
+            deadline = time::earliest_deadline(deadline, Dht::next_deadline_ms(space_gateway));
+        }
+        deadline
+    }
+
+    /// Wait on the network gateway's transport until the next deadline of the engine
+    fn wait_ready(&mut self, max_wait: Duration) -> Lib3hProtocolResult<()> {
+        let max_wait_ms = max_wait.as_millis() as u64;
+        let wait_ms = match self.next_deadline_ms() {
+            Some(deadline) => {
+                std::cmp::min(deadline.saturating_sub(time::since_epoch_ms()), max_wait_ms)
+            }
+            None => max_wait_ms,
+        };
+        if wait_ms == 0 {
+            return Ok(());
+        }
+        let mut network_gateway = lock_handle(&self.network_gateway).map_err(Lib3hError::from)?;
+        Transport::wait_ready(&mut *network_gateway, Duration::from_millis(wait_ms))
+            .map_err(Lib3hError::from)?;
+        Ok(())
+    }
+}
+
+/// Diagnostics
+impl<T: Transport, D: Dht> RealEngine<T, D> {
+    /// Snapshot of the engine's internal state, for debugging stuck nodes
+    pub fn snapshot(&self) -> EngineSnapshot {
+        let mut network_connections: Vec<_> = self.network_connections.iter().cloned().collect();
+        network_connections.sort();
+        // Lock one handle at a time: space gateways lock the network gateway,
+        // which locks the network transport.
+        // Snapshots help most on a broken engine, so read through poisoned locks.
+        let network_transport = self
+            .network_transport
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .transport_snapshot();
+        let network_gateway = self
+            .network_gateway
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .gateway_snapshot();
+        let mut space_gateway_list: Vec<SpaceGatewaySnapshot> = self
+            .space_gateway_map
+            .iter()
+            .map(|(chain_id, space_gateway)| SpaceGatewaySnapshot {
+                space_address: chain_id.0.clone(),
+                agent_id: chain_id.1.clone(),
+                gateway: space_gateway.gateway_snapshot(),
+            })
+            .collect();
+        space_gateway_list.sort_by_cached_key(|space| {
+            (space.space_address.to_string(), space.agent_id.to_string())
+        });
+        EngineSnapshot {
+            name: self.name.clone(),
+            timestamp: time::since_epoch_ms(),
+            process_count: self.process_count,
+            inbox_depth: self.inbox.len(),
+            network_connections,
+            network_transport,
+            network_gateway,
+            space_gateway_list,
+        }
+    }
+
+    /// Snapshot of the engine's internal state, as pretty printed JSON
+    pub fn dump_state(&self) -> Lib3hResult<String> {
+        Ok(serde_json::to_string_pretty(&self.snapshot())?)
+    }
 }
 
 /// Drop
@@ -207,10 +639,12 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             }
         }
         // Done
-        self.network_gateway.borrow_mut().close_all().map_err(|e| {
-            error!("Closing of some connection failed: {:?}", e);
-            e
-        })?;
+        lock_handle(&self.network_gateway)?
+            .close_all()
+            .map_err(|e| {
+                error!("Closing of some connection failed: {:?}", e);
+                e
+            })?;
         Ok(())
     }
 
@@ -252,7 +686,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             Lib3hClientProtocol::Connect(msg) => {
                 // Convert into TransportCommand & post to network gateway
                 let cmd = TransportCommand::Connect(msg.peer_uri);
-                Transport::post(&mut *self.network_gateway.borrow_mut(), cmd)?;
+                Transport::post(&mut *lock_handle(&self.network_gateway)?, cmd)?;
             }
             Lib3hClientProtocol::JoinSpace(msg) => {
                 let mut output = self.serve_JoinSpace(&msg)?;
@@ -452,13 +886,22 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
                     }
                 }
             }
+            Lib3hClientProtocol::DumpState(msg) => {
+                outbox.push(Lib3hServerProtocol::DumpStateResult(DumpStateResultData {
+                    request_id: msg.request_id,
+                    state: self.dump_state()?,
+                }));
+            }
         }
         Ok(outbox)
     }
 
     /// Create a gateway for this agent in this space, if not already part of it.
     /// Must not already be part of this space.
-    fn serve_JoinSpace(&mut self, join_msg: &SpaceData) -> Lib3hResult<Vec<Lib3hServerProtocol>> {
+    fn serve_JoinSpace(
+        &mut self,
+        join_msg: &JoinSpaceData,
+    ) -> Lib3hResult<Vec<Lib3hServerProtocol>> {
         // Prepare response
         let mut res = GenericResultData {
             request_id: join_msg.request_id.clone(),
@@ -476,27 +919,38 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
         output.push(Lib3hServerProtocol::SuccessResult(res));
         // First create DhtConfig for space gateway
         let agent_id: String = join_msg.agent_id.clone().into();
-        let this_net_peer = self.network_gateway.borrow().this_peer().clone();
+        let this_net_peer = lock_handle(&self.network_gateway)?.this_peer().clone();
         let this_peer_transport_id_as_uri =
             // TODO #175 - encapsulate this conversion logic
             Url::parse(format!("transportId:{}", this_net_peer.peer_address.clone()).as_str()).unwrap();
+        let space_address: String = join_msg.space_address.clone().into();
+        let replication_factor = self
+            .config
+            .space_replication_factor_map
+            .get(&space_address)
+            .cloned()
+            .unwrap_or(self.config.dht_replication_factor);
         let dht_config = DhtConfig {
             this_peer_address: agent_id,
             this_peer_uri: this_peer_transport_id_as_uri,
             custom: self.config.dht_custom_config.clone(),
             gossip_interval: self.config.dht_gossip_interval,
             timeout_threshold: self.config.dht_timeout_threshold,
+            anti_entropy_interval: self.config.dht_anti_entropy_interval,
+            replication_factor,
+            storage_path: space_dht_storage_path(&self.config.work_dir, &join_msg),
+            eviction_grace_period: self.config.dht_eviction_grace_period,
+            storage_budget: join_msg.storage_budget.clone(),
         };
         // Create new space gateway for this ChainId
         let new_space_gateway = P2pGateway::new_with_space(
-            Rc::clone(&self.network_gateway),
+            Arc::clone(&self.network_gateway),
             &join_msg.space_address,
             self.dht_factory,
             &dht_config,
         );
 
         // TODO #150 - Send JoinSpace to all known peers
-        let space_address: String = join_msg.space_address.clone().into();
         let peer = new_space_gateway.this_peer().to_owned();
         let mut payload = Vec::new();
         let p2p_msg = P2pProtocol::BroadcastJoinSpace(space_address.clone(), peer.clone());
@@ -509,7 +963,9 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             space_address,
             peer.peer_address,
         );
-        self.network_gateway.borrow_mut().send_all(&payload).ok();
+        lock_handle(&self.network_gateway)?
+            .send_all(&payload.into())
+            .ok();
         // TODO END
 
         // Add it to space map
@@ -580,7 +1036,7 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
             .unwrap();
         // Send
         let peer_address: String = msg.to_agent_id.clone().into();
-        let res = space_gateway.send(&[peer_address.as_str()], &payload);
+        let res = space_gateway.send(&[peer_address.as_str()], &payload.into());
         if let Err(e) = res {
             response.result_info = e.to_string().as_bytes().to_vec();
             return Lib3hServerProtocol::FailureResult(response);
@@ -648,3 +1104,69 @@ fn includes(list_a: &[Address], list_b: &[Address]) -> bool {
     let set_b: HashSet<_> = list_b.iter().map(|addr| addr).collect();
     set_b.is_subset(&set_a)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib3h_sodium::SodiumCryptoSystem;
+
+    #[test]
+    fn test_self_signed_transport_identity() {
+        let crypto = SodiumCryptoSystem::new();
+        let work_dir =
+            std::env::temp_dir().join(format!("lib3h_self_signed_identity_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&work_dir);
+        let work_dir = work_dir.to_str().unwrap();
+        let (transport_keys, tls_config) =
+            self_signed_transport_identity(&crypto, work_dir).unwrap();
+        // Signed through the CryptoSystem, verifiable by any peer
+        let certificate = unwrap_to!(tls_config => TlsConfig::SuppliedCertificate);
+        let binding = parse_certificate_binding(&certificate.certificate_chain()[0]).unwrap();
+        assert_eq!(binding.transport_id, transport_keys.transport_id);
+        assert!(binding.is_signed());
+        let path = Path::new(work_dir).join("tls").join("certificate.json");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // Next run loads the same identity
+        let (loaded_keys, loaded_tls_config) =
+            self_signed_transport_identity(&crypto, work_dir).unwrap();
+        assert_eq!(loaded_keys.transport_id, transport_keys.transport_id);
+        assert_eq!(loaded_tls_config, tls_config);
+        // A corrupt file is replaced
+        std::fs::write(&path, b"not json").unwrap();
+        let (new_keys, _) = self_signed_transport_identity(&crypto, work_dir).unwrap();
+        assert_ne!(new_keys.transport_id, transport_keys.transport_id);
+        let (loaded_keys, _) = self_signed_transport_identity(&crypto, work_dir).unwrap();
+        assert_eq!(loaded_keys.transport_id, new_keys.transport_id);
+        let _ = std::fs::remove_dir_all(work_dir);
+    }
+
+    #[test]
+    fn test_space_dht_storage_path_is_escaped() {
+        let join_msg = |space_address: &str, agent_id: &str| JoinSpaceData {
+            request_id: "".to_string(),
+            space_address: space_address.to_string().into(),
+            agent_id: agent_id.to_string().into(),
+            storage_budget: None,
+        };
+        assert_eq!(
+            space_dht_storage_path("", &join_msg("space", "agent")),
+            None
+        );
+        let path = space_dht_storage_path("/work", &join_msg("../../etc", "x")).unwrap();
+        assert_eq!(path.parent().unwrap(), Path::new("/work/dht"));
+        assert_eq!(
+            path.file_name().unwrap(),
+            "space_2e2e2f2e2e2f657463_78.json"
+        );
+        // Underscores in addresses cannot make two pairs share a file
+        assert_ne!(
+            space_dht_storage_path("/work", &join_msg("a_b", "c")),
+            space_dht_storage_path("/work", &join_msg("a", "b_c"))
+        );
+    }
+}
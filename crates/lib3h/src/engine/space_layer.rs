@@ -3,15 +3,16 @@
 use crate::{
     dht::{dht_protocol::*, dht_trait::Dht},
     engine::{p2p_protocol::SpaceAddress, ChainId, RealEngine},
+    error::{Lib3hError, Lib3hResult},
     gateway::P2pGateway,
     transport::transport_trait::Transport,
 };
 use lib3h_protocol::{
     data_types::*, error::Lib3hProtocolResult, protocol_server::Lib3hServerProtocol,
 };
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use rmp_serde::Serializer;
 use serde::Serialize;
-use std::collections::HashMap;
 
 /// Space layer related private methods
 /// Engine does not process a space gateway's Transport because it is shared with the network layer
@@ -40,114 +41,172 @@ impl<T: Transport, D: Dht> RealEngine<T, D> {
         None
     }
 
-    /// Process all space gateways
+    /// Process all space gateways.
+    /// Spaces are served in ChainId order, with or without a worker pool,
+    /// so the outbox is deterministic and keeps each space's event ordering.
+    ///
+    /// Workers share the network gateway: each gossip a space sends locks it,
+    /// then the network transport, for as long as it takes to queue the payload.
+    /// Bundles are serialized before taking the lock, so workers contend only
+    /// while queueing, but spaces gossiping at the same time take turns.
     pub(crate) fn process_space_gateways(
         &mut self,
     ) -> Lib3hProtocolResult<Vec<Lib3hServerProtocol>> {
-        // Process all gateways' DHT
+        let name = self.name.as_str();
+        let space_gateway_list: Vec<_> = self.space_gateway_map.iter_mut().collect();
+        // Process all gateways' DHT and convert their events
+        let output_list: Vec<Lib3hProtocolResult<Vec<Lib3hServerProtocol>>> =
+            match &self.space_worker_pool {
+                None => space_gateway_list
+                    .into_iter()
+                    .map(|(chain_id, space_gateway)| {
+                        process_space_gateway(name, chain_id, space_gateway)
+                    })
+                    .collect(),
+                Some(pool) => pool.install(|| {
+                    space_gateway_list
+                        .into_par_iter()
+                        .map(|(chain_id, space_gateway)| {
+                            process_space_gateway(name, chain_id, space_gateway)
+                        })
+                        .collect()
+                }),
+            };
+        // Merge outputs
         let mut outbox = Vec::new();
-        let mut dht_outbox = HashMap::new();
-        for (chain_id, space_gateway) in self.space_gateway_map.iter_mut() {
-            let (did_work, event_list) = Dht::process(space_gateway)?;
-            if did_work {
-                // TODO: perf optim, don't copy chain_id
-                dht_outbox.insert(chain_id.clone(), event_list);
-            }
-        }
-        // Process all gateway DHT events
-        for (chain_id, evt_list) in dht_outbox {
-            for evt in evt_list {
-                let mut output = self.handle_spaceDhtEvent(&chain_id, evt.clone())?;
-                outbox.append(&mut output);
-            }
+        for output in output_list {
+            outbox.append(&mut output?);
         }
         Ok(outbox)
     }
+}
 
-    /// Handle a DhtEvent sent to us by a space gateway
-    fn handle_spaceDhtEvent(
-        &mut self,
-        chain_id: &ChainId,
-        cmd: DhtEvent,
-    ) -> Lib3hProtocolResult<Vec<Lib3hServerProtocol>> {
-        debug!(
-            "{} << handle_spaceDhtEvent: [{:?}] - {:?}",
-            self.name, chain_id, cmd,
-        );
-        let mut outbox = Vec::new();
-        let space_gateway = self
-            .space_gateway_map
-            .get_mut(chain_id)
-            .expect("Should have the space gateway we receive an event from.");
-        match cmd {
-            DhtEvent::GossipTo(_gossip_data) => {
-                // n/a - should have been handled by gateway
-            }
-            DhtEvent::GossipUnreliablyTo(_data) => {
-                // n/a - should have been handled by gateway
-            }
-            // HoldPeerRequested from gossip
-            DhtEvent::HoldPeerRequested(peer_data) => {
-                debug!(
-                    "{} -- ({}).post() HoldPeer {:?}",
-                    self.name,
-                    space_gateway.identifier(),
-                    peer_data,
-                );
-                // For now accept all request
-                Dht::post(space_gateway, DhtCommand::HoldPeer(peer_data))?;
-            }
-            DhtEvent::PeerTimedOut(_peer_address) => {
-                // no-op
-            }
-            // HoldEntryRequested from gossip
-            // -> Send each aspect to Core for validation
-            DhtEvent::HoldEntryRequested(from, entry) => {
-                for aspect in entry.aspect_list {
-                    let lib3h_msg =
-                        Lib3hServerProtocol::HandleStoreEntryAspect(StoreEntryAspectData {
-                            request_id: "FIXME".to_string(), // TODO #168
-                            space_address: chain_id.0.clone(),
-                            provider_agent_id: from.clone().into(),
-                            entry_address: entry.entry_address.clone(),
-                            entry_aspect: aspect,
-                        });
-                    outbox.push(lib3h_msg)
-                }
-            }
-            // FetchEntryResponse: Send back as a query response to Core
-            // TODO #169 - Discern Fetch from Query
-            DhtEvent::FetchEntryResponse(response) => {
-                let mut query_result = Vec::new();
-                response
-                    .entry
-                    .serialize(&mut Serializer::new(&mut query_result))
-                    .unwrap();
-                let msg_data = QueryEntryResultData {
-                    space_address: chain_id.0.clone(),
-                    entry_address: response.entry.entry_address.clone(),
-                    request_id: response.msg_id.clone(),
-                    requester_agent_id: chain_id.1.clone(), // TODO #150 - get requester from channel from p2p-protocol
-                    responder_agent_id: chain_id.1.clone(),
-                    query_result,
-                };
-                outbox.push(Lib3hServerProtocol::QueryEntryResult(msg_data))
-            }
-            DhtEvent::EntryPruned(_address) => {
-                // TODO #174
-            }
-            // EntryDataRequested: Change it into a Lib3hServerProtocol::HandleFetchEntry.
-            DhtEvent::EntryDataRequested(fetch_entry) => {
-                let msg_data = FetchEntryData {
+/// Build the worker pool for processing space gateways, if workers are requested
+pub(crate) fn new_space_worker_pool(worker_count: usize) -> Lib3hResult<Option<ThreadPool>> {
+    if worker_count == 0 {
+        return Ok(None);
+    }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(worker_count)
+        .thread_name(|i| format!("lib3h-space-worker-{}", i))
+        .build()
+        .map_err(|e| Lib3hError::new_other(&e.to_string()))?;
+    Ok(Some(pool))
+}
+
+/// Process a space gateway's DHT and handle its events.
+/// Does not touch the engine so it can run on a worker thread.
+fn process_space_gateway<T: Transport, D: Dht>(
+    name: &str,
+    chain_id: &ChainId,
+    space_gateway: &mut P2pGateway<P2pGateway<T, D>, D>,
+) -> Lib3hProtocolResult<Vec<Lib3hServerProtocol>> {
+    let mut outbox = Vec::new();
+    let (did_work, event_list) = Dht::process(space_gateway)?;
+    if did_work {
+        for evt in event_list {
+            let mut output = handle_spaceDhtEvent(name, chain_id, space_gateway, evt)?;
+            outbox.append(&mut output);
+        }
+    }
+    Ok(outbox)
+}
+
+/// Handle a DhtEvent sent to us by a space gateway
+fn handle_spaceDhtEvent<T: Transport, D: Dht>(
+    name: &str,
+    chain_id: &ChainId,
+    space_gateway: &mut P2pGateway<P2pGateway<T, D>, D>,
+    cmd: DhtEvent,
+) -> Lib3hProtocolResult<Vec<Lib3hServerProtocol>> {
+    debug!(
+        "{} << handle_spaceDhtEvent: [{:?}] - {:?}",
+        name, chain_id, cmd,
+    );
+    let mut outbox = Vec::new();
+    match cmd {
+        DhtEvent::GossipTo(_gossip_data) => {
+            // n/a - should have been handled by gateway
+        }
+        DhtEvent::GossipUnreliablyTo(_data) => {
+            // n/a - should have been handled by gateway
+        }
+        // HoldPeerRequested from gossip
+        DhtEvent::HoldPeerRequested(peer_data) => {
+            debug!(
+                "{} -- ({}).post() HoldPeer {:?}",
+                name,
+                space_gateway.identifier(),
+                peer_data,
+            );
+            // For now accept all request
+            Dht::post(space_gateway, DhtCommand::HoldPeer(peer_data))?;
+        }
+        DhtEvent::PeerTimedOut(_peer_address) => {
+            // no-op - agent may come back within the eviction grace period.
+            // Its node's connection is shared by all spaces: network layer closes it.
+        }
+        // Let Core know the agent is not part of the space anymore
+        DhtEvent::PeerEvicted(peer_data) => {
+            let lib3h_msg = Lib3hServerProtocol::PeerEvicted(PeerEvictedData {
+                space_address: chain_id.0.clone(),
+                agent_id: chain_id.1.clone(),
+                peer_agent_id: peer_data.peer_address.into(),
+            });
+            outbox.push(lib3h_msg)
+        }
+        // HoldEntryRequested from gossip
+        // -> Send each aspect to Core for validation
+        DhtEvent::HoldEntryRequested(from, entry) => {
+            for aspect in entry.aspect_list {
+                let lib3h_msg = Lib3hServerProtocol::HandleStoreEntryAspect(StoreEntryAspectData {
+                    request_id: "FIXME".to_string(), // TODO #168
                     space_address: chain_id.0.clone(),
-                    entry_address: fetch_entry.entry_address.clone(),
-                    request_id: fetch_entry.msg_id.clone(),
-                    provider_agent_id: chain_id.1.clone(),
-                    aspect_address_list: None,
-                };
-                outbox.push(Lib3hServerProtocol::HandleFetchEntry(msg_data))
+                    provider_agent_id: from.clone().into(),
+                    entry_address: entry.entry_address.clone(),
+                    entry_aspect: aspect,
+                });
+                outbox.push(lib3h_msg)
             }
         }
-        Ok(outbox)
+        // FetchEntryResponse: Send back as a query response to Core
+        // TODO #169 - Discern Fetch from Query
+        DhtEvent::FetchEntryResponse(response) => {
+            let mut query_result = Vec::new();
+            response
+                .entry
+                .serialize(&mut Serializer::new(&mut query_result))
+                .unwrap();
+            let msg_data = QueryEntryResultData {
+                space_address: chain_id.0.clone(),
+                entry_address: response.entry.entry_address.clone(),
+                request_id: response.msg_id.clone(),
+                requester_agent_id: chain_id.1.clone(), // TODO #150 - get requester from channel from p2p-protocol
+                responder_agent_id: chain_id.1.clone(),
+                query_result,
+            };
+            outbox.push(Lib3hServerProtocol::QueryEntryResult(msg_data))
+        }
+        // EntryPruned: Ask Core to drop it, e.g. as the storage budget was exceeded
+        DhtEvent::EntryPruned(entry_address) => {
+            let lib3h_msg = Lib3hServerProtocol::HandleDropEntry(DropEntryData {
+                space_address: chain_id.0.clone(),
+                request_id: format!("EntryPruned_{}", entry_address),
+                entry_address,
+            });
+            outbox.push(lib3h_msg)
+        }
+        // EntryDataRequested: Change it into a Lib3hServerProtocol::HandleFetchEntry.
+        DhtEvent::EntryDataRequested(fetch_entry) => {
+            let msg_data = FetchEntryData {
+                space_address: chain_id.0.clone(),
+                entry_address: fetch_entry.entry_address.clone(),
+                request_id: fetch_entry.msg_id.clone(),
+                provider_agent_id: chain_id.1.clone(),
+                aspect_address_list: None,
+            };
+            outbox.push(Lib3hServerProtocol::HandleFetchEntry(msg_data))
+        }
     }
+    Ok(outbox)
 }
@@ -4,6 +4,7 @@ use crate::transport::error::TransportError;
 use lib3h_crypto_api::CryptoError;
 use lib3h_protocol::error::{ErrorKind as Lib3hProtocolErrorKind, Lib3hProtocolError};
 use rmp_serde::decode::Error as RMPSerdeDecodeError;
+use serde_json::Error as SerdeJsonError;
 use std::{error::Error as StdError, fmt, io, result};
 
 /// A type alias for `Result<T, Lib3hError>`.
@@ -54,6 +55,8 @@ pub enum ErrorKind {
     HcId(hcid::HcidError),
     /// Error originating from [MessagePack](rmp_serde) deserializing crate.
     RmpSerdeDecodeError(RMPSerdeDecodeError),
+    /// Error originating from [JSON](serde_json) serializing crate.
+    SerdeJsonError(SerdeJsonError),
     /// Error from the [lib3h_crypto_api] crate.
     CryptoApiError(CryptoError),
     /// Error occuring when the key is not present in the Map.
@@ -78,6 +81,7 @@ impl StdError for Lib3hError {
             ErrorKind::Lib3hProtocolError(ref err) => Some(err),
             ErrorKind::HcId(ref err) => Some(err),
             ErrorKind::RmpSerdeDecodeError(ref err) => Some(err),
+            ErrorKind::SerdeJsonError(ref err) => Some(err),
             ErrorKind::CryptoApiError(ref err) => Some(err),
             ErrorKind::Other(ref _s) | ErrorKind::KeyNotFound(ref _s) => None,
             _ => unreachable!(),
@@ -93,6 +97,7 @@ impl fmt::Display for Lib3hError {
             ErrorKind::Lib3hProtocolError(ref err) => err.fmt(f),
             ErrorKind::HcId(ref err) => err.fmt(f),
             ErrorKind::RmpSerdeDecodeError(ref err) => err.fmt(f),
+            ErrorKind::SerdeJsonError(ref err) => err.fmt(f),
             ErrorKind::CryptoApiError(ref err) => err.fmt(f),
             ErrorKind::KeyNotFound(ref s) => write!(f, "Key: '{}' not found", s),
             ErrorKind::Other(ref s) => write!(f, "Unknown error encountered: '{}'.", s),
@@ -131,6 +136,12 @@ impl From<RMPSerdeDecodeError> for Lib3hError {
     }
 }
 
+impl From<SerdeJsonError> for Lib3hError {
+    fn from(err: SerdeJsonError) -> Self {
+        Lib3hError::new(ErrorKind::SerdeJsonError(err))
+    }
+}
+
 impl From<CryptoError> for Lib3hError {
     fn from(err: CryptoError) -> Self {
         Lib3hError::new(ErrorKind::CryptoApiError(err))
@@ -4,7 +4,7 @@ use crate::{
     dht::{dht_protocol::*, dht_trait::Dht},
     engine::{p2p_protocol::*, NETWORK_GATEWAY_ID},
     error::Lib3hResult,
-    gateway::P2pGateway,
+    gateway::{lock_handle, P2pGateway},
     transport::transport_trait::Transport,
 };
 use lib3h_protocol::{Address, DidWork};
@@ -78,6 +78,12 @@ impl<T: Transport, D: Dht> Dht for P2pGateway<T, D> {
         // Done
         Ok((did_work, dht_event_list))
     }
+    fn next_deadline_ms(&self) -> Option<u64> {
+        self.inner_dht.next_deadline_ms()
+    }
+    fn dht_snapshot(&self) -> DhtSnapshot {
+        self.inner_dht.dht_snapshot()
+    }
 }
 
 /// Private internals
@@ -110,9 +116,7 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
                         .get_connection_id(&to_peer_address)
                         .expect("Should gossip to a known peer");
                     // Forward gossip to the inner_transport
-                    self.inner_transport
-                        .borrow_mut()
-                        .send(&[&to_conn_id], &payload)?;
+                    lock_handle(&self.inner_transport)?.send(&[&to_conn_id], &payload.into())?;
                 }
             }
             DhtEvent::GossipUnreliablyTo(_data) => {
@@ -124,6 +128,23 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
             DhtEvent::PeerTimedOut(_peer_address) => {
                 // no-op
             }
+            DhtEvent::PeerEvicted(peer_data) => {
+                // Peer is gone from the DHT: forget its connection.
+                let maybe_connection_id = self.connection_map.remove(&peer_data.peer_uri);
+                // Space gateways only forget it: the network connection is shared by
+                // every space, the network layer closes it when the node times out.
+                // Network gateway closes it, in case it was reopened after the timeout.
+                if self.identifier == NETWORK_GATEWAY_ID {
+                    if let Some(connection_id) = maybe_connection_id {
+                        if let Err(e) = lock_handle(&self.inner_transport)?.close(&connection_id) {
+                            debug!(
+                                "({}) connection {} of evicted peer already closed: {:?}",
+                                self.identifier, connection_id, e,
+                            );
+                        }
+                    }
+                }
+            }
             DhtEvent::HoldEntryRequested(_from, _data) => {
                 // no-op
             }
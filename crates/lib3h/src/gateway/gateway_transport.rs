@@ -3,7 +3,8 @@
 use crate::{
     dht::{dht_protocol::*, dht_trait::Dht},
     engine::p2p_protocol::P2pProtocol,
-    gateway::P2pGateway,
+    gateway::{lock_handle, P2pGateway},
+    time,
     transport::{
         error::{TransportError, TransportResult},
         protocol::{TransportCommand, TransportEvent},
@@ -11,9 +12,10 @@ use crate::{
         ConnectionId, ConnectionIdRef,
     },
 };
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use url::Url;
 
 /// Compose Transport
@@ -22,7 +24,7 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
     fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
         trace!("({}).connect() {}", self.identifier, uri);
         // Connect
-        let connection_id = self.inner_transport.borrow_mut().connect(&uri)?;
+        let connection_id = lock_handle(&self.inner_transport)?.connect(&uri)?;
         // Store result in connection map
         self.connection_map
             .insert(uri.clone(), connection_id.clone());
@@ -32,18 +34,18 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
 
     // TODO #176 - remove conn id conn_map??
     fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
-        self.inner_transport.borrow_mut().close(id)
+        lock_handle(&self.inner_transport)?.close(id)
     }
 
     // TODO #176
     fn close_all(&mut self) -> TransportResult<()> {
-        self.inner_transport.borrow_mut().close_all()
+        lock_handle(&self.inner_transport)?.close_all()
     }
 
     /// id_list =
     ///   - Network : transportId
     ///   - space   : agentId
-    fn send(&mut self, dht_id_list: &[&ConnectionIdRef], payload: &[u8]) -> TransportResult<()> {
+    fn send(&mut self, dht_id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
         // get connectionId from the inner dht first
         let dht_uri_list = self.dht_address_to_uri_list(dht_id_list)?;
         // send
@@ -68,11 +70,11 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
         }
         let ref_list: Vec<&str> = conn_list.iter().map(|v| v.as_str()).collect();
         // Send on the inner Transport
-        self.inner_transport.borrow_mut().send(&ref_list, payload)
+        lock_handle(&self.inner_transport)?.send(&ref_list, payload)
     }
 
     ///
-    fn send_all(&mut self, payload: &[u8]) -> TransportResult<()> {
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
         let connection_list = self.connection_id_list()?;
         let dht_id_list: Vec<&str> = connection_list.iter().map(|v| &**v).collect();
         trace!("({}) send_all() {:?}", self.identifier, dht_id_list);
@@ -82,7 +84,7 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
     ///
     fn bind(&mut self, url: &Url) -> TransportResult<Url> {
         trace!("({}) bind() {}", self.identifier, url);
-        self.inner_transport.borrow_mut().bind(url)
+        lock_handle(&self.inner_transport)?.bind(url)
     }
 
     ///
@@ -118,7 +120,7 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
         // Transport::process() on the network gateway,
         // otherwise remove this code and have RealEngine explicitly call the process of the
         // Network transport.
-        let (inner_did_work, mut event_list) = self.inner_transport.borrow_mut().process()?;
+        let (inner_did_work, mut event_list) = lock_handle(&self.inner_transport)?.process()?;
         trace!(
             "({}).Transport.inner_process() - output: {} {}",
             self.identifier,
@@ -130,12 +132,33 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
             outbox.append(&mut event_list);
         }
         // Handle TransportEvents
-        for evt in outbox.clone().iter() {
-            self.handle_TransportEvent(&evt)?;
+        for evt in outbox.iter() {
+            self.handle_TransportEvent(evt)?;
         }
         Ok((did_work, outbox))
     }
 
+    /// Pending commands or whatever the inner transport needs
+    fn next_deadline_ms(&self) -> Option<u64> {
+        let inner_deadline = match lock_handle(&self.inner_transport) {
+            Ok(inner_transport) => inner_transport.next_deadline_ms(),
+            // process() right away, so it reports the poisoned lock
+            Err(_) => return Some(time::since_epoch_ms()),
+        };
+        if self.transport_inbox.is_empty() {
+            return inner_deadline;
+        }
+        time::earliest_deadline(Some(time::since_epoch_ms()), inner_deadline)
+    }
+
+    /// Wait on the inner transport, unless commands are pending
+    fn wait_ready(&mut self, max_wait: Duration) -> TransportResult<()> {
+        if !self.transport_inbox.is_empty() {
+            return Ok(());
+        }
+        lock_handle(&self.inner_transport)?.wait_ready(max_wait)
+    }
+
     /// A Gateway uses its inner_dht's peerData.peer_address as connectionId
     fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
         let peer_data_list = self.inner_dht.get_peer_list();
@@ -148,10 +171,16 @@ impl<T: Transport, D: Dht> Transport for P2pGateway<T, D> {
 
     /// TODO: return a higher-level uri instead
     fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
-        self.inner_transport.borrow().get_uri(id)
+        lock_handle(&self.inner_transport).ok()?.get_uri(id)
         //let maybe_peer_data = self.inner_dht.get_peer(id);
         //maybe_peer_data.map(|pd| pd.peer_address)
     }
+
+    fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        lock_handle(&self.inner_transport)
+            .ok()?
+            .get_remote_certificate(id)
+    }
 }
 
 /// Private internals
@@ -184,6 +213,25 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
         }
         let uri = maybe_uri.unwrap();
         trace!("({}) new_connection: {} -> {}", self.identifier, uri, id,);
+        if let Some(certificate_verifier) = &self.certificate_verifier {
+            // No certificate means no transport id: it cannot be let in either
+            let maybe_transport_id = self
+                .get_remote_certificate(id)
+                .and_then(|certificate| certificate_verifier(&certificate));
+            match maybe_transport_id {
+                Some(transport_id) => {
+                    self.bound_transport_id_map
+                        .insert(id.to_string(), transport_id);
+                }
+                None => {
+                    error!(
+                        "({}) Closing connection {} ({}): no certificate bound to a transport id",
+                        self.identifier, id, uri
+                    );
+                    return lock_handle(&self.inner_transport)?.close(id);
+                }
+            }
+        }
         // TODO #176 - Maybe we shouldn't have different code paths for populating
         // the connection_map between space and network gateways.
         let maybe_previous = self.connection_map.insert(uri.clone(), id.to_string());
@@ -208,7 +256,7 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
             our_peer_address,
             id,
         );
-        return self.inner_transport.borrow_mut().send(&[&id], &buf);
+        return lock_handle(&self.inner_transport)?.send(&[&id], &buf.into());
     }
 
     /// Process a transportEvent received from our internal connection.
@@ -224,7 +272,8 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
                     "({}) Connection Error for {}: {}\n Closing connection.",
                     self.identifier, id, e,
                 );
-                self.inner_transport.borrow_mut().close(id)?;
+                self.bound_transport_id_map.remove(id);
+                lock_handle(&self.inner_transport)?.close(id)?;
             }
             TransportEvent::ConnectResult(id) => {
                 info!("({}) Outgoing connection opened: {}", self.identifier, id);
@@ -234,7 +283,8 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
                 info!("({}) Incoming connection opened: {}", self.identifier, id);
                 self.handle_new_connection(id)?;
             }
-            TransportEvent::ConnectionClosed(_id) => {
+            TransportEvent::ConnectionClosed(id) => {
+                self.bound_transport_id_map.remove(id);
                 // TODO #176
             }
             TransportEvent::ReceivedData(connection_id, payload) => {
@@ -251,13 +301,23 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
                             "Received PeerAddress: {} | {} ({})",
                             peer_address, gateway_id, self.identifier
                         );
-                        let peer_uri = self
-                            .inner_transport
-                            .borrow_mut()
+                        let peer_uri = lock_handle(&self.inner_transport)?
                             .get_uri(connection_id)
                             .expect("FIXME"); // TODO #58
                         debug!("peer_uri of: {} = {}", connection_id, peer_uri);
                         if self.identifier == gateway_id {
+                            // Without a verified binding, no PeerAddress matches
+                            let is_mismatch = self.certificate_verifier.is_some()
+                                && self.bound_transport_id_map.get(connection_id)
+                                    != Some(&peer_address);
+                            if is_mismatch {
+                                error!(
+                                    "({}) Closing connection {}: PeerAddress {} does not match its certificate",
+                                    self.identifier, connection_id, peer_address
+                                );
+                                self.bound_transport_id_map.remove(connection_id);
+                                return lock_handle(&self.inner_transport)?.close(connection_id);
+                            }
                             let peer = PeerData {
                                 peer_address: peer_address.clone(),
                                 peer_uri,
@@ -318,3 +378,146 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::{dht_trait::DhtConfig, mirror_dht::MirrorDht};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    /// Transport whose connections present the certificates it is given
+    struct CertificateTransport {
+        certificate_map: HashMap<ConnectionId, Vec<u8>>,
+        closed_list: Vec<ConnectionId>,
+    }
+
+    impl Transport for CertificateTransport {
+        fn connect(&mut self, _uri: &Url) -> TransportResult<ConnectionId> {
+            Err(TransportError::new("not supported".to_string()))
+        }
+        fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
+            self.closed_list.push(id.to_string());
+            Ok(())
+        }
+        fn close_all(&mut self) -> TransportResult<()> {
+            Ok(())
+        }
+        fn send(
+            &mut self,
+            _id_list: &[&ConnectionIdRef],
+            _payload: &Opaque,
+        ) -> TransportResult<()> {
+            Ok(())
+        }
+        fn send_all(&mut self, _payload: &Opaque) -> TransportResult<()> {
+            Ok(())
+        }
+        fn bind(&mut self, url: &Url) -> TransportResult<Url> {
+            Ok(url.clone())
+        }
+        fn post(&mut self, _command: TransportCommand) -> TransportResult<()> {
+            Ok(())
+        }
+        fn process(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)> {
+            Ok((false, vec![]))
+        }
+        fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
+            Ok(vec![])
+        }
+        fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
+            Url::parse(&format!("mem://{}", id)).ok()
+        }
+        fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+            self.certificate_map.get(id).cloned()
+        }
+    }
+
+    fn peer_address_message(gateway_id: &str, peer_address: &str) -> TransportEvent {
+        let mut buf = Vec::new();
+        P2pProtocol::PeerAddress(
+            gateway_id.to_string(),
+            peer_address.to_string(),
+            time::since_epoch_ms(),
+        )
+        .serialize(&mut Serializer::new(&mut buf))
+        .unwrap();
+        TransportEvent::ReceivedData("alex_conn".to_string(), buf.into())
+    }
+
+    #[test]
+    fn test_certificate_binding_is_enforced() {
+        let mut certificate_map = HashMap::new();
+        certificate_map.insert("alex_conn".to_string(), b"bound to alex".to_vec());
+        certificate_map.insert("forged_conn".to_string(), b"garbage".to_vec());
+        let transport = Arc::new(Mutex::new(CertificateTransport {
+            certificate_map,
+            closed_list: Vec::new(),
+        }));
+        let dht_config = DhtConfig::new("this_peer", &Url::parse("mem://this_peer").unwrap());
+        let mut gateway = P2pGateway::new(
+            "__network__",
+            Arc::clone(&transport),
+            MirrorDht::new_with_config,
+            &dht_config,
+        );
+        gateway.set_certificate_verifier(Box::new(|certificate| {
+            if certificate == b"bound to alex" {
+                Some("alex".to_string())
+            } else {
+                None
+            }
+        }));
+        let closed_list = || transport.lock().unwrap().closed_list.clone();
+
+        // No certificate, or one not bound to a transport id
+        for id in &["no_certificate_conn", "forged_conn"] {
+            gateway
+                .handle_TransportEvent(&TransportEvent::IncomingConnectionEstablished(
+                    id.to_string(),
+                ))
+                .unwrap();
+            assert!(closed_list().contains(&id.to_string()));
+        }
+
+        // A bound connection claiming another PeerAddress
+        gateway
+            .handle_TransportEvent(&TransportEvent::IncomingConnectionEstablished(
+                "alex_conn".to_string(),
+            ))
+            .unwrap();
+        assert!(!closed_list().contains(&"alex_conn".to_string()));
+        gateway
+            .handle_TransportEvent(&peer_address_message("__network__", "billy"))
+            .unwrap();
+        assert!(closed_list().contains(&"alex_conn".to_string()));
+        assert!(gateway.inner_dht.get_peer("billy").is_none());
+
+        // Once the binding is gone, not even the right PeerAddress is accepted
+        gateway
+            .handle_TransportEvent(&peer_address_message("__network__", "alex"))
+            .unwrap();
+        assert!(gateway.inner_dht.get_peer("alex").is_none());
+        assert_eq!(
+            closed_list().iter().filter(|id| *id == "alex_conn").count(),
+            2
+        );
+
+        // A bound connection with its own PeerAddress is held
+        gateway
+            .handle_TransportEvent(&TransportEvent::IncomingConnectionEstablished(
+                "alex_conn".to_string(),
+            ))
+            .unwrap();
+        gateway
+            .handle_TransportEvent(&peer_address_message("__network__", "alex"))
+            .unwrap();
+        assert!(gateway.inner_dht.get_peer("alex").is_some());
+        assert_eq!(
+            closed_list().iter().filter(|id| *id == "alex_conn").count(),
+            2
+        );
+    }
+}
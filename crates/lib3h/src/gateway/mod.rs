@@ -3,23 +3,48 @@ pub mod gateway_transport;
 pub mod p2p_gateway;
 
 use crate::{
-    dht::dht_trait::Dht,
-    transport::{protocol::TransportCommand, transport_trait::Transport, ConnectionId},
+    dht::{dht_protocol::DhtSnapshot, dht_trait::Dht},
+    transport::{
+        error::{TransportError, TransportResult},
+        protocol::TransportCommand,
+        transport_trait::Transport,
+        ConnectionId,
+    },
 };
 use std::{
-    cell::RefCell,
-    collections::{HashMap, VecDeque},
-    rc::Rc,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
 };
 
 use url::Url;
 
+/// Shareable handle to a Transport.
+/// Space gateways use it to reach the network gateway they are layered on.
+/// It is `Send` as long as the Transport is, so engines can move across threads.
+///
+/// Lock order: space gateway -> network gateway -> network transport.
+/// While holding a handle, only lock the handles after it in that order.
+/// `Mutex` is not re-entrant: locking a handle already held by the same thread
+/// deadlocks, where the former `RefCell` panicked.
+pub type TransportHandle<T> = Arc<Mutex<T>>;
+
+/// Lock a TransportHandle, see its lock order.
+/// Fails if a thread panicked while holding the lock, instead of panicking too.
+pub(crate) fn lock_handle<T>(handle: &TransportHandle<T>) -> TransportResult<MutexGuard<'_, T>> {
+    handle
+        .lock()
+        .map_err(|_| TransportError::new("transport handle lock is poisoned".to_string()))
+}
+
+/// Transport id a DER certificate is validly bound to, None if it is not
+pub type CertificateVerifier = Box<dyn Fn(&[u8]) -> Option<String> + Send>;
+
 /// Gateway to a P2P network.
 /// Combines a transport and a DHT.
 /// Tracks distributed data for that P2P network in a DHT.
 /// P2pGateway should not `post() & process()` its inner transport but call it synchrounously.
 pub struct P2pGateway<T: Transport, D: Dht> {
-    inner_transport: Rc<RefCell<T>>,
+    inner_transport: TransportHandle<T>,
     inner_dht: D,
     /// Used for distinguishing gateways
     identifier: String,
@@ -27,4 +52,38 @@ pub struct P2pGateway<T: Transport, D: Dht> {
     connection_map: HashMap<Url, ConnectionId>,
     /// Own inbox for TransportCommands which is processed during Transport::process()
     transport_inbox: VecDeque<TransportCommand>,
+    /// Checks the remote certificate of new inner connections, if set
+    certificate_verifier: Option<CertificateVerifier>,
+    /// Transport id of each inner connection, as verified from its remote certificate.
+    /// The PeerAddress received over that connection must match it.
+    bound_transport_id_map: HashMap<ConnectionId, String>,
+}
+
+/// Diagnostic snapshot of a P2pGateway
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct GatewaySnapshot {
+    pub identifier: String,
+    /// peer uri -> connectionId of the inner transport
+    pub connection_map: BTreeMap<String, ConnectionId>,
+    /// Number of TransportCommands waiting to be processed
+    pub transport_inbox_depth: usize,
+    pub dht: DhtSnapshot,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_handle_poisoned() {
+        let handle: TransportHandle<u32> = Arc::new(Mutex::new(42));
+        assert_eq!(*lock_handle(&handle).unwrap(), 42);
+        let poisoner = Arc::clone(&handle);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(lock_handle(&handle).is_err());
+    }
 }
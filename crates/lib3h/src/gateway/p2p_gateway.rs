@@ -2,15 +2,11 @@
 
 use crate::{
     dht::dht_trait::{Dht, DhtConfig, DhtFactory},
-    gateway::P2pGateway,
+    gateway::{CertificateVerifier, GatewaySnapshot, P2pGateway, TransportHandle},
     transport::transport_trait::Transport,
 };
 use lib3h_protocol::Address;
-use std::{
-    cell::RefCell,
-    collections::{HashMap, VecDeque},
-    rc::Rc,
-};
+use std::collections::{HashMap, VecDeque};
 
 /// Public interface
 impl<T: Transport, D: Dht> P2pGateway<T, D> {
@@ -18,6 +14,21 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
     pub fn identifier(&self) -> &str {
         self.identifier.as_str()
     }
+
+    /// Snapshot of this Gateway's state for diagnostics.
+    /// Does not lock the inner transport.
+    pub fn gateway_snapshot(&self) -> GatewaySnapshot {
+        GatewaySnapshot {
+            identifier: self.identifier.clone(),
+            connection_map: self
+                .connection_map
+                .iter()
+                .map(|(uri, id)| (uri.to_string(), id.clone()))
+                .collect(),
+            transport_inbox_depth: self.transport_inbox.len(),
+            dht: self.inner_dht.dht_snapshot(),
+        }
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -30,7 +41,7 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
     /// Bind and set advertise on construction by using the name as URL.
     pub fn new(
         identifier: &str,
-        inner_transport: Rc<RefCell<T>>,
+        inner_transport: TransportHandle<T>,
         dht_factory: DhtFactory<D>,
         dht_config: &DhtConfig,
     ) -> Self {
@@ -40,9 +51,17 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
             identifier: identifier.to_owned(),
             connection_map: HashMap::new(),
             transport_inbox: VecDeque::new(),
+            certificate_verifier: None,
+            bound_transport_id_map: HashMap::new(),
         }
     }
 
+    /// Close new inner connections whose remote certificate is not bound to a transport id,
+    /// and those whose PeerAddress is not that transport id
+    pub fn set_certificate_verifier(&mut self, certificate_verifier: CertificateVerifier) {
+        self.certificate_verifier = Some(certificate_verifier);
+    }
+
     /// Helper for getting a connectionId from a peer_address
     pub(crate) fn get_connection_id(&self, peer_address: &str) -> Option<String> {
         // get peer_uri
@@ -78,7 +97,7 @@ impl<T: Transport, D: Dht> P2pGateway<T, D> {
 impl<T: Transport, D: Dht> P2pGateway<P2pGateway<T, D>, D> {
     /// Constructors
     pub fn new_with_space(
-        network_gateway: Rc<RefCell<P2pGateway<T, D>>>,
+        network_gateway: TransportHandle<P2pGateway<T, D>>,
         space_address: &Address,
         dht_factory: DhtFactory<D>,
         dht_config: &DhtConfig,
@@ -90,6 +109,8 @@ impl<T: Transport, D: Dht> P2pGateway<P2pGateway<T, D>, D> {
             identifier,
             connection_map: HashMap::new(),
             transport_inbox: VecDeque::new(),
+            certificate_verifier: None,
+            bound_transport_id_map: HashMap::new(),
         }
     }
 }
@@ -1,9 +1,23 @@
 extern crate hcid;
 extern crate lib3h_crypto_api;
 extern crate lib3h_protocol;
+#[cfg(target_os = "linux")]
+extern crate libc;
+#[cfg(feature = "system-tls")]
 extern crate native_tls;
+#[cfg(feature = "system-tls")]
+extern crate openssl;
+extern crate rayon;
+extern crate rcgen;
+extern crate ring;
+#[cfg(feature = "rustls-tls")]
+extern crate rustls;
 extern crate tungstenite;
+extern crate untrusted;
 extern crate url_serde;
+#[cfg(feature = "rustls-tls")]
+extern crate webpki;
+extern crate x509_parser;
 #[macro_use]
 extern crate lazy_static;
 #[cfg(test)]
@@ -13,6 +27,7 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate rmp_serde;
+extern crate serde_json;
 #[macro_use]
 extern crate log;
 
@@ -24,6 +39,8 @@ pub mod error;
 pub mod gateway;
 pub mod time;
 pub mod transport;
+pub mod transport_tcp;
+pub mod transport_udp;
 pub mod transport_wss;
 
 #[cfg(test)]
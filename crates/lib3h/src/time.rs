@@ -13,6 +13,15 @@ pub fn since_epoch_ms() -> u64 {
     in_ms
 }
 
+/// Return the earliest of two optional deadlines
+pub fn earliest_deadline(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -27,4 +36,12 @@ pub mod tests {
         assert!(second > first);
         assert!(second >= 8);
     }
+
+    #[test]
+    pub fn test_earliest_deadline() {
+        assert_eq!(earliest_deadline(None, None), None);
+        assert_eq!(earliest_deadline(Some(42), None), Some(42));
+        assert_eq!(earliest_deadline(None, Some(42)), Some(42));
+        assert_eq!(earliest_deadline(Some(42), Some(7)), Some(7));
+    }
 }
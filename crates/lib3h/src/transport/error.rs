@@ -63,12 +63,20 @@ impl From<tungstenite::Error> for TransportError {
     }
 }
 
+#[cfg(feature = "system-tls")]
 impl From<native_tls::Error> for TransportError {
     fn from(error: native_tls::Error) -> Self {
         Self(format!("{:?}", error))
     }
 }
 
+#[cfg(feature = "system-tls")]
+impl From<openssl::error::ErrorStack> for TransportError {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        Self(format!("{:?}", error))
+    }
+}
+
 impl<S: std::fmt::Debug + std::io::Read + std::io::Write>
     From<HandshakeError<ServerHandshake<S, NoCallback>>> for TransportError
 {
@@ -77,6 +85,7 @@ impl<S: std::fmt::Debug + std::io::Read + std::io::Write>
     }
 }
 
+#[cfg(feature = "system-tls")]
 impl<T: std::io::Read + std::io::Write + std::fmt::Debug> From<native_tls::HandshakeError<T>>
     for TransportError
 {
@@ -3,7 +3,7 @@ use crate::transport::{
     protocol::TransportEvent,
     ConnectionId, ConnectionIdRef,
 };
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 use std::{
     collections::{HashMap, VecDeque},
     sync::{Mutex, RwLock},
@@ -56,7 +56,7 @@ pub struct MemoryServer {
     /// Address of this server
     this_uri: Url,
     /// Inboxes for payloads from each of its connections.
-    inbox_map: HashMap<Url, VecDeque<Vec<u8>>>,
+    inbox_map: HashMap<Url, VecDeque<Opaque>>,
     /// Inbox of connection state change requests
     /// (true = incoming connection, false = connection closed)
     connection_inbox: Vec<(ConnectionId, bool)>,
@@ -147,7 +147,7 @@ impl MemoryServer {
     }
 
     /// Receive payload from another node, i.e. fill our inbox for this connectionId
-    pub fn post(&mut self, from_uri: &Url, payload: &[u8]) -> TransportResult<()> {
+    pub fn post(&mut self, from_uri: &Url, payload: &Opaque) -> TransportResult<()> {
         let maybe_inbox = self.inbox_map.get_mut(from_uri);
         if let None = maybe_inbox {
             return Err(TransportError::new(format!(
@@ -155,7 +155,7 @@ impl MemoryServer {
                 self.this_uri, from_uri
             )));
         }
-        maybe_inbox.unwrap().push_back(payload.to_vec());
+        maybe_inbox.unwrap().push_back(payload.clone());
         Ok(())
     }
 
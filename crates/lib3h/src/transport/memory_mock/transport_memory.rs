@@ -1,11 +1,11 @@
 use crate::transport::{
     error::{TransportError, TransportResult},
     memory_mock::memory_server,
-    protocol::{TransportCommand, TransportEvent},
+    protocol::{TransportCommand, TransportEvent, TransportSnapshot},
     transport_trait::Transport,
     ConnectionId, ConnectionIdRef,
 };
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     sync::{Arc, Mutex},
@@ -195,7 +195,7 @@ impl Transport for TransportMemory {
     }
 
     /// Send payload to known connectionIds in `id_list`
-    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &[u8]) -> TransportResult<()> {
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
         if self.maybe_my_uri.is_none() {
             return Err(TransportError::new(
                 "Cannot send before bounding".to_string(),
@@ -230,7 +230,7 @@ impl Transport for TransportMemory {
     }
 
     /// Send to all known connectionIds
-    fn send_all(&mut self, payload: &[u8]) -> TransportResult<()> {
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
         let id_list = self.connection_id_list()?;
         for id in id_list {
             self.send(&[id.as_str()], payload)?;
@@ -326,10 +326,7 @@ impl Transport for TransportMemory {
                         .inbound_connection_map
                         .get(&in_cid)
                         .expect("Should have outbound at this stage");
-                    outbox.push(TransportEvent::ReceivedData(
-                        out_cid.to_string(),
-                        data.clone(),
-                    ));
+                    outbox.push(TransportEvent::ReceivedData(out_cid.to_string(), data));
                 }
                 // We are not expecting anything else from the MemoryServer
                 _ => unreachable!(),
@@ -338,6 +335,20 @@ impl Transport for TransportMemory {
         // Done
         Ok((did_work, outbox))
     }
+
+    /// Only pending commands. Incoming data is polled from the memory servers.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if self.cmd_inbox.is_empty() {
+            return None;
+        }
+        Some(crate::time::since_epoch_ms())
+    }
+
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        let mut snapshot = TransportSnapshot::from_transport(self);
+        snapshot.inbox_depth = self.cmd_inbox.len();
+        snapshot
+    }
 }
 
 impl TransportMemory {
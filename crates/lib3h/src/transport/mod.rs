@@ -1,7 +1,9 @@
 //! common types and traits for working with Transport instances
 pub mod error;
 pub mod memory_mock;
+pub mod poll;
 pub mod protocol;
+pub mod transport_composite;
 pub mod transport_crypto;
 pub mod transport_trait;
 
@@ -16,8 +18,11 @@ pub mod tests {
 
     use crate::{
         transport::{
-            memory_mock::transport_memory, protocol::TransportEvent, transport_trait::Transport,
+            memory_mock::transport_memory, protocol::TransportEvent,
+            transport_composite::TransportComposite, transport_trait::Transport,
         },
+        transport_tcp::TransportTcp,
+        transport_udp::TransportUdp,
         transport_wss::{TlsConfig, TransportWss},
     };
 
@@ -45,23 +50,172 @@ pub mod tests {
         enable_logging_for_test(true);
         let mut node_A = TransportWss::with_std_tcp_stream(TlsConfig::Unencrypted);
         let mut node_B = TransportWss::with_std_tcp_stream(TlsConfig::Unencrypted);
-        let uri_A = Url::parse("wss://127.0.0.1:64529/A").unwrap();
-        let uri_B = Url::parse("wss://127.0.0.1:64530/B").unwrap();
+        let uri_A = Url::parse("wss://127.0.0.1:0/A").unwrap();
+        let uri_B = Url::parse("wss://127.0.0.1:0/B").unwrap();
 
         send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
     }
 
+    #[test]
+    fn wss_send_test_ipv6() {
+        enable_logging_for_test(true);
+        let mut node_A = TransportWss::with_std_tcp_stream(TlsConfig::Unencrypted);
+        let mut node_B = TransportWss::with_std_tcp_stream(TlsConfig::Unencrypted);
+        let uri_A = Url::parse("ws://[::1]:0/A").unwrap();
+        let uri_B = Url::parse("ws://[::1]:0/B").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+        let idBA = node_B.connection_id_list().unwrap()[0].clone();
+        assert_eq!(
+            node_B.get_uri(&idBA).unwrap().host(),
+            Some(url::Host::Ipv6("::1".parse().unwrap()))
+        );
+    }
+
     #[test]
     fn wss_send_test_tls() {
         enable_logging_for_test(true);
         let mut node_A = TransportWss::with_std_tcp_stream(TlsConfig::FakeServer);
         let mut node_B = TransportWss::with_std_tcp_stream(TlsConfig::FakeServer);
-        let uri_A = Url::parse("wss://127.0.0.1:64531/TLS_A").unwrap();
-        let uri_B = Url::parse("wss://127.0.0.1:64532/TLS_B").unwrap();
+        let uri_A = Url::parse("wss://127.0.0.1:0/TLS_A").unwrap();
+        let uri_B = Url::parse("wss://127.0.0.1:0/TLS_B").unwrap();
 
         send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
     }
 
+    // Only the rustls backend can require client certificates
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn wss_send_test_mutual_tls() {
+        use crate::transport_wss::{certificate::generate_bound_for_test, trust::TlsTrustPolicy};
+        enable_logging_for_test(true);
+        let (id_A, certificate_A) = generate_bound_for_test();
+        let (id_B, certificate_B) = generate_bound_for_test();
+        let mut node_A =
+            TransportWss::with_std_tcp_stream(TlsConfig::SuppliedCertificate(certificate_A));
+        let mut node_B =
+            TransportWss::with_std_tcp_stream(TlsConfig::SuppliedCertificate(certificate_B));
+        node_A.set_trust_policy(Some(TlsTrustPolicy::TransportIds(vec![id_B.clone()])));
+        node_B.set_trust_policy(Some(TlsTrustPolicy::TransportIds(vec![id_A.clone()])));
+        let uri_A = Url::parse("wss://127.0.0.1:0/MTLS_A").unwrap();
+        let uri_B = Url::parse("wss://127.0.0.1:0/MTLS_B").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+
+        // Both ends know who they are talking to
+        let idAB = node_A.connection_id_list().unwrap()[0].clone();
+        let identity = node_A.get_peer_identity(&idAB).unwrap();
+        assert!(identity.is_trusted);
+        assert_eq!(identity.common_name, Some(id_B));
+        let idBA = node_B.connection_id_list().unwrap()[0].clone();
+        let identity = node_B.get_peer_identity(&idBA).unwrap();
+        assert!(identity.is_trusted);
+        assert_eq!(identity.common_name, Some(id_A));
+    }
+
+    #[test]
+    fn tcp_send_test() {
+        enable_logging_for_test(true);
+        let mut node_A = TransportTcp::with_std_tcp_stream(TlsConfig::Unencrypted);
+        let mut node_B = TransportTcp::with_std_tcp_stream(TlsConfig::Unencrypted);
+        let uri_A = Url::parse("tcp://127.0.0.1:0").unwrap();
+        let uri_B = Url::parse("tcp://127.0.0.1:0").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+    }
+
+    #[test]
+    fn tcp_send_test_tls() {
+        enable_logging_for_test(true);
+        let mut node_A = TransportTcp::with_std_tcp_stream(TlsConfig::FakeServer);
+        let mut node_B = TransportTcp::with_std_tcp_stream(TlsConfig::FakeServer);
+        let uri_A = Url::parse("tcp://127.0.0.1:0").unwrap();
+        let uri_B = Url::parse("tcp://127.0.0.1:0").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+        let idAB = node_A.connection_id_list().unwrap()[0].clone();
+        let identity = node_A.get_peer_identity(&idAB).unwrap();
+        assert_eq!(identity.common_name, Some("holo.host".to_string()));
+        assert!(!identity.is_trusted);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_send_test() {
+        use crate::transport_tcp::unix::TransportUnix;
+        enable_logging_for_test(true);
+        let mut node_A = TransportUnix::with_std_unix_stream(TlsConfig::Unencrypted);
+        let mut node_B = TransportUnix::with_std_unix_stream(TlsConfig::Unencrypted);
+        let dir = std::env::temp_dir();
+        let uri = |name: &str| {
+            let path = dir.join(format!(
+                "lib3h_unix_send_test_{}_{}.sock",
+                std::process::id(),
+                name
+            ));
+            Url::parse(&format!("unix://{}", path.display())).unwrap()
+        };
+
+        send_test(&mut node_A, &mut node_B, &uri("A"), &uri("B"));
+    }
+
+    #[test]
+    fn udp_send_test() {
+        enable_logging_for_test(true);
+        let mut node_A = TransportUdp::new();
+        let mut node_B = TransportUdp::new();
+        let uri_A = Url::parse("udp://127.0.0.1:0").unwrap();
+        let uri_B = Url::parse("udp://127.0.0.1:0").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+    }
+
+    #[test]
+    fn composite_send_test() {
+        enable_logging_for_test(true);
+        let composite = || {
+            let mut transport = TransportComposite::new();
+            transport
+                .add_transport(
+                    &["tcp"],
+                    None,
+                    Box::new(TransportTcp::with_std_tcp_stream(TlsConfig::Unencrypted)),
+                )
+                .unwrap();
+            let udp_url = Url::parse("udp://127.0.0.1:0").unwrap();
+            transport
+                .add_transport(&["udp"], Some(udp_url), Box::new(TransportUdp::new()))
+                .unwrap();
+            transport
+        };
+        let mut node_A = composite();
+        let mut node_B = composite();
+        let uri_A = Url::parse("tcp://127.0.0.1:0").unwrap();
+        let uri_B = Url::parse("tcp://127.0.0.1:0").unwrap();
+
+        send_test(&mut node_A, &mut node_B, &uri_A, &uri_B);
+
+        // The udp transports were bound too
+        let bound_url_list = node_B.bound_url_list();
+        assert_eq!(bound_url_list.len(), 2);
+        let udp_url_B = bound_url_list
+            .iter()
+            .find(|url| url.scheme() == "udp")
+            .unwrap();
+        assert_ne!(udp_url_B.port(), Some(0));
+        let idAB_udp = node_A.connect(udp_url_B).unwrap();
+        let mut event_list = Vec::new();
+        for _x in 0..NUM_PROCESS_LOOPS {
+            let (_did_work_B, mut event_list_B) = node_B.process().unwrap();
+            let (_did_work_A, mut event_list_A) = node_A.process().unwrap();
+            event_list.append(&mut event_list_B);
+            event_list.append(&mut event_list_A);
+        }
+        assert!(event_list.contains(&TransportEvent::ConnectResult(idAB_udp.clone())));
+        assert_eq!(node_A.connection_id_list().unwrap().len(), 2);
+        assert_eq!(node_B.connection_id_list().unwrap().len(), 2);
+    }
+
     fn send_test(
         node_A: &mut impl Transport,
         node_B: &mut impl Transport,
@@ -79,7 +233,7 @@ pub mod tests {
 
         // Send A -> B
         let payload = [1, 2, 3, 4];
-        node_A.send(&[&idAB], &payload).unwrap();
+        node_A.send(&[&idAB], &payload.to_vec().into()).unwrap();
         let mut did_work = false;
         let mut event_list = Vec::new();
 
@@ -119,7 +273,7 @@ pub mod tests {
         let id_list = node_B.connection_id_list().unwrap();
         // referencing node_B's connection list
         let idBA = id_list[0].clone();
-        node_B.send(&[&idBA], &payload).unwrap();
+        node_B.send(&[&idBA], &payload.to_vec().into()).unwrap();
         did_work = false;
         event_list.clear();
         for _x in 0..NUM_PROCESS_LOOPS {
@@ -0,0 +1,217 @@
+//! Readiness notification for sockets, so transports only touch the sockets
+//! that have I/O to do, and can sleep until one does.
+//! Backed by epoll on Linux. Elsewhere `Poller::new()` fails, and transports
+//! fall back to trying every socket on each `process()`.
+
+use std::{io, time::Duration};
+
+#[cfg(unix)]
+pub use std::os::unix::io::RawFd;
+/// Sockets have no file descriptor here, `Poller` is not supported anyway
+#[cfg(not(unix))]
+pub type RawFd = i32;
+
+/// Identifies a registered socket in the readiness events
+pub type Token = u64;
+
+/// What a socket is watched for. Level triggered: a socket keeps being
+/// reported as long as it stays readable (or writable).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interest {
+    pub readable: bool,
+    pub writable: bool,
+}
+
+impl Interest {
+    pub const READABLE: Interest = Interest {
+        readable: true,
+        writable: false,
+    };
+    pub const READABLE_WRITABLE: Interest = Interest {
+        readable: true,
+        writable: true,
+    };
+}
+
+#[cfg(target_os = "linux")]
+pub struct Poller {
+    epoll_fd: RawFd,
+}
+
+#[cfg(target_os = "linux")]
+impl Poller {
+    pub fn new() -> io::Result<Self> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Poller { epoll_fd })
+    }
+
+    pub fn register(&self, fd: RawFd, token: Token, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, fd, token, interest)
+    }
+
+    pub fn reregister(&self, fd: RawFd, token: Token, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, fd, token, interest)
+    }
+
+    /// Closing a socket deregisters it too
+    pub fn deregister(&self, fd: RawFd) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, fd, 0, Interest::READABLE)
+    }
+
+    /// Append the tokens of the ready sockets to `ready`, waiting up to `timeout`
+    /// for one, or forever if None.
+    pub fn wait(&self, timeout: Option<Duration>, ready: &mut Vec<Token>) -> io::Result<()> {
+        let timeout_ms = match timeout {
+            None => -1,
+            Some(timeout) => {
+                // Round up: a timeout under a millisecond must wait, not spin
+                let mut timeout_ms = timeout.as_millis();
+                if timeout.subsec_nanos() % 1_000_000 != 0 {
+                    timeout_ms += 1;
+                }
+                std::cmp::min(timeout_ms, i32::max_value() as u128) as i32
+            }
+        };
+        let mut event_list = vec![libc::epoll_event { events: 0, u64: 0 }; 256];
+        let count = unsafe {
+            libc::epoll_wait(
+                self.epoll_fd,
+                event_list.as_mut_ptr(),
+                event_list.len() as i32,
+                timeout_ms,
+            )
+        };
+        if count < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(error);
+        }
+        for event in &event_list[..count as usize] {
+            // copy out of the packed struct
+            let token = event.u64;
+            ready.push(token);
+        }
+        Ok(())
+    }
+
+    fn ctl(&self, op: i32, fd: RawFd, token: Token, interest: Interest) -> io::Result<()> {
+        let mut events = 0;
+        if interest.readable {
+            events |= libc::EPOLLIN | libc::EPOLLRDHUP;
+        }
+        if interest.writable {
+            events |= libc::EPOLLOUT;
+        }
+        let mut event = libc::epoll_event {
+            events: events as u32,
+            u64: token,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll_fd, op, fd, &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.epoll_fd);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct Poller {
+    _private: (),
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Poller {
+    pub fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "readiness polling is only supported on Linux",
+        ))
+    }
+
+    pub fn register(&self, _fd: RawFd, _token: Token, _interest: Interest) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn reregister(&self, _fd: RawFd, _token: Token, _interest: Interest) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn deregister(&self, _fd: RawFd) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn wait(&self, _timeout: Option<Duration>, _ready: &mut Vec<Token>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        os::unix::{io::AsRawFd, net::UnixStream},
+    };
+
+    #[test]
+    fn test_readiness() {
+        let poller = Poller::new().unwrap();
+        let (mut a, b) = UnixStream::pair().unwrap();
+        poller
+            .register(b.as_raw_fd(), 7, Interest::READABLE)
+            .unwrap();
+
+        // Nothing to read yet
+        let mut ready = Vec::new();
+        poller
+            .wait(Some(Duration::from_millis(0)), &mut ready)
+            .unwrap();
+        assert!(ready.is_empty());
+
+        // Timeouts under a millisecond still wait
+        let start = std::time::Instant::now();
+        poller
+            .wait(Some(Duration::from_micros(100)), &mut ready)
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_micros(100));
+        assert!(ready.is_empty());
+
+        // Reported once there is
+        a.write_all(&[1]).unwrap();
+        poller
+            .wait(Some(Duration::from_millis(1000)), &mut ready)
+            .unwrap();
+        assert_eq!(ready, vec![7]);
+
+        // An empty send buffer is writable right away
+        ready.clear();
+        poller
+            .reregister(b.as_raw_fd(), 8, Interest::READABLE_WRITABLE)
+            .unwrap();
+        poller
+            .wait(Some(Duration::from_millis(0)), &mut ready)
+            .unwrap();
+        assert_eq!(ready, vec![8]);
+
+        // Nothing is reported after deregistering
+        ready.clear();
+        poller.deregister(b.as_raw_fd()).unwrap();
+        poller
+            .wait(Some(Duration::from_millis(0)), &mut ready)
+            .unwrap();
+        assert!(ready.is_empty());
+    }
+}
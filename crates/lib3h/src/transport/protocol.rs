@@ -1,12 +1,13 @@
-use crate::transport::{error::TransportError, ConnectionId};
+use crate::transport::{error::TransportError, transport_trait::Transport, ConnectionId};
+use lib3h_protocol::opaque::Opaque;
 use url::Url;
 
 /// Commands that can be sent to an implementor of the Transport trait and handled during `process()`
 #[derive(Debug, PartialEq, Clone)]
 pub enum TransportCommand {
     Connect(Url),
-    Send(Vec<ConnectionId>, Vec<u8>),
-    SendAll(Vec<u8>),
+    Send(Vec<ConnectionId>, Opaque),
+    SendAll(Opaque),
     Close(ConnectionId),
     CloseAll,
     Bind(Url),
@@ -22,7 +23,47 @@ pub enum TransportEvent {
     /// we have received an incoming connection
     IncomingConnectionEstablished(ConnectionId),
     /// We have received data from a connection
-    ReceivedData(ConnectionId, Vec<u8>),
+    ReceivedData(ConnectionId, Opaque),
     /// A connection closed for whatever reason
     ConnectionClosed(ConnectionId),
 }
+
+/// Diagnostic snapshot of a Transport's connections
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TransportSnapshot {
+    /// Number of TransportCommands waiting to be processed
+    pub inbox_depth: usize,
+    pub connection_list: Vec<ConnectionSnapshot>,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ConnectionSnapshot {
+    pub id: ConnectionId,
+    pub uri: Option<String>,
+    /// Implementation specific connection state, e.g. "handshaking"
+    pub state: String,
+    /// Number of payloads waiting to be written on this connection
+    pub send_queue_depth: usize,
+}
+
+impl TransportSnapshot {
+    /// Build a snapshot from what the Transport trait getters expose.
+    /// Every listed connection is reported as "open".
+    pub fn from_transport<T: Transport + ?Sized>(transport: &T) -> Self {
+        let mut id_list = transport.connection_id_list().unwrap_or_default();
+        id_list.sort();
+        let connection_list = id_list
+            .into_iter()
+            .map(|id| ConnectionSnapshot {
+                uri: transport.get_uri(&id).map(|uri| uri.to_string()),
+                id,
+                state: "open".to_string(),
+                send_queue_depth: 0,
+            })
+            .collect();
+        TransportSnapshot {
+            inbox_depth: 0,
+            connection_list,
+        }
+    }
+}
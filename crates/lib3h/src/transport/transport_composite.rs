@@ -0,0 +1,473 @@
+use crate::transport::{
+    error::{TransportError, TransportResult},
+    protocol::{ConnectionSnapshot, TransportCommand, TransportEvent, TransportSnapshot},
+    transport_trait::Transport,
+    ConnectionId, ConnectionIdRef,
+};
+use lib3h_protocol::{opaque::Opaque, DidWork};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// Longest `wait_ready()` of each inner transport when there are several:
+/// one cannot tell whether another has incoming data while it waits
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+/// An inner transport and the url schemes routed to it
+struct Route {
+    scheme_list: Vec<String>,
+    /// Where `bind()` binds this transport, unless it is the one the bound url routes to
+    bind_url: Option<Url>,
+    /// What the transport's `bind()` returned
+    bound_url: Option<Url>,
+    transport: Box<dyn Transport>,
+}
+
+lazy_static! {
+    /// Counter of the number of TransportComposite that spawned
+    static ref TRANSPORT_COUNT: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+}
+
+/// Transport made of several inner transports, so a node can talk to remotes
+/// of different url schemes, e.g. `wss://` and `tcp://`.
+/// `connect()` picks the inner transport by the url's scheme, later calls go to
+/// the transport owning the connection. Inner connection ids are replaced by ids
+/// unique across all inner transports.
+pub struct TransportComposite {
+    route_list: Vec<Route>,
+    /// Mapping of connectionId -> (route index, inner connectionId)
+    id_map: HashMap<ConnectionId, (usize, ConnectionId)>,
+    /// Mapping of (route index, inner connectionId) -> connectionId
+    inner_id_map: HashMap<(usize, ConnectionId), ConnectionId>,
+    inbox: VecDeque<TransportCommand>,
+    own_id: u64,
+    n_id: u64,
+}
+
+impl TransportComposite {
+    pub fn new() -> Self {
+        let mut tc = TRANSPORT_COUNT
+            .lock()
+            .expect("could not lock transport count mutex");
+        *tc += 1;
+        TransportComposite {
+            route_list: Vec::new(),
+            id_map: HashMap::new(),
+            inner_id_map: HashMap::new(),
+            inbox: VecDeque::new(),
+            own_id: *tc,
+            n_id: 0,
+        }
+    }
+
+    /// Route the urls of `scheme_list` to `transport`.
+    /// `bind()` also binds it at `bind_url`, if any: transports without one
+    /// can only connect, unless the url given to `bind()` routes to them.
+    pub fn add_transport(
+        &mut self,
+        scheme_list: &[&str],
+        bind_url: Option<Url>,
+        transport: Box<dyn Transport>,
+    ) -> TransportResult<()> {
+        for scheme in scheme_list {
+            if self.priv_route(scheme).is_ok() {
+                return Err(TransportError::new(format!(
+                    "scheme {} is already routed",
+                    scheme
+                )));
+            }
+        }
+        self.route_list.push(Route {
+            scheme_list: scheme_list
+                .iter()
+                .map(|scheme| scheme.to_string())
+                .collect(),
+            bind_url,
+            bound_url: None,
+            transport,
+        });
+        Ok(())
+    }
+
+    /// Urls the inner transports are bound to since `bind()`,
+    /// e.g. with the ports the OS picked for port 0
+    pub fn bound_url_list(&self) -> Vec<Url> {
+        self.route_list
+            .iter()
+            .filter_map(|route| route.bound_url.clone())
+            .collect()
+    }
+
+    // -- private -- //
+
+    // index of the route handling this url scheme
+    fn priv_route(&self, scheme: &str) -> TransportResult<usize> {
+        self.route_list
+            .iter()
+            .position(|route| route.scheme_list.iter().any(|s| s == scheme))
+            .ok_or_else(|| TransportError::new(format!("no transport for scheme {}", scheme)))
+    }
+
+    // our connectionId for a connection of an inner transport, created on first sight.
+    // Only for connections being opened: see priv_known_outer_id() for the others
+    fn priv_outer_id(&mut self, index: usize, inner_id: &ConnectionIdRef) -> ConnectionId {
+        let key = (index, inner_id.to_string());
+        if let Some(id) = self.inner_id_map.get(&key) {
+            return id.clone();
+        }
+        self.n_id += 1;
+        let id = format!("composite{}_{}", self.own_id, self.n_id);
+        self.id_map.insert(id.clone(), key.clone());
+        self.inner_id_map.insert(key, id.clone());
+        id
+    }
+
+    // our connectionId for a connection of an inner transport, if we know it
+    fn priv_known_outer_id(
+        &self,
+        index: usize,
+        inner_id: &ConnectionIdRef,
+    ) -> Option<ConnectionId> {
+        self.inner_id_map
+            .get(&(index, inner_id.to_string()))
+            .cloned()
+    }
+
+    fn priv_forget(&mut self, id: &ConnectionIdRef) -> Option<(usize, ConnectionId)> {
+        let key = self.id_map.remove(id)?;
+        self.inner_id_map.remove(&key);
+        Some(key)
+    }
+
+    // serve the commands received through post()
+    fn priv_process_inbox(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)> {
+        let mut did_work = false;
+        let mut event_list = Vec::new();
+        while let Some(command) = self.inbox.pop_front() {
+            did_work = true;
+            match command {
+                TransportCommand::Connect(url) => {
+                    self.connect(&url)?;
+                }
+                TransportCommand::Send(id_list, payload) => {
+                    let id_ref_list: Vec<&ConnectionIdRef> =
+                        id_list.iter().map(|id| id.as_str()).collect();
+                    self.send(&id_ref_list, &payload)?;
+                }
+                TransportCommand::SendAll(payload) => self.send_all(&payload)?,
+                TransportCommand::Close(id) => {
+                    self.close(&id)?;
+                    event_list.push(TransportEvent::ConnectionClosed(id));
+                }
+                TransportCommand::CloseAll => {
+                    let id_list = self.connection_id_list()?;
+                    self.close_all()?;
+                    for id in id_list {
+                        event_list.push(TransportEvent::ConnectionClosed(id));
+                    }
+                }
+                TransportCommand::Bind(url) => {
+                    self.bind(&url)?;
+                }
+            }
+        }
+        Ok((did_work, event_list))
+    }
+
+    // rename the connectionIds of an inner transport's event.
+    // Only incoming connections get a new connectionId: events of connections
+    // we do not know, e.g. arriving after we closed them, are dropped.
+    fn priv_map_event(&mut self, index: usize, event: TransportEvent) -> Option<TransportEvent> {
+        let mapped_event = match event {
+            TransportEvent::IncomingConnectionEstablished(id) => {
+                TransportEvent::IncomingConnectionEstablished(self.priv_outer_id(index, &id))
+            }
+            TransportEvent::ErrorOccured(id, e) => match self.priv_known_outer_id(index, &id) {
+                Some(outer_id) => TransportEvent::ErrorOccured(outer_id, e),
+                None => {
+                    debug!(
+                        "transport_composite: error of unknown connection {}: {:?}",
+                        id, e
+                    );
+                    return None;
+                }
+            },
+            TransportEvent::ConnectResult(id) => {
+                TransportEvent::ConnectResult(self.priv_known_outer_id(index, &id)?)
+            }
+            TransportEvent::ReceivedData(id, payload) => {
+                TransportEvent::ReceivedData(self.priv_known_outer_id(index, &id)?, payload)
+            }
+            TransportEvent::ConnectionClosed(id) => {
+                let outer_id = self.priv_known_outer_id(index, &id)?;
+                self.priv_forget(&outer_id);
+                TransportEvent::ConnectionClosed(outer_id)
+            }
+        };
+        Some(mapped_event)
+    }
+}
+
+impl Default for TransportComposite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for TransportComposite {
+    /// connect with the transport routed by the uri's scheme
+    fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
+        let index = self.priv_route(uri.scheme())?;
+        let inner_id = self.route_list[index].transport.connect(uri)?;
+        Ok(self.priv_outer_id(index, &inner_id))
+    }
+
+    fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
+        match self.priv_forget(id) {
+            Some((index, inner_id)) => self.route_list[index].transport.close(&inner_id),
+            None => Ok(()),
+        }
+    }
+
+    fn close_all(&mut self) -> TransportResult<()> {
+        let mut errors: Vec<TransportError> = Vec::new();
+        for route in self.route_list.iter_mut() {
+            if let Err(e) = route.transport.close_all() {
+                errors.push(e);
+            }
+        }
+        self.id_map.clear();
+        self.inner_id_map.clear();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
+    /// send a payload through the transports owning the connections
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
+        let mut inner_id_list: Vec<Vec<&ConnectionIdRef>> = vec![Vec::new(); self.route_list.len()];
+        for id in id_list {
+            if let Some((index, inner_id)) = self.id_map.get(*id) {
+                inner_id_list[*index].push(inner_id.as_str());
+            }
+        }
+        for (route, inner_id_list) in self.route_list.iter_mut().zip(inner_id_list) {
+            if !inner_id_list.is_empty() {
+                route.transport.send(&inner_id_list, payload)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
+        for route in self.route_list.iter_mut() {
+            route.transport.send_all(payload)?;
+        }
+        Ok(())
+    }
+
+    /// bind the transport routed by the url's scheme at that url,
+    /// and every other transport at its own bind url.
+    /// Returns the url bound by the first, see `bound_url_list()` for all of them.
+    fn bind(&mut self, url: &Url) -> TransportResult<Url> {
+        let index = self.priv_route(url.scheme())?;
+        let bound_url = self.route_list[index].transport.bind(url)?;
+        self.route_list[index].bound_url = Some(bound_url.clone());
+        for (i, route) in self.route_list.iter_mut().enumerate() {
+            if i == index {
+                continue;
+            }
+            if let Some(bind_url) = &route.bind_url {
+                route.bound_url = Some(route.transport.bind(bind_url)?);
+            }
+        }
+        Ok(bound_url)
+    }
+
+    fn post(&mut self, command: TransportCommand) -> TransportResult<()> {
+        self.inbox.push_back(command);
+        Ok(())
+    }
+
+    /// serve posted commands, then process every inner transport
+    fn process(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)> {
+        let (mut did_work, mut event_list) = self.priv_process_inbox()?;
+        for index in 0..self.route_list.len() {
+            let (inner_did_work, inner_event_list) = self.route_list[index].transport.process()?;
+            did_work |= inner_did_work;
+            for event in inner_event_list {
+                if let Some(event) = self.priv_map_event(index, event) {
+                    event_list.push(event);
+                }
+            }
+        }
+        Ok((did_work, event_list))
+    }
+
+    fn next_deadline_ms(&self) -> Option<u64> {
+        if !self.inbox.is_empty() {
+            return Some(crate::time::since_epoch_ms());
+        }
+        self.route_list.iter().fold(None, |deadline, route| {
+            crate::time::earliest_deadline(deadline, route.transport.next_deadline_ms())
+        })
+    }
+
+    /// Wait on the single inner transport, or on each in turn for at most `WAIT_SLICE`,
+    /// stopping as soon as one has work to do
+    fn wait_ready(&mut self, max_wait: Duration) -> TransportResult<()> {
+        if !self.inbox.is_empty() {
+            return Ok(());
+        }
+        if self.route_list.len() == 1 {
+            return self.route_list[0].transport.wait_ready(max_wait);
+        }
+        let start = Instant::now();
+        for route in self.route_list.iter_mut() {
+            let remaining = max_wait.checked_sub(start.elapsed()).unwrap_or_default();
+            if remaining == Duration::from_millis(0) {
+                break;
+            }
+            route
+                .transport
+                .wait_ready(std::cmp::min(remaining, WAIT_SLICE))?;
+            let is_due = route
+                .transport
+                .next_deadline_ms()
+                .map_or(false, |deadline| deadline <= crate::time::since_epoch_ms());
+            if is_due {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
+        let mut id_list = Vec::new();
+        for (index, route) in self.route_list.iter().enumerate() {
+            for inner_id in route.transport.connection_id_list()? {
+                if let Some(id) = self.inner_id_map.get(&(index, inner_id)) {
+                    id_list.push(id.clone());
+                }
+            }
+        }
+        Ok(id_list)
+    }
+
+    fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
+        let (index, inner_id) = self.id_map.get(id)?;
+        self.route_list[*index].transport.get_uri(inner_id)
+    }
+
+    fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        let (index, inner_id) = self.id_map.get(id)?;
+        self.route_list[*index]
+            .transport
+            .get_remote_certificate(inner_id)
+    }
+
+    /// the inner transports' connections, under our connectionIds
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        let mut inbox_depth = self.inbox.len();
+        let mut connection_list: Vec<ConnectionSnapshot> = Vec::new();
+        for (index, route) in self.route_list.iter().enumerate() {
+            let snapshot = route.transport.transport_snapshot();
+            inbox_depth += snapshot.inbox_depth;
+            for mut connection in snapshot.connection_list {
+                if let Some(id) = self.inner_id_map.get(&(index, connection.id.clone())) {
+                    connection.id = id.clone();
+                    connection_list.push(connection);
+                }
+            }
+        }
+        connection_list.sort_by(|a, b| a.id.cmp(&b.id));
+        TransportSnapshot {
+            inbox_depth,
+            connection_list,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::memory_mock::transport_memory::TransportMemory;
+
+    #[test]
+    fn test_route_by_scheme() {
+        let mut transport = TransportComposite::new();
+        transport
+            .add_transport(&["mem"], None, Box::new(TransportMemory::new()))
+            .unwrap();
+        // A scheme goes to a single transport
+        assert!(transport
+            .add_transport(&["mem"], None, Box::new(TransportMemory::new()))
+            .is_err());
+        let url = Url::parse("tcp://127.0.0.1:1234").unwrap();
+        assert!(transport.connect(&url).is_err());
+        assert!(transport.bind(&url).is_err());
+    }
+
+    #[test]
+    fn test_unique_ids() {
+        let mut transport = TransportComposite::new();
+        let a = transport.priv_outer_id(0, "mem1_1");
+        let b = transport.priv_outer_id(1, "mem1_1");
+        assert_ne!(a, b);
+        assert_eq!(transport.priv_outer_id(0, "mem1_1"), a);
+        assert_eq!(transport.priv_forget(&a), Some((0, "mem1_1".to_string())));
+        assert_ne!(transport.priv_outer_id(0, "mem1_1"), a);
+    }
+
+    #[test]
+    fn test_late_events_are_dropped() {
+        let mut transport = TransportComposite::new();
+        let payload: Opaque = vec![1, 2, 3].into();
+        // Unknown connections do not get a connectionId
+        assert_eq!(
+            transport.priv_map_event(
+                0,
+                TransportEvent::ReceivedData("mem1_1".into(), payload.clone())
+            ),
+            None
+        );
+        assert_eq!(
+            transport.priv_map_event(0, TransportEvent::ConnectionClosed("mem1_1".into())),
+            None
+        );
+        assert!(transport.id_map.is_empty());
+
+        // Incoming connections do
+        let id = match transport.priv_map_event(
+            0,
+            TransportEvent::IncomingConnectionEstablished("mem1_1".into()),
+        ) {
+            Some(TransportEvent::IncomingConnectionEstablished(id)) => id,
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert_eq!(
+            transport.priv_map_event(
+                0,
+                TransportEvent::ReceivedData("mem1_1".into(), payload.clone())
+            ),
+            Some(TransportEvent::ReceivedData(id.clone(), payload.clone()))
+        );
+        assert_eq!(
+            transport.priv_map_event(0, TransportEvent::ConnectionClosed("mem1_1".into())),
+            Some(TransportEvent::ConnectionClosed(id))
+        );
+
+        // Data arriving after the close is dropped
+        assert_eq!(
+            transport.priv_map_event(0, TransportEvent::ReceivedData("mem1_1".into(), payload)),
+            None
+        );
+        assert!(transport.id_map.is_empty());
+        assert!(transport.inner_id_map.is_empty());
+    }
+}
@@ -1,10 +1,10 @@
 use crate::transport::{
     error::TransportResult,
-    protocol::{TransportCommand, TransportEvent},
+    protocol::{TransportCommand, TransportEvent, TransportSnapshot},
     transport_trait::Transport,
     ConnectionId, ConnectionIdRef,
 };
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 
 use url::Url;
 
@@ -35,11 +35,11 @@ impl<T: Transport> Transport for TransportCrypto<T> {
         self.inner_transport.close_all()
     }
 
-    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &[u8]) -> TransportResult<()> {
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
         self.inner_transport.send(id_list, payload)
     }
 
-    fn send_all(&mut self, payload: &[u8]) -> TransportResult<()> {
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
         self.inner_transport.send_all(payload)
     }
 
@@ -55,6 +55,10 @@ impl<T: Transport> Transport for TransportCrypto<T> {
         self.inner_transport.process()
     }
 
+    fn next_deadline_ms(&self) -> Option<u64> {
+        self.inner_transport.next_deadline_ms()
+    }
+
     fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
         self.inner_transport.connection_id_list()
     }
@@ -62,4 +66,12 @@ impl<T: Transport> Transport for TransportCrypto<T> {
     fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
         self.inner_transport.get_uri(id)
     }
+
+    fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        self.inner_transport.get_remote_certificate(id)
+    }
+
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        self.inner_transport.transport_snapshot()
+    }
 }
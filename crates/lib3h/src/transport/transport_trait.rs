@@ -1,17 +1,19 @@
 use crate::transport::{
     error::TransportResult,
-    protocol::{TransportCommand, TransportEvent},
+    protocol::{TransportCommand, TransportEvent, TransportSnapshot},
     ConnectionId, ConnectionIdRef,
 };
 
+use std::time::Duration;
 use url::Url;
 
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 
 /// Represents a pool of connections to remote nodes.
 /// Methods are for synchronous processing.
 /// Otherwise use `post()` & `process()` for aysnchronous processing.
-pub trait Transport {
+/// Must be `Send` so gateways can be processed by worker threads.
+pub trait Transport: Send {
     // -- Synchronous -- //
     /// establish a connection to a remote node
     fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId>;
@@ -20,9 +22,10 @@ pub trait Transport {
     /// close all existing open connections
     fn close_all(&mut self) -> TransportResult<()>;
     /// send a payload to remote nodes
-    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &[u8]) -> TransportResult<()>;
+    /// the payload buffer is shared, not copied, between the targeted connections
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()>;
     /// send a payload to all remote nodes
-    fn send_all(&mut self, payload: &[u8]) -> TransportResult<()>;
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()>;
     /// Bind to a network interface
     /// Return the advertise
     fn bind(&mut self, url: &url::Url) -> TransportResult<Url>;
@@ -33,10 +36,32 @@ pub trait Transport {
     /// Process TransportProtocol messages received from owner and
     /// also poll TransportEvents received from the network.
     fn process(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)>;
+    /// Earliest time (ms since epoch) at which `process()` has work to do,
+    /// e.g. a heartbeat or a pending handshake step.
+    /// None if only incoming network data or a `post()` can create work.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        None
+    }
+    /// Block until `process()` has work to do, e.g. incoming network data or
+    /// `next_deadline_ms()` being due, for at most `max_wait`.
+    /// Transports that cannot tell return right away, callers then keep calling `process()`.
+    fn wait_ready(&mut self, _max_wait: Duration) -> TransportResult<()> {
+        Ok(())
+    }
 
     // -- Getters -- //
     /// get a list of all open transport ids
     fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>>;
     /// get uri from a connectionId
     fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url>;
+    /// get the DER encoded certificate a remote presented on a connection, if any
+    fn get_remote_certificate(&self, _id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        None
+    }
+
+    // -- Debug -- //
+    /// Snapshot of this transport's connections for diagnostics
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        TransportSnapshot::from_transport(self)
+    }
 }
@@ -0,0 +1,164 @@
+//! Length-prefixed framing of `TransportTcp` streams.
+//!
+//! A frame is the payload length (u32, big endian), a kind byte, then the payload.
+
+use crate::transport::error::{TransportError, TransportResult};
+
+/// Length and kind
+pub const HEADER_LEN: usize = 5;
+
+/// Larger frames are refused rather than buffered
+pub const MAX_PAYLOAD_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    /// Payload of a `Transport::send()`
+    Data,
+    /// Heartbeat, answered with a `Pong`
+    Ping,
+    Pong,
+    /// The remote is closing the connection
+    Close,
+}
+
+impl FrameKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameKind::Data => 0,
+            FrameKind::Ping => 1,
+            FrameKind::Pong => 2,
+            FrameKind::Close => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> TransportResult<Self> {
+        match byte {
+            0 => Ok(FrameKind::Data),
+            1 => Ok(FrameKind::Ping),
+            2 => Ok(FrameKind::Pong),
+            3 => Ok(FrameKind::Close),
+            _ => Err(TransportError::new(format!("unknown frame kind {}", byte))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+/// Append a frame to `out`
+pub fn encode(kind: FrameKind, payload: &[u8], out: &mut Vec<u8>) -> TransportResult<()> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(TransportError::new(format!(
+            "payload of {} bytes exceeds the maximum frame size",
+            payload.len()
+        )));
+    }
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.push(kind.to_byte());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Reassembles the frames of a stream, however its bytes are split.
+/// Payloads are gathered in a buffer of their own, handed over as is.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Header of the frame being read, until it is whole
+    header: Vec<u8>,
+    /// Kind and length of the frame being read, once its header is whole
+    pending: Option<(FrameKind, usize)>,
+    payload: Vec<u8>,
+}
+
+impl Decoder {
+    /// Take bytes from the front of `input`, up to the end of the current frame.
+    /// Returns how many bytes were taken, and the frame if they completed it.
+    pub fn decode(&mut self, input: &[u8]) -> TransportResult<(usize, Option<Frame>)> {
+        let mut used = 0;
+        if self.pending.is_none() {
+            used = std::cmp::min(HEADER_LEN - self.header.len(), input.len());
+            self.header.extend_from_slice(&input[..used]);
+            if self.header.len() < HEADER_LEN {
+                return Ok((used, None));
+            }
+            let mut len_bytes = [0; 4];
+            len_bytes.copy_from_slice(&self.header[..4]);
+            let payload_len = u32::from_be_bytes(len_bytes) as usize;
+            if payload_len > MAX_PAYLOAD_LEN {
+                return Err(TransportError::new(format!(
+                    "remote frame of {} bytes exceeds the maximum frame size",
+                    payload_len
+                )));
+            }
+            let kind = FrameKind::from_byte(self.header[4])?;
+            self.header.clear();
+            // Grown as the payload arrives, a header alone cannot reserve much
+            self.payload = Vec::with_capacity(std::cmp::min(payload_len, 64 * 1024));
+            self.pending = Some((kind, payload_len));
+        }
+        let (kind, payload_len) = self.pending.expect("frame header was just read");
+        let take = std::cmp::min(payload_len - self.payload.len(), input.len() - used);
+        self.payload.extend_from_slice(&input[used..used + take]);
+        used += take;
+        if self.payload.len() < payload_len {
+            return Ok((used, None));
+        }
+        self.pending = None;
+        let payload = std::mem::replace(&mut self.payload, Vec::new());
+        Ok((used, Some(Frame { kind, payload })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every frame `input` completes
+    fn decode_all(decoder: &mut Decoder, mut input: &[u8]) -> TransportResult<Vec<Frame>> {
+        let mut frame_list = Vec::new();
+        while !input.is_empty() {
+            let (used, frame) = decoder.decode(input)?;
+            input = &input[used..];
+            frame_list.extend(frame);
+        }
+        Ok(frame_list)
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut buffer = Vec::new();
+        encode(FrameKind::Data, &[1, 2, 3], &mut buffer).unwrap();
+        encode(FrameKind::Ping, &[], &mut buffer).unwrap();
+        assert_eq!(buffer[..HEADER_LEN], [0, 0, 0, 3, 0]);
+
+        let mut decoder = Decoder::default();
+        let (len, frame) = decoder.decode(&buffer).unwrap();
+        let frame = frame.unwrap();
+        assert_eq!(frame.kind, FrameKind::Data);
+        assert_eq!(frame.payload, vec![1, 2, 3]);
+        assert_eq!(len, HEADER_LEN + 3);
+        let (len, frame) = decoder.decode(&buffer[HEADER_LEN + 3..]).unwrap();
+        let frame = frame.unwrap();
+        assert_eq!(frame.kind, FrameKind::Ping);
+        assert!(frame.payload.is_empty());
+        assert_eq!(len, HEADER_LEN);
+
+        // Partial frames wait for more data, split anywhere
+        for split in 0..buffer.len() {
+            let mut decoder = Decoder::default();
+            let mut frame_list = decode_all(&mut decoder, &buffer[..split]).unwrap();
+            frame_list.append(&mut decode_all(&mut decoder, &buffer[split..]).unwrap());
+            assert_eq!(frame_list.len(), 2);
+            assert_eq!(frame_list[0].payload, vec![1, 2, 3]);
+            assert_eq!(frame_list[1].kind, FrameKind::Ping);
+        }
+        // Garbage is refused
+        assert!(Decoder::default().decode(&[0, 0, 0, 0, 9]).is_err());
+        assert!(Decoder::default()
+            .decode(&[0xff, 0xff, 0xff, 0xff, 0])
+            .is_err());
+    }
+}
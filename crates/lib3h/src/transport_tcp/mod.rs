@@ -0,0 +1,614 @@
+//! abstraction for working with plain TCP connections:
+//! length-prefixed frames over any rust io Read/Write stream, optionally within TLS.
+//! Meant for links between backend nodes, where websocket framing and handshakes
+//! buy nothing. The same framing runs over Unix domain sockets, see `unix`.
+
+pub mod frame;
+mod tcp;
+#[cfg(unix)]
+pub mod unix;
+
+use crate::{
+    transport::{
+        error::{TransportError, TransportResult},
+        protocol::{ConnectionSnapshot, TransportCommand, TransportEvent, TransportSnapshot},
+        transport_trait::Transport,
+        ConnectionId, ConnectionIdRef,
+    },
+    transport_wss::{
+        tls,
+        trust::{self, TlsPeerIdentity, TlsTrustPolicy},
+        TlsConfig, TlsHandshake, DEFAULT_HEARTBEAT_MS, DEFAULT_HEARTBEAT_WAIT_MS,
+    },
+};
+use frame::FrameKind;
+use lib3h_protocol::{opaque::Opaque, DidWork};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// Url scheme of `TransportTcp` connections over TCP
+pub const TCP_SCHEME: &str = "tcp";
+
+/// Url scheme of `TransportTcp` connections over Unix domain sockets
+pub const UNIX_SCHEME: &str = "unix";
+
+// an internal state sequence for stream building
+#[derive(Debug)]
+enum TcpStreamState<T: Read + Write + std::fmt::Debug> {
+    None,
+    Connecting(T),
+    ConnectingSrv(T),
+    TlsMidHandshake(tls::TlsMidHandshake<T>),
+    TlsSrvMidHandshake(tls::TlsMidHandshake<T>),
+    Ready(T),
+    ReadyTls(tls::TlsStream<T>),
+}
+
+impl<T: Read + Write + std::fmt::Debug> TcpStreamState<T> {
+    /// Short name of the current state, for diagnostics
+    fn state_name(&self) -> &'static str {
+        match self {
+            TcpStreamState::None => "closed",
+            TcpStreamState::Connecting(_) | TcpStreamState::ConnectingSrv(_) => "connecting",
+            TcpStreamState::TlsMidHandshake(_) | TcpStreamState::TlsSrvMidHandshake(_) => {
+                "tls_handshake"
+            }
+            TcpStreamState::Ready(_) | TcpStreamState::ReadyTls(_) => "ready",
+        }
+    }
+}
+
+/// Represents an individual connection
+#[derive(Debug)]
+struct TcpInfo<T: Read + Write + std::fmt::Debug> {
+    id: ConnectionId,
+    url: Url,
+    last_msg: Instant,
+    last_ping: Instant,
+    send_queue: Vec<Opaque>,
+    /// Encoded frames the socket did not take yet
+    write_buffer: Vec<u8>,
+    /// Frame partly received
+    decoder: frame::Decoder,
+    /// The last process() stopped reading at its limit, the socket may have more
+    is_read_pending: bool,
+    stateful_socket: TcpStreamState<T>,
+    /// Identity of the remote according to its certificate, once the TLS handshake is done
+    peer_identity: Option<TlsPeerIdentity>,
+}
+
+impl<T: Read + Write + std::fmt::Debug> TcpInfo<T> {
+    fn new(id: ConnectionId, url: Url, stateful_socket: TcpStreamState<T>) -> Self {
+        TcpInfo {
+            id,
+            url,
+            last_msg: Instant::now(),
+            last_ping: Instant::now(),
+            send_queue: Vec::new(),
+            write_buffer: Vec::new(),
+            decoder: frame::Decoder::default(),
+            is_read_pending: false,
+            stateful_socket,
+            peer_identity: None,
+        }
+    }
+
+    /// Tell the remote we are closing, on a best effort basis
+    fn close(&mut self) -> TransportResult<()> {
+        frame::encode(FrameKind::Close, &[], &mut self.write_buffer)?;
+        let res = match &mut self.stateful_socket {
+            TcpStreamState::Ready(socket) => socket.write(&self.write_buffer),
+            TcpStreamState::ReadyTls(socket) => socket.write(&self.write_buffer),
+            _ => Ok(0),
+        };
+        self.stateful_socket = TcpStreamState::None;
+        match res {
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+/// A factory callback connecting base streams of type T to a url
+pub type StreamFactory<T> = fn(url: &Url) -> TransportResult<T>;
+
+/// A function that accepts a pending connection, if any, along with the url of the remote
+pub type Acceptor<T> = Box<dyn FnMut() -> TransportResult<Option<(T, Url)>> + Send>;
+
+/// A function that binds to a url and produces socket acceptors of type T,
+/// along with the url actually bound
+pub type Bind<T> = Box<dyn FnMut(&Url) -> TransportResult<(Acceptor<T>, Url)> + Send>;
+
+/// Most bytes read from one connection per process(), so a fast remote cannot
+/// starve the others. Larger frames are gathered over several process().
+const MAX_READ_PER_PROCESS: usize = 256 * 1024;
+
+lazy_static! {
+    /// Counter of the number of TransportTcp that spawned
+    static ref TRANSPORT_COUNT: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+}
+
+/// A "Transport" implementation sending length-prefixed frames, see `frame`,
+/// any rust io Read/Write stream should be able to serve as the base
+pub struct TransportTcp<T: Read + Write + std::fmt::Debug> {
+    tls_config: TlsConfig,
+    /// When set, remotes must present a certificate satisfying it, see `TransportWss`
+    trust_policy: Option<TlsTrustPolicy>,
+    /// Servers ask for client certificates even without a trust policy
+    require_client_certificate: bool,
+    stream_factory: StreamFactory<T>,
+    stream_sockets: HashMap<ConnectionId, TcpInfo<T>>,
+    event_queue: Vec<TransportEvent>,
+    own_id: u64,
+    n_id: u64,
+    inbox: VecDeque<TransportCommand>,
+    bind: Bind<T>,
+    acceptor: Option<Acceptor<T>>,
+}
+
+impl<T: Read + Write + std::fmt::Debug + Send> Transport for TransportTcp<T> {
+    /// connect to a remote TransportTcp
+    fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
+        let socket = (self.stream_factory)(uri)?;
+        let id = self.priv_next_id();
+        let info = TcpInfo::new(id.clone(), uri.clone(), TcpStreamState::Connecting(socket));
+        self.stream_sockets.insert(id.clone(), info);
+        Ok(id)
+    }
+
+    /// close a currently tracked connection
+    fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
+        if let Some(mut info) = self.stream_sockets.remove(id) {
+            info.close()?;
+        }
+        Ok(())
+    }
+
+    /// close all currently tracked connections
+    fn close_all(&mut self) -> TransportResult<()> {
+        let mut errors: Vec<TransportError> = Vec::new();
+        for (_id, mut info) in self.stream_sockets.drain() {
+            if let Err(e) = info.close() {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
+    /// send a message to one or more remote connected nodes
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
+        for id in id_list {
+            if let Some(info) = self.stream_sockets.get_mut(*id) {
+                info.send_queue.push(payload.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// send a message to all remote nodes
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
+        for info in self.stream_sockets.values_mut() {
+            info.send_queue.push(payload.clone());
+        }
+        Ok(())
+    }
+
+    /// Returns the url actually bound, e.g. with the port the OS picked for port 0
+    fn bind(&mut self, url: &Url) -> TransportResult<Url> {
+        let (acceptor, bound_url) = (self.bind)(url)?;
+        self.acceptor = Some(acceptor);
+        Ok(bound_url)
+    }
+
+    fn post(&mut self, command: TransportCommand) -> TransportResult<()> {
+        self.inbox.push_back(command);
+        Ok(())
+    }
+
+    /// this should be called frequently on the event loop
+    /// serves posted commands, accepts connections, reads and writes frames
+    fn process(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)> {
+        let mut did_work = self.priv_process_inbox()?;
+        did_work |= self.priv_process_accept();
+        did_work |= self.priv_process_stream_sockets();
+        Ok((did_work, self.event_queue.drain(..).collect()))
+    }
+
+    /// Next heartbeat of a ready socket, or now if a socket is mid-handshake
+    /// or has frames to write.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        let now = crate::time::since_epoch_ms();
+        if !self.inbox.is_empty() || !self.event_queue.is_empty() {
+            return Some(now);
+        }
+        let mut deadline = None;
+        for info in self.stream_sockets.values() {
+            let info_deadline = match info.stateful_socket {
+                TcpStreamState::None => continue,
+                TcpStreamState::Ready(_) | TcpStreamState::ReadyTls(_) => {
+                    if !info.send_queue.is_empty()
+                        || !info.write_buffer.is_empty()
+                        || info.is_read_pending
+                    {
+                        now
+                    } else {
+                        let elapsed = info.last_msg.elapsed().as_millis() as u64;
+                        let heartbeat = DEFAULT_HEARTBEAT_MS as u64 + 1;
+                        now + heartbeat.saturating_sub(elapsed)
+                    }
+                }
+                _ => now,
+            };
+            deadline = crate::time::earliest_deadline(deadline, Some(info_deadline));
+        }
+        deadline
+    }
+
+    /// get a list of all open transport ids
+    fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
+        Ok(self.stream_sockets.keys().cloned().collect())
+    }
+
+    /// get uri from a connectionId
+    fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
+        self.stream_sockets.get(id).map(|info| info.url.clone())
+    }
+
+    /// get the certificate the remote presented on a connection
+    fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        self.get_peer_identity(id)
+            .map(|identity| identity.certificate_der)
+    }
+
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        let mut connection_list: Vec<ConnectionSnapshot> = self
+            .stream_sockets
+            .values()
+            .map(|info| ConnectionSnapshot {
+                id: info.id.clone(),
+                uri: Some(info.url.to_string()),
+                state: info.stateful_socket.state_name().to_string(),
+                send_queue_depth: info.send_queue.len(),
+            })
+            .collect();
+        connection_list.sort_by(|a, b| a.id.cmp(&b.id));
+        TransportSnapshot {
+            inbox_depth: self.inbox.len(),
+            connection_list,
+        }
+    }
+}
+
+impl<T: Read + Write + std::fmt::Debug + Send> TransportTcp<T> {
+    pub fn new(stream_factory: StreamFactory<T>, bind: Bind<T>, tls_config: TlsConfig) -> Self {
+        let mut tc = TRANSPORT_COUNT
+            .lock()
+            .expect("could not lock transport count mutex");
+        *tc += 1;
+        TransportTcp {
+            tls_config,
+            trust_policy: None,
+            require_client_certificate: false,
+            stream_factory,
+            stream_sockets: HashMap::new(),
+            event_queue: Vec::new(),
+            own_id: *tc,
+            n_id: 0,
+            inbox: VecDeque::new(),
+            bind,
+            acceptor: None,
+        }
+    }
+
+    /// Require remotes to present a certificate satisfying `trust_policy`,
+    /// or accept any with `None`. Applies to connections opened afterwards.
+    pub fn set_trust_policy(&mut self, trust_policy: Option<TlsTrustPolicy>) {
+        self.trust_policy = trust_policy;
+    }
+
+    /// Ask remotes connecting to us for a certificate even without a trust policy,
+    /// e.g. for a gateway to check it is bound to a transport id.
+    /// Applies to connections accepted afterwards.
+    pub fn set_require_client_certificate(&mut self, require_client_certificate: bool) {
+        self.require_client_certificate = require_client_certificate;
+    }
+
+    /// Identity of the remote on a TLS connection, see `TlsPeerIdentity::is_trusted`
+    pub fn get_peer_identity(&self, id: &ConnectionIdRef) -> Option<TlsPeerIdentity> {
+        self.stream_sockets
+            .get(id)
+            .and_then(|info| info.peer_identity.clone())
+    }
+
+    // -- private -- //
+
+    // generate a unique id for
+    fn priv_next_id(&mut self) -> ConnectionId {
+        self.n_id += 1;
+        format!("tcp{}_{}", self.own_id, self.n_id)
+    }
+
+    // serve the commands received through post()
+    fn priv_process_inbox(&mut self) -> TransportResult<DidWork> {
+        let mut did_work = false;
+        while let Some(command) = self.inbox.pop_front() {
+            did_work = true;
+            match command {
+                TransportCommand::Connect(url) => {
+                    // ConnectResult is published once the connection is established
+                    self.connect(&url)?;
+                }
+                TransportCommand::Send(id_list, payload) => {
+                    let id_ref_list: Vec<&ConnectionIdRef> =
+                        id_list.iter().map(|id| id.as_str()).collect();
+                    self.send(&id_ref_list, &payload)?;
+                }
+                TransportCommand::SendAll(payload) => self.send_all(&payload)?,
+                TransportCommand::Close(id) => {
+                    self.close(&id)?;
+                    self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                }
+                TransportCommand::CloseAll => {
+                    let id_list = self.connection_id_list()?;
+                    self.close_all()?;
+                    for id in id_list {
+                        self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                    }
+                }
+                TransportCommand::Bind(url) => {
+                    self.bind(&url)?;
+                }
+            }
+        }
+        Ok(did_work)
+    }
+
+    // accept all pending incoming connections
+    fn priv_process_accept(&mut self) -> DidWork {
+        let mut accepted = Vec::new();
+        if let Some(acceptor) = &mut self.acceptor {
+            loop {
+                match acceptor() {
+                    Ok(Some(connection)) => accepted.push(connection),
+                    Ok(None) => break,
+                    Err(err) => {
+                        warn!("transport_tcp: accept error: {:?}", err);
+                        break;
+                    }
+                }
+            }
+        }
+        let did_work = !accepted.is_empty();
+        for (socket, url) in accepted {
+            let id = self.priv_next_id();
+            debug!("transport_tcp: accepted {} from {}", id, url);
+            let info = TcpInfo::new(id.clone(), url, TcpStreamState::ConnectingSrv(socket));
+            self.stream_sockets.insert(id, info);
+        }
+        did_work
+    }
+
+    // see if any work needs to be done on our stream sockets
+    fn priv_process_stream_sockets(&mut self) -> DidWork {
+        let mut did_work = false;
+        let heartbeat_wait = Duration::from_millis(DEFAULT_HEARTBEAT_WAIT_MS as u64);
+
+        // take sockets out, so we can mut ref into self and it at same time
+        let sockets: Vec<(ConnectionId, TcpInfo<T>)> = self.stream_sockets.drain().collect();
+
+        for (id, mut info) in sockets {
+            if let Err(e) = self.priv_process_socket(&mut did_work, &mut info) {
+                // the socket was moved out and dropped, the connection is closed
+                self.event_queue
+                    .push(TransportEvent::ErrorOccured(id.clone(), e));
+            }
+            if let TcpStreamState::None = info.stateful_socket {
+                self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                continue;
+            }
+            if info.last_msg.elapsed() > heartbeat_wait {
+                debug!("transport_tcp: {} timed out", id);
+                self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                continue;
+            }
+            self.stream_sockets.insert(id, info);
+        }
+
+        did_work
+    }
+
+    // process the state machine of an individual socket stream
+    fn priv_process_socket(
+        &mut self,
+        did_work: &mut bool,
+        info: &mut TcpInfo<T>,
+    ) -> TransportResult<()> {
+        // move the socket out, to be replaced
+        let socket = std::mem::replace(&mut info.stateful_socket, TcpStreamState::None);
+        match socket {
+            TcpStreamState::None => {
+                // stream must have closed, do nothing
+                Ok(())
+            }
+            TcpStreamState::Connecting(socket) => {
+                info.last_msg = Instant::now();
+                *did_work = true;
+                if let TlsConfig::Unencrypted = &self.tls_config {
+                    info.stateful_socket = TcpStreamState::Ready(socket);
+                    self.event_queue
+                        .push(TransportEvent::ConnectResult(info.id.clone()));
+                    return Ok(());
+                }
+                let certificate = self.tls_config.tls_certificate()?;
+                self.priv_tls_handshake(info, tls::connect(&certificate, socket)?, false)
+            }
+            TcpStreamState::ConnectingSrv(socket) => {
+                info.last_msg = Instant::now();
+                *did_work = true;
+                if let TlsConfig::Unencrypted = &self.tls_config {
+                    info.stateful_socket = TcpStreamState::Ready(socket);
+                    self.event_queue
+                        .push(TransportEvent::IncomingConnectionEstablished(
+                            info.id.clone(),
+                        ));
+                    return Ok(());
+                }
+                let certificate = self.tls_config.tls_certificate()?;
+                let require_client_certificate =
+                    self.require_client_certificate || self.trust_policy.is_some();
+                let handshake = tls::accept(&certificate, require_client_certificate, socket)?;
+                self.priv_tls_handshake(info, handshake, true)
+            }
+            TcpStreamState::TlsMidHandshake(socket) => {
+                self.priv_tls_handshake(info, tls::handshake(socket)?, false)
+            }
+            TcpStreamState::TlsSrvMidHandshake(socket) => {
+                self.priv_tls_handshake(info, tls::handshake(socket)?, true)
+            }
+            TcpStreamState::Ready(mut socket) => {
+                if self.priv_process_frames(did_work, info, &mut socket)? {
+                    info.stateful_socket = TcpStreamState::Ready(socket);
+                }
+                Ok(())
+            }
+            TcpStreamState::ReadyTls(mut socket) => {
+                if self.priv_process_frames(did_work, info, &mut socket)? {
+                    info.stateful_socket = TcpStreamState::ReadyTls(socket);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // process tls handshaking
+    fn priv_tls_handshake(
+        &mut self,
+        info: &mut TcpInfo<T>,
+        handshake: TlsHandshake<T>,
+        is_server: bool,
+    ) -> TransportResult<()> {
+        match handshake {
+            TlsHandshake::MidHandshake(socket) => {
+                info.stateful_socket = if is_server {
+                    TcpStreamState::TlsSrvMidHandshake(socket)
+                } else {
+                    TcpStreamState::TlsMidHandshake(socket)
+                };
+            }
+            TlsHandshake::Ready(socket) => {
+                info.last_msg = Instant::now();
+                info.peer_identity = trust::peer_identity(
+                    &self.trust_policy,
+                    &tls::peer_certificate_chain(&socket)?,
+                )?;
+                info.stateful_socket = TcpStreamState::ReadyTls(socket);
+                let id = info.id.clone();
+                self.event_queue.push(if is_server {
+                    TransportEvent::IncomingConnectionEstablished(id)
+                } else {
+                    TransportEvent::ConnectResult(id)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // write queued payloads and heartbeats, read and publish received frames.
+    // Returns false once the connection is closed.
+    fn priv_process_frames<S: Read + Write>(
+        &mut self,
+        did_work: &mut bool,
+        info: &mut TcpInfo<T>,
+        socket: &mut S,
+    ) -> TransportResult<bool> {
+        for payload in info.send_queue.drain(..) {
+            if let Err(e) =
+                frame::encode(FrameKind::Data, payload.as_slice(), &mut info.write_buffer)
+            {
+                self.event_queue
+                    .push(TransportEvent::ErrorOccured(info.id.clone(), e));
+            }
+        }
+        let heartbeat = Duration::from_millis(DEFAULT_HEARTBEAT_MS as u64);
+        if info.last_msg.elapsed() > heartbeat && info.last_ping.elapsed() > heartbeat {
+            frame::encode(FrameKind::Ping, &[], &mut info.write_buffer)?;
+            info.last_ping = Instant::now();
+        }
+
+        // Write what the socket takes, the rest waits for the next process()
+        while !info.write_buffer.is_empty() {
+            match socket.write(&info.write_buffer) {
+                Ok(0) => return Err(TransportError::new("connection closed".to_string())),
+                Ok(written) => {
+                    info.write_buffer.drain(..written);
+                    *did_work = true;
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match socket.flush() {
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+            res => res?,
+        }
+
+        let mut is_open = true;
+        let mut chunk = [0; 4096];
+        let mut read_total = 0;
+        let mut frame_list = Vec::new();
+        info.is_read_pending = false;
+        while read_total < MAX_READ_PER_PROCESS {
+            let max_read = std::cmp::min(chunk.len(), MAX_READ_PER_PROCESS - read_total);
+            match socket.read(&mut chunk[..max_read]) {
+                Ok(0) => {
+                    is_open = false;
+                    break;
+                }
+                Ok(read) => {
+                    let mut input = &chunk[..read];
+                    while !input.is_empty() {
+                        let (used, frame) = info.decoder.decode(input)?;
+                        input = &input[used..];
+                        frame_list.extend(frame);
+                    }
+                    read_total += read;
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if read_total >= MAX_READ_PER_PROCESS {
+            // the rest is read on the next process()
+            info.is_read_pending = true;
+            *did_work = true;
+        }
+
+        for frame in frame_list {
+            info.last_msg = Instant::now();
+            *did_work = true;
+            match frame.kind {
+                FrameKind::Data => self.event_queue.push(TransportEvent::ReceivedData(
+                    info.id.clone(),
+                    frame.payload.into(),
+                )),
+                FrameKind::Ping => frame::encode(FrameKind::Pong, &[], &mut info.write_buffer)?,
+                FrameKind::Pong => (),
+                FrameKind::Close => {
+                    is_open = false;
+                    break;
+                }
+            }
+        }
+        Ok(is_open)
+    }
+}
@@ -0,0 +1,66 @@
+//! TcpStream specific functions
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_tcp::{Acceptor, Bind, TransportTcp, TCP_SCHEME},
+    transport_wss::TlsConfig,
+};
+
+use std::net::{TcpListener, TcpStream};
+
+impl TransportTcp<TcpStream> {
+    /// convenience constructor for creating a tcp "Transport"
+    /// instance that is based of the rust std TcpStream
+    pub fn with_std_tcp_stream(tls_config: TlsConfig) -> Self {
+        let bind: Bind<TcpStream> = Box::new(move |url| Self::tcp_bind(url));
+        TransportTcp::new(Self::tcp_connect, bind, tls_config)
+    }
+
+    fn tcp_connect(url: &url::Url) -> TransportResult<TcpStream> {
+        if url.scheme() != TCP_SCHEME {
+            return Err(TransportError::new(format!(
+                "TransportTcp cannot connect to {}",
+                url
+            )));
+        }
+        let host_port = format!(
+            "{}:{}",
+            url.host_str()
+                .ok_or_else(|| TransportError("bad connect host".into()))?,
+            url.port()
+                .ok_or_else(|| TransportError("bad connect port".into()))?,
+        );
+        let socket = TcpStream::connect(host_port)?;
+        socket.set_nonblocking(true)?;
+        socket.set_nodelay(true)?;
+        Ok(socket)
+    }
+
+    /// Listen on the address of `url`. Returns the url actually bound,
+    /// with the port the OS picked if `url` asked for port 0.
+    fn tcp_bind(url: &url::Url) -> TransportResult<(Acceptor<TcpStream>, url::Url)> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| TransportError::new(format!("bind url has no host: {}", url)))?;
+        let port = url
+            .port()
+            .ok_or_else(|| TransportError::new(format!("bind url has no port: {}", url)))?;
+        let listener = TcpListener::bind(format!("{}:{}", host, port))?;
+        listener.set_nonblocking(true)?;
+        let mut bound_url = url.clone();
+        bound_url
+            .set_port(Some(listener.local_addr()?.port()))
+            .map_err(|()| TransportError::new(format!("cannot set port of {}", url)))?;
+        let acceptor: Acceptor<TcpStream> = Box::new(move || match listener.accept() {
+            Ok((tcp_stream, socket_address)) => {
+                tcp_stream.set_nonblocking(true)?;
+                tcp_stream.set_nodelay(true)?;
+                let url = url::Url::parse(&format!("{}://{}", TCP_SCHEME, socket_address))?;
+                Ok(Some((tcp_stream, url)))
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        });
+        Ok((acceptor, bound_url))
+    }
+}
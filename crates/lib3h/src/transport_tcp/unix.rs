@@ -0,0 +1,164 @@
+//! UnixStream specific functions: `TransportTcp` framing between nodes on the same host.
+//! Urls are `unix://` followed by the absolute path of the socket file,
+//! e.g. `unix:///tmp/lib3h/node.sock`. Accepted connections are told apart
+//! by a fragment numbering them, e.g. `unix:///tmp/lib3h/node.sock#3`.
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_tcp::{Acceptor, Bind, TransportTcp, UNIX_SCHEME},
+    transport_wss::TlsConfig,
+};
+
+use std::{
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+/// `TransportTcp` over Unix domain sockets
+pub type TransportUnix = TransportTcp<UnixStream>;
+
+/// Path of the socket file a `unix://` url names
+fn socket_path(url: &url::Url) -> TransportResult<PathBuf> {
+    if url.scheme() != UNIX_SCHEME || url.path().is_empty() || url.path() == "/" {
+        return Err(TransportError::new(format!(
+            "not a unix socket url: {}",
+            url
+        )));
+    }
+    Ok(PathBuf::from(url.path()))
+}
+
+/// Listener that removes its socket file once dropped
+struct UnixAcceptor {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Drop for UnixAcceptor {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(
+                "transport_tcp::unix could not remove {:?}: {:?}",
+                self.path, e
+            );
+        }
+    }
+}
+
+impl TransportUnix {
+    /// convenience constructor for creating a unix socket "Transport"
+    /// instance that is based of the rust std UnixStream
+    pub fn with_std_unix_stream(tls_config: TlsConfig) -> Self {
+        let bind: Bind<UnixStream> = Box::new(move |url| Self::unix_bind(url));
+        TransportTcp::new(Self::unix_connect, bind, tls_config)
+    }
+
+    fn unix_connect(url: &url::Url) -> TransportResult<UnixStream> {
+        let socket = UnixStream::connect(socket_path(url)?)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    fn unix_bind(url: &url::Url) -> TransportResult<(Acceptor<UnixStream>, url::Url)> {
+        let path = socket_path(url)?;
+        Self::remove_stale_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let acceptor = UnixAcceptor { listener, path };
+        // Remotes have no address of their own: they are known by the bound url
+        // and the number of their connection, so each gets a url of its own
+        let bound_url = url.clone();
+        let mut connection_count: u64 = 0;
+        let acceptor: Acceptor<UnixStream> = Box::new(move || match acceptor.listener.accept() {
+            Ok((unix_stream, _socket_address)) => {
+                unix_stream.set_nonblocking(true)?;
+                connection_count += 1;
+                let mut connection_url = bound_url.clone();
+                connection_url.set_fragment(Some(&connection_count.to_string()));
+                Ok(Some((unix_stream, connection_url)))
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        });
+        Ok((acceptor, url.clone()))
+    }
+
+    // A socket file left behind by a node that did not shut down cleanly
+    // would fail the bind: remove it unless someone is still listening on it.
+    // Anything else at the path is not ours to delete.
+    fn remove_stale_socket(path: &Path) -> TransportResult<()> {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(TransportError::new(format!(
+                "cannot bind {:?}: it exists and is not a socket",
+                path
+            )));
+        }
+        if UnixStream::connect(path).is_err() {
+            debug!("transport_tcp::unix removing stale socket {:?}", path);
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_path() {
+        let url = url::Url::parse("unix:///tmp/lib3h/node.sock").unwrap();
+        assert_eq!(
+            socket_path(&url).unwrap(),
+            PathBuf::from("/tmp/lib3h/node.sock")
+        );
+        assert!(socket_path(&url::Url::parse("unix:///").unwrap()).is_err());
+        assert!(socket_path(&url::Url::parse("tcp://127.0.0.1:1234").unwrap()).is_err());
+        // The fragment of an accepted connection is not part of the path
+        assert_eq!(
+            socket_path(&url::Url::parse("unix:///tmp/lib3h/node.sock#2").unwrap()).unwrap(),
+            PathBuf::from("/tmp/lib3h/node.sock")
+        );
+    }
+
+    #[test]
+    fn test_accepted_urls_are_unique() {
+        let path = std::env::temp_dir().join(format!(
+            "lib3h_test_accepted_urls_{}.sock",
+            std::process::id()
+        ));
+        let url = url::Url::parse(&format!("unix://{}", path.display())).unwrap();
+        let (mut acceptor, bound_url) = TransportUnix::unix_bind(&url).unwrap();
+        assert_eq!(bound_url, url);
+
+        let _stream_a = UnixStream::connect(&path).unwrap();
+        let _stream_b = UnixStream::connect(&path).unwrap();
+        let (_, url_a) = acceptor().unwrap().unwrap();
+        let (_, url_b) = acceptor().unwrap().unwrap();
+        assert_ne!(url_a, url_b);
+        assert_eq!(socket_path(&url_a).unwrap(), path);
+        assert_eq!(socket_path(&url_b).unwrap(), path);
+        assert!(acceptor().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bind_keeps_files_that_are_not_sockets() {
+        let path = std::env::temp_dir().join(format!(
+            "lib3h_test_not_a_socket_{}.sock",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"keep me").unwrap();
+        let url = url::Url::parse(&format!("unix://{}", path.display())).unwrap();
+        assert!(TransportUnix::unix_bind(&url).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
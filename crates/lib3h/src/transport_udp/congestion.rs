@@ -0,0 +1,145 @@
+//! Congestion control of a `TransportUdp` connection: a TCP Reno style window,
+//! counted in packets, and the retransmission timeout of RFC 6298.
+
+use std::time::Duration;
+
+/// Window of a new connection
+pub const INITIAL_WINDOW: f64 = 4.0;
+/// Window never shrinks below this after a loss
+pub const MIN_WINDOW: f64 = 2.0;
+/// Window never grows above this
+pub const MAX_WINDOW: f64 = 1024.0;
+
+/// Retransmission timeout before any round trip was measured
+pub const INITIAL_RTO: Duration = Duration::from_millis(1000);
+pub const MIN_RTO: Duration = Duration::from_millis(200);
+pub const MAX_RTO: Duration = Duration::from_millis(10_000);
+
+#[derive(Debug, Clone)]
+pub struct Congestion {
+    /// Packets allowed in flight
+    window: f64,
+    /// Slow start threshold: the window doubles every round trip below it,
+    /// and grows by one packet per round trip above it
+    slow_start_threshold: f64,
+    smoothed_rtt: Option<Duration>,
+    rtt_variance: Duration,
+    rto: Duration,
+}
+
+impl Default for Congestion {
+    fn default() -> Self {
+        Congestion {
+            window: INITIAL_WINDOW,
+            slow_start_threshold: MAX_WINDOW,
+            smoothed_rtt: None,
+            rtt_variance: Duration::from_millis(0),
+            rto: INITIAL_RTO,
+        }
+    }
+}
+
+impl Congestion {
+    /// Number of packets allowed in flight
+    pub fn window(&self) -> usize {
+        self.window as usize
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// A packet was acknowledged. `rtt_sample` is its round trip time,
+    /// if it was not retransmitted (Karn's algorithm).
+    pub fn on_ack(&mut self, rtt_sample: Option<Duration>) {
+        if self.window < self.slow_start_threshold {
+            self.window += 1.0;
+        } else {
+            self.window += 1.0 / self.window;
+        }
+        self.window = self.window.min(MAX_WINDOW);
+        if let Some(rtt) = rtt_sample {
+            self.update_rto(rtt);
+        }
+    }
+
+    /// A packet was reported missing by later acknowledgements
+    pub fn on_loss(&mut self) {
+        self.slow_start_threshold = (self.window / 2.0).max(MIN_WINDOW);
+        self.window = self.slow_start_threshold;
+    }
+
+    /// The retransmission timer expired: restart from slow start, and back off
+    pub fn on_timeout(&mut self) {
+        self.slow_start_threshold = (self.window / 2.0).max(MIN_WINDOW);
+        self.window = 1.0;
+        self.rto = std::cmp::min(self.rto * 2, MAX_RTO);
+    }
+
+    fn update_rto(&mut self, rtt: Duration) {
+        match self.smoothed_rtt {
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_variance = rtt / 2;
+            }
+            Some(smoothed_rtt) => {
+                let deviation = if smoothed_rtt > rtt {
+                    smoothed_rtt - rtt
+                } else {
+                    rtt - smoothed_rtt
+                };
+                self.rtt_variance = (self.rtt_variance * 3 + deviation) / 4;
+                self.smoothed_rtt = Some((smoothed_rtt * 7 + rtt) / 8);
+            }
+        }
+        let rto = self.smoothed_rtt.unwrap_or(rtt) + self.rtt_variance * 4;
+        self.rto = std::cmp::max(MIN_RTO, std::cmp::min(rto, MAX_RTO));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let mut congestion = Congestion::default();
+        assert_eq!(congestion.window(), 4);
+        // Slow start: one more packet per ack
+        for _ in 0..4 {
+            congestion.on_ack(None);
+        }
+        assert_eq!(congestion.window(), 8);
+        // Loss halves the window, then it grows by about one packet per window of acks
+        congestion.on_loss();
+        assert_eq!(congestion.window(), 4);
+        for _ in 0..4 {
+            congestion.on_ack(None);
+        }
+        assert_eq!(congestion.window(), 4);
+        congestion.on_ack(None);
+        assert_eq!(congestion.window(), 5);
+        // Timeout goes back to a single packet
+        congestion.on_timeout();
+        assert_eq!(congestion.window(), 1);
+    }
+
+    #[test]
+    fn test_rto() {
+        let mut congestion = Congestion::default();
+        assert_eq!(congestion.rto(), INITIAL_RTO);
+        congestion.on_ack(Some(Duration::from_millis(100)));
+        // 100 + 4 * 50
+        assert_eq!(congestion.rto(), Duration::from_millis(300));
+        // Fast round trips stay above the minimum
+        for _ in 0..20 {
+            congestion.on_ack(Some(Duration::from_millis(1)));
+        }
+        assert_eq!(congestion.rto(), MIN_RTO);
+        // Timeouts back off up to the maximum
+        for _ in 0..10 {
+            congestion.on_timeout();
+        }
+        assert_eq!(congestion.rto(), MAX_RTO);
+    }
+}
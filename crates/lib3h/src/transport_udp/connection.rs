@@ -0,0 +1,710 @@
+//! Reliability layer of a single `TransportUdp` connection, independent of the socket:
+//! handshake, sequencing, acknowledgements, retransmission and fragmentation.
+//!
+//! Messages are split into fragments, each sent as a numbered Data packet.
+//! The receiver acknowledges packets cumulatively plus a selective bitmask,
+//! and delivers a message as soon as all its fragments arrived: a lost packet only
+//! delays the message it belongs to, not the ones sent after it.
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_udp::{
+        congestion::Congestion,
+        packet::{Packet, MAX_FRAGMENT_LEN},
+    },
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// A packet transmitted this many times without being acknowledged means the
+/// remote is gone
+pub const MAX_TRANSMIT_COUNT: u32 = 8;
+
+/// Packets further ahead of the first missing one are dropped, bounding what
+/// the receiver buffers
+pub const MAX_RECEIVE_AHEAD: u32 = 4096;
+
+/// Most messages being reassembled at once, beyond which the remote is dropped
+pub const MAX_REASSEMBLY_COUNT: usize = MAX_RECEIVE_AHEAD as usize;
+
+/// Most bytes of fragments held for messages being reassembled, beyond which
+/// the remote is dropped. Room for the largest message `queue_message()` takes.
+pub const MAX_REASSEMBLY_BYTES: usize = 128 * 1024 * 1024;
+
+/// A packet skipped by this many acknowledgements of later packets is retransmitted
+/// without waiting for the timeout
+const FAST_RETRANSMIT_THRESHOLD: u32 = 3;
+
+/// What the remote did, as seen through `on_packet()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// The handshake completed
+    Established,
+    /// A whole message arrived
+    Received(Vec<u8>),
+    /// The remote closed the connection
+    Closed,
+    /// The remote started a new handshake after sending data: it restarted,
+    /// and a new connection must replace this one
+    Reset,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    packet: Packet,
+    sent_at: Instant,
+    transmit_count: u32,
+    /// Number of acknowledgements of later packets since it was sent
+    skipped_count: u32,
+    retransmit_now: bool,
+}
+
+#[derive(Debug)]
+struct PendingFragment {
+    message_id: u32,
+    fragment_index: u16,
+    fragment_count: u16,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Reassembly {
+    fragment_count: u16,
+    /// Fragments received so far, by index
+    fragment_map: BTreeMap<u16, Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct ReliableConnection {
+    is_established: bool,
+    syn_sent_at: Option<Instant>,
+    syn_count: u32,
+    syn_ack_due: bool,
+    ack_due: bool,
+    pong_due: bool,
+    last_recv: Instant,
+    last_send: Instant,
+    congestion: Congestion,
+    // -- sending -- //
+    next_seq: u32,
+    next_message_id: u32,
+    pending: VecDeque<PendingFragment>,
+    in_flight: BTreeMap<u32, InFlight>,
+    /// Retransmission timer, running while packets are in flight
+    rto_deadline: Option<Instant>,
+    /// Losses of packets sent before this one belong to the same congestion event
+    recovery_seq: u32,
+    // -- receiving -- //
+    next_expected: u32,
+    received_ahead: BTreeSet<u32>,
+    reassembly_map: HashMap<u32, Reassembly>,
+    /// Bytes of all fragments in `reassembly_map`
+    reassembly_bytes: usize,
+}
+
+impl ReliableConnection {
+    /// Connection we open: it sends Syn until the remote answers
+    pub fn client(now: Instant) -> Self {
+        Self::new(now)
+    }
+
+    /// Connection opened by a remote's Syn
+    pub fn server(now: Instant) -> Self {
+        let mut connection = Self::new(now);
+        connection.is_established = true;
+        connection.syn_ack_due = true;
+        connection
+    }
+
+    fn new(now: Instant) -> Self {
+        ReliableConnection {
+            is_established: false,
+            syn_sent_at: None,
+            syn_count: 0,
+            syn_ack_due: false,
+            ack_due: false,
+            pong_due: false,
+            last_recv: now,
+            last_send: now,
+            congestion: Congestion::default(),
+            next_seq: 0,
+            next_message_id: 0,
+            pending: VecDeque::new(),
+            in_flight: BTreeMap::new(),
+            rto_deadline: None,
+            recovery_seq: 0,
+            next_expected: 0,
+            received_ahead: BTreeSet::new(),
+            reassembly_map: HashMap::new(),
+            reassembly_bytes: 0,
+        }
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.is_established
+    }
+
+    /// Time since anything was received from the remote
+    pub fn idle_time(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_recv)
+    }
+
+    /// Number of packets waiting to be sent or acknowledged
+    pub fn queue_depth(&self) -> usize {
+        self.pending.len() + self.in_flight.len()
+    }
+
+    /// Split a message into fragments and queue them for sending
+    pub fn queue_message(&mut self, payload: &[u8]) -> TransportResult<()> {
+        let fragment_count =
+            std::cmp::max(1, (payload.len() + MAX_FRAGMENT_LEN - 1) / MAX_FRAGMENT_LEN);
+        if fragment_count > u16::max_value() as usize {
+            return Err(TransportError::new(format!(
+                "payload of {} bytes is too large for udp",
+                payload.len()
+            )));
+        }
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        for fragment_index in 0..fragment_count {
+            let start = fragment_index * MAX_FRAGMENT_LEN;
+            let end = std::cmp::min(start + MAX_FRAGMENT_LEN, payload.len());
+            self.pending.push_back(PendingFragment {
+                message_id,
+                fragment_index: fragment_index as u16,
+                fragment_count: fragment_count as u16,
+                payload: payload[start..end].to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Handle a packet received from the remote.
+    /// Fails if the remote makes us hold too many fragments, see `MAX_REASSEMBLY_BYTES`.
+    pub fn on_packet(
+        &mut self,
+        packet: Packet,
+        now: Instant,
+    ) -> TransportResult<Vec<ConnectionEvent>> {
+        self.last_recv = now;
+        let mut event_list = Vec::new();
+        match packet {
+            Packet::Syn => {
+                if self.priv_has_received_data() {
+                    // The remote only sends Syn before it is established, so it
+                    // restarted: none of our sequencing state applies any more
+                    event_list.push(ConnectionEvent::Reset);
+                    return Ok(event_list);
+                }
+                // Our SynAck was lost
+                self.syn_ack_due = true;
+                self.priv_establish(&mut event_list);
+            }
+            Packet::SynAck => self.priv_establish(&mut event_list),
+            Packet::Data {
+                seq,
+                message_id,
+                fragment_index,
+                fragment_count,
+                payload,
+            } => {
+                // Data means the remote accepted us, even if its SynAck got lost
+                self.priv_establish(&mut event_list);
+                if let Some(message) =
+                    self.priv_on_data(seq, message_id, fragment_index, fragment_count, payload)?
+                {
+                    event_list.push(ConnectionEvent::Received(message));
+                }
+            }
+            Packet::Ack {
+                next_expected,
+                selective,
+            } => self.priv_on_ack(next_expected, selective, now),
+            Packet::Ping => self.pong_due = true,
+            Packet::Pong => (),
+            Packet::Close => event_list.push(ConnectionEvent::Closed),
+        }
+        Ok(event_list)
+    }
+
+    /// Packets to send now: handshake, acknowledgements, retransmissions, new data
+    /// within the congestion window, and heartbeats.
+    /// Fails once the remote stopped acknowledging.
+    pub fn poll_transmit(
+        &mut self,
+        now: Instant,
+        heartbeat: Duration,
+    ) -> TransportResult<Vec<Packet>> {
+        let mut packet_list = Vec::new();
+        if !self.is_established {
+            let is_syn_due = match self.syn_sent_at {
+                None => true,
+                Some(sent_at) => now.duration_since(sent_at) >= self.congestion.rto(),
+            };
+            if is_syn_due {
+                if self.syn_count >= MAX_TRANSMIT_COUNT {
+                    return Err(TransportError::new("udp handshake timed out".to_string()));
+                }
+                if self.syn_sent_at.is_some() {
+                    self.congestion.on_timeout();
+                }
+                self.syn_sent_at = Some(now);
+                self.syn_count += 1;
+                packet_list.push(Packet::Syn);
+            }
+            return Ok(packet_list);
+        }
+        if self.syn_ack_due {
+            self.syn_ack_due = false;
+            packet_list.push(Packet::SynAck);
+        }
+        if self.ack_due {
+            self.ack_due = false;
+            packet_list.push(self.priv_ack());
+        }
+        if self.pong_due {
+            self.pong_due = false;
+            packet_list.push(Packet::Pong);
+        }
+        self.priv_retransmit(now, &mut packet_list)?;
+        while self.in_flight.len() < self.congestion.window() {
+            let fragment = match self.pending.pop_front() {
+                None => break,
+                Some(fragment) => fragment,
+            };
+            let seq = self.next_seq;
+            self.next_seq = self.next_seq.wrapping_add(1);
+            let packet = Packet::Data {
+                seq,
+                message_id: fragment.message_id,
+                fragment_index: fragment.fragment_index,
+                fragment_count: fragment.fragment_count,
+                payload: fragment.payload,
+            };
+            packet_list.push(packet.clone());
+            self.in_flight.insert(
+                seq,
+                InFlight {
+                    packet,
+                    sent_at: now,
+                    transmit_count: 1,
+                    skipped_count: 0,
+                    retransmit_now: false,
+                },
+            );
+            if self.rto_deadline.is_none() {
+                self.rto_deadline = Some(now + self.congestion.rto());
+            }
+        }
+        if packet_list.is_empty()
+            && now.duration_since(self.last_send) >= heartbeat
+            && now.duration_since(self.last_recv) >= heartbeat
+        {
+            packet_list.push(Packet::Ping);
+        }
+        if !packet_list.is_empty() {
+            self.last_send = now;
+        }
+        Ok(packet_list)
+    }
+
+    /// Earliest time at which `poll_transmit()` has something to send
+    pub fn next_deadline(&self, now: Instant, heartbeat: Duration) -> Instant {
+        if self.syn_ack_due
+            || self.ack_due
+            || self.pong_due
+            || (self.is_established
+                && !self.pending.is_empty()
+                && self.in_flight.len() < self.congestion.window())
+            || self.in_flight.values().any(|f| f.retransmit_now)
+        {
+            return now;
+        }
+        let heartbeat_deadline = std::cmp::max(self.last_send, self.last_recv) + heartbeat;
+        let deadline = match (self.is_established, self.syn_sent_at, self.rto_deadline) {
+            (false, Some(sent_at), _) => sent_at + self.congestion.rto(),
+            (false, None, _) => now,
+            (true, _, Some(rto_deadline)) => rto_deadline,
+            (true, _, None) => heartbeat_deadline,
+        };
+        std::cmp::min(deadline, heartbeat_deadline)
+    }
+
+    // -- private -- //
+
+    fn priv_establish(&mut self, event_list: &mut Vec<ConnectionEvent>) {
+        if !self.is_established {
+            self.is_established = true;
+            event_list.push(ConnectionEvent::Established);
+        }
+    }
+
+    fn priv_has_received_data(&self) -> bool {
+        self.next_expected != 0 || !self.received_ahead.is_empty()
+    }
+
+    fn priv_ack(&self) -> Packet {
+        let mut selective = 0;
+        for i in 0..32 {
+            let seq = self.next_expected.wrapping_add(1 + i);
+            if self.received_ahead.contains(&seq) {
+                selective |= 1 << i;
+            }
+        }
+        Packet::Ack {
+            next_expected: self.next_expected,
+            selective,
+        }
+    }
+
+    // record a received Data packet, returns the message it completes, if any
+    fn priv_on_data(
+        &mut self,
+        seq: u32,
+        message_id: u32,
+        fragment_index: u16,
+        fragment_count: u16,
+        payload: Vec<u8>,
+    ) -> TransportResult<Option<Vec<u8>>> {
+        if seq >= self.next_expected.saturating_add(MAX_RECEIVE_AHEAD) {
+            // Too far ahead, the remote will retransmit it
+            return Ok(None);
+        }
+        // Acknowledge even duplicates, the remote may have missed our Ack
+        self.ack_due = true;
+        if seq < self.next_expected || !self.received_ahead.insert(seq) {
+            return Ok(None);
+        }
+        while self.received_ahead.remove(&self.next_expected) {
+            self.next_expected += 1;
+        }
+        if fragment_count == 1 {
+            return Ok(Some(payload));
+        }
+        if !self.reassembly_map.contains_key(&message_id)
+            && self.reassembly_map.len() >= MAX_REASSEMBLY_COUNT
+        {
+            return Err(TransportError::new(format!(
+                "udp remote has more than {} messages in progress",
+                MAX_REASSEMBLY_COUNT
+            )));
+        }
+        if self.reassembly_bytes + payload.len() > MAX_REASSEMBLY_BYTES {
+            return Err(TransportError::new(format!(
+                "udp remote has more than {} bytes of messages in progress",
+                MAX_REASSEMBLY_BYTES
+            )));
+        }
+        let reassembly = self
+            .reassembly_map
+            .entry(message_id)
+            .or_insert_with(|| Reassembly {
+                fragment_count,
+                fragment_map: BTreeMap::new(),
+            });
+        if reassembly.fragment_count != fragment_count {
+            warn!(
+                "transport_udp: inconsistent fragment count for message {}",
+                message_id
+            );
+            return Ok(None);
+        }
+        if reassembly.fragment_map.contains_key(&fragment_index) {
+            return Ok(None);
+        }
+        self.reassembly_bytes += payload.len();
+        reassembly.fragment_map.insert(fragment_index, payload);
+        if reassembly.fragment_map.len() < fragment_count as usize {
+            return Ok(None);
+        }
+        let reassembly = match self.reassembly_map.remove(&message_id) {
+            None => return Ok(None),
+            Some(reassembly) => reassembly,
+        };
+        // BTreeMap values come in fragment index order
+        let message = reassembly
+            .fragment_map
+            .into_iter()
+            .map(|(_index, fragment)| fragment)
+            .collect::<Vec<Vec<u8>>>()
+            .concat();
+        self.reassembly_bytes -= message.len();
+        Ok(Some(message))
+    }
+
+    fn priv_on_ack(&mut self, next_expected: u32, selective: u32, now: Instant) {
+        let acked_list: Vec<u32> = self
+            .in_flight
+            .keys()
+            .cloned()
+            .filter(|seq| {
+                *seq < next_expected || {
+                    let offset = seq.wrapping_sub(next_expected).wrapping_sub(1);
+                    offset < 32 && selective & (1 << offset) != 0
+                }
+            })
+            .collect();
+        let highest_acked = match acked_list.last() {
+            None => return,
+            Some(seq) => *seq,
+        };
+        for seq in &acked_list {
+            if let Some(in_flight) = self.in_flight.remove(seq) {
+                let rtt_sample = if in_flight.transmit_count == 1 {
+                    Some(now.duration_since(in_flight.sent_at))
+                } else {
+                    None
+                };
+                self.congestion.on_ack(rtt_sample);
+            }
+        }
+        // New data was acknowledged: restart the retransmission timer
+        self.rto_deadline = if self.in_flight.is_empty() {
+            None
+        } else {
+            Some(now + self.congestion.rto())
+        };
+        // Packets sent before the highest acknowledged one were probably lost
+        let mut is_loss = false;
+        for (seq, in_flight) in self.in_flight.range_mut(..highest_acked) {
+            in_flight.skipped_count += 1;
+            if in_flight.skipped_count == FAST_RETRANSMIT_THRESHOLD {
+                in_flight.retransmit_now = true;
+                is_loss |= *seq >= self.recovery_seq;
+            }
+        }
+        if is_loss {
+            self.congestion.on_loss();
+            self.recovery_seq = self.next_seq;
+        }
+    }
+
+    fn priv_retransmit(
+        &mut self,
+        now: Instant,
+        packet_list: &mut Vec<Packet>,
+    ) -> TransportResult<()> {
+        let is_timed_out = match self.rto_deadline {
+            Some(deadline) => now >= deadline,
+            None => false,
+        };
+        if is_timed_out {
+            // Nothing acknowledged for a whole timeout is lost: it all goes out again,
+            // and new data waits for the window to reopen from one. Resending only the
+            // oldest packet would leave the others filling the window, with no later
+            // acknowledgements to trigger their fast retransmit.
+            self.congestion.on_timeout();
+            self.recovery_seq = self.next_seq;
+            for in_flight in self.in_flight.values_mut() {
+                in_flight.retransmit_now = true;
+            }
+            self.rto_deadline = Some(now + self.congestion.rto());
+        }
+        for in_flight in self.in_flight.values_mut() {
+            if !in_flight.retransmit_now {
+                continue;
+            }
+            if in_flight.transmit_count >= MAX_TRANSMIT_COUNT {
+                return Err(TransportError::new(
+                    "udp remote stopped acknowledging".to_string(),
+                ));
+            }
+            in_flight.retransmit_now = false;
+            in_flight.sent_at = now;
+            in_flight.transmit_count += 1;
+            in_flight.skipped_count = 0;
+            packet_list.push(in_flight.packet.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport_udp::congestion::MAX_RTO;
+
+    const HEARTBEAT: Duration = Duration::from_millis(2000);
+
+    // Exchange packets between two connections for a number of 50ms rounds,
+    // dropping those `drop_packet` picks. Returns what each side received.
+    fn exchange<F: FnMut(usize) -> bool>(
+        a: &mut ReliableConnection,
+        b: &mut ReliableConnection,
+        start: Instant,
+        round_count: u32,
+        mut drop_packet: F,
+    ) -> (Vec<ConnectionEvent>, Vec<ConnectionEvent>) {
+        let mut a_events = Vec::new();
+        let mut b_events = Vec::new();
+        let mut packet_count = 0;
+        for round in 0..round_count {
+            let now = start + Duration::from_millis(50 * u64::from(round));
+            for packet in a.poll_transmit(now, HEARTBEAT).unwrap() {
+                packet_count += 1;
+                if !drop_packet(packet_count) {
+                    b_events.append(&mut b.on_packet(packet, now).unwrap());
+                }
+            }
+            for packet in b.poll_transmit(now, HEARTBEAT).unwrap() {
+                packet_count += 1;
+                if !drop_packet(packet_count) {
+                    a_events.append(&mut a.on_packet(packet, now).unwrap());
+                }
+            }
+        }
+        (a_events, b_events)
+    }
+
+    #[test]
+    fn test_handshake_and_fragments() {
+        let start = Instant::now();
+        let mut a = ReliableConnection::client(start);
+        let mut b = ReliableConnection::server(start);
+        let big_message: Vec<u8> = (0..3 * MAX_FRAGMENT_LEN + 10).map(|i| i as u8).collect();
+        a.queue_message(&[1, 2, 3]).unwrap();
+        a.queue_message(&big_message).unwrap();
+        b.queue_message(&[]).unwrap();
+        let (a_events, b_events) = exchange(&mut a, &mut b, start, 10, |_| false);
+        assert_eq!(
+            a_events,
+            vec![
+                ConnectionEvent::Established,
+                ConnectionEvent::Received(vec![])
+            ]
+        );
+        assert_eq!(
+            b_events,
+            vec![
+                ConnectionEvent::Received(vec![1, 2, 3]),
+                ConnectionEvent::Received(big_message)
+            ]
+        );
+        assert_eq!(a.queue_depth(), 0);
+        assert_eq!(b.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_lossy_link() {
+        let start = Instant::now();
+        let mut a = ReliableConnection::client(start);
+        let mut b = ReliableConnection::server(start);
+        let message_list: Vec<Vec<u8>> = (0..50u8).map(|i| vec![i; (i as usize) * 100]).collect();
+        for message in &message_list {
+            a.queue_message(message).unwrap();
+        }
+        // About a quarter of the packets are lost, handshake and acks included.
+        // A fixed pseudo-random sequence keeps the test deterministic.
+        let mut state: u32 = 42;
+        let (_a_events, b_events) = exchange(&mut a, &mut b, start, 2000, |_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) % 4 == 0
+        });
+        let mut received: Vec<Vec<u8>> = b_events
+            .into_iter()
+            .filter_map(|event| match event {
+                ConnectionEvent::Received(message) => Some(message),
+                _ => None,
+            })
+            .collect();
+        // Each message arrives exactly once, possibly out of order
+        received.sort();
+        let mut expected = message_list.clone();
+        expected.sort();
+        assert_eq!(received, expected);
+        assert_eq!(a.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_dead_remote() {
+        let start = Instant::now();
+        let mut a = ReliableConnection::client(start);
+        let mut now = start;
+        let mut result = Ok(vec![]);
+        for _ in 0..100 {
+            result = a.poll_transmit(now, HEARTBEAT);
+            if result.is_err() {
+                break;
+            }
+            now += MAX_RTO;
+        }
+        assert!(result.is_err());
+        assert!(!a.is_established());
+    }
+
+    #[test]
+    fn test_syn_after_data_resets() {
+        let start = Instant::now();
+        let mut a = ReliableConnection::client(start);
+        let mut b = ReliableConnection::server(start);
+        a.queue_message(&[1, 2, 3]).unwrap();
+        let (_a_events, b_events) = exchange(&mut a, &mut b, start, 5, |_| false);
+        assert_eq!(b_events, vec![ConnectionEvent::Received(vec![1, 2, 3])]);
+
+        // A Syn before any data is a retransmission, our SynAck may have been lost
+        let mut c = ReliableConnection::server(start);
+        assert_eq!(c.on_packet(Packet::Syn, start).unwrap(), vec![]);
+        assert_eq!(
+            c.poll_transmit(start, HEARTBEAT).unwrap(),
+            vec![Packet::SynAck]
+        );
+
+        // After data, the remote restarted
+        let now = start + Duration::from_millis(500);
+        assert_eq!(
+            b.on_packet(Packet::Syn, now).unwrap(),
+            vec![ConnectionEvent::Reset]
+        );
+        // and a new connection takes over from sequence 0
+        let mut a = ReliableConnection::client(now);
+        let mut b = ReliableConnection::server(now);
+        a.queue_message(&[4, 5]).unwrap();
+        let (a_events, b_events) = exchange(&mut a, &mut b, now, 5, |_| false);
+        assert_eq!(a_events, vec![ConnectionEvent::Established]);
+        assert_eq!(b_events, vec![ConnectionEvent::Received(vec![4, 5])]);
+    }
+
+    #[test]
+    fn test_reassembly_limits() {
+        let start = Instant::now();
+        let fragment = |seq: u32, message_id: u32| Packet::Data {
+            seq,
+            message_id,
+            fragment_index: 0,
+            fragment_count: u16::max_value(),
+            payload: vec![0; MAX_FRAGMENT_LEN],
+        };
+
+        // Too many messages in progress
+        let mut b = ReliableConnection::server(start);
+        for i in 0..MAX_REASSEMBLY_COUNT as u32 {
+            b.on_packet(fragment(i, i), start).unwrap();
+        }
+        let seq = MAX_REASSEMBLY_COUNT as u32;
+        assert!(b.on_packet(fragment(seq, seq), start).is_err());
+
+        // Too many bytes in progress, spread over a few large messages
+        let mut b = ReliableConnection::server(start);
+        let fragment_limit = MAX_REASSEMBLY_BYTES / MAX_FRAGMENT_LEN;
+        let fragments_per_message = u32::from(u16::max_value()) - 1;
+        let mut result = Ok(vec![]);
+        for i in 0..=fragment_limit as u32 {
+            result = b.on_packet(
+                Packet::Data {
+                    seq: i,
+                    message_id: i / fragments_per_message,
+                    fragment_index: (i % fragments_per_message) as u16,
+                    fragment_count: u16::max_value(),
+                    payload: vec![0; MAX_FRAGMENT_LEN],
+                },
+                start,
+            );
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,492 @@
+//! A reliable "Transport" over UDP, for meshes of many short-lived peers:
+//! connections cost a single round trip, and a lost packet only delays the message
+//! it belongs to. See `connection` for the reliability layer and `congestion`
+//! for how the send rate adapts to the network.
+//!
+//! All connections of a transport share one socket, and are told apart by the
+//! address of the remote. Payloads are not encrypted.
+
+pub mod congestion;
+pub mod connection;
+pub mod packet;
+
+use crate::{
+    transport::{
+        error::{TransportError, TransportResult},
+        protocol::{ConnectionSnapshot, TransportCommand, TransportEvent, TransportSnapshot},
+        transport_trait::Transport,
+        ConnectionId, ConnectionIdRef,
+    },
+    transport_wss::{DEFAULT_HEARTBEAT_MS, DEFAULT_HEARTBEAT_WAIT_MS},
+};
+use connection::{ConnectionEvent, ReliableConnection};
+use lib3h_protocol::{opaque::Opaque, DidWork};
+use packet::{Packet, MAX_PACKET_LEN};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// Url scheme of `TransportUdp` connections
+pub const UDP_SCHEME: &str = "udp";
+
+/// Receive errors tolerated in one process() before leaving the rest for the next
+const MAX_RECEIVE_ERROR_COUNT: usize = 16;
+
+/// Represents an individual connection
+#[derive(Debug)]
+struct UdpInfo {
+    url: Url,
+    address: SocketAddr,
+    connection: ReliableConnection,
+}
+
+lazy_static! {
+    /// Counter of the number of TransportUdp that spawned
+    static ref TRANSPORT_COUNT: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+}
+
+/// A "Transport" implementation based on a std UdpSocket
+pub struct TransportUdp {
+    /// Created by `bind()`, or on the first `connect()` if not bound
+    socket: Option<UdpSocket>,
+    /// Whether remotes may open connections to us
+    is_bound: bool,
+    connection_map: HashMap<ConnectionId, UdpInfo>,
+    address_map: HashMap<SocketAddr, ConnectionId>,
+    event_queue: Vec<TransportEvent>,
+    inbox: VecDeque<TransportCommand>,
+    own_id: u64,
+    n_id: u64,
+}
+
+fn socket_address(url: &Url) -> TransportResult<SocketAddr> {
+    if url.scheme() != UDP_SCHEME {
+        return Err(TransportError::new(format!("not a udp url: {}", url)));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| TransportError::new(format!("udp url has no host: {}", url)))?;
+    let port = url
+        .port()
+        .ok_or_else(|| TransportError::new(format!("udp url has no port: {}", url)))?;
+    format!("{}:{}", host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| TransportError::new(format!("could not resolve {}", url)))
+}
+
+fn heartbeat() -> Duration {
+    Duration::from_millis(DEFAULT_HEARTBEAT_MS as u64)
+}
+
+impl Transport for TransportUdp {
+    /// start the handshake with a remote TransportUdp,
+    /// `ConnectResult` is published once it answers
+    fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
+        let address = socket_address(uri)?;
+        if self.socket.is_none() {
+            let any_address: SocketAddr = if address.is_ipv6() {
+                "[::]:0".parse().expect("valid address")
+            } else {
+                "0.0.0.0:0".parse().expect("valid address")
+            };
+            self.socket = Some(Self::priv_socket(any_address)?);
+        }
+        let id = self.priv_next_id();
+        self.priv_insert_connection(
+            id.clone(),
+            uri.clone(),
+            address,
+            ReliableConnection::client(Instant::now()),
+        );
+        Ok(id)
+    }
+
+    /// close a currently tracked connection, telling the remote on a best effort basis
+    fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
+        if let Some(info) = self.connection_map.remove(id) {
+            self.address_map.remove(&info.address);
+            self.priv_send_to(&info.address, &Packet::Close)?;
+        }
+        Ok(())
+    }
+
+    /// close all currently tracked connections
+    fn close_all(&mut self) -> TransportResult<()> {
+        let mut errors: Vec<TransportError> = Vec::new();
+        for id in self.connection_id_list()? {
+            if let Err(e) = self.close(&id) {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
+    /// send a message to one or more remote connected nodes
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
+        for id in id_list {
+            if let Some(info) = self.connection_map.get_mut(*id) {
+                info.connection.queue_message(payload.as_slice())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// send a message to all remote nodes
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
+        for info in self.connection_map.values_mut() {
+            info.connection.queue_message(payload.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the url actually bound, e.g. with the port the OS picked for port 0
+    fn bind(&mut self, url: &Url) -> TransportResult<Url> {
+        let socket = Self::priv_socket(socket_address(url)?)?;
+        let mut bound_url = url.clone();
+        bound_url
+            .set_port(Some(socket.local_addr()?.port()))
+            .map_err(|()| TransportError::new(format!("cannot set port of {}", url)))?;
+        self.socket = Some(socket);
+        self.is_bound = true;
+        Ok(bound_url)
+    }
+
+    fn post(&mut self, command: TransportCommand) -> TransportResult<()> {
+        self.inbox.push_back(command);
+        Ok(())
+    }
+
+    /// this should be called frequently on the event loop
+    /// serves posted commands, receives datagrams and sends what connections have due
+    fn process(&mut self) -> TransportResult<(DidWork, Vec<TransportEvent>)> {
+        let mut did_work = self.priv_process_inbox()?;
+        did_work |= self.priv_process_receive()?;
+        did_work |= self.priv_process_transmit();
+        Ok((did_work, self.event_queue.drain(..).collect()))
+    }
+
+    fn next_deadline_ms(&self) -> Option<u64> {
+        let now_ms = crate::time::since_epoch_ms();
+        if !self.inbox.is_empty() || !self.event_queue.is_empty() {
+            return Some(now_ms);
+        }
+        let now = Instant::now();
+        self.connection_map
+            .values()
+            .map(|info| {
+                let deadline = info.connection.next_deadline(now, heartbeat());
+                if deadline > now {
+                    now_ms + (deadline - now).as_millis() as u64
+                } else {
+                    now_ms
+                }
+            })
+            .min()
+    }
+
+    /// get a list of all open transport ids
+    fn connection_id_list(&self) -> TransportResult<Vec<ConnectionId>> {
+        Ok(self.connection_map.keys().cloned().collect())
+    }
+
+    /// get uri from a connectionId
+    fn get_uri(&self, id: &ConnectionIdRef) -> Option<Url> {
+        self.connection_map.get(id).map(|info| info.url.clone())
+    }
+
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        let mut connection_list: Vec<ConnectionSnapshot> = self
+            .connection_map
+            .iter()
+            .map(|(id, info)| ConnectionSnapshot {
+                id: id.clone(),
+                uri: Some(info.url.to_string()),
+                state: if info.connection.is_established() {
+                    "ready"
+                } else {
+                    "connecting"
+                }
+                .to_string(),
+                send_queue_depth: info.connection.queue_depth(),
+            })
+            .collect();
+        connection_list.sort_by(|a, b| a.id.cmp(&b.id));
+        TransportSnapshot {
+            inbox_depth: self.inbox.len(),
+            connection_list,
+        }
+    }
+}
+
+impl TransportUdp {
+    pub fn new() -> Self {
+        let mut tc = TRANSPORT_COUNT
+            .lock()
+            .expect("could not lock transport count mutex");
+        *tc += 1;
+        TransportUdp {
+            socket: None,
+            is_bound: false,
+            connection_map: HashMap::new(),
+            address_map: HashMap::new(),
+            event_queue: Vec::new(),
+            inbox: VecDeque::new(),
+            own_id: *tc,
+            n_id: 0,
+        }
+    }
+
+    // -- private -- //
+
+    // generate a unique id for
+    fn priv_next_id(&mut self) -> ConnectionId {
+        self.n_id += 1;
+        format!("udp{}_{}", self.own_id, self.n_id)
+    }
+
+    fn priv_socket(address: SocketAddr) -> TransportResult<UdpSocket> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    fn priv_insert_connection(
+        &mut self,
+        id: ConnectionId,
+        url: Url,
+        address: SocketAddr,
+        connection: ReliableConnection,
+    ) {
+        // A remote reconnecting from the same address replaces its old connection
+        if let Some(old_id) = self.address_map.insert(address, id.clone()) {
+            self.connection_map.remove(&old_id);
+            self.event_queue
+                .push(TransportEvent::ConnectionClosed(old_id));
+        }
+        self.connection_map.insert(
+            id,
+            UdpInfo {
+                url,
+                address,
+                connection,
+            },
+        );
+    }
+
+    // drop a connection, publishing why
+    fn priv_drop_connection(&mut self, id: ConnectionId, error: Option<TransportError>) {
+        if let Some(info) = self.connection_map.remove(&id) {
+            self.address_map.remove(&info.address);
+        }
+        if let Some(error) = error {
+            self.event_queue
+                .push(TransportEvent::ErrorOccured(id.clone(), error));
+        }
+        self.event_queue.push(TransportEvent::ConnectionClosed(id));
+    }
+
+    // best effort: lost datagrams are retransmitted by the connection
+    fn priv_send_to(&self, address: &SocketAddr, packet: &Packet) -> TransportResult<()> {
+        let socket = match &self.socket {
+            None => return Ok(()),
+            Some(socket) => socket,
+        };
+        match socket.send_to(&packet.encode(), address) {
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    // serve the commands received through post()
+    fn priv_process_inbox(&mut self) -> TransportResult<DidWork> {
+        let mut did_work = false;
+        while let Some(command) = self.inbox.pop_front() {
+            did_work = true;
+            match command {
+                TransportCommand::Connect(url) => {
+                    self.connect(&url)?;
+                }
+                TransportCommand::Send(id_list, payload) => {
+                    let id_ref_list: Vec<&ConnectionIdRef> =
+                        id_list.iter().map(|id| id.as_str()).collect();
+                    self.send(&id_ref_list, &payload)?;
+                }
+                TransportCommand::SendAll(payload) => self.send_all(&payload)?,
+                TransportCommand::Close(id) => {
+                    self.close(&id)?;
+                    self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                }
+                TransportCommand::CloseAll => {
+                    let id_list = self.connection_id_list()?;
+                    self.close_all()?;
+                    for id in id_list {
+                        self.event_queue.push(TransportEvent::ConnectionClosed(id));
+                    }
+                }
+                TransportCommand::Bind(url) => {
+                    self.bind(&url)?;
+                }
+            }
+        }
+        Ok(did_work)
+    }
+
+    // read all pending datagrams and hand them to their connection
+    fn priv_process_receive(&mut self) -> TransportResult<DidWork> {
+        let mut did_work = false;
+        let mut error_count = 0;
+        let mut buffer = [0; MAX_PACKET_LEN];
+        loop {
+            let (len, address) = match &self.socket {
+                None => break,
+                Some(socket) => match socket.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        // e.g. the ICMP port unreachable of a remote that went away,
+                        // which must not stop the other connections
+                        warn!("transport_udp: receive error: {:?}", e);
+                        error_count += 1;
+                        if error_count >= MAX_RECEIVE_ERROR_COUNT {
+                            break;
+                        }
+                        continue;
+                    }
+                },
+            };
+            did_work = true;
+            let packet = match Packet::decode(&buffer[..len]) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    debug!("transport_udp: dropping datagram from {}: {:?}", address, e);
+                    continue;
+                }
+            };
+            self.priv_handle_packet(address, packet)?;
+        }
+        Ok(did_work)
+    }
+
+    fn priv_handle_packet(&mut self, address: SocketAddr, packet: Packet) -> TransportResult<()> {
+        let now = Instant::now();
+        let id = match self.address_map.get(&address) {
+            Some(id) => id.clone(),
+            None => {
+                if packet != Packet::Syn || !self.is_bound {
+                    debug!("transport_udp: no connection with {}", address);
+                    return Ok(());
+                }
+                let id = self.priv_next_id();
+                let url = Url::parse(&format!("{}://{}", UDP_SCHEME, address))?;
+                self.priv_insert_connection(
+                    id.clone(),
+                    url,
+                    address,
+                    ReliableConnection::server(now),
+                );
+                self.event_queue
+                    .push(TransportEvent::IncomingConnectionEstablished(id.clone()));
+                return Ok(());
+            }
+        };
+        let result = match self.connection_map.get_mut(&id) {
+            None => return Ok(()),
+            Some(info) => info.connection.on_packet(packet, now),
+        };
+        let event_list = match result {
+            Ok(event_list) => event_list,
+            Err(e) => {
+                warn!("transport_udp: dropping {}: {:?}", id, e);
+                let _ = self.priv_send_to(&address, &Packet::Close);
+                self.priv_drop_connection(id, Some(e));
+                return Ok(());
+            }
+        };
+        for event in event_list {
+            match event {
+                ConnectionEvent::Established => self
+                    .event_queue
+                    .push(TransportEvent::ConnectResult(id.clone())),
+                ConnectionEvent::Received(payload) => self
+                    .event_queue
+                    .push(TransportEvent::ReceivedData(id.clone(), payload.into())),
+                ConnectionEvent::Closed => {
+                    self.priv_drop_connection(id.clone(), None);
+                    break;
+                }
+                ConnectionEvent::Reset => {
+                    // The remote restarted: a new connection replaces the old one,
+                    // which priv_insert_connection() reports closed
+                    debug!("transport_udp: {} restarted by {}", id, address);
+                    let url = match self.connection_map.get(&id) {
+                        None => break,
+                        Some(info) => info.url.clone(),
+                    };
+                    let new_id = self.priv_next_id();
+                    self.priv_insert_connection(
+                        new_id.clone(),
+                        url,
+                        address,
+                        ReliableConnection::server(now),
+                    );
+                    self.event_queue
+                        .push(TransportEvent::IncomingConnectionEstablished(new_id));
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // send what each connection has due, and drop the dead ones
+    fn priv_process_transmit(&mut self) -> DidWork {
+        let mut did_work = false;
+        let now = Instant::now();
+        let heartbeat_wait = Duration::from_millis(DEFAULT_HEARTBEAT_WAIT_MS as u64);
+        let mut dead_list = Vec::new();
+        let mut outbox = Vec::new();
+        for (id, info) in self.connection_map.iter_mut() {
+            if info.connection.idle_time(now) > heartbeat_wait {
+                debug!("transport_udp: {} timed out", id);
+                dead_list.push((id.clone(), None));
+                continue;
+            }
+            match info.connection.poll_transmit(now, heartbeat()) {
+                Ok(packet_list) => {
+                    for packet in packet_list {
+                        outbox.push((id.clone(), info.address, packet));
+                    }
+                }
+                Err(e) => dead_list.push((id.clone(), Some(e))),
+            }
+        }
+        for (id, address, packet) in outbox {
+            did_work = true;
+            if let Err(e) = self.priv_send_to(&address, &packet) {
+                dead_list.push((id, Some(e)));
+            }
+        }
+        for (id, error) in dead_list {
+            if self.connection_map.contains_key(&id) {
+                self.priv_drop_connection(id, error);
+            }
+        }
+        did_work
+    }
+}
+
+impl Default for TransportUdp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
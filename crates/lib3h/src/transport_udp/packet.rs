@@ -0,0 +1,200 @@
+//! Wire format of `TransportUdp` datagrams.
+//!
+//! Every packet starts with the protocol version and a kind byte,
+//! followed by the fields of that kind. Integers are big endian.
+
+use crate::transport::error::{TransportError, TransportResult};
+
+/// Bumped on incompatible changes of the wire format
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Largest fragment payload, keeps datagrams under common path MTUs
+pub const MAX_FRAGMENT_LEN: usize = 1200;
+
+/// Largest datagram we can receive
+pub const MAX_PACKET_LEN: usize = 2 + DATA_HEADER_LEN + MAX_FRAGMENT_LEN;
+
+/// seq, message_id, fragment_index, fragment_count
+const DATA_HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    /// Opens a connection
+    Syn,
+    /// Accepts a connection
+    SynAck,
+    /// One fragment of a message. `seq` numbers fragments for acknowledgement
+    /// and retransmission, `message_id` groups them for reassembly.
+    Data {
+        seq: u32,
+        message_id: u32,
+        fragment_index: u16,
+        fragment_count: u16,
+        payload: Vec<u8>,
+    },
+    /// Acknowledges every Data packet before `next_expected`,
+    /// and packet `next_expected + 1 + i` for each bit i set in `selective`
+    Ack {
+        next_expected: u32,
+        selective: u32,
+    },
+    /// Heartbeat, answered with a `Pong`
+    Ping,
+    Pong,
+    /// The remote is closing the connection
+    Close,
+}
+
+const KIND_SYN: u8 = 0;
+const KIND_SYN_ACK: u8 = 1;
+const KIND_DATA: u8 = 2;
+const KIND_ACK: u8 = 3;
+const KIND_PING: u8 = 4;
+const KIND_PONG: u8 = 5;
+const KIND_CLOSE: u8 = 6;
+
+fn malformed() -> TransportError {
+    TransportError::new("malformed udp packet".to_string())
+}
+
+fn read_u32(input: &[u8], at: usize) -> TransportResult<u32> {
+    let bytes = input.get(at..at + 4).ok_or_else(malformed)?;
+    let mut buf = [0; 4];
+    buf.copy_from_slice(bytes);
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u16(input: &[u8], at: usize) -> TransportResult<u16> {
+    let bytes = input.get(at..at + 2).ok_or_else(malformed)?;
+    let mut buf = [0; 2];
+    buf.copy_from_slice(bytes);
+    Ok(u16::from_be_bytes(buf))
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![PROTOCOL_VERSION];
+        match self {
+            Packet::Syn => out.push(KIND_SYN),
+            Packet::SynAck => out.push(KIND_SYN_ACK),
+            Packet::Data {
+                seq,
+                message_id,
+                fragment_index,
+                fragment_count,
+                payload,
+            } => {
+                out.push(KIND_DATA);
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.extend_from_slice(&message_id.to_be_bytes());
+                out.extend_from_slice(&fragment_index.to_be_bytes());
+                out.extend_from_slice(&fragment_count.to_be_bytes());
+                out.extend_from_slice(payload);
+            }
+            Packet::Ack {
+                next_expected,
+                selective,
+            } => {
+                out.push(KIND_ACK);
+                out.extend_from_slice(&next_expected.to_be_bytes());
+                out.extend_from_slice(&selective.to_be_bytes());
+            }
+            Packet::Ping => out.push(KIND_PING),
+            Packet::Pong => out.push(KIND_PONG),
+            Packet::Close => out.push(KIND_CLOSE),
+        }
+        out
+    }
+
+    pub fn decode(input: &[u8]) -> TransportResult<Self> {
+        if input.len() < 2 {
+            return Err(malformed());
+        }
+        if input[0] != PROTOCOL_VERSION {
+            return Err(TransportError::new(format!(
+                "unsupported udp protocol version {}",
+                input[0]
+            )));
+        }
+        let packet = match input[1] {
+            KIND_SYN => Packet::Syn,
+            KIND_SYN_ACK => Packet::SynAck,
+            KIND_DATA => {
+                if input.len() < 2 + DATA_HEADER_LEN {
+                    return Err(malformed());
+                }
+                let fragment_index = read_u16(input, 10)?;
+                let fragment_count = read_u16(input, 12)?;
+                if fragment_index >= fragment_count {
+                    return Err(malformed());
+                }
+                Packet::Data {
+                    seq: read_u32(input, 2)?,
+                    message_id: read_u32(input, 6)?,
+                    fragment_index,
+                    fragment_count,
+                    payload: input[2 + DATA_HEADER_LEN..].to_vec(),
+                }
+            }
+            KIND_ACK => Packet::Ack {
+                next_expected: read_u32(input, 2)?,
+                selective: read_u32(input, 6)?,
+            },
+            KIND_PING => Packet::Ping,
+            KIND_PONG => Packet::Pong,
+            KIND_CLOSE => Packet::Close,
+            kind => {
+                return Err(TransportError::new(format!(
+                    "unknown udp packet kind {}",
+                    kind
+                )));
+            }
+        };
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let packet_list = vec![
+            Packet::Syn,
+            Packet::SynAck,
+            Packet::Data {
+                seq: 7,
+                message_id: 3,
+                fragment_index: 1,
+                fragment_count: 2,
+                payload: vec![1, 2, 3],
+            },
+            Packet::Ack {
+                next_expected: 8,
+                selective: 0b101,
+            },
+            Packet::Ping,
+            Packet::Pong,
+            Packet::Close,
+        ];
+        for packet in packet_list {
+            assert_eq!(Packet::decode(&packet.encode()).unwrap(), packet);
+        }
+        // Garbage is refused
+        assert!(Packet::decode(&[]).is_err());
+        assert!(Packet::decode(&[PROTOCOL_VERSION + 1, KIND_SYN]).is_err());
+        assert!(Packet::decode(&[PROTOCOL_VERSION, 42]).is_err());
+        assert!(Packet::decode(&[PROTOCOL_VERSION, KIND_ACK, 0, 0]).is_err());
+        let mut bad_fragment = Packet::Data {
+            seq: 0,
+            message_id: 0,
+            fragment_index: 0,
+            fragment_count: 1,
+            payload: vec![],
+        }
+        .encode();
+        bad_fragment[11] = 1;
+        assert!(Packet::decode(&bad_fragment).is_err());
+    }
+}
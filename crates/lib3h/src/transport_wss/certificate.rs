@@ -0,0 +1,278 @@
+//! Self-signed TLS certificates bound to a transport id.
+//!
+//! Each node generates its own TLS key and a self-signed certificate whose subject
+//! holds the node's transport id (common name) and a signature of the certificate's
+//! public key by the transport secret key (description, hex encoded).
+//! A peer can then check that whoever completed the TLS handshake also owns the
+//! transport id it advertises.
+//!
+//! Certificates are built with rcgen and read with x509-parser rather than by the
+//! TLS backend, so they are the same whichever backend is compiled in.
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_wss::TlsCertificate,
+};
+use rcgen::{
+    Certificate, CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_ECDSA_P256_SHA256,
+};
+use ring::signature::ED25519;
+use x509_parser::{parse_subject_public_key_info, parse_x509_der, X509Name};
+
+/// commonName (2.5.4.3)
+const OID_COMMON_NAME: &[u64] = &[2, 5, 4, 3];
+/// description (2.5.4.13)
+const OID_DESCRIPTION: &[u64] = &[2, 5, 4, 13];
+
+/// What a certificate claims about its owner
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateBinding {
+    /// Transport id named by the certificate
+    pub transport_id: String,
+    /// Public key of the certificate, as in its subject public key bit string
+    pub public_key: Vec<u8>,
+    /// Signature of `public_key` by the transport secret key
+    pub signature: Vec<u8>,
+}
+
+impl CertificateBinding {
+    /// True if `signature` is the transport id's signature of `public_key`,
+    /// i.e. the owner of the transport keys vouches for the certificate's key.
+    /// Transport ids are Ed25519 public keys, as signed with by the `CryptoSystem`.
+    pub fn is_signed(&self) -> bool {
+        let transport_public_key = match hcid::HcidEncoding::with_kind("hcm0")
+            .and_then(|hcm0| hcm0.decode(&self.transport_id))
+        {
+            Ok(transport_public_key) => transport_public_key,
+            Err(_) => return false,
+        };
+        ring::signature::verify(
+            &ED25519,
+            untrusted::Input::from(&transport_public_key),
+            untrusted::Input::from(&self.public_key),
+            untrusted::Input::from(&self.signature),
+        )
+        .is_ok()
+    }
+}
+
+/// Subject of a certificate, and its public key
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateSubject {
+    pub common_name: Option<String>,
+    pub description: Option<String>,
+    /// DER encoded SubjectPublicKeyInfo
+    pub public_key_der: Vec<u8>,
+    /// Subject public key bit string
+    pub public_key: Vec<u8>,
+}
+
+fn certificate_error<E: std::fmt::Debug>(error: E) -> TransportError {
+    TransportError::new(format!("{:?}", error))
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(text: &str) -> TransportResult<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+        return Err(TransportError::new(format!("invalid hex: {}", text)));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&text[i..i + 2], 16)
+                .map_err(|_| TransportError::new(format!("invalid hex: {}", text)))
+        })
+        .collect()
+}
+
+/// Length of the header and of the content of the DER element starting `der`
+fn der_element_lengths(der: &[u8]) -> TransportResult<(usize, usize)> {
+    let invalid = || TransportError::new("invalid DER element".to_string());
+    let first_length_byte = *der.get(1).ok_or_else(invalid)?;
+    if first_length_byte < 0x80 {
+        return Ok((2, first_length_byte as usize));
+    }
+    let length_size = (first_length_byte & 0x7f) as usize;
+    if length_size == 0 || length_size > 4 {
+        return Err(invalid());
+    }
+    let length_bytes = der.get(2..2 + length_size).ok_or_else(invalid)?;
+    let length = length_bytes
+        .iter()
+        .fold(0, |length, byte| (length << 8) | *byte as usize);
+    Ok((2 + length_size, length))
+}
+
+/// DER encoded SubjectPublicKeyInfo of a DER encoded certificate, which
+/// x509-parser only hands out parsed
+fn find_public_key_der(certificate_der: &[u8]) -> TransportResult<&[u8]> {
+    let invalid = || TransportError::new("invalid certificate".to_string());
+    // Certificate, then TBSCertificate
+    let (header_len, _) = der_element_lengths(certificate_der)?;
+    let tbs = certificate_der.get(header_len..).ok_or_else(invalid)?;
+    let (header_len, content_len) = der_element_lengths(tbs)?;
+    let mut fields = tbs
+        .get(header_len..header_len + content_len)
+        .ok_or_else(invalid)?;
+    // Optional [0] version, then serial number, signature, issuer, validity and subject
+    let field_count = if fields.first() == Some(&0xa0) { 6 } else { 5 };
+    for _ in 0..field_count {
+        let (header_len, content_len) = der_element_lengths(fields)?;
+        fields = fields.get(header_len + content_len..).ok_or_else(invalid)?;
+    }
+    let (header_len, content_len) = der_element_lengths(fields)?;
+    fields.get(..header_len + content_len).ok_or_else(invalid)
+}
+
+/// Text of the first attribute of `name` with the given oid
+fn find_attribute(name: &X509Name, oid: &[u64]) -> TransportResult<Option<String>> {
+    name.rdn_seq
+        .iter()
+        .flat_map(|rdn| rdn.set.iter())
+        .find(|attribute| attribute.attr_type.iter().eq(oid.iter()))
+        .map(|attribute| {
+            let text = attribute
+                .attr_value
+                .content
+                .as_slice()
+                .map_err(certificate_error)?;
+            std::str::from_utf8(text)
+                .map(|text| text.to_string())
+                .map_err(certificate_error)
+        })
+        .transpose()
+}
+
+/// Generate a TLS key and a self-signed certificate for `transport_id`.
+/// `sign` must return the signature of its argument, the certificate's public key,
+/// by the transport secret key.
+pub fn generate_self_signed<F>(transport_id: &str, sign: F) -> TransportResult<TlsCertificate>
+where
+    F: FnOnce(&[u8]) -> TransportResult<Vec<u8>>,
+{
+    let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256).map_err(certificate_error)?;
+    let public_key_der = key_pair.public_key_der();
+    let (_, public_key_info) =
+        parse_subject_public_key_info(&public_key_der).map_err(certificate_error)?;
+    let signature = sign(public_key_info.subject_public_key.data)?;
+    // Valid from 1975 to 4096, the binding to the transport id is what matters
+    let mut params = CertificateParams::new(Vec::new());
+    params.alg = &PKCS_ECDSA_P256_SHA256;
+    params.key_pair = Some(key_pair);
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, transport_id);
+    params.distinguished_name.push(
+        DnType::CustomDnType(OID_DESCRIPTION.to_vec()),
+        hex_encode(&signature),
+    );
+    let certificate = Certificate::from_params(params).map_err(certificate_error)?;
+    Ok(TlsCertificate::from_der(
+        vec![certificate.serialize_der().map_err(certificate_error)?],
+        certificate.serialize_private_key_der(),
+    ))
+}
+
+/// Read the subject and public key of a DER encoded certificate.
+/// Its signature is not checked.
+pub fn parse_certificate_subject(certificate_der: &[u8]) -> TransportResult<CertificateSubject> {
+    let (_, certificate) = parse_x509_der(certificate_der).map_err(certificate_error)?;
+    let subject = &certificate.tbs_certificate.subject;
+    Ok(CertificateSubject {
+        common_name: find_attribute(subject, OID_COMMON_NAME)?,
+        description: find_attribute(subject, OID_DESCRIPTION)?,
+        public_key_der: find_public_key_der(certificate_der)?.to_vec(),
+        public_key: certificate
+            .tbs_certificate
+            .subject_pki
+            .subject_public_key
+            .data
+            .to_vec(),
+    })
+}
+
+/// Read the binding of a DER encoded certificate. Its signature is not checked.
+pub fn parse_certificate_binding(certificate_der: &[u8]) -> TransportResult<CertificateBinding> {
+    let subject = parse_certificate_subject(certificate_der)?;
+    let transport_id = subject
+        .common_name
+        .ok_or_else(|| TransportError::new("certificate has no common name".to_string()))?;
+    let signature = subject
+        .description
+        .ok_or_else(|| TransportError::new("certificate has no description".to_string()))?;
+    Ok(CertificateBinding {
+        transport_id,
+        public_key: subject.public_key,
+        signature: hex_decode(&signature)?,
+    })
+}
+
+/// Certificate bound to fresh transport keys, and their transport id
+#[cfg(test)]
+pub(crate) fn generate_bound_for_test() -> (String, TlsCertificate) {
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair as _},
+    };
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(untrusted::Input::from(pkcs8.as_ref())).unwrap();
+    let transport_id = hcid::HcidEncoding::with_kind("hcm0")
+        .unwrap()
+        .encode(key_pair.public_key().as_ref())
+        .unwrap();
+    let certificate = generate_self_signed(&transport_id, |public_key| {
+        Ok(key_pair.sign(public_key).as_ref().to_vec())
+    })
+    .unwrap();
+    (transport_id, certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport_wss::FAKE_CERTIFICATE;
+
+    #[test]
+    fn test_generate_and_parse() {
+        let certificate = generate_self_signed("HcMtransportId", |public_key| {
+            Ok(public_key.iter().rev().cloned().collect())
+        })
+        .unwrap();
+        let binding = parse_certificate_binding(&certificate.certificate_chain[0]).unwrap();
+        assert_eq!(binding.transport_id, "HcMtransportId");
+        let expected_signature: Vec<u8> = binding.public_key.iter().rev().cloned().collect();
+        assert_eq!(binding.signature, expected_signature);
+        // Public key as found in the SubjectPublicKeyInfo
+        let subject = parse_certificate_subject(&certificate.certificate_chain[0]).unwrap();
+        assert!(subject.public_key_der.ends_with(&binding.public_key));
+        // Certificates without a binding are refused
+        let fake_subject = parse_certificate_subject(FAKE_CERTIFICATE).unwrap();
+        assert_eq!(fake_subject.common_name, Some("holo.host".to_string()));
+        assert!(parse_certificate_binding(FAKE_CERTIFICATE).is_err());
+    }
+
+    #[test]
+    fn test_binding_signature() {
+        let (transport_id, certificate) = generate_bound_for_test();
+        let binding = parse_certificate_binding(&certificate.certificate_chain[0]).unwrap();
+        assert_eq!(binding.transport_id, transport_id);
+        assert!(binding.is_signed());
+        // Naming a transport id is not enough
+        let spoofed =
+            generate_self_signed(&transport_id, |public_key| Ok(public_key.to_vec())).unwrap();
+        let binding = parse_certificate_binding(&spoofed.certificate_chain[0]).unwrap();
+        assert!(!binding.is_signed());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(&[0, 15, 255]), "000fff");
+        assert_eq!(hex_decode("000fff").unwrap(), vec![0, 15, 255]);
+        assert!(hex_decode("0f0").is_err());
+        assert!(hex_decode("zz").is_err());
+    }
+}
@@ -1,29 +1,47 @@
 //! abstraction for working with Websocket connections
 //! based on any rust io Read/Write Stream
 
+pub mod certificate;
 mod tcp;
+#[cfg(feature = "system-tls")]
+pub(crate) mod tls_native;
+#[cfg(feature = "rustls-tls")]
+pub(crate) mod tls_rustls;
+pub mod trust;
+
+#[cfg(not(any(feature = "system-tls", feature = "rustls-tls")))]
+compile_error!("TransportWss needs a TLS backend: enable feature system-tls or rustls-tls");
+#[cfg(all(feature = "system-tls", feature = "rustls-tls"))]
+compile_error!("TransportWss takes a single TLS backend: enable only one of features system-tls and rustls-tls, e.g. with --no-default-features");
+
+#[cfg(feature = "system-tls")]
+pub(crate) use tls_native as tls;
+#[cfg(feature = "rustls-tls")]
+pub(crate) use tls_rustls as tls;
 
 use crate::transport::{
     error::{TransportError, TransportResult},
-    protocol::{TransportCommand, TransportEvent},
+    poll::{Interest, Poller, RawFd, Token},
+    protocol::{ConnectionSnapshot, TransportCommand, TransportEvent, TransportSnapshot},
     transport_trait::Transport,
     ConnectionId, ConnectionIdRef,
 };
-use lib3h_protocol::DidWork;
+use lib3h_protocol::{opaque::Opaque, DidWork};
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     io::{Read, Write},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use trust::{TlsPeerIdentity, TlsTrustPolicy};
 use url::Url;
 
-static FAKE_PKCS12: &'static [u8] = include_bytes!("fake_key.p12");
-static FAKE_PASS: &'static str = "hello";
+static FAKE_CERTIFICATE: &'static [u8] = include_bytes!("fake_cert.der");
+static FAKE_PRIVATE_KEY: &'static [u8] = include_bytes!("fake_key.der");
 
 // -- some internal types for readability -- //
 
-type TlsConnectResult<T> = Result<TlsStream<T>, native_tls::HandshakeError<T>>;
 type WsHandshakeError<T> =
     tungstenite::handshake::HandshakeError<tungstenite::handshake::client::ClientHandshake<T>>;
 type WsConnectResult<T> =
@@ -44,11 +62,11 @@ type WssSrvHandshakeError<T> = tungstenite::handshake::HandshakeError<
     >,
 >;
 type WssSrvAcceptResult<T> = Result<WssStream<T>, WssSrvHandshakeError<T>>;
-type TlsMidHandshake<T> = native_tls::MidHandshakeTlsStream<BaseStream<T>>;
+type TlsMidHandshake<T> = tls::TlsMidHandshake<BaseStream<T>>;
 
 type BaseStream<T> = T;
-type TlsSrvMidHandshake<T> = native_tls::MidHandshakeTlsStream<BaseStream<T>>;
-type TlsStream<T> = native_tls::TlsStream<BaseStream<T>>;
+type TlsSrvMidHandshake<T> = tls::TlsMidHandshake<BaseStream<T>>;
+type TlsStream<T> = tls::TlsStream<BaseStream<T>>;
 type WsMidHandshake<T> = tungstenite::handshake::MidHandshake<tungstenite::ClientHandshake<T>>;
 type WsSrvMidHandshake<T> = tungstenite::handshake::MidHandshake<
     tungstenite::ServerHandshake<T, tungstenite::handshake::server::NoCallback>,
@@ -63,6 +81,13 @@ type WssStream<T> = tungstenite::protocol::WebSocket<TlsStream<T>>;
 
 type SocketMap<T> = std::collections::HashMap<String, WssInfo<T>>;
 
+// progress of a tls handshake over socket `S`, as reported by the tls backend
+#[derive(Debug)]
+pub(crate) enum TlsHandshake<S: Read + Write + std::fmt::Debug> {
+    MidHandshake(tls::TlsMidHandshake<S>),
+    Ready(tls::TlsStream<S>),
+}
+
 // an internal state sequence for stream building
 #[derive(Debug)]
 enum WebsocketStreamState<T: Read + Write + std::fmt::Debug> {
@@ -82,6 +107,26 @@ enum WebsocketStreamState<T: Read + Write + std::fmt::Debug> {
     ReadyWss(Box<WssStream<T>>),
 }
 
+impl<T: Read + Write + std::fmt::Debug> WebsocketStreamState<T> {
+    /// Short name of the current state, for diagnostics
+    fn state_name(&self) -> &'static str {
+        match self {
+            WebsocketStreamState::None => "closed",
+            WebsocketStreamState::Connecting(_) | WebsocketStreamState::ConnectingSrv(_) => {
+                "connecting"
+            }
+            WebsocketStreamState::TlsMidHandshake(_)
+            | WebsocketStreamState::TlsSrvMidHandshake(_) => "tls_handshake",
+            WebsocketStreamState::TlsReady(_) | WebsocketStreamState::TlsSrvReady(_) => "tls_ready",
+            WebsocketStreamState::WsMidHandshake(_)
+            | WebsocketStreamState::WsSrvMidHandshake(_)
+            | WebsocketStreamState::WssMidHandshake(_)
+            | WebsocketStreamState::WssSrvMidHandshake(_) => "ws_handshake",
+            WebsocketStreamState::ReadyWs(_) | WebsocketStreamState::ReadyWss(_) => "ready",
+        }
+    }
+}
+
 /// how often should we send a heartbeat if we have not received msgs
 pub const DEFAULT_HEARTBEAT_MS: usize = 2000;
 
@@ -94,8 +139,18 @@ pub struct WssInfo<T: Read + Write + std::fmt::Debug> {
     id: ConnectionId,
     url: url::Url,
     last_msg: std::time::Instant,
-    send_queue: Vec<Vec<u8>>,
+    send_queue: VecDeque<Opaque>,
     stateful_socket: WebsocketStreamState<T>,
+    /// Identity of the remote according to its certificate, once the TLS handshake is done
+    peer_identity: Option<TlsPeerIdentity>,
+    /// Socket registered for readiness notification, see `TransportWss::enable_poll`
+    raw_fd: Option<RawFd>,
+    token: Option<Token>,
+    /// There may be something to read: reported by the poller,
+    /// or the last read returned a message and more may be buffered
+    is_ready: bool,
+    /// A write would have blocked, the rest waits until the socket is writable
+    is_write_blocked: bool,
 }
 
 impl<T: Read + Write + std::fmt::Debug> WssInfo<T> {
@@ -113,7 +168,12 @@ impl<T: Read + Write + std::fmt::Debug> WssInfo<T> {
             id: id.clone(),
             url,
             last_msg: std::time::Instant::now(),
-            send_queue: Vec::new(),
+            send_queue: VecDeque::new(),
+            peer_identity: None,
+            raw_fd: None,
+            token: None,
+            is_ready: true,
+            is_write_blocked: false,
             stateful_socket: match is_server {
                 false => WebsocketStreamState::Connecting(socket),
                 true => WebsocketStreamState::ConnectingSrv(socket),
@@ -130,10 +190,47 @@ impl<T: Read + Write + std::fmt::Debug> WssInfo<T> {
     }
 }
 
+/// Certificate and private key a node presents during the TLS handshake
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TlsCertificate {
+    /// PKCS#12 archive holding both, only supported by the native-tls backend
+    #[serde(default)]
     pkcs12_data: Vec<u8>,
+    #[serde(default)]
     passphrase: String,
+    /// DER encoded certificate chain, leaf first. Used instead of `pkcs12_data` if not empty.
+    #[serde(default)]
+    certificate_chain: Vec<Vec<u8>>,
+    /// DER encoded private key of the leaf certificate (PKCS#8)
+    #[serde(default)]
+    private_key: Vec<u8>,
+}
+
+impl TlsCertificate {
+    /// Certificate and key from a PKCS#12 archive (native-tls backend only)
+    pub fn from_pkcs12(pkcs12_data: Vec<u8>, passphrase: &str) -> Self {
+        TlsCertificate {
+            pkcs12_data,
+            passphrase: passphrase.to_string(),
+            certificate_chain: Vec::new(),
+            private_key: Vec::new(),
+        }
+    }
+
+    /// DER encoded certificate chain, leaf first, and PKCS#8 private key
+    pub fn from_der(certificate_chain: Vec<Vec<u8>>, private_key: Vec<u8>) -> Self {
+        TlsCertificate {
+            pkcs12_data: Vec::new(),
+            passphrase: String::new(),
+            certificate_chain,
+            private_key,
+        }
+    }
+
+    /// DER encoded certificate chain, leaf first. Empty for a PKCS#12 archive.
+    pub fn certificate_chain(&self) -> &[Vec<u8>] {
+        &self.certificate_chain
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -141,10 +238,38 @@ pub enum TlsConfig {
     Unencrypted,
     FakeServer,
     SuppliedCertificate(TlsCertificate),
+    /// Generate a certificate for this node, signed by its transport keys.
+    /// Must be resolved to a `SuppliedCertificate` before use, see `certificate`.
+    SelfSigned,
+}
+
+impl TlsConfig {
+    /// Certificate and key we present to remotes, in DER form
+    pub(crate) fn tls_certificate(&self) -> TransportResult<TlsCertificate> {
+        match self {
+            TlsConfig::Unencrypted => Err(TransportError::new(
+                "Unencrypted TlsConfig has no certificate".to_string(),
+            )),
+            TlsConfig::FakeServer => Ok(TlsCertificate::from_der(
+                vec![FAKE_CERTIFICATE.to_vec()],
+                FAKE_PRIVATE_KEY.to_vec(),
+            )),
+            TlsConfig::SuppliedCertificate(cert) => {
+                if cert.certificate_chain.is_empty() {
+                    tls::pkcs12_to_der(&cert.pkcs12_data, &cert.passphrase)
+                } else {
+                    Ok(cert.clone())
+                }
+            }
+            TlsConfig::SelfSigned => Err(TransportError::new(
+                "SelfSigned TlsConfig has no certificate yet".to_string(),
+            )),
+        }
+    }
 }
 
 /// A factory callback for generating base streams of type T
-pub type StreamFactory<T> = fn(uri: &str) -> TransportResult<T>;
+pub type StreamFactory<T> = fn(uri: &Url) -> TransportResult<T>;
 
 pub trait IdGenerator {
     fn next_id(&mut self) -> ConnectionId;
@@ -178,15 +303,48 @@ impl ConnectionIdFactory {
 }
 
 /// A function that produces accepted sockets of type R wrapped in a TransportInfo
-pub type Acceptor<T> = Box<dyn FnMut(ConnectionIdFactory) -> TransportResult<WssInfo<T>>>;
+pub type Acceptor<T> = Box<dyn FnMut(ConnectionIdFactory) -> TransportResult<WssInfo<T>> + Send>;
+
+/// What a `Bind` produces
+pub struct Binding<T: Read + Write + std::fmt::Debug> {
+    pub acceptor: Acceptor<T>,
+    /// url actually bound, e.g. with the port picked for port 0
+    pub url: Url,
+    /// listening socket, if it can be polled for incoming connections
+    pub raw_fd: Option<RawFd>,
+}
 
 /// A function that binds to a url and produces sockt acceptors of type T
-pub type Bind<T> = Box<dyn FnMut(&Url) -> TransportResult<Acceptor<T>>>;
+pub type Bind<T> = Box<dyn FnMut(&Url) -> TransportResult<Binding<T>> + Send>;
+
+/// Gives the socket under a stream of type T, to register it with a `Poller`
+pub type RawFdGetter<T> = fn(stream: &T) -> Option<RawFd>;
+
+/// Token of the listening socket, connections count from 1
+const LISTENER_TOKEN: Token = 0;
+
+/// Readiness notification state of a TransportWss
+struct WssPoll<T> {
+    poller: Poller,
+    raw_fd: RawFdGetter<T>,
+    /// Mapping of token -> connectionId
+    token_map: HashMap<Token, ConnectionId>,
+    next_token: Token,
+    /// Listening socket is registered with the poller,
+    /// otherwise the acceptor is tried on every `process()`
+    is_listener_polled: bool,
+    is_listener_ready: bool,
+}
 
 /// A "Transport" implementation based off the websocket protocol
 /// any rust io Read/Write stream should be able to serve as the base
 pub struct TransportWss<T: Read + Write + std::fmt::Debug> {
     tls_config: TlsConfig,
+    /// When set, remotes must present a certificate satisfying it:
+    /// servers require client certificates, and clients check the server's.
+    trust_policy: Option<TlsTrustPolicy>,
+    /// Servers ask for client certificates even without a trust policy
+    require_client_certificate: bool,
     stream_factory: StreamFactory<T>,
     stream_sockets: SocketMap<T>,
     event_queue: Vec<TransportEvent>,
@@ -194,21 +352,17 @@ pub struct TransportWss<T: Read + Write + std::fmt::Debug> {
     inbox: VecDeque<TransportCommand>,
     bind: Bind<T>,
     acceptor: TransportResult<Acceptor<T>>,
+    /// None: every socket is tried on each `process()`
+    poll: Option<WssPoll<T>>,
 }
 
-impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
+impl<T: Read + Write + std::fmt::Debug + Send> Transport for TransportWss<T> {
     /// connect to a remote websocket service
     fn connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
-        let host_port = format!(
-            "{}:{}",
-            uri.host_str()
-                .ok_or_else(|| TransportError("bad connect host".into()))?,
-            uri.port()
-                .ok_or_else(|| TransportError("bad connect port".into()))?,
-        );
-        let socket = (self.stream_factory)(&host_port)?;
+        let socket = (self.stream_factory)(uri)?;
         let id = self.priv_next_id();
-        let info = WssInfo::client(id.clone(), uri.clone(), socket);
+        let mut info = WssInfo::client(id.clone(), uri.clone(), socket);
+        self.priv_register(&mut info);
         self.stream_sockets.insert(id.clone(), info);
         Ok(id)
     }
@@ -216,6 +370,7 @@ impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
     /// close a currently tracked connection
     fn close(&mut self, id: &ConnectionIdRef) -> TransportResult<()> {
         if let Some(mut info) = self.stream_sockets.remove(id) {
+            self.priv_deregister(&info);
             info.close()?;
         }
         Ok(())
@@ -233,6 +388,7 @@ impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
                 .expect("should not be None")
                 .to_string();
             if let Some(mut info) = self.stream_sockets.remove(&key) {
+                self.priv_deregister(&info);
                 if let Err(e) = info.close() {
                     errors.push(e);
                 }
@@ -257,6 +413,12 @@ impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
         res.map(|info| info.url.clone())
     }
 
+    /// get the certificate the remote presented on a connection
+    fn get_remote_certificate(&self, id: &ConnectionIdRef) -> Option<Vec<u8>> {
+        self.get_peer_identity(id)
+            .map(|identity| identity.certificate_der)
+    }
+
     fn post(&mut self, command: TransportCommand) -> TransportResult<()> {
         self.inbox.push_back(command);
         Ok(())
@@ -270,11 +432,87 @@ impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
         Ok((did_work, self.event_queue.drain(..).collect()))
     }
 
+    /// Next heartbeat of a ready socket, or now if a socket is mid-handshake,
+    /// has messages queued, or was reported readable.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        let now = crate::time::since_epoch_ms();
+        if !self.inbox.is_empty() || !self.event_queue.is_empty() {
+            return Some(now);
+        }
+        let is_polled = self.poll.is_some();
+        if self
+            .poll
+            .as_ref()
+            .map_or(false, |poll| poll.is_listener_ready)
+        {
+            return Some(now);
+        }
+        let mut deadline = None;
+        for info in self.stream_sockets.values() {
+            let info_deadline = match info.stateful_socket {
+                WebsocketStreamState::None => continue,
+                WebsocketStreamState::ReadyWs(_) | WebsocketStreamState::ReadyWss(_) => {
+                    let has_writes =
+                        !(info.send_queue.is_empty() || is_polled && info.is_write_blocked);
+                    if has_writes || (is_polled && info.is_ready) {
+                        now
+                    } else {
+                        let elapsed = info.last_msg.elapsed().as_millis() as u64;
+                        let heartbeat = DEFAULT_HEARTBEAT_MS as u64 + 1;
+                        now + heartbeat.saturating_sub(elapsed)
+                    }
+                }
+                _ => now,
+            };
+            deadline = crate::time::earliest_deadline(deadline, Some(info_deadline));
+        }
+        deadline
+    }
+
+    /// Block until a socket is ready or `next_deadline_ms()` is due,
+    /// for at most `max_wait`. Returns right away without `enable_poll()`,
+    /// callers then keep calling `process()`.
+    fn wait_ready(&mut self, max_wait: Duration) -> TransportResult<()> {
+        if self.poll.is_none() {
+            return Ok(());
+        }
+        let max_wait_ms = max_wait.as_millis() as u64;
+        let wait_ms = match self.next_deadline_ms() {
+            Some(deadline) => std::cmp::min(
+                deadline.saturating_sub(crate::time::since_epoch_ms()),
+                max_wait_ms,
+            ),
+            None => max_wait_ms,
+        };
+        if wait_ms == 0 {
+            return Ok(());
+        }
+        self.priv_poll(Duration::from_millis(wait_ms))
+    }
+
+    fn transport_snapshot(&self) -> TransportSnapshot {
+        let mut connection_list: Vec<ConnectionSnapshot> = self
+            .stream_sockets
+            .values()
+            .map(|info| ConnectionSnapshot {
+                id: info.id.clone(),
+                uri: Some(info.url.to_string()),
+                state: info.stateful_socket.state_name().to_string(),
+                send_queue_depth: info.send_queue.len(),
+            })
+            .collect();
+        connection_list.sort_by(|a, b| a.id.cmp(&b.id));
+        TransportSnapshot {
+            inbox_depth: self.inbox.len(),
+            connection_list,
+        }
+    }
+
     /// send a message to one or more remote connected nodes
-    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &[u8]) -> TransportResult<()> {
+    fn send(&mut self, id_list: &[&ConnectionIdRef], payload: &Opaque) -> TransportResult<()> {
         for id in id_list {
             if let Some(info) = self.stream_sockets.get_mut(&id.to_string()) {
-                info.send_queue.push(payload.to_vec());
+                info.send_queue.push_back(payload.clone());
             }
         }
 
@@ -282,26 +520,40 @@ impl<T: Read + Write + std::fmt::Debug> Transport for TransportWss<T> {
     }
 
     /// send a message to all remote nodes
-    fn send_all(&mut self, payload: &[u8]) -> TransportResult<()> {
+    fn send_all(&mut self, payload: &Opaque) -> TransportResult<()> {
         for info in self.stream_sockets.values_mut() {
-            info.send_queue.push(payload.to_vec());
+            info.send_queue.push_back(payload.clone());
         }
         Ok(())
     }
 
+    /// listen at `url`, returns the url actually bound: with port 0
+    /// the OS picks a free port
     fn bind(&mut self, url: &Url) -> TransportResult<Url> {
-        let acceptor = (self.bind)(&url.clone());
-        acceptor.map(|acceptor| {
-            self.acceptor = Ok(acceptor);
-            url.clone()
-        })
+        let binding = (self.bind)(url)?;
+        if let Some(poll) = &mut self.poll {
+            poll.is_listener_polled = false;
+            if let Some(raw_fd) = binding.raw_fd {
+                match poll
+                    .poller
+                    .register(raw_fd, LISTENER_TOKEN, Interest::READABLE)
+                {
+                    Ok(()) => poll.is_listener_polled = true,
+                    Err(e) => warn!("transport_wss: could not poll listener: {:?}", e),
+                }
+            }
+        }
+        self.acceptor = Ok(binding.acceptor);
+        Ok(binding.url)
     }
 }
 
-impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
+impl<T: Read + Write + std::fmt::Debug + Send + std::marker::Sized> TransportWss<T> {
     pub fn new(stream_factory: StreamFactory<T>, bind: Bind<T>, tls_config: TlsConfig) -> Self {
         TransportWss {
             tls_config,
+            trust_policy: None,
+            require_client_certificate: false,
             stream_factory,
             stream_sockets: std::collections::HashMap::new(),
             event_queue: Vec::new(),
@@ -309,9 +561,46 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
             inbox: VecDeque::new(),
             bind,
             acceptor: Err(TransportError("acceptor not initialized".into())),
+            poll: None,
         }
     }
 
+    /// Only process the sockets that are ready, instead of trying all of them,
+    /// and allow `wait_ready()` to sleep until one is.
+    /// Applies to sockets bound and connected afterwards.
+    /// Fails where readiness polling is not supported.
+    pub fn enable_poll(&mut self, raw_fd: RawFdGetter<T>) -> TransportResult<()> {
+        self.poll = Some(WssPoll {
+            poller: Poller::new()?,
+            raw_fd,
+            token_map: HashMap::new(),
+            next_token: LISTENER_TOKEN + 1,
+            is_listener_polled: false,
+            is_listener_ready: false,
+        });
+        Ok(())
+    }
+
+    /// Require remotes to present a certificate satisfying `trust_policy`,
+    /// or accept any with `None`. Applies to connections opened afterwards.
+    pub fn set_trust_policy(&mut self, trust_policy: Option<TlsTrustPolicy>) {
+        self.trust_policy = trust_policy;
+    }
+
+    /// Ask remotes connecting to us for a certificate even without a trust policy,
+    /// e.g. for a gateway to check it is bound to a transport id.
+    /// Applies to connections accepted afterwards.
+    pub fn set_require_client_certificate(&mut self, require_client_certificate: bool) {
+        self.require_client_certificate = require_client_certificate;
+    }
+
+    /// Identity of the remote on a TLS connection, see `TlsPeerIdentity::is_trusted`
+    pub fn get_peer_identity(&self, id: &ConnectionIdRef) -> Option<TlsPeerIdentity> {
+        self.stream_sockets
+            .get(&id.to_string())
+            .and_then(|info| info.peer_identity.clone())
+    }
+
     /// connect and wait for a Connect event response
     pub fn wait_connect(&mut self, uri: &Url) -> TransportResult<ConnectionId> {
         // Launch connection attempt
@@ -348,21 +637,120 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
     }
 
     fn priv_process_accept(&mut self) -> DidWork {
-        match &mut self.acceptor {
+        if let Some(poll) = &mut self.poll {
+            if poll.is_listener_polled {
+                if !poll.is_listener_ready {
+                    return false;
+                }
+                // level triggered: reported again if more connections are pending
+                poll.is_listener_ready = false;
+            }
+        }
+        let accepted = match &mut self.acceptor {
             Err(err) => {
                 warn!("acceptor in error state: {:?}", err);
+                return false;
+            }
+            Ok(acceptor) => (acceptor)(self.n_id.clone()),
+        };
+        match accepted {
+            Ok(mut wss_info) => {
+                self.priv_register(&mut wss_info);
+                let connection_id = wss_info.id.clone();
+                let _insert_result = self.stream_sockets.insert(connection_id, wss_info);
+                true
+            }
+            Err(err) => {
+                warn!("did not accept any connections: {:?}", err);
                 false
             }
-            Ok(acceptor) => (acceptor)(self.n_id.clone())
-                .map(move |wss_info| {
-                    let connection_id = wss_info.id.clone();
-                    let _insert_result = self.stream_sockets.insert(connection_id, wss_info);
-                    true
-                })
-                .unwrap_or_else(|err| {
-                    warn!("did not accept any connections: {:?}", err);
-                    false
-                }),
+        }
+    }
+
+    // register a new connection's socket with the poller, if any
+    fn priv_register(&mut self, info: &mut WssInfo<T>) {
+        let poll = match &mut self.poll {
+            None => return,
+            Some(poll) => poll,
+        };
+        let raw_fd = match &info.stateful_socket {
+            WebsocketStreamState::Connecting(socket)
+            | WebsocketStreamState::ConnectingSrv(socket) => (poll.raw_fd)(socket),
+            _ => None,
+        };
+        let raw_fd = match raw_fd {
+            // tried on every process() instead
+            None => return,
+            Some(raw_fd) => raw_fd,
+        };
+        let token = poll.next_token;
+        if let Err(e) = poll.poller.register(raw_fd, token, Interest::READABLE) {
+            warn!("transport_wss: could not poll {}: {:?}", info.id, e);
+            return;
+        }
+        poll.next_token += 1;
+        poll.token_map.insert(token, info.id.clone());
+        info.raw_fd = Some(raw_fd);
+        info.token = Some(token);
+    }
+
+    fn priv_deregister(&mut self, info: &WssInfo<T>) {
+        if let (Some(poll), Some(raw_fd), Some(token)) = (&mut self.poll, info.raw_fd, info.token) {
+            poll.token_map.remove(&token);
+            // fails if the socket is already closed, which deregistered it
+            let _ = poll.poller.deregister(raw_fd);
+        }
+    }
+
+    // also watch for writability while a write is blocked
+    fn priv_update_interest(&mut self, info: &WssInfo<T>, was_write_blocked: bool) {
+        if info.is_write_blocked == was_write_blocked {
+            return;
+        }
+        if let (Some(poll), Some(raw_fd), Some(token)) = (&mut self.poll, info.raw_fd, info.token) {
+            let interest = if info.is_write_blocked {
+                Interest::READABLE_WRITABLE
+            } else {
+                Interest::READABLE
+            };
+            if let Err(e) = poll.poller.reregister(raw_fd, token, interest) {
+                warn!("transport_wss: could not poll {}: {:?}", info.id, e);
+            }
+        }
+    }
+
+    // wait up to `timeout` for sockets to be ready, and mark them
+    fn priv_poll(&mut self, timeout: Duration) -> TransportResult<()> {
+        let poll = match &mut self.poll {
+            None => return Ok(()),
+            Some(poll) => poll,
+        };
+        let mut ready = Vec::new();
+        poll.poller.wait(Some(timeout), &mut ready)?;
+        for token in ready {
+            if token == LISTENER_TOKEN {
+                poll.is_listener_ready = true;
+            } else if let Some(id) = poll.token_map.get(&token) {
+                if let Some(info) = self.stream_sockets.get_mut(id) {
+                    info.is_ready = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // whether process() has anything to do with this socket
+    fn priv_needs_processing(&self, info: &WssInfo<T>) -> bool {
+        if self.poll.is_none() || info.token.is_none() || info.is_ready {
+            return true;
+        }
+        match info.stateful_socket {
+            // a blocked write waits for the poller to report the socket writable
+            WebsocketStreamState::ReadyWs(_) | WebsocketStreamState::ReadyWss(_) => {
+                !info.send_queue.is_empty() && !info.is_write_blocked
+            }
+            // handshakes are short, no need to track which way they wait
+            _ => true,
         }
     }
 
@@ -370,6 +758,9 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
     fn priv_process_stream_sockets(&mut self) -> TransportResult<DidWork> {
         let mut did_work = false;
 
+        // see which sockets are ready, without waiting
+        self.priv_poll(Duration::from_millis(0))?;
+
         // accept some incoming connections
         did_work |= self.priv_process_accept();
 
@@ -377,28 +768,53 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
         let sockets: Vec<(String, WssInfo<T>)> = self.stream_sockets.drain().collect();
 
         for (id, mut info) in sockets {
-            if let Err(e) = self.priv_process_socket(&mut did_work, &mut info) {
-                self.event_queue
-                    .push(TransportEvent::ErrorOccured(info.id.clone(), e));
+            let was_write_blocked = info.is_write_blocked;
+            if self.priv_needs_processing(&info) {
+                if let Err(e) = self.priv_process_socket(&mut did_work, &mut info) {
+                    self.event_queue
+                        .push(TransportEvent::ErrorOccured(info.id.clone(), e));
+                }
             }
             if let WebsocketStreamState::None = info.stateful_socket {
+                self.priv_deregister(&info);
                 self.event_queue
                     .push(TransportEvent::ConnectionClosed(info.id));
                 continue;
             }
             if info.last_msg.elapsed().as_millis() as usize > DEFAULT_HEARTBEAT_MS {
-                if let WebsocketStreamState::ReadyWss(socket) = &mut info.stateful_socket {
-                    socket.write_message(tungstenite::Message::Ping(vec![]))?;
-                }
-                if let WebsocketStreamState::ReadyWs(socket) = &mut info.stateful_socket {
-                    socket.write_message(tungstenite::Message::Ping(vec![]))?;
+                let ping = tungstenite::Message::Ping(vec![]);
+                let result = match &mut info.stateful_socket {
+                    WebsocketStreamState::ReadyWss(socket) => socket.write_message(ping),
+                    WebsocketStreamState::ReadyWs(socket) => socket.write_message(ping),
+                    _ => Ok(()),
+                };
+                match result {
+                    // the ping stays buffered until the socket is writable
+                    Err(tungstenite::Error::Io(ref e))
+                        if e.kind() == std::io::ErrorKind::WouldBlock =>
+                    {
+                        info.is_write_blocked = true;
+                    }
+                    Err(e) => {
+                        // only this connection is lost, keep processing the others
+                        self.priv_deregister(&info);
+                        self.priv_fail_send_queue(&mut info, &e);
+                        self.event_queue
+                            .push(TransportEvent::ErrorOccured(info.id.clone(), e.into()));
+                        self.event_queue
+                            .push(TransportEvent::ConnectionClosed(info.id));
+                        continue;
+                    }
+                    Ok(()) => {}
                 }
             } else if info.last_msg.elapsed().as_millis() as usize > DEFAULT_HEARTBEAT_WAIT_MS {
+                self.priv_deregister(&info);
                 self.event_queue
                     .push(TransportEvent::ConnectionClosed(info.id));
                 info.stateful_socket = WebsocketStreamState::None;
                 continue;
             }
+            self.priv_update_interest(&info, was_write_blocked);
             self.stream_sockets.insert(id, info);
         }
 
@@ -433,13 +849,9 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
                         )?;
                     }
                     _ => {
-                        let connector = native_tls::TlsConnector::builder()
-                            .danger_accept_invalid_certs(true)
-                            .danger_accept_invalid_hostnames(true)
-                            .build()
-                            .expect("failed to build TlsConnector");
+                        let certificate = self.tls_config.tls_certificate()?;
                         info.stateful_socket =
-                            self.priv_tls_handshake(connector.connect(info.url.as_str(), socket))?;
+                            self.priv_tls_handshake(tls::connect(&certificate, socket))?;
                     }
                 }
                 Ok(())
@@ -452,32 +864,31 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
                         self.priv_ws_srv_handshake(&info.id, tungstenite::accept(socket))?;
                     return Ok(());
                 }
-                let ident = match &self.tls_config {
-                    TlsConfig::Unencrypted => unimplemented!(),
-                    TlsConfig::FakeServer => {
-                        native_tls::Identity::from_pkcs12(FAKE_PKCS12, FAKE_PASS)?
-                    }
-                    TlsConfig::SuppliedCertificate(cert) => {
-                        native_tls::Identity::from_pkcs12(&cert.pkcs12_data, &cert.passphrase)?
-                    }
-                };
-                let acceptor = native_tls::TlsAcceptor::builder(ident)
-                    .build()
-                    .expect("failed to build TlsAcceptor");
-                info.stateful_socket = self.priv_tls_srv_handshake(acceptor.accept(socket))?;
+                let certificate = self.tls_config.tls_certificate()?;
+                let require_client_certificate =
+                    self.require_client_certificate || self.trust_policy.is_some();
+                info.stateful_socket = self.priv_tls_srv_handshake(tls::accept(
+                    &certificate,
+                    require_client_certificate,
+                    socket,
+                ))?;
                 Ok(())
             }
             WebsocketStreamState::TlsMidHandshake(socket) => {
-                info.stateful_socket = self.priv_tls_handshake(socket.handshake())?;
+                info.stateful_socket = self.priv_tls_handshake(tls::handshake(socket))?;
                 Ok(())
             }
             WebsocketStreamState::TlsSrvMidHandshake(socket) => {
-                info.stateful_socket = self.priv_tls_srv_handshake(socket.handshake())?;
+                info.stateful_socket = self.priv_tls_srv_handshake(tls::handshake(socket))?;
                 Ok(())
             }
             WebsocketStreamState::TlsReady(socket) => {
                 info.last_msg = std::time::Instant::now();
                 *did_work = true;
+                info.peer_identity = trust::peer_identity(
+                    &self.trust_policy,
+                    &tls::peer_certificate_chain(&socket)?,
+                )?;
                 info.stateful_socket = self
                     .priv_wss_handshake(&info.id, tungstenite::client(info.url.clone(), socket))?;
                 Ok(())
@@ -485,6 +896,10 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
             WebsocketStreamState::TlsSrvReady(socket) => {
                 info.last_msg = std::time::Instant::now();
                 *did_work = true;
+                info.peer_identity = trust::peer_identity(
+                    &self.trust_policy,
+                    &tls::peer_certificate_chain(&socket)?,
+                )?;
                 info.stateful_socket =
                     self.priv_wss_srv_handshake(&info.id, tungstenite::accept(socket))?;
                 Ok(())
@@ -506,112 +921,164 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
                 Ok(())
             }
             WebsocketStreamState::ReadyWs(mut socket) => {
-                // This seems to be wrong. Messages shouldn't be drained.
-                let msgs: Vec<Vec<u8>> = info.send_queue.drain(..).collect();
-                for msg in msgs {
-                    // TODO: fix this line! if there is an error, all the remaining messages will be lost!
-                    socket.write_message(tungstenite::Message::Binary(msg))?;
+                if self.priv_process_ready(did_work, info, &mut socket)? {
+                    info.stateful_socket = WebsocketStreamState::ReadyWs(socket);
                 }
+                Ok(())
+            }
+            WebsocketStreamState::ReadyWss(mut socket) => {
+                if self.priv_process_ready(did_work, info, &mut socket)? {
+                    info.stateful_socket = WebsocketStreamState::ReadyWss(socket);
+                }
+                Ok(())
+            }
+        }
+    }
 
-                match socket.read_message() {
-                    Err(tungstenite::error::Error::Io(e)) => {
-                        if e.kind() == std::io::ErrorKind::WouldBlock {
-                            info.stateful_socket = WebsocketStreamState::ReadyWs(socket);
-                            return Ok(());
-                        }
-                        Err(e.into())
-                    }
-                    Err(tungstenite::error::Error::ConnectionClosed(_)) => {
-                        // close event will be published
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                    Ok(msg) => {
-                        info.last_msg = std::time::Instant::now();
-                        *did_work = true;
-                        let qmsg = match msg {
-                            tungstenite::Message::Text(s) => Some(s.into_bytes()),
-                            tungstenite::Message::Binary(b) => Some(b),
-                            _ => None,
-                        };
-
-                        if let Some(msg) = qmsg {
-                            self.event_queue
-                                .push(TransportEvent::ReceivedData(info.id.clone(), msg));
-                        }
-                        info.stateful_socket = WebsocketStreamState::ReadyWs(socket);
-                        Ok(())
-                    }
+    // write out the send queue, then read a message
+    // returns false if the connection was closed
+    fn priv_process_ready<S: Read + Write>(
+        &mut self,
+        did_work: &mut bool,
+        info: &mut WssInfo<T>,
+        socket: &mut tungstenite::protocol::WebSocket<S>,
+    ) -> TransportResult<bool> {
+        if let Err(e) = self.priv_write_send_queue(did_work, info, socket) {
+            self.priv_fail_send_queue(info, &e);
+            return Err(e.into());
+        }
+
+        // set again by the poller once there is more to read
+        info.is_ready = false;
+        match socket.read_message() {
+            Err(tungstenite::error::Error::Io(e)) => {
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    return Ok(true);
                 }
+                self.priv_fail_send_queue(info, &e);
+                Err(e.into())
             }
-            WebsocketStreamState::ReadyWss(mut socket) => {
-                // This seems to be wrong. Messages shouldn't be drained.
-                let msgs: Vec<Vec<u8>> = info.send_queue.drain(..).collect();
-                for msg in msgs {
-                    // TODO: fix this line! if there is an error, all the remaining messages will be lost!
-                    socket.write_message(tungstenite::Message::Binary(msg))?;
+            Err(tungstenite::error::Error::ConnectionClosed(e)) => {
+                // close event will be published
+                self.priv_fail_send_queue(info, &e);
+                Ok(false)
+            }
+            Err(e) => {
+                self.priv_fail_send_queue(info, &e);
+                Err(e.into())
+            }
+            Ok(msg) => {
+                info.last_msg = std::time::Instant::now();
+                *did_work = true;
+                // more messages may be buffered already
+                info.is_ready = true;
+                let qmsg = match msg {
+                    tungstenite::Message::Text(s) => Some(s.into_bytes()),
+                    tungstenite::Message::Binary(b) => Some(b),
+                    _ => None,
+                };
+
+                if let Some(msg) = qmsg {
+                    self.event_queue
+                        .push(TransportEvent::ReceivedData(info.id.clone(), msg.into()));
                 }
+                Ok(true)
+            }
+        }
+    }
 
-                match socket.read_message() {
-                    Err(tungstenite::error::Error::Io(e)) => {
-                        if e.kind() == std::io::ErrorKind::WouldBlock {
-                            info.stateful_socket = WebsocketStreamState::ReadyWss(socket);
-                            return Ok(());
-                        }
-                        Err(e.into())
-                    }
-                    Err(tungstenite::error::Error::ConnectionClosed(_)) => {
-                        // close event will be published
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                    Ok(msg) => {
-                        info.last_msg = std::time::Instant::now();
-                        *did_work = true;
-                        let qmsg = match msg {
-                            tungstenite::Message::Text(s) => Some(s.into_bytes()),
-                            tungstenite::Message::Binary(b) => Some(b),
-                            _ => None,
-                        };
-
-                        if let Some(msg) = qmsg {
-                            self.event_queue
-                                .push(TransportEvent::ReceivedData(info.id.clone(), msg));
-                        }
-                        info.stateful_socket = WebsocketStreamState::ReadyWss(socket);
-                        Ok(())
-                    }
+    // write queued payloads in order, until the socket would block.
+    // A payload cut short by WouldBlock stays buffered in the websocket,
+    // which writes it out before anything else.
+    // Errs if the connection is broken, payloads not sent yet are left queued.
+    fn priv_write_send_queue<S: Read + Write>(
+        &mut self,
+        did_work: &mut bool,
+        info: &mut WssInfo<T>,
+        socket: &mut tungstenite::protocol::WebSocket<S>,
+    ) -> Result<(), tungstenite::Error> {
+        // flush what an earlier WouldBlock left buffered, and pings, pongs, close
+        info.is_write_blocked = false;
+        match socket.write_pending() {
+            Err(tungstenite::Error::Io(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                info.is_write_blocked = true;
+                return Ok(());
+            }
+            result => result?,
+        }
+        while let Some(payload) = info.send_queue.pop_front() {
+            let payload_len = payload.len();
+            match socket.write_message(tungstenite::Message::Binary(payload.into_vec())) {
+                Ok(()) => *did_work = true,
+                Err(tungstenite::Error::Io(ref e))
+                    if e.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    *did_work = true;
+                    info.is_write_blocked = true;
+                    return Ok(());
+                }
+                Err(tungstenite::Error::SendQueueFull(msg)) => {
+                    info.send_queue.push_front(msg.into_data().into());
+                    info.is_write_blocked = true;
+                    return Ok(());
+                }
+                // only this payload is refused, the connection is fine
+                Err(tungstenite::Error::Capacity(e)) => {
+                    self.priv_payload_error(&info.id, payload_len, &e);
+                }
+                Err(e) => {
+                    self.priv_payload_error(&info.id, payload_len, &e);
+                    return Err(e);
                 }
             }
         }
+        Ok(())
+    }
+
+    // the connection is broken: report every payload that will never be sent
+    fn priv_fail_send_queue(&mut self, info: &mut WssInfo<T>, error: &dyn std::fmt::Debug) {
+        for payload in info.send_queue.drain(..).collect::<Vec<_>>() {
+            self.priv_payload_error(&info.id, payload.len(), error);
+        }
+    }
+
+    fn priv_payload_error(
+        &mut self,
+        id: &ConnectionIdRef,
+        payload_len: usize,
+        error: &dyn std::fmt::Debug,
+    ) {
+        self.event_queue.push(TransportEvent::ErrorOccured(
+            id.to_string(),
+            TransportError::new(format!(
+                "payload of {} bytes not sent: {:?}",
+                payload_len, error
+            )),
+        ));
     }
 
     // process tls handshaking
     fn priv_tls_handshake(
         &mut self,
-        res: TlsConnectResult<T>,
+        res: TransportResult<TlsHandshake<BaseStream<T>>>,
     ) -> TransportResult<WebsocketStreamState<T>> {
-        match res {
-            Err(native_tls::HandshakeError::WouldBlock(socket)) => {
-                Ok(WebsocketStreamState::TlsMidHandshake(socket))
-            }
-            Err(e) => Err(e.into()),
-            Ok(socket) => Ok(WebsocketStreamState::TlsReady(socket)),
+        match res? {
+            TlsHandshake::MidHandshake(socket) => Ok(WebsocketStreamState::TlsMidHandshake(socket)),
+            TlsHandshake::Ready(socket) => Ok(WebsocketStreamState::TlsReady(socket)),
         }
     }
 
     // process tls handshaking
     fn priv_tls_srv_handshake(
         &mut self,
-        res: TlsConnectResult<T>,
+        res: TransportResult<TlsHandshake<BaseStream<T>>>,
     ) -> TransportResult<WebsocketStreamState<T>> {
         trace!("[t] processing tls connect result: {:?}", res);
-        match res {
-            Err(native_tls::HandshakeError::WouldBlock(socket)) => {
+        match res? {
+            TlsHandshake::MidHandshake(socket) => {
                 Ok(WebsocketStreamState::TlsSrvMidHandshake(socket))
             }
-            Err(e) => Err(e.into()),
-            Ok(socket) => Ok(WebsocketStreamState::TlsSrvReady(socket)),
+            TlsHandshake::Ready(socket) => Ok(WebsocketStreamState::TlsSrvReady(socket)),
         }
     }
 
@@ -691,3 +1158,253 @@ impl<T: Read + Write + std::fmt::Debug + std::marker::Sized> TransportWss<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use std::io;
+
+    #[derive(Debug, Default)]
+    struct MockState {
+        written: Vec<u8>,
+        /// bytes accepted before writes would block
+        write_budget: usize,
+        is_broken: bool,
+    }
+
+    /// Stream with nothing to read, and writes we control
+    #[derive(Debug, Clone)]
+    struct MockStream(Arc<Mutex<MockState>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+            if state.is_broken {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            if state.write_budget == 0 {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let len = std::cmp::min(state.write_budget, buf.len());
+            state.written.extend_from_slice(&buf[..len]);
+            state.write_budget -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn mock_transport() -> TransportWss<MockStream> {
+        TransportWss::new(
+            |_uri| Err(TransportError::new("no remote".into())),
+            Box::new(|_url: &Url| Err(TransportError::new("no bind".into()))),
+            TlsConfig::Unencrypted,
+        )
+    }
+
+    // add a ready connection over a mock stream
+    fn add_mock_connection(
+        transport: &mut TransportWss<MockStream>,
+        id: &str,
+        state: &Arc<Mutex<MockState>>,
+    ) {
+        let stream = MockStream(state.clone());
+        let mut info = WssInfo::client(
+            id.to_string(),
+            Url::parse("ws://127.0.0.1:1").unwrap(),
+            stream.clone(),
+        );
+        info.stateful_socket = WebsocketStreamState::ReadyWs(Box::new(
+            tungstenite::protocol::WebSocket::from_raw_socket(
+                stream,
+                tungstenite::protocol::Role::Server,
+                None,
+            ),
+        ));
+        transport.stream_sockets.insert(id.to_string(), info);
+    }
+
+    // unmasked binary frame of a short payload
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x82, payload.len() as u8];
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_would_block_keeps_payloads() {
+        let state = Arc::new(Mutex::new(MockState {
+            write_budget: 15,
+            ..MockState::default()
+        }));
+        let mut transport = mock_transport();
+        add_mock_connection(&mut transport, "ws_mock", &state);
+        for i in 1..=3 {
+            transport.send(&["ws_mock"], &vec![i; 10].into()).unwrap();
+        }
+
+        // The second payload is cut short, the third waits
+        let (_did_work, event_list) = transport.process().unwrap();
+        assert!(event_list.is_empty());
+        let mut expected = frame(&[1; 10]);
+        expected.extend_from_slice(&frame(&[2; 10])[..3]);
+        assert_eq!(state.lock().unwrap().written, expected);
+        let snapshot = transport.transport_snapshot();
+        assert_eq!(snapshot.connection_list[0].send_queue_depth, 1);
+
+        // Everything goes out in order once the socket is writable again
+        state.lock().unwrap().write_budget = 1000;
+        let (_did_work, event_list) = transport.process().unwrap();
+        assert!(event_list.is_empty());
+        let mut expected = frame(&[1; 10]);
+        expected.extend_from_slice(&frame(&[2; 10]));
+        expected.extend_from_slice(&frame(&[3; 10]));
+        assert_eq!(state.lock().unwrap().written, expected);
+        let snapshot = transport.transport_snapshot();
+        assert_eq!(snapshot.connection_list[0].send_queue_depth, 0);
+    }
+
+    #[test]
+    fn test_write_error_reported_per_payload() {
+        let state = Arc::new(Mutex::new(MockState {
+            is_broken: true,
+            ..MockState::default()
+        }));
+        let mut transport = mock_transport();
+        add_mock_connection(&mut transport, "ws_mock", &state);
+        transport.send(&["ws_mock"], &vec![1; 10].into()).unwrap();
+        transport.send(&["ws_mock"], &vec![2; 20].into()).unwrap();
+
+        let (_did_work, event_list) = transport.process().unwrap();
+        let payload_error_list: Vec<String> = event_list
+            .iter()
+            .filter_map(|event| match event {
+                TransportEvent::ErrorOccured(id, e) if e.0.starts_with("payload") => {
+                    assert_eq!(id, "ws_mock");
+                    Some(e.0.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(payload_error_list.len(), 2);
+        assert!(payload_error_list[0].starts_with("payload of 10 bytes not sent"));
+        assert!(payload_error_list[1].starts_with("payload of 20 bytes not sent"));
+        assert_eq!(
+            event_list.last(),
+            Some(&TransportEvent::ConnectionClosed("ws_mock".to_string()))
+        );
+        assert!(transport.connection_id_list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failed_heartbeat_keeps_other_connections() {
+        let broken_state = Arc::new(Mutex::new(MockState::default()));
+        let healthy_state = Arc::new(Mutex::new(MockState {
+            write_budget: 1000,
+            ..MockState::default()
+        }));
+        let mut transport = mock_transport();
+        add_mock_connection(&mut transport, "ws_broken", &broken_state);
+        add_mock_connection(&mut transport, "ws_healthy", &healthy_state);
+        let stale = std::time::Instant::now()
+            - std::time::Duration::from_millis(DEFAULT_HEARTBEAT_MS as u64 + 100);
+        for info in transport.stream_sockets.values_mut() {
+            info.last_msg = stale;
+        }
+        broken_state.lock().unwrap().is_broken = true;
+
+        let (_did_work, event_list) = transport.process().unwrap();
+        assert!(event_list.contains(&TransportEvent::ConnectionClosed("ws_broken".to_string())));
+        assert_eq!(
+            transport.connection_id_list().unwrap(),
+            vec!["ws_healthy".to_string()]
+        );
+        // The healthy connection got its ping
+        assert_eq!(healthy_state.lock().unwrap().written, vec![0x89, 0]);
+    }
+
+    #[test]
+    fn test_wait_ready() {
+        wait_ready_test(TlsConfig::Unencrypted, "ws");
+    }
+
+    #[test]
+    fn test_wait_ready_tls() {
+        wait_ready_test(TlsConfig::FakeServer, "wss");
+    }
+
+    fn wait_ready_test(tls_config: TlsConfig, scheme: &str) {
+        let mut node_A = TransportWss::with_std_tcp_stream(tls_config.clone());
+        let mut node_B = TransportWss::with_std_tcp_stream(tls_config);
+        if node_A.poll.is_none() {
+            // readiness polling is not supported here
+            return;
+        }
+        let uri_B = node_B
+            .bind(&Url::parse(&format!("{}://127.0.0.1:0/B", scheme)).unwrap())
+            .unwrap();
+        let idAB = node_A.connect(&uri_B).unwrap();
+        let mut is_connected = false;
+        let mut maybe_idBA = None;
+        for _ in 0..100 {
+            node_A.wait_ready(Duration::from_millis(10)).unwrap();
+            let (_did_work, event_list) = node_A.process().unwrap();
+            is_connected |= event_list.contains(&TransportEvent::ConnectResult(idAB.clone()));
+            node_B.wait_ready(Duration::from_millis(10)).unwrap();
+            let (_did_work, event_list) = node_B.process().unwrap();
+            for event in event_list {
+                if let TransportEvent::IncomingConnectionEstablished(id) = event {
+                    maybe_idBA = Some(id);
+                }
+            }
+            if is_connected && maybe_idBA.is_some() {
+                break;
+            }
+        }
+        assert!(is_connected);
+        let idBA = maybe_idBA.unwrap();
+        for _ in 0..3 {
+            node_A.process().unwrap();
+            node_B.process().unwrap();
+        }
+
+        // Idle: sleeps until max_wait
+        let start = std::time::Instant::now();
+        node_A.wait_ready(Duration::from_millis(200)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        // Woken up by incoming data, until all of it is read.
+        // Over TLS, several messages may be decrypted from a single read of the socket.
+        let payload_list: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![4, 5], vec![6]];
+        for payload in &payload_list {
+            node_B.send(&[&idBA], &payload.clone().into()).unwrap();
+        }
+        node_B.process().unwrap();
+        let mut event_list = Vec::new();
+        for _ in 0..10 {
+            let start = std::time::Instant::now();
+            node_A.wait_ready(Duration::from_millis(2000)).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(1000));
+            let (_did_work, mut new_event_list) = node_A.process().unwrap();
+            event_list.append(&mut new_event_list);
+            if event_list.len() >= payload_list.len() {
+                break;
+            }
+        }
+        let expected: Vec<TransportEvent> = payload_list
+            .into_iter()
+            .map(|payload| TransportEvent::ReceivedData(idAB.clone(), payload.into()))
+            .collect();
+        assert_eq!(event_list, expected);
+    }
+}
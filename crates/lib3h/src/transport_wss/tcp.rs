@@ -2,83 +2,125 @@
 //! TcpStream specific functions
 
 use crate::{
-    transport::error::TransportResult,
+    transport::{
+        error::{TransportError, TransportResult},
+        poll::RawFd,
+    },
     transport_wss::{
-        Acceptor, Bind, ConnectionIdFactory, IdGenerator, TlsConfig, TransportWss, WssInfo,
+        Acceptor, Bind, Binding, ConnectionIdFactory, IdGenerator, TlsConfig, TransportWss, WssInfo,
     },
 };
 
-use std::net::{TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+#[cfg(unix)]
+fn raw_fd(socket: &impl AsRawFd) -> Option<RawFd> {
+    Some(socket.as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn raw_fd<S>(_socket: &S) -> Option<RawFd> {
+    None
+}
+
+/// Socket address a ws or wss url names, IPv4 or IPv6.
+/// Urls without a port use the scheme's default one.
+pub(crate) fn socket_address(url: &url::Url) -> TransportResult<SocketAddr> {
+    let host = url
+        .host()
+        .ok_or_else(|| TransportError::new(format!("url has no host: {}", url)))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| TransportError::new(format!("url has no port: {}", url)))?;
+    match host {
+        url::Host::Ipv4(ip) => Ok(SocketAddr::new(ip.into(), port)),
+        url::Host::Ipv6(ip) => Ok(SocketAddr::new(ip.into(), port)),
+        url::Host::Domain(domain) => (domain, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| TransportError::new(format!("could not resolve {}", url))),
+    }
+}
 
 impl TransportWss<std::net::TcpStream> {
     /// convenience constructor for creating a websocket "Transport"
     /// instance that is based of the rust std TcpStream
     pub fn with_std_tcp_stream(tls_config: TlsConfig) -> Self {
         let bind: Bind<TcpStream> = Box::new(move |url| Self::tcp_bind(url));
-        TransportWss::new(
+        let mut transport = TransportWss::new(
             |uri| {
-                let socket = std::net::TcpStream::connect(uri)?;
+                let socket = std::net::TcpStream::connect(socket_address(uri)?)?;
                 socket.set_nonblocking(true)?;
+                // rustls writes each handshake record separately, don't let
+                // Nagle hold the last ones back
+                socket.set_nodelay(true)?;
                 Ok(socket)
             },
             bind,
             tls_config,
-        )
+        );
+        if let Err(e) = transport.enable_poll(|tcp_stream| raw_fd(tcp_stream)) {
+            debug!("transport_wss::tcp trying every socket instead: {:?}", e);
+        }
+        transport
     }
 
-    fn tcp_bind(url: &url::Url) -> TransportResult<Acceptor<TcpStream>> {
-        // TODO return transport result rather than expect()
-        let host = url.host_str().expect("host name must be supplied");
-        let port = url.port().unwrap_or(80); // TODO default or error here?
-        let formatted_url = format!("{}:{}", host, port);
-        debug!("formatted url: {}", formatted_url);
-        TcpListener::bind(formatted_url)
-            .map_err(|err| err.into())
-            .and_then(move |listener: TcpListener| {
-                listener
-                    .set_nonblocking(true)
-                    .map_err(|err| {
-                        error!("transport_wss::tcp listener error: {:?}", err);
-                        err.into()
-                    })
-                    .map(|()| {
-                        let acceptor: Acceptor<TcpStream> =
-                            Box::new(move |mut connection_id_factory: ConnectionIdFactory| {
-                                let connection_id = connection_id_factory.next_id();
-                                listener
-                                    .accept()
-                                    .map_err(|err| {
-                                        error!("transport_wss::tcp accept error: {:?}", err);
-                                        err.into()
-                                    })
-                                    .and_then(|(tcp_stream, socket_address)| {
-                                        tcp_stream.set_nonblocking(true)?;
-                                        let v4_socket_address = format!(
-                                            "wss://{}:{}",
-                                            socket_address.ip(),
-                                            socket_address.port()
-                                        );
+    /// Listen on the address of `url`. Returns the url actually bound,
+    /// with the port the OS picked if `url` asked for port 0.
+    fn tcp_bind(url: &url::Url) -> TransportResult<Binding<TcpStream>> {
+        let listener = TcpListener::bind(socket_address(url)?)?;
+        listener.set_nonblocking(true).map_err(|err| {
+            error!("transport_wss::tcp listener error: {:?}", err);
+            err
+        })?;
+        let mut bound_url = url.clone();
+        bound_url
+            .set_port(Some(listener.local_addr()?.port()))
+            .map_err(|()| TransportError::new(format!("cannot set port of {}", url)))?;
+        let listener_fd = raw_fd(&listener);
+        let scheme = url.scheme().to_string();
+        let acceptor: Acceptor<TcpStream> =
+            Box::new(move |mut connection_id_factory: ConnectionIdFactory| {
+                let connection_id = connection_id_factory.next_id();
+                let (tcp_stream, socket_address) = listener.accept().map_err(|err| {
+                    error!("transport_wss::tcp accept error: {:?}", err);
+                    err
+                })?;
+                tcp_stream.set_nonblocking(true)?;
+                tcp_stream.set_nodelay(true)?;
+                // SocketAddr formats IPv6 addresses in brackets, as urls want them
+                let url = url::Url::parse(&format!("{}://{}", scheme, socket_address)).map_err(
+                    |err| {
+                        error!("transport_wss::tcp url error: {:?}", err);
+                        err
+                    },
+                )?;
+                debug!("transport_wss::tcp accepted for url {}", url);
+                Ok(WssInfo::server(connection_id, url, tcp_stream))
+            });
+        Ok(Binding {
+            acceptor,
+            url: bound_url,
+            raw_fd: listener_fd,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                                        debug!(
-                                            "transport_wss::tcp v4 socket_address: {}",
-                                            v4_socket_address
-                                        );
-                                        url::Url::parse(v4_socket_address.as_str())
-                                            .map(|url| {
-                                                error!(
-                                                    "transport_wss::tcp accepted for url {}",
-                                                    url.clone()
-                                                );
-                                                WssInfo::server(connection_id, url, tcp_stream)
-                                            })
-                                            .map_err(|err| {
-                                                error!("transport_wss::tcp url error: {:?}", err);
-                                                err.into()
-                                            })
-                                    })
-                            });
-                        acceptor
-                    })
-            })
+    #[test]
+    fn test_socket_address() {
+        let address = |url: &str| socket_address(&url::Url::parse(url).unwrap()).unwrap();
+        assert_eq!(
+            address("wss://127.0.0.1:1234/path"),
+            "127.0.0.1:1234".parse().unwrap()
+        );
+        assert_eq!(address("ws://[::1]:1234"), "[::1]:1234".parse().unwrap());
+        assert_eq!(address("wss://[::1]"), "[::1]:443".parse().unwrap());
+        assert_eq!(address("ws://127.0.0.1"), "127.0.0.1:80".parse().unwrap());
     }
 }
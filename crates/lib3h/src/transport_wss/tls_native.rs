@@ -0,0 +1,134 @@
+//! TLS backend of `TransportWss` based on native-tls, i.e. the system's TLS library
+//! (default, `system-tls` feature). openssl converts certificates and checks CA chains.
+//! native-tls cannot request client certificates, so servers requiring them
+//! (see `TlsTrustPolicy`) need the `rustls-tls` backend.
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_wss::{TlsCertificate, TlsHandshake},
+};
+use native_tls::{HandshakeError, Identity, TlsAcceptor, TlsConnector};
+use openssl::{
+    pkcs12::Pkcs12,
+    pkey::PKey,
+    stack::Stack,
+    x509::{store::X509StoreBuilder, X509StoreContext, X509},
+};
+use std::io::{Read, Write};
+
+pub type TlsStream<T> = native_tls::TlsStream<T>;
+pub type TlsMidHandshake<T> = native_tls::MidHandshakeTlsStream<T>;
+
+/// Passphrase of the PKCS#12 archives handed to native-tls, which only loads those
+const PKCS12_PASSPHRASE: &str = "lib3h";
+
+/// Certificate chain and private key of a PKCS#12 archive
+pub fn pkcs12_to_der(pkcs12_data: &[u8], passphrase: &str) -> TransportResult<TlsCertificate> {
+    let pkcs12 = Pkcs12::from_der(pkcs12_data)?.parse(passphrase)?;
+    let mut certificate_chain = vec![pkcs12.cert.to_der()?];
+    if let Some(chain) = pkcs12.chain {
+        for certificate in chain {
+            certificate_chain.push(certificate.to_der()?);
+        }
+    }
+    Ok(TlsCertificate::from_der(
+        certificate_chain,
+        pkcs12.pkey.private_key_to_der()?,
+    ))
+}
+
+fn identity(certificate: &TlsCertificate) -> TransportResult<Identity> {
+    let mut chain = certificate.certificate_chain.iter();
+    let leaf = chain
+        .next()
+        .ok_or_else(|| TransportError::new("empty certificate chain".to_string()))?;
+    let mut intermediates = Stack::new()?;
+    for intermediate in chain {
+        intermediates.push(X509::from_der(intermediate)?)?;
+    }
+    let private_key = PKey::private_key_from_der(&certificate.private_key)?;
+    let leaf = X509::from_der(leaf)?;
+    let mut builder = Pkcs12::builder();
+    builder.ca(intermediates);
+    let pkcs12 = builder.build(PKCS12_PASSPHRASE, "", &private_key, &leaf)?;
+    Ok(Identity::from_pkcs12(&pkcs12.to_der()?, PKCS12_PASSPHRASE)?)
+}
+
+fn progress<T: Read + Write + std::fmt::Debug>(
+    res: Result<TlsStream<T>, HandshakeError<T>>,
+) -> TransportResult<TlsHandshake<T>> {
+    match res {
+        Err(HandshakeError::WouldBlock(socket)) => Ok(TlsHandshake::MidHandshake(socket)),
+        Err(e) => Err(e.into()),
+        Ok(socket) => Ok(TlsHandshake::Ready(socket)),
+    }
+}
+
+/// Start the client side of a handshake.
+/// Any server certificate is accepted, they are checked against the trust policy after.
+pub(crate) fn connect<T: Read + Write + std::fmt::Debug>(
+    certificate: &TlsCertificate,
+    socket: T,
+) -> TransportResult<TlsHandshake<T>> {
+    let connector = TlsConnector::builder()
+        .identity(identity(certificate)?)
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .use_sni(false)
+        .build()?;
+    progress(connector.connect("", socket))
+}
+
+/// Start the server side of a handshake
+pub(crate) fn accept<T: Read + Write + std::fmt::Debug>(
+    certificate: &TlsCertificate,
+    require_client_certificate: bool,
+    socket: T,
+) -> TransportResult<TlsHandshake<T>> {
+    if require_client_certificate {
+        return Err(TransportError::new(
+            "requiring client certificates needs the rustls-tls feature".to_string(),
+        ));
+    }
+    progress(TlsAcceptor::new(identity(certificate)?)?.accept(socket))
+}
+
+/// Carry on with a handshake that would have blocked
+pub(crate) fn handshake<T: Read + Write + std::fmt::Debug>(
+    socket: TlsMidHandshake<T>,
+) -> TransportResult<TlsHandshake<T>> {
+    progress(socket.handshake())
+}
+
+/// DER encoded certificates the remote presented, leaf first.
+/// native-tls only exposes the leaf.
+pub fn peer_certificate_chain<T: Read + Write>(
+    socket: &TlsStream<T>,
+) -> TransportResult<Vec<Vec<u8>>> {
+    match socket.peer_certificate()? {
+        Some(certificate) => Ok(vec![certificate.to_der()?]),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// True if the leaf of `certificate_chain` was issued by one of the DER encoded CAs
+pub fn verify_chain(ca_list: &[Vec<u8>], certificate_chain: &[Vec<u8>]) -> TransportResult<bool> {
+    let (leaf, intermediate_list) = certificate_chain
+        .split_first()
+        .ok_or_else(|| TransportError::new("empty certificate chain".to_string()))?;
+    let mut store = X509StoreBuilder::new()?;
+    for ca in ca_list {
+        store.add_cert(X509::from_der(ca)?)?;
+    }
+    let store = store.build();
+    let mut intermediates = Stack::new()?;
+    for intermediate in intermediate_list {
+        intermediates.push(X509::from_der(intermediate)?)?;
+    }
+    let leaf = X509::from_der(leaf)?;
+    let mut context = X509StoreContext::new()?;
+    let is_valid = context.init(&store, &leaf, &intermediates, |context| {
+        context.verify_cert()
+    })?;
+    Ok(is_valid)
+}
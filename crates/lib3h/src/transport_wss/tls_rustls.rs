@@ -0,0 +1,232 @@
+//! Pure-Rust TLS backend of `TransportWss` based on rustls (`rustls-tls` feature)
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_wss::{TlsCertificate, TlsHandshake},
+};
+use rustls::{
+    Certificate, ClientCertVerified, ClientCertVerifier, ClientConfig, ClientSession,
+    DistinguishedNames, NoClientAuth, PrivateKey, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, ServerConfig, ServerSession, Session, StreamOwned, TLSError,
+};
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+/// A TLS connection, either side
+pub enum TlsStream<T: Read + Write> {
+    Client(StreamOwned<ClientSession, T>),
+    Server(StreamOwned<ServerSession, T>),
+}
+
+/// rustls sessions handshake in place
+pub type TlsMidHandshake<T> = TlsStream<T>;
+
+impl<T: Read + Write> Read for TlsStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            TlsStream::Client(stream) => stream.read(buf),
+            TlsStream::Server(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<T: Read + Write> Write for TlsStream<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            TlsStream::Client(stream) => stream.write(buf),
+            TlsStream::Server(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            TlsStream::Client(stream) => stream.flush(),
+            TlsStream::Server(stream) => stream.flush(),
+        }
+    }
+}
+
+impl<T: Read + Write + std::fmt::Debug> std::fmt::Debug for TlsStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TlsStream::Client(stream) => write!(f, "TlsStream::Client({:?})", stream.sock),
+            TlsStream::Server(stream) => write!(f, "TlsStream::Server({:?})", stream.sock),
+        }
+    }
+}
+
+fn tls_error<E: std::fmt::Debug>(error: E) -> TransportError {
+    TransportError::new(format!("{:?}", error))
+}
+
+/// PKCS#12 archives are left to the native-tls backend
+pub fn pkcs12_to_der(_pkcs12_data: &[u8], _passphrase: &str) -> TransportResult<TlsCertificate> {
+    Err(TransportError::new(
+        "PKCS#12 certificates need the system-tls feature, use TlsCertificate::from_der"
+            .to_string(),
+    ))
+}
+
+// The handshake accepts any certificate, they are checked against the trust policy after
+struct AcceptAnyServerCertificate;
+
+impl ServerCertVerifier for AcceptAnyServerCertificate {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+struct AcceptAnyClientCertificate;
+
+impl ClientCertVerifier for AcceptAnyClientCertificate {
+    fn client_auth_root_subjects(&self) -> DistinguishedNames {
+        DistinguishedNames::new()
+    }
+
+    fn verify_client_cert(
+        &self,
+        _presented_certs: &[Certificate],
+    ) -> Result<ClientCertVerified, TLSError> {
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+fn certificate_chain(certificate: &TlsCertificate) -> Vec<Certificate> {
+    certificate
+        .certificate_chain
+        .iter()
+        .map(|der| Certificate(der.clone()))
+        .collect()
+}
+
+// drive a session's handshake as far as the socket allows, true once it is done
+fn complete_handshake<S: Session, T: Read + Write>(
+    stream: &mut StreamOwned<S, T>,
+) -> TransportResult<bool> {
+    while stream.sess.is_handshaking() {
+        match stream.sess.complete_io(&mut stream.sock) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Start the client side of a handshake
+pub(crate) fn connect<T: Read + Write + std::fmt::Debug>(
+    certificate: &TlsCertificate,
+    socket: T,
+) -> TransportResult<TlsHandshake<T>> {
+    let mut config = ClientConfig::new();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAnyServerCertificate));
+    config.set_single_client_cert(
+        certificate_chain(certificate),
+        PrivateKey(certificate.private_key.clone()),
+    );
+    // Any server certificate is accepted, so the name only serves SNI
+    let dns_name = webpki::DNSNameRef::try_from_ascii_str("lib3h.invalid").map_err(tls_error)?;
+    let session = ClientSession::new(&Arc::new(config), dns_name);
+    handshake(TlsStream::Client(StreamOwned::new(session, socket)))
+}
+
+/// Start the server side of a handshake
+pub(crate) fn accept<T: Read + Write + std::fmt::Debug>(
+    certificate: &TlsCertificate,
+    require_client_certificate: bool,
+    socket: T,
+) -> TransportResult<TlsHandshake<T>> {
+    let mut config = if require_client_certificate {
+        ServerConfig::new(Arc::new(AcceptAnyClientCertificate))
+    } else {
+        ServerConfig::new(NoClientAuth::new())
+    };
+    config
+        .set_single_cert(
+            certificate_chain(certificate),
+            PrivateKey(certificate.private_key.clone()),
+        )
+        .map_err(tls_error)?;
+    let session = ServerSession::new(&Arc::new(config));
+    handshake(TlsStream::Server(StreamOwned::new(session, socket)))
+}
+
+/// Carry on with a handshake that would have blocked
+pub(crate) fn handshake<T: Read + Write + std::fmt::Debug>(
+    mut socket: TlsMidHandshake<T>,
+) -> TransportResult<TlsHandshake<T>> {
+    let is_done = match &mut socket {
+        TlsStream::Client(stream) => complete_handshake(stream)?,
+        TlsStream::Server(stream) => complete_handshake(stream)?,
+    };
+    if is_done {
+        Ok(TlsHandshake::Ready(socket))
+    } else {
+        Ok(TlsHandshake::MidHandshake(socket))
+    }
+}
+
+/// DER encoded certificates the remote presented, leaf first
+pub fn peer_certificate_chain<T: Read + Write>(
+    socket: &TlsStream<T>,
+) -> TransportResult<Vec<Vec<u8>>> {
+    let certificate_list = match socket {
+        TlsStream::Client(stream) => stream.sess.get_peer_certificates(),
+        TlsStream::Server(stream) => stream.sess.get_peer_certificates(),
+    };
+    Ok(certificate_list
+        .unwrap_or_default()
+        .into_iter()
+        .map(|certificate| certificate.0)
+        .collect())
+}
+
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// True if the leaf of `certificate_chain` was issued by one of the DER encoded CAs
+pub fn verify_chain(ca_list: &[Vec<u8>], certificate_chain: &[Vec<u8>]) -> TransportResult<bool> {
+    let (leaf, intermediate_list) = certificate_chain
+        .split_first()
+        .ok_or_else(|| TransportError::new("empty certificate chain".to_string()))?;
+    let mut trust_anchor_list = Vec::new();
+    for ca in ca_list {
+        trust_anchor_list.push(
+            webpki::trust_anchor_util::cert_der_as_trust_anchor(untrusted::Input::from(ca))
+                .map_err(tls_error)?,
+        );
+    }
+    let intermediate_list: Vec<untrusted::Input> = intermediate_list
+        .iter()
+        .map(|der| untrusted::Input::from(der))
+        .collect();
+    let now = webpki::Time::try_from(std::time::SystemTime::now()).map_err(tls_error)?;
+    let leaf = webpki::EndEntityCert::from(untrusted::Input::from(leaf)).map_err(tls_error)?;
+    Ok(leaf
+        .verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TLSServerTrustAnchors(&trust_anchor_list),
+            &intermediate_list,
+            now,
+        )
+        .is_ok())
+}
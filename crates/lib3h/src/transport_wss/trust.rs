@@ -0,0 +1,131 @@
+//! Trust policies for the certificates remotes present during the TLS handshake.
+//!
+//! The handshake itself accepts any certificate; once it is done the remote's
+//! certificate is checked against the policy, and the connection dropped if it fails.
+
+use crate::{
+    transport::error::{TransportError, TransportResult},
+    transport_wss::{
+        certificate::{parse_certificate_binding, parse_certificate_subject},
+        tls,
+    },
+};
+
+/// Which certificates to accept from remotes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TlsTrustPolicy {
+    /// DER encoded CA certificates, one of which must have issued the remote's certificate
+    CaBundle(Vec<Vec<u8>>),
+    /// DER encoded public keys, one of which must be the remote certificate's key
+    PinnedKeys(Vec<Vec<u8>>),
+    /// Transport ids, one of which must be named by the remote's certificate,
+    /// which must be signed by that transport id, see `certificate`
+    TransportIds(Vec<String>),
+}
+
+/// Who is on the other end of a TLS connection, according to its certificate
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsPeerIdentity {
+    /// DER encoded certificate the remote presented
+    pub certificate_der: Vec<u8>,
+    /// DER encoded public key of that certificate
+    pub public_key_der: Vec<u8>,
+    /// Subject common name, e.g. the transport id of a self-signed certificate
+    pub common_name: Option<String>,
+    /// True if the certificate was checked against a `TlsTrustPolicy`
+    pub is_trusted: bool,
+}
+
+impl TlsPeerIdentity {
+    /// Identity claimed by a DER encoded certificate
+    pub fn from_certificate(certificate_der: &[u8]) -> TransportResult<Self> {
+        let subject = parse_certificate_subject(certificate_der)?;
+        Ok(TlsPeerIdentity {
+            certificate_der: certificate_der.to_vec(),
+            public_key_der: subject.public_key_der,
+            common_name: subject.common_name,
+            is_trusted: false,
+        })
+    }
+}
+
+impl TlsTrustPolicy {
+    /// Identity of the remote if the DER encoded certificates it presented,
+    /// leaf first, satisfy the policy.
+    pub fn verify(&self, certificate_chain: &[Vec<u8>]) -> TransportResult<TlsPeerIdentity> {
+        let leaf = certificate_chain
+            .first()
+            .ok_or_else(|| TransportError::new("remote presented no certificate".to_string()))?;
+        let mut identity = TlsPeerIdentity::from_certificate(leaf)?;
+        let is_trusted = match self {
+            TlsTrustPolicy::CaBundle(ca_list) => tls::verify_chain(ca_list, certificate_chain)?,
+            TlsTrustPolicy::PinnedKeys(key_list) => key_list.contains(&identity.public_key_der),
+            TlsTrustPolicy::TransportIds(transport_id_list) => {
+                match parse_certificate_binding(leaf) {
+                    Ok(binding) => {
+                        transport_id_list.contains(&binding.transport_id) && binding.is_signed()
+                    }
+                    Err(_) => false,
+                }
+            }
+        };
+        if !is_trusted {
+            return Err(TransportError::new(format!(
+                "remote certificate ({:?}) does not satisfy trust policy",
+                identity.common_name
+            )));
+        }
+        identity.is_trusted = true;
+        Ok(identity)
+    }
+}
+
+/// Identity of the remote once a TLS handshake is done, from the certificates it
+/// presented, or an error if they do not satisfy `trust_policy`.
+pub(crate) fn peer_identity(
+    trust_policy: &Option<TlsTrustPolicy>,
+    certificate_chain: &[Vec<u8>],
+) -> TransportResult<Option<TlsPeerIdentity>> {
+    match (trust_policy, certificate_chain.first()) {
+        (Some(trust_policy), _) => Ok(Some(trust_policy.verify(certificate_chain)?)),
+        (None, Some(certificate)) => Ok(Some(TlsPeerIdentity::from_certificate(certificate)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport_wss::certificate::{generate_bound_for_test, generate_self_signed};
+
+    #[test]
+    fn test_trust_policy() {
+        let (id_a, certificate_a) = generate_bound_for_test();
+        let certificate_a = certificate_a.certificate_chain;
+        let (_, certificate_b) = generate_bound_for_test();
+        let certificate_b = certificate_b.certificate_chain;
+        // Transport ids
+        let policy = TlsTrustPolicy::TransportIds(vec![id_a.clone()]);
+        let identity = policy.verify(&certificate_a).unwrap();
+        assert_eq!(identity.common_name, Some(id_a.clone()));
+        assert!(identity.is_trusted);
+        assert!(policy.verify(&certificate_b).is_err());
+        assert!(policy.verify(&[]).is_err());
+        // Naming a listed transport id without its signature is not enough
+        let spoofed = generate_self_signed(&id_a, |public_key| Ok(public_key.to_vec()))
+            .unwrap()
+            .certificate_chain;
+        assert!(policy.verify(&spoofed).is_err());
+        // Pinned keys
+        let key_b = TlsPeerIdentity::from_certificate(&certificate_b[0])
+            .unwrap()
+            .public_key_der;
+        let policy = TlsTrustPolicy::PinnedKeys(vec![key_b]);
+        assert!(policy.verify(&certificate_a).is_err());
+        assert!(policy.verify(&certificate_b).is_ok());
+        // CA bundle: a self-signed certificate is its own CA
+        let policy = TlsTrustPolicy::CaBundle(certificate_a.clone());
+        assert!(policy.verify(&certificate_a).is_ok());
+        assert!(policy.verify(&certificate_b).is_err());
+    }
+}
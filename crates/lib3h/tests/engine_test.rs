@@ -8,11 +8,17 @@ extern crate backtrace;
 extern crate lib3h;
 extern crate lib3h_protocol;
 extern crate lib3h_sodium;
+extern crate serde_json;
 
 use lib3h::{
-    dht::{dht_trait::Dht, mirror_dht::MirrorDht},
-    engine::{RealEngine, RealEngineConfig},
+    dht::{
+        dht_trait::{Dht, DhtFactory},
+        kademlia_dht::KademliaDht,
+        mirror_dht::MirrorDht,
+    },
+    engine::{real_engine::new_network_engine, EngineSnapshot, RealEngine, RealEngineConfig},
     transport::{memory_mock::transport_memory::TransportMemory, transport_trait::Transport},
+    transport_tcp::TransportTcp,
     transport_wss::{TlsConfig, TransportWss},
 };
 use lib3h_protocol::{
@@ -20,6 +26,7 @@ use lib3h_protocol::{
     protocol_server::Lib3hServerProtocol,
 };
 use lib3h_sodium::SodiumCryptoSystem;
+use std::{collections::HashMap, time::Duration};
 use url::Url;
 use utils::constants::*;
 
@@ -29,6 +36,8 @@ use utils::constants::*;
 
 type TwoEnginesTestFn = fn(alex: &mut Box<dyn NetworkEngine>, billy: &mut Box<dyn NetworkEngine>);
 
+type EngineSetupFn = fn(name: &str) -> Box<dyn NetworkEngine>;
+
 lazy_static! {
     pub static ref TWO_ENGINES_BASIC_TEST_FNS: Vec<(TwoEnginesTestFn, bool)> = vec![
         (setup_only, true),
@@ -59,8 +68,37 @@ fn enable_logging_for_test(enable: bool) {
 //--------------------------------------------------------------------------------------------------
 
 fn basic_setup_mock(name: &str) -> RealEngine<TransportMemory, MirrorDht> {
+    basic_setup_mock_with_space_workers(name, 0)
+}
+
+fn basic_setup_mock_with_space_workers(
+    name: &str,
+    space_worker_count: usize,
+) -> RealEngine<TransportMemory, MirrorDht> {
+    basic_setup_mock_with_dht(name, space_worker_count, MirrorDht::new_with_config)
+}
+
+fn basic_setup_mock_kademlia(name: &str) -> RealEngine<TransportMemory, KademliaDht> {
+    basic_setup_mock_with_dht(name, 0, KademliaDht::new_with_config)
+}
+
+fn boxed_setup_mock(name: &str) -> Box<dyn NetworkEngine> {
+    Box::new(basic_setup_mock(name))
+}
+
+/// Bind under another name so it can run alongside the MirrorDht tests
+fn boxed_setup_mock_kademlia(name: &str) -> Box<dyn NetworkEngine> {
+    Box::new(basic_setup_mock_kademlia(&format!("{}_kademlia", name)))
+}
+
+fn basic_setup_mock_with_dht<D: Dht>(
+    name: &str,
+    space_worker_count: usize,
+    dht_factory: DhtFactory<D>,
+) -> RealEngine<TransportMemory, D> {
     let config = RealEngineConfig {
         tls_config: TlsConfig::Unencrypted,
+        tls_trust_policy: None,
         socket_type: "mem".into(),
         bootstrap_nodes: vec![],
         work_dir: String::new(),
@@ -68,13 +106,18 @@ fn basic_setup_mock(name: &str) -> RealEngine<TransportMemory, MirrorDht> {
         bind_url: Url::parse(format!("mem://{}", name).as_str()).unwrap(),
         dht_gossip_interval: 100,
         dht_timeout_threshold: 1000,
+        dht_anti_entropy_interval: 10000,
+        dht_eviction_grace_period: 60000,
         dht_custom_config: vec![],
+        space_worker_count,
+        dht_replication_factor: 3,
+        space_replication_factor_map: HashMap::new(),
     };
     let engine = RealEngine::new_mock(
         Box::new(SodiumCryptoSystem::new()),
         config,
         name.into(),
-        MirrorDht::new_with_config,
+        dht_factory,
     )
     .unwrap();
     let p2p_binding = engine.advertise();
@@ -88,14 +131,20 @@ fn basic_setup_mock(name: &str) -> RealEngine<TransportMemory, MirrorDht> {
 fn basic_setup_wss() -> RealEngine<TransportWss<std::net::TcpStream>, MirrorDht> {
     let config = RealEngineConfig {
         tls_config: TlsConfig::Unencrypted,
+        tls_trust_policy: None,
         socket_type: "ws".into(),
         bootstrap_nodes: vec![],
         work_dir: String::new(),
         log_level: 'd',
-        bind_url: Url::parse("wss://127.0.0.1:64519").unwrap(),
+        bind_url: Url::parse("wss://127.0.0.1:0").unwrap(),
         dht_gossip_interval: 200,
         dht_timeout_threshold: 2000,
+        dht_anti_entropy_interval: 10000,
+        dht_eviction_grace_period: 60000,
         dht_custom_config: vec![],
+        space_worker_count: 0,
+        dht_replication_factor: 3,
+        space_replication_factor_map: HashMap::new(),
     };
     let engine = RealEngine::new(
         Box::new(SodiumCryptoSystem::new()),
@@ -106,6 +155,43 @@ fn basic_setup_wss() -> RealEngine<TransportWss<std::net::TcpStream>, MirrorDht>
     .unwrap();
     let p2p_binding = engine.advertise();
     println!("test_engine advertise: {}", p2p_binding);
+    // Bound to the port the OS picked
+    assert_ne!(p2p_binding.port(), Some(0));
+    engine
+}
+
+fn tcp_config(bind_url: &str) -> RealEngineConfig {
+    RealEngineConfig {
+        tls_config: TlsConfig::Unencrypted,
+        tls_trust_policy: None,
+        socket_type: "tcp".into(),
+        bootstrap_nodes: vec![],
+        work_dir: String::new(),
+        log_level: 'd',
+        bind_url: Url::parse(bind_url).unwrap(),
+        dht_gossip_interval: 200,
+        dht_timeout_threshold: 2000,
+        dht_anti_entropy_interval: 10000,
+        dht_eviction_grace_period: 60000,
+        dht_custom_config: vec![],
+        space_worker_count: 0,
+        dht_replication_factor: 3,
+        space_replication_factor_map: HashMap::new(),
+    }
+}
+
+fn basic_setup_tcp() -> RealEngine<TransportTcp<std::net::TcpStream>, MirrorDht> {
+    let engine = RealEngine::new_tcp(
+        Box::new(SodiumCryptoSystem::new()),
+        tcp_config("tcp://127.0.0.1:0"),
+        "test_engine_tcp".into(),
+        MirrorDht::new_with_config,
+    )
+    .unwrap();
+    let p2p_binding = engine.advertise();
+    println!("test_engine advertise: {}", p2p_binding);
+    // Bound to the port the OS picked
+    assert_ne!(p2p_binding.port(), Some(0));
     engine
 }
 
@@ -163,6 +249,60 @@ fn basic_track_test_wss() {
     basic_track_test(&mut engine);
 }
 
+#[test]
+fn wait_ready_test_wss() {
+    enable_logging_for_test(true);
+    let mut engine = basic_setup_wss();
+    engine.process().unwrap();
+    // Returns by the engine's next deadline at the latest
+    let start = std::time::Instant::now();
+    engine.wait_ready(Duration::from_millis(300)).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(1000));
+    // Nothing to wait for once a message is posted
+    engine
+        .post(Lib3hClientProtocol::JoinSpace(JoinSpaceData {
+            request_id: "track_a_1".into(),
+            space_address: SPACE_ADDRESS_A.clone(),
+            agent_id: ALEX_AGENT_ID.clone(),
+            storage_budget: None,
+        }))
+        .unwrap();
+    let start = std::time::Instant::now();
+    engine.wait_ready(Duration::from_millis(2000)).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[test]
+fn basic_track_test_tcp() {
+    enable_logging_for_test(true);
+    // Setup
+    let mut engine = basic_setup_tcp();
+    basic_track_test(&mut engine);
+}
+
+#[test]
+fn new_network_engine_by_socket_type_test() {
+    enable_logging_for_test(true);
+    let engine = new_network_engine(
+        Box::new(SodiumCryptoSystem::new()),
+        tcp_config("tcp://127.0.0.1:0"),
+        "test_engine_by_socket_type",
+        MirrorDht::new_with_config,
+    )
+    .unwrap();
+    assert_eq!(engine.advertise().scheme(), "tcp");
+    // Unknown socket types are refused
+    let mut config = tcp_config("tcp://127.0.0.1:0");
+    config.socket_type = "carrier-pigeon".into();
+    assert!(new_network_engine(
+        Box::new(SodiumCryptoSystem::new()),
+        config,
+        "test_engine_bad_socket_type",
+        MirrorDht::new_with_config,
+    )
+    .is_err());
+}
+
 #[test]
 fn basic_track_test_mock() {
     enable_logging_for_test(true);
@@ -171,12 +311,173 @@ fn basic_track_test_mock() {
     basic_track_test(&mut engine);
 }
 
+#[test]
+fn basic_track_test_mock_kademlia() {
+    enable_logging_for_test(true);
+    // Setup
+    let mut engine = basic_setup_mock_kademlia("basic_track_test_mock_kademlia");
+    basic_track_test(&mut engine);
+}
+
+#[test]
+fn basic_track_test_mock_with_space_workers() {
+    enable_logging_for_test(true);
+    // Setup
+    let mut engine = basic_setup_mock_with_space_workers("basic_track_test_mock_workers", 2);
+    basic_track_test(&mut engine);
+    // Join more spaces so the workers have several gateways to process
+    for space_address in &[&*SPACE_ADDRESS_B, &*SPACE_ADDRESS_C] {
+        let track_space = JoinSpaceData {
+            request_id: format!("track_{}", space_address),
+            space_address: (*space_address).clone(),
+            agent_id: ALEX_AGENT_ID.clone(),
+            storage_budget: None,
+        };
+        engine
+            .post(Lib3hClientProtocol::JoinSpace(track_space))
+            .unwrap();
+    }
+    let (did_work, srv_msg_list) = engine.process().unwrap();
+    assert!(did_work);
+    assert_eq!(srv_msg_list.len(), 6);
+    // Hold an entry in every space, in unsorted order
+    let space_list = vec![
+        SPACE_ADDRESS_C.clone(),
+        SPACE_ADDRESS_A.clone(),
+        SPACE_ADDRESS_B.clone(),
+    ];
+    for space_address in space_list.iter() {
+        let entry = EntryData {
+            entry_address: ENTRY_ADDRESS_1.clone(),
+            aspect_list: vec![EntryAspectData {
+                aspect_address: ASPECT_ADDRESS_1.clone(),
+                type_hint: "test".into(),
+                aspect: ASPECT_CONTENT_1.clone(),
+                publish_ts: 42,
+            }],
+        };
+        engine
+            .post(Lib3hClientProtocol::HoldEntry(ProvidedEntryData {
+                space_address: space_address.clone(),
+                provider_agent_id: ALEX_AGENT_ID.clone(),
+                entry,
+            }))
+            .unwrap();
+    }
+    // Space outputs should be merged in space order
+    let (_did_work, srv_msg_list) = engine.process().unwrap();
+    let fetch_space_list: Vec<_> = srv_msg_list
+        .iter()
+        .map(|msg| {
+            unwrap_to!(msg => Lib3hServerProtocol::HandleFetchEntry)
+                .space_address
+                .clone()
+        })
+        .collect();
+    assert_eq!(
+        fetch_space_list,
+        vec![
+            SPACE_ADDRESS_A.clone(),
+            SPACE_ADDRESS_B.clone(),
+            SPACE_ADDRESS_C.clone(),
+        ]
+    );
+}
+
+#[test]
+fn basic_track_test_mock_in_thread() {
+    enable_logging_for_test(true);
+    fn assert_send<E: Send>() {}
+    assert_send::<RealEngine<TransportWss<std::net::TcpStream>, MirrorDht>>();
+    // Setup
+    let mut engine = basic_setup_mock("basic_track_test_mock_in_thread");
+    // Move engine to another thread
+    std::thread::spawn(move || basic_track_test(&mut engine))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn basic_dump_state_test_mock() {
+    enable_logging_for_test(true);
+    // Setup
+    let mut engine = basic_setup_mock("basic_dump_state_test_mock");
+    basic_track_test(&mut engine);
+    // Hold an entry so the DHT has an entry and a pending fetch request
+    let entry = EntryData {
+        entry_address: ENTRY_ADDRESS_1.clone(),
+        aspect_list: vec![EntryAspectData {
+            aspect_address: ASPECT_ADDRESS_1.clone(),
+            type_hint: "test".into(),
+            aspect: ASPECT_CONTENT_1.clone(),
+            publish_ts: 42,
+        }],
+    };
+    engine
+        .post(Lib3hClientProtocol::HoldEntry(ProvidedEntryData {
+            space_address: SPACE_ADDRESS_A.clone(),
+            provider_agent_id: ALEX_AGENT_ID.clone(),
+            entry,
+        }))
+        .unwrap();
+    let (_did_work, srv_msg_list) = engine.process().unwrap();
+    assert_eq!(srv_msg_list.len(), 1);
+    // Rust API
+    let snapshot = engine.snapshot();
+    assert_eq!(snapshot.name, "basic_dump_state_test_mock");
+    assert_eq!(snapshot.inbox_depth, 0);
+    assert_eq!(snapshot.space_gateway_list.len(), 1);
+    let space = &snapshot.space_gateway_list[0];
+    assert_eq!(space.space_address, *SPACE_ADDRESS_A);
+    assert_eq!(space.agent_id, *ALEX_AGENT_ID);
+    assert_eq!(
+        space.gateway.dht.this_peer.peer_address,
+        ALEX_AGENT_ID.to_string()
+    );
+    assert_eq!(space.gateway.dht.entry_list.len(), 1);
+    let entry_snapshot = &space.gateway.dht.entry_list[0];
+    assert_eq!(entry_snapshot.entry_address, *ENTRY_ADDRESS_1);
+    assert_eq!(
+        entry_snapshot.aspect_address_list,
+        vec![ASPECT_ADDRESS_1.clone()]
+    );
+    assert_eq!(
+        space.gateway.dht.pending_fetch_request_list,
+        vec![ENTRY_ADDRESS_1.to_string()]
+    );
+    // Diagnostic protocol message
+    engine
+        .post(Lib3hClientProtocol::DumpState(DumpStateData {
+            request_id: "dump_1".into(),
+        }))
+        .unwrap();
+    let (did_work, srv_msg_list) = engine.process().unwrap();
+    assert!(did_work);
+    assert_eq!(srv_msg_list.len(), 1);
+    let res_msg = unwrap_to!(srv_msg_list[0] => Lib3hServerProtocol::DumpStateResult);
+    assert_eq!(res_msg.request_id, "dump_1".to_string());
+    let dumped: EngineSnapshot = serde_json::from_str(&res_msg.state).unwrap();
+    assert_eq!(dumped.process_count, snapshot.process_count + 1);
+    assert_eq!(
+        dumped.network_gateway.identifier,
+        snapshot.network_gateway.identifier
+    );
+    assert_eq!(dumped.space_gateway_list.len(), 1);
+    let dumped_dht = &dumped.space_gateway_list[0].gateway.dht;
+    assert_eq!(dumped_dht.entry_list, space.gateway.dht.entry_list);
+    assert_eq!(
+        dumped_dht.pending_fetch_request_list,
+        space.gateway.dht.pending_fetch_request_list
+    );
+}
+
 fn basic_track_test<T: Transport, D: Dht>(engine: &mut RealEngine<T, D>) {
     // Test
-    let mut track_space = SpaceData {
+    let mut track_space = JoinSpaceData {
         request_id: "track_a_1".into(),
         space_address: SPACE_ADDRESS_A.clone(),
         agent_id: ALEX_AGENT_ID.clone(),
+        storage_budget: None,
     };
     // First track should succeed
     engine
@@ -218,12 +519,23 @@ fn basic_two_nodes_mock() {
     enable_logging_for_test(true);
     // Launch tests on each setup
     for (test_fn, can_setup) in TWO_ENGINES_BASIC_TEST_FNS.iter() {
-        launch_two_nodes_test_with_memory_network(*test_fn, *can_setup).unwrap();
+        launch_two_nodes_test_with_memory_network(boxed_setup_mock, *test_fn, *can_setup).unwrap();
+    }
+}
+
+#[test]
+fn basic_two_nodes_mock_kademlia() {
+    enable_logging_for_test(true);
+    // Launch same tests with the Kademlia DHT
+    for (test_fn, can_setup) in TWO_ENGINES_BASIC_TEST_FNS.iter() {
+        launch_two_nodes_test_with_memory_network(boxed_setup_mock_kademlia, *test_fn, *can_setup)
+            .unwrap();
     }
 }
 
 // Do general test with config
 fn launch_two_nodes_test_with_memory_network(
+    setup_fn: EngineSetupFn,
     test_fn: TwoEnginesTestFn,
     can_setup: bool,
 ) -> Result<(), ()> {
@@ -232,8 +544,8 @@ fn launch_two_nodes_test_with_memory_network(
     println!("=======================");
 
     // Setup
-    let mut alex: Box<dyn NetworkEngine> = Box::new(basic_setup_mock("alex"));
-    let mut billy: Box<dyn NetworkEngine> = Box::new(basic_setup_mock("billy"));
+    let mut alex = setup_fn("alex");
+    let mut billy = setup_fn("billy");
     if can_setup {
         basic_two_setup(&mut alex, &mut billy);
     }
@@ -277,10 +589,11 @@ fn basic_two_setup(alex: &mut Box<dyn NetworkEngine>, billy: &mut Box<dyn Networ
 
     // Alex joins space A
     println!("\n Alex joins space \n");
-    let mut track_space = SpaceData {
+    let mut track_space = JoinSpaceData {
         request_id: "track_a_1".into(),
         space_address: SPACE_ADDRESS_A.clone(),
         agent_id: ALEX_AGENT_ID.clone(),
+        storage_budget: None,
     };
     alex.post(Lib3hClientProtocol::JoinSpace(track_space.clone()))
         .unwrap();
@@ -312,7 +625,7 @@ fn basic_two_send_message(alex: &mut Box<dyn NetworkEngine>, billy: &mut Box<dyn
         request_id: "dm_1".to_string(),
         to_agent_id: BILLY_AGENT_ID.clone(),
         from_agent_id: ALEX_AGENT_ID.clone(),
-        content: "wah".as_bytes().to_vec(),
+        content: "wah".as_bytes().into(),
     };
     // Send
     println!("\nAlex sends DM to Billy...\n");
@@ -338,7 +651,7 @@ fn basic_two_send_message(alex: &mut Box<dyn NetworkEngine>, billy: &mut Box<dyn
     let mut res_dm = req_dm.clone();
     res_dm.to_agent_id = req_dm.from_agent_id.clone();
     res_dm.from_agent_id = req_dm.to_agent_id.clone();
-    res_dm.content = format!("echo: {}", content).as_bytes().to_vec();
+    res_dm.content = format!("echo: {}", content).into_bytes().into();
     billy
         .post(Lib3hClientProtocol::HandleSendDirectMessageResult(
             res_dm.clone(),
@@ -366,10 +679,11 @@ fn basic_two_join_first(alex: &mut Box<dyn NetworkEngine>, billy: &mut Box<dyn N
     // Setup: Track before connecting
 
     // A joins space
-    let mut track_space = SpaceData {
+    let mut track_space = JoinSpaceData {
         request_id: "track_a_1".into(),
         space_address: SPACE_ADDRESS_A.clone(),
         agent_id: ALEX_AGENT_ID.clone(),
+        storage_budget: None,
     };
     println!("\n Alex joins space \n");
     alex.post(Lib3hClientProtocol::JoinSpace(track_space.clone()))
@@ -24,6 +24,7 @@ use lib3h::{
 };
 use lib3h_protocol::{network_engine::NetworkEngine, Address};
 use node_mock::NodeMock;
+use std::collections::HashMap;
 use test_suites::{
     three_basic::*, two_basic::*, two_connection::*, two_get_lists::*, two_spaces::*,
 };
@@ -84,6 +85,7 @@ fn setup_memory_node(name: &str, agent_id_arg: Address, fn_name: &str) -> NodeMo
     let fn_name = fn_name.replace("::", "__");
     let config = RealEngineConfig {
         tls_config: TlsConfig::Unencrypted,
+        tls_trust_policy: None,
         socket_type: "mem".into(),
         bootstrap_nodes: vec![],
         work_dir: String::new(),
@@ -91,7 +93,12 @@ fn setup_memory_node(name: &str, agent_id_arg: Address, fn_name: &str) -> NodeMo
         bind_url: Url::parse(format!("mem://{}/{}", fn_name, name).as_str()).unwrap(),
         dht_gossip_interval: 500,
         dht_timeout_threshold: 3000,
+        dht_anti_entropy_interval: 10000,
+        dht_eviction_grace_period: 60000,
         dht_custom_config: vec![],
+        space_worker_count: 0,
+        dht_replication_factor: 3,
+        space_replication_factor_map: HashMap::new(),
     };
     NodeMock::new_with_config(name, agent_id_arg, config, construct_mock_engine)
 }
@@ -106,13 +113,14 @@ fn setup_wss_node(
     let port = generate_port();
     let protocol = match tls_config {
         TlsConfig::Unencrypted => "ws",
-        TlsConfig::SuppliedCertificate(_) | TlsConfig::FakeServer => "wss",
+        TlsConfig::SuppliedCertificate(_) | TlsConfig::FakeServer | TlsConfig::SelfSigned => "wss",
     };
     let bind_url = Url::parse(format!("{}://127.0.0.1:{}/{}", protocol, port, fn_name).as_str())
         .expect("invalid web socket url");
 
     let config = RealEngineConfig {
         tls_config: tls_config,
+        tls_trust_policy: None,
         socket_type: protocol.into(),
         bootstrap_nodes: vec![],
         work_dir: String::new(),
@@ -120,7 +128,12 @@ fn setup_wss_node(
         bind_url,
         dht_gossip_interval: 500,
         dht_timeout_threshold: 3005,
+        dht_anti_entropy_interval: 10000,
+        dht_eviction_grace_period: 60000,
         dht_custom_config: vec![],
+        space_worker_count: 0,
+        dht_replication_factor: 3,
+        space_replication_factor_map: HashMap::new(),
     };
     NodeMock::new_with_config(name, agent_id_arg, config, construct_mock_engine)
 }
@@ -323,6 +336,26 @@ fn test_two_wss_tls_nodes_connection_suite() {
     }
 }
 
+// -- Wss+Tls Transport Tests with self-signed certificates --
+// Servers then require client certificates, which only the rustls backend can
+#[cfg(feature = "rustls-tls")]
+#[test]
+fn test_two_wss_self_signed_nodes_basic_suite() {
+    enable_logging_for_test(true);
+    for (test_fn, can_setup) in TWO_NODES_BASIC_TEST_FNS.iter() {
+        launch_two_wss_nodes_test(*test_fn, TlsConfig::SelfSigned, *can_setup).unwrap();
+    }
+}
+
+#[cfg(feature = "rustls-tls")]
+#[test]
+fn test_two_wss_self_signed_nodes_spaces_suite() {
+    enable_logging_for_test(true);
+    for (test_fn, can_setup) in TWO_NODES_SPACES_TEST_FNS.iter() {
+        launch_two_wss_nodes_test(*test_fn, TlsConfig::SelfSigned, *can_setup).unwrap();
+    }
+}
+
 // Do general test with config
 fn launch_two_wss_nodes_test(
     test_fn: TwoNodesTestFn,
@@ -146,10 +146,11 @@ impl NodeMock {
         space_address: &Address,
         can_set_current: bool,
     ) -> Lib3hResult<String> {
-        let join_space = lib3h_protocol::data_types::SpaceData {
+        let join_space = lib3h_protocol::data_types::JoinSpaceData {
             request_id: self.generate_request_id(),
             space_address: space_address.clone(),
             agent_id: self.agent_id.clone(),
+            storage_budget: None,
         };
         let protocol_msg = Lib3hClientProtocol::JoinSpace(join_space.clone()).into();
 
@@ -457,7 +458,7 @@ impl NodeMock {
             request_id: request_id.clone(),
             to_agent_id: to_agent_id.clone(),
             from_agent_id: self.agent_id.clone(),
-            content,
+            content: content.into(),
         };
         let p = Lib3hClientProtocol::SendDirectMessage(msg_data.clone()).into();
         self.engine
@@ -479,7 +480,7 @@ impl NodeMock {
             request_id: request_id.to_owned(),
             to_agent_id: to_agent_id.clone(),
             from_agent_id: self.agent_id.clone(),
-            content: response_content,
+            content: response_content.into(),
         };
         self.engine
             .post(Lib3hClientProtocol::HandleSendDirectMessageResult(response.clone()).into())
@@ -703,6 +704,9 @@ impl NodeMock {
             Lib3hServerProtocol::Disconnected(_msg) => {
                 // no-op
             }
+            Lib3hServerProtocol::PeerEvicted(_msg) => {
+                // no-op
+            }
             Lib3hServerProtocol::SendDirectMessageResult(_msg) => {
                 // no-op
             }
@@ -760,6 +764,9 @@ impl NodeMock {
             Lib3hServerProtocol::HandleGetGossipingEntryList(_msg) => {
                 // no-op
             }
+            Lib3hServerProtocol::DumpStateResult(_msg) => {
+                // no-op
+            }
             Lib3hServerProtocol::Terminated => {
                 // no-op
             }
@@ -1,4 +1,4 @@
-use crate::Address;
+use crate::{opaque::Opaque, Address};
 use std::cmp::Ordering;
 use url::Url;
 
@@ -125,6 +125,16 @@ pub struct DisconnectedData {
     pub network_id: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PeerEvictedData {
+    /// Space the peer was evicted from
+    pub space_address: Address,
+    /// Our agent in that space
+    pub agent_id: Address,
+    /// Agent that has not been heard of for too long
+    pub peer_agent_id: Address,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Space tracking
 //--------------------------------------------------------------------------------------------------
@@ -137,6 +147,47 @@ pub struct SpaceData {
     pub agent_id: Address,
 }
 
+/// `SpaceData` of a `JoinSpace`, with the settings of the agent in that space
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct JoinSpaceData {
+    /// Identifier of this request
+    pub request_id: String,
+    pub space_address: Address,
+    pub agent_id: Address,
+    /// Limit on what this agent holds in the space. None means no limit.
+    #[serde(default)]
+    pub storage_budget: Option<StorageBudgetData>,
+}
+
+/// Storage budget of an agent in a space.
+/// Once exceeded, entries are let go and core is asked to drop them with `HandleDropEntry`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StorageBudgetData {
+    /// Maximum number of aspects held. None means no limit.
+    pub max_aspect_count: Option<u64>,
+    /// Maximum number of aspect content bytes held, as reported by core. None means no limit.
+    pub max_byte_count: Option<u64>,
+    /// Which entries to let go first
+    #[serde(default)]
+    pub prune_policy: PrunePolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PrunePolicy {
+    /// Entries held for the longest time
+    Oldest,
+    /// Entries whose location is the farthest from the agent's
+    Farthest,
+    /// Entries requested the least recently
+    LeastRecentlyUsed,
+}
+
+impl Default for PrunePolicy {
+    fn default() -> Self {
+        PrunePolicy::LeastRecentlyUsed
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Direct Messaging
 //--------------------------------------------------------------------------------------------------
@@ -148,7 +199,7 @@ pub struct DirectMessageData {
     pub to_agent_id: Address,
     pub from_agent_id: Address,
     #[serde(with = "base64")]
-    pub content: Vec<u8>,
+    pub content: Opaque,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -248,6 +299,23 @@ pub struct EntryListData {
     pub address_map: std::collections::HashMap<Address, Vec<Address>>, // Aspect addresses per entry
 }
 
+//--------------------------------------------------------------------------------------------------
+// Diagnostics
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DumpStateData {
+    /// Identifier of this request
+    pub request_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DumpStateResultData {
+    pub request_id: String,
+    /// Implementation specific snapshot of the network module's internal state, JSON encoded
+    pub state: String,
+}
+
 // ---------- serialization helper for binary data as base 64 ---------- //
 
 mod base64 {
@@ -264,11 +332,12 @@ mod base64 {
         ))
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    pub fn deserialize<'de, D, B>(deserializer: D) -> Result<B, D::Error>
     where
         D: Deserializer<'de>,
+        B: From<Vec<u8>>,
     {
         let s = <String>::deserialize(deserializer)?;
-        base64::decode(&s).map_err(de::Error::custom)
+        base64::decode(&s).map(B::from).map_err(de::Error::custom)
     }
 }
@@ -8,6 +8,7 @@ extern crate rmp_serde;
 
 pub mod data_types;
 pub mod network_engine;
+pub mod opaque;
 pub mod protocol_client;
 pub mod protocol_server;
 
@@ -3,6 +3,7 @@ use crate::{
     protocol_server::Lib3hServerProtocol, DidWork,
 };
 
+use std::time::Duration;
 use url::Url;
 
 /// Common interface for all types of network modules to be used by the Lib3hWorker
@@ -15,4 +16,17 @@ pub trait NetworkEngine {
     fn process(&mut self) -> Lib3hProtocolResult<(DidWork, Vec<Lib3hServerProtocol>)>;
     /// Get qualified transport address
     fn advertise(&self) -> Url;
+    /// Earliest time (in milliseconds since UNIX epoch) at which `process()` needs
+    /// to be called again, e.g. for gossip, timeout checks or heartbeats.
+    /// A time in the past means there is pending work.
+    /// None means only new incoming data or a `post()` can create work.
+    fn next_deadline_ms(&self) -> Option<u64> {
+        None
+    }
+    /// Block until `process()` has work to do, i.e. incoming network data or
+    /// `next_deadline_ms()` being due, for at most `max_wait`.
+    /// Engines that cannot tell return right away, callers then keep calling `process()`.
+    fn wait_ready(&mut self, _max_wait: Duration) -> Lib3hProtocolResult<()> {
+        Ok(())
+    }
 }
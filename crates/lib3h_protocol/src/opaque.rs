@@ -0,0 +1,229 @@
+//! Reference-counted byte buffer used for payloads that travel through
+//! transports, gateways and the engine without being copied.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Deref, Range},
+    sync::Arc,
+};
+
+/// Immutable byte buffer backed by a shared allocation.
+/// Cloning or slicing an `Opaque` only bumps a reference count,
+/// so the same payload can be handed to several connections or queues
+/// while the bytes are stored once.
+#[derive(Clone)]
+pub struct Opaque {
+    data: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl Opaque {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        Vec::new().into()
+    }
+
+    /// view the bytes of this buffer
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.start..self.end]
+    }
+
+    /// get a sub-range of this buffer, sharing the same allocation
+    /// panics if the range is out of bounds
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "Opaque::slice range {:?} out of bounds for length {}",
+            range,
+            self.len()
+        );
+        Opaque {
+            data: Arc::clone(&self.data),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+
+    /// convert into an owned Vec<u8>
+    /// the bytes are only copied if the allocation is shared or sliced
+    pub fn into_vec(self) -> Vec<u8> {
+        let Opaque { data, start, end } = self;
+        if start == 0 && end == data.len() {
+            match Arc::try_unwrap(data) {
+                Ok(vec) => vec,
+                Err(data) => data[..].to_vec(),
+            }
+        } else {
+            data[start..end].to_vec()
+        }
+    }
+}
+
+impl Default for Opaque {
+    fn default() -> Self {
+        Opaque::new()
+    }
+}
+
+impl From<Vec<u8>> for Opaque {
+    fn from(vec: Vec<u8>) -> Self {
+        let end = vec.len();
+        Opaque {
+            data: Arc::new(vec),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<&[u8]> for Opaque {
+    fn from(bytes: &[u8]) -> Self {
+        bytes.to_vec().into()
+    }
+}
+
+impl From<Opaque> for Vec<u8> {
+    fn from(opaque: Opaque) -> Self {
+        opaque.into_vec()
+    }
+}
+
+impl Deref for Opaque {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Opaque {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl PartialEq for Opaque {
+    fn eq(&self, other: &Opaque) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Opaque {}
+
+impl PartialEq<Vec<u8>> for Opaque {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Hash for Opaque {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl fmt::Debug for Opaque {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl Serialize for Opaque {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.as_slice())
+    }
+}
+
+impl<'de> Deserialize<'de> for Opaque {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(OpaqueVisitor)
+    }
+}
+
+struct OpaqueVisitor;
+
+impl<'de> de::Visitor<'de> for OpaqueVisitor {
+    type Value = Opaque;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte buffer")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Opaque, E> {
+        Ok(v.into())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Opaque, E> {
+        Ok(v.into())
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Opaque, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut out: Vec<u8> = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            out.push(b);
+        }
+        Ok(out.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmp_serde::{Deserializer, Serializer};
+
+    #[test]
+    fn it_shares_the_allocation_on_clone_and_slice() {
+        let a: Opaque = vec![1, 2, 3, 4, 5].into();
+        let b = a.clone();
+        let c = a.slice(1..4);
+        assert_eq!(a.as_slice().as_ptr(), b.as_slice().as_ptr());
+        assert_eq!(&a[1..4], c.as_slice());
+        assert_eq!(c.as_slice().as_ptr(), a[1..].as_ptr());
+        assert_eq!(c.slice(1..2).as_slice(), &[3]);
+        assert_eq!(c, Opaque::from(&[2u8, 3, 4][..]));
+    }
+
+    #[test]
+    fn it_into_vec_without_copy_when_unique() {
+        let vec = vec![1, 2, 3];
+        let ptr = vec.as_ptr();
+        let a: Opaque = vec.into();
+        let out = a.into_vec();
+        assert_eq!(ptr, out.as_ptr());
+
+        let a: Opaque = out.into();
+        let b = a.clone();
+        assert_eq!(vec![1, 2, 3], a.into_vec());
+        assert_eq!(vec![2, 3], b.slice(1..3).into_vec());
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_panics_on_out_of_bounds_slice() {
+        let a: Opaque = vec![1, 2, 3].into();
+        a.slice(2..4);
+    }
+
+    #[test]
+    fn it_roundtrips_through_msgpack() {
+        let a: Opaque = vec![0, 1, 2, 254, 255].into();
+        let a = a.slice(1..5);
+        let mut buf = Vec::new();
+        a.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        let mut de = Deserializer::new(&buf[..]);
+        let b: Opaque = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(a, b);
+    }
+}
@@ -24,7 +24,7 @@ pub enum Lib3hClientProtocol {
 
     // -- Space -- //
     /// Order the p2p module to be part of the network of the specified space.
-    JoinSpace(SpaceData),
+    JoinSpace(JoinSpaceData),
     /// Order the p2p module to leave the network of the specified space.
     LeaveSpace(SpaceData),
 
@@ -52,6 +52,10 @@ pub enum Lib3hClientProtocol {
     HandleGetAuthoringEntryListResult(EntryListData),
     HandleGetGossipingEntryListResult(EntryListData),
 
+    // -- Diagnostics -- //
+    /// Request a dump of the network module's internal state, for debugging
+    DumpState(DumpStateData),
+
     // -- N3h specific functinonality -- //
     Shutdown,
 }
@@ -23,6 +23,8 @@ pub enum Lib3hServerProtocol {
     Connected(ConnectedData),
     /// Notification of disconnection from a network
     Disconnected(DisconnectedData),
+    /// Notification that a peer has been silent for too long and was dropped from a space
+    PeerEvicted(PeerEvictedData),
 
     // -- Direct Messaging -- //
     /// the response received from a previous `SendDirectMessage`
@@ -49,6 +51,10 @@ pub enum Lib3hServerProtocol {
     HandleGetAuthoringEntryList(GetListData),
     HandleGetGossipingEntryList(GetListData),
 
+    // -- Diagnostics -- //
+    /// Response to a `DumpState` request
+    DumpStateResult(DumpStateResultData),
+
     // -- N3h specific functinonality -- //
     Terminated,
     P2pReady,
@@ -41,10 +41,15 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
         network_id: "nid".to_string(),
     }));
 
-    test_client(Lib3hClientProtocol::JoinSpace(SpaceData {
+    test_client(Lib3hClientProtocol::JoinSpace(JoinSpaceData {
         request_id: "rid".to_string(),
         space_address: "adr".to_string().into(),
         agent_id: "aid".to_string().into(),
+        storage_budget: Some(StorageBudgetData {
+            max_aspect_count: Some(1000),
+            max_byte_count: None,
+            prune_policy: PrunePolicy::LeastRecentlyUsed,
+        }),
     }));
 
     test_client(Lib3hClientProtocol::LeaveSpace(SpaceData {
@@ -58,7 +63,7 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
         request_id: "rid".to_string(),
         to_agent_id: "aid".to_string().into(),
         from_agent_id: "aid".to_string().into(),
-        content: b"yo".to_vec(),
+        content: b"yo".to_vec().into(),
     }));
 
     test_client(Lib3hClientProtocol::HandleSendDirectMessageResult(
@@ -67,7 +72,7 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
             request_id: "rid".to_string(),
             to_agent_id: "aid".to_string().into(),
             from_agent_id: "aid".to_string().into(),
-            content: b"yo".to_vec(),
+            content: b"yo".to_vec().into(),
         },
     ));
 
@@ -167,6 +172,10 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
         },
     ));
 
+    test_client(Lib3hClientProtocol::DumpState(DumpStateData {
+        request_id: "rid".to_string(),
+    }));
+
     test_client(Lib3hClientProtocol::Shutdown);
 
     // -- server -- //
@@ -196,13 +205,19 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
         network_id: "nid".to_string(),
     }));
 
+    test_server(Lib3hServerProtocol::PeerEvicted(PeerEvictedData {
+        space_address: "adr".to_string().into(),
+        agent_id: "aid".to_string().into(),
+        peer_agent_id: "aid2".to_string().into(),
+    }));
+
     test_server(Lib3hServerProtocol::SendDirectMessageResult(
         DirectMessageData {
             space_address: "adr".to_string().into(),
             request_id: "rid".to_string(),
             to_agent_id: "aid".to_string().into(),
             from_agent_id: "aid".to_string().into(),
-            content: b"yo".to_vec(),
+            content: b"yo".to_vec().into(),
         },
     ));
 
@@ -212,7 +227,7 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
             request_id: "rid".to_string(),
             to_agent_id: "aid".to_string().into(),
             from_agent_id: "aid".to_string().into(),
-            content: b"yo".to_vec(),
+            content: b"yo".to_vec().into(),
         },
     ));
 
@@ -297,6 +312,11 @@ pub fn dump_lib3h_protocol_as_json_for_n3h() {
         },
     ));
 
+    test_server(Lib3hServerProtocol::DumpStateResult(DumpStateResultData {
+        request_id: "rid".to_string(),
+        state: "{}".to_string(),
+    }));
+
     test_server(Lib3hServerProtocol::Terminated);
 
     test_server(Lib3hServerProtocol::P2pReady);
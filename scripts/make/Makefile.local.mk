@@ -24,6 +24,7 @@ test: tools
 		-A clippy::pedantic -A clippy::restriction \
 		-D clippy::complexity -D clippy::perf -D clippy::correctness
 	$(ENV) RUST_BACKTRACE=1 cargo test
+	cd crates/lib3h && $(ENV) RUST_BACKTRACE=1 cargo test --no-default-features --features rustls-tls
 
 fmt: tools
 	cargo fmt